@@ -0,0 +1,120 @@
+/*
+ * C interface of ms-teams-ws, see src/ffi.rs. Generated by cbindgen, do not
+ * edit: TEAMS_WS_UPDATE_HEADER=1 cargo test --features ffi ffi
+ *
+ * Build with: cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * Functions returning an int return an exit status: 0 ok, 1 failed,
+ * 2 usage, 3 not connected, 4 not in meeting, 5 not permitted, 6 timeout.
+ * teams_ws_last_error describes the last failure on the calling thread.
+ */
+
+#ifndef MS_TEAMS_WS_H
+#define MS_TEAMS_WS_H
+
+/**
+ * A connected client, created by `teams_ws_connect` and released by
+ * `teams_ws_free`.
+ */
+typedef struct TeamsWsClient TeamsWsClient;
+
+/**
+ * Called with the meeting state as JSON, e.g.
+ * `{"isMuted":true,"isInMeeting":true,...}`, and the `user_data` given
+ * on registration. The string is only valid during the call.
+ */
+typedef void (*TeamsWsStateCallback)(const char *state_json, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Connects to Teams and returns the client, or `NULL` on failure.
+ *
+ * `url`, `token` and `app` may be `NULL` for the URL and token of the
+ * environment and the user config, and the default app name shown when
+ * Teams asks to pair. Tokens Teams grants are stored in the user config.
+ *
+ * # Safety
+ *
+ * The arguments must be `NULL` or NUL-terminated UTF-8 strings.
+ */
+TeamsWsClient *teams_ws_connect(const char *url, const char *token, const char *app);
+
+/**
+ * Sends the action named `action`, e.g. `"toggle-mute"` or
+ * `"raise-hand"`, and waits for Teams' answer.
+ *
+ * # Safety
+ *
+ * `client` must come from `teams_ws_connect` and `action` must be a
+ * NUL-terminated string.
+ */
+int teams_ws_send_action(TeamsWsClient *client, const char *action);
+
+/**
+ * Toggles the microphone and waits for Teams' answer.
+ *
+ * # Safety
+ *
+ * `client` must come from `teams_ws_connect`.
+ */
+int teams_ws_toggle_mute(TeamsWsClient *client);
+
+/**
+ * Returns the meeting state Teams last reported as JSON, or `NULL` before
+ * it reported one. Release the string with `teams_ws_string_free`.
+ *
+ * # Safety
+ *
+ * `client` must come from `teams_ws_connect`.
+ */
+char *teams_ws_state(TeamsWsClient *client);
+
+/**
+ * Calls `callback` with the meeting state after every change, on the
+ * thread of the client, until another callback is registered or the
+ * client is released. A `NULL` callback unregisters it. The previous
+ * callback is not called anymore once this returns.
+ *
+ * # Safety
+ *
+ * `client` must come from `teams_ws_connect`, and `callback` and
+ * `user_data` must stay usable from another thread until then.
+ */
+int teams_ws_set_state_callback(TeamsWsClient *client,
+                                TeamsWsStateCallback callback,
+                                void *user_data);
+
+/**
+ * Returns the last error on the calling thread, or `NULL`. The string is
+ * valid until the next call failing on the thread.
+ */
+const char *teams_ws_last_error(void);
+
+/**
+ * Releases a string returned by this library.
+ *
+ * # Safety
+ *
+ * `string` must be `NULL` or come from this library and not be released yet.
+ */
+void teams_ws_string_free(char *string);
+
+/**
+ * Closes the connection and releases `client`, after the state callback
+ * returned if it is running.
+ *
+ * # Safety
+ *
+ * `client` must be `NULL` or come from `teams_ws_connect` and not be
+ * released yet.
+ */
+void teams_ws_free(TeamsWsClient *client);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MS_TEAMS_WS_H */
@@ -0,0 +1,170 @@
+use crate::messages::{ClientMessage, Command, CustomAction, MeetingAction};
+use std::borrow::Cow;
+
+/// An action that can be sent to Teams with `TeamsWebsocket::send`.
+///
+/// Implemented by `MeetingAction`, `Command` and `ClientMessage`.
+/// Downstream crates implement it to send actions this crate does not know
+/// yet, e.g. ones a Teams preview added. Those are sent as a
+/// `ClientMessage::custom` and get the same checks, queueing and audit
+/// logging as the known ones.
+///
+/// # Example
+/// ```rust
+/// struct ToggleCaptions;
+///
+/// impl Action for ToggleCaptions {
+///     fn wire_name(&self) -> Cow<'static, str> {
+///         Cow::Borrowed("toggle-captions")
+///     }
+/// }
+///
+/// let id = websocket.send(ToggleCaptions).await?;
+/// ```
+pub trait Action {
+    /// Returns the name of the action on the wire, e.g. `toggle-mute`.
+    fn wire_name(&self) -> Cow<'static, str>;
+
+    /// Returns the parameters sent with the action.
+    fn parameters(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Returns whether Teams only performs the action in a meeting.
+    fn requires_meeting(&self) -> bool {
+        true
+    }
+
+    /// Returns the `MeetingAction` this action is, so the checks for it,
+    /// e.g. the permissions and the confirmation hook, apply.
+    fn meeting_action(&self) -> Option<MeetingAction> {
+        None
+    }
+
+    /// Returns the message sending the action.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the parameters of a `MeetingAction` are not a
+    /// `ClientMessageParameter`.
+    fn to_message(&self) -> Result<ClientMessage, serde_json::Error> {
+        match self.meeting_action() {
+            Some(action) => {
+                let parameters = self.parameters().map(serde_json::from_value).transpose()?;
+                Ok(ClientMessage::new(action, parameters))
+            }
+            None => Ok(ClientMessage::custom(CustomAction {
+                name: self.wire_name().into_owned(),
+                parameters: self.parameters(),
+                requires_meeting: self.requires_meeting(),
+            })),
+        }
+    }
+
+    /// Returns the message sending the action, like `to_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `to_message`.
+    fn into_message(self) -> Result<ClientMessage, serde_json::Error>
+    where
+        Self: Sized,
+    {
+        self.to_message()
+    }
+}
+
+impl<A: Action + ?Sized> Action for &A {
+    fn wire_name(&self) -> Cow<'static, str> {
+        (**self).wire_name()
+    }
+
+    fn parameters(&self) -> Option<serde_json::Value> {
+        (**self).parameters()
+    }
+
+    fn requires_meeting(&self) -> bool {
+        (**self).requires_meeting()
+    }
+
+    fn meeting_action(&self) -> Option<MeetingAction> {
+        (**self).meeting_action()
+    }
+
+    fn to_message(&self) -> Result<ClientMessage, serde_json::Error> {
+        (**self).to_message()
+    }
+}
+
+impl Action for MeetingAction {
+    fn wire_name(&self) -> Cow<'static, str> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => Cow::Owned(name),
+            _ => Cow::Borrowed("none"),
+        }
+    }
+
+    fn requires_meeting(&self) -> bool {
+        MeetingAction::requires_meeting(*self)
+    }
+
+    fn meeting_action(&self) -> Option<MeetingAction> {
+        Some(*self)
+    }
+}
+
+impl Action for Command {
+    fn wire_name(&self) -> Cow<'static, str> {
+        self.action().wire_name()
+    }
+
+    fn parameters(&self) -> Option<serde_json::Value> {
+        self.parameter()
+            .and_then(|parameter| serde_json::to_value(parameter).ok())
+    }
+
+    fn requires_meeting(&self) -> bool {
+        self.action().requires_meeting()
+    }
+
+    fn meeting_action(&self) -> Option<MeetingAction> {
+        Some(self.action())
+    }
+
+    fn to_message(&self) -> Result<ClientMessage, serde_json::Error> {
+        Ok(ClientMessage::from(*self))
+    }
+}
+
+/// Keeps the request id, the origin and a custom action of the message.
+impl Action for ClientMessage {
+    fn wire_name(&self) -> Cow<'static, str> {
+        ClientMessage::wire_name(self)
+    }
+
+    fn parameters(&self) -> Option<serde_json::Value> {
+        match &self.custom {
+            Some(custom) => custom.parameters.clone(),
+            None => self
+                .parameters
+                .as_ref()
+                .and_then(|parameters| serde_json::to_value(parameters).ok()),
+        }
+    }
+
+    fn requires_meeting(&self) -> bool {
+        ClientMessage::requires_meeting(self)
+    }
+
+    fn meeting_action(&self) -> Option<MeetingAction> {
+        self.custom.is_none().then_some(self.action)
+    }
+
+    fn to_message(&self) -> Result<ClientMessage, serde_json::Error> {
+        Ok(self.clone())
+    }
+
+    fn into_message(self) -> Result<ClientMessage, serde_json::Error> {
+        Ok(self)
+    }
+}
@@ -0,0 +1,233 @@
+use crate::lifecycle::MeetingPhase;
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingUpdate, ServerMessage};
+use crate::state::StateTracker;
+use crate::{TeamsWebsocket, TeamsWsError};
+use futures_util::future::select_all;
+use futures_util::FutureExt;
+use serde::Serialize;
+use std::error::Error;
+
+/// The combined presence of all accounts of an `Aggregator`.
+///
+/// # Fields
+///
+/// * `in_meeting` - Whether any account is in a meeting.
+/// * `sharing` - Whether any account is sharing its screen.
+/// * `has_unread_messages` - Whether any account has unread messages.
+/// * `active` - The account commands are routed to, if any is in a meeting.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Presence {
+    pub in_meeting: bool,
+    pub sharing: bool,
+    pub has_unread_messages: bool,
+    pub active: Option<String>,
+}
+
+struct Account {
+    name: String,
+    websocket: TeamsWebsocket,
+    tracker: StateTracker,
+}
+
+/// Combines several connections, e.g. work and personal accounts or classic
+/// and new Teams, into a single presence.
+///
+/// Commands sent with `Aggregator::send` go to the account currently in a
+/// meeting, preferring one that is presenting.
+///
+/// # Example
+/// ```rust
+/// let mut aggregator = Aggregator::new();
+/// aggregator.add("work", work).add("personal", personal);
+/// loop {
+///     let Some((account, message)) = aggregator.receive().await else { break };
+///     message?;
+///     set_busylight(aggregator.presence().in_meeting);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Aggregator {
+    accounts: Vec<Account>,
+}
+
+impl Aggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a connected websocket under `name`.
+    pub fn add(&mut self, name: impl Into<String>, websocket: TeamsWebsocket) -> &mut Self {
+        self.accounts.push(Account {
+            name: name.into(),
+            websocket,
+            tracker: StateTracker::new(),
+        });
+        self
+    }
+
+    /// Returns the names of the accounts in the order they were added.
+    pub fn accounts(&self) -> impl Iterator<Item = &str> {
+        self.accounts.iter().map(|account| account.name.as_str())
+    }
+
+    /// Returns the state of the account `name`.
+    pub fn state(&self, name: &str) -> Option<&StateTracker> {
+        self.account(name).map(|account| &account.tracker)
+    }
+
+    /// Returns the websocket of the account `name`.
+    pub fn websocket(&mut self, name: &str) -> Option<&mut TeamsWebsocket> {
+        self.accounts
+            .iter_mut()
+            .find(|account| account.name == name)
+            .map(|account| &mut account.websocket)
+    }
+
+    fn account(&self, name: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.name == name)
+    }
+
+    /// Returns the account commands are routed to: the first presenting
+    /// account, otherwise the first account in a meeting.
+    pub fn active(&self) -> Option<&str> {
+        let in_phase = |phase: MeetingPhase| {
+            self.accounts
+                .iter()
+                .find(|account| account.tracker.lifecycle().phase() == phase)
+        };
+        in_phase(MeetingPhase::Presenting)
+            .or_else(|| {
+                self.accounts.iter().find(|account| {
+                    account
+                        .tracker
+                        .meeting_state()
+                        .is_some_and(|state| state.is_in_meeting)
+                })
+            })
+            .map(|account| account.name.as_str())
+    }
+
+    /// Returns the combined presence of all accounts.
+    pub fn presence(&self) -> Presence {
+        let any = |field: fn(&crate::messages::MeetingState) -> bool| {
+            self.accounts
+                .iter()
+                .any(|account| account.tracker.meeting_state().is_some_and(field))
+        };
+        Presence {
+            in_meeting: any(|state| state.is_in_meeting),
+            sharing: any(|state| state.is_sharing),
+            has_unread_messages: any(|state| state.has_unread_messages),
+            active: self.active().map(str::to_string),
+        }
+    }
+
+    fn apply(&mut self, index: usize, update: &MeetingUpdate) {
+        self.accounts[index].tracker.apply(update);
+    }
+
+    /// Receives the next message of any account and returns it with the
+    /// account name, or `None` without accounts.
+    ///
+    /// Meeting updates are applied to the account's state before returning.
+    pub async fn receive(&mut self) -> Option<(String, Result<ServerMessage, Box<dyn Error>>)> {
+        if self.accounts.is_empty() {
+            return None;
+        }
+        let (message, index, _) = select_all(
+            self.accounts
+                .iter_mut()
+                .map(|account| account.websocket.receive().boxed_local()),
+        )
+        .await;
+        if let Ok(ServerMessage {
+            meeting_update: Some(update),
+            ..
+        }) = &message
+        {
+            self.apply(index, update);
+        }
+        Some((self.accounts[index].name.clone(), message))
+    }
+
+    /// Sends `message` to the active account.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeamsWsError::NoActiveMeeting` if no account is in a meeting,
+    /// otherwise the errors of `TeamsWebsocket::send`.
+    pub async fn send(&mut self, message: ClientMessage) -> Result<u32, Box<dyn Error>> {
+        let Some(name) = self.active().map(str::to_string) else {
+            return Err(Box::new(TeamsWsError::NoActiveMeeting));
+        };
+        debug!(target: logging::CONNECTION, "Routing {:?} to account {}", message.action, name);
+        self.websocket(&name)
+            .expect("active account exists")
+            .send(message)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{MeetingAction, MeetingState};
+    use crate::types::AppIdentifiers;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_aggregator_presence() {
+        Runtime::new().unwrap().block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let mut aggregator = Aggregator::new();
+            aggregator
+                .add(
+                    "work",
+                    TeamsWebsocket::builder(identifier.clone())
+                        .ignore_environment()
+                        .build()
+                        .unwrap(),
+                )
+                .add(
+                    "personal",
+                    TeamsWebsocket::builder(identifier)
+                        .ignore_environment()
+                        .build()
+                        .unwrap(),
+                );
+            assert_eq!(aggregator.presence(), Presence::default());
+            let error = aggregator
+                .send(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::NoActiveMeeting)
+            ));
+
+            let in_meeting = |is_sharing| MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState {
+                    is_in_meeting: true,
+                    is_sharing,
+                    ..MeetingState::default()
+                }),
+            };
+            aggregator.apply(0, &in_meeting(false));
+            aggregator.apply(1, &in_meeting(true));
+            let presence = aggregator.presence();
+            assert!(presence.in_meeting && presence.sharing);
+            assert_eq!(presence.active.as_deref(), Some("personal"));
+
+            aggregator.apply(1, &in_meeting(false));
+            assert_eq!(aggregator.active(), Some("work"));
+        });
+    }
+}
@@ -0,0 +1,213 @@
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction};
+use crate::TeamsWsError;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The window in which a command blocks conflicting lower-priority commands by default.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+
+/// What a group of conflicting actions controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Control {
+    Microphone,
+    Camera,
+    Background,
+    Hand,
+    Call,
+}
+
+fn control(action: MeetingAction) -> Option<Control> {
+    match action {
+        MeetingAction::Mute | MeetingAction::Unmute | MeetingAction::ToggleMute => {
+            Some(Control::Microphone)
+        }
+        MeetingAction::HideVideo | MeetingAction::ShowVideo | MeetingAction::ToggleVideo => {
+            Some(Control::Camera)
+        }
+        MeetingAction::BlurBackground
+        | MeetingAction::UnblurBackground
+        | MeetingAction::ToggleBlurBackground => Some(Control::Background),
+        MeetingAction::RaiseHand | MeetingAction::LowerHand | MeetingAction::ToggleHand => {
+            Some(Control::Hand)
+        }
+        MeetingAction::LeaveCall | MeetingAction::StopSharing => Some(Control::Call),
+        MeetingAction::None
+        | MeetingAction::QueryMeetingState
+        | MeetingAction::React
+        | MeetingAction::ToggleUI => None,
+    }
+}
+
+struct Claim {
+    priority: i32,
+    origin: String,
+    action: MeetingAction,
+    at: Instant,
+}
+
+/// Resolves conflicting commands from several integrations driving the
+/// same connection.
+///
+/// Sources are identified by the origin of a `ClientMessage`, matched by
+/// prefix, so `rules` covers `rules:<rule name>`. After a command, a
+/// different command for the same control (microphone, camera, background,
+/// hand or the call itself) from a lower-priority source is suppressed for
+/// the window. Unknown and missing origins have priority 0.
+///
+/// # Example
+/// ```rust
+/// let arbiter = Arbiter::new()
+///     .priority("hotkeys", 100)
+///     .priority("mqtt", 50)
+///     .priority("rules", 10);
+/// let websocket = TeamsWebsocket::builder(identifier).arbiter(arbiter).build()?;
+/// ```
+pub struct Arbiter {
+    priorities: Vec<(String, i32)>,
+    window: Duration,
+    claims: HashMap<Control, Claim>,
+}
+
+impl Arbiter {
+    pub fn new() -> Self {
+        Self {
+            priorities: Vec::new(),
+            window: DEFAULT_WINDOW,
+            claims: HashMap::new(),
+        }
+    }
+
+    /// Sets the priority of origins starting with `origin`, higher wins.
+    pub fn priority(mut self, origin: impl Into<String>, priority: i32) -> Self {
+        self.priorities.push((origin.into(), priority));
+        // Longest prefix first, so `rules:night` can override `rules`.
+        self.priorities
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Sets how long a command blocks conflicting lower-priority commands.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Returns the priority of `origin`.
+    pub fn priority_of(&self, origin: Option<&str>) -> i32 {
+        let origin = origin.unwrap_or_default();
+        self.priorities
+            .iter()
+            .find(|(prefix, _)| origin.starts_with(prefix.as_str()))
+            .map(|(_, priority)| *priority)
+            .unwrap_or(0)
+    }
+
+    /// Admits `message` or fails with `TeamsWsError::Suppressed` if it
+    /// conflicts with a recent command of a higher-priority source. Only
+    /// `claim` makes it block other commands, once it was sent.
+    pub fn check(&self, message: &ClientMessage) -> Result<(), TeamsWsError> {
+        let Some(claim) = self.blocking_claim(message) else {
+            return Ok(());
+        };
+        if claim.action == message.action {
+            // Agrees with the higher-priority command.
+            return Ok(());
+        }
+        let e = TeamsWsError::Suppressed {
+            action: message.action,
+            by: claim.origin.clone(),
+        };
+        info!(
+            target: logging::POLICY,
+            "{} from {}",
+            e,
+            message.origin.as_deref().unwrap_or("unknown")
+        );
+        Err(e)
+    }
+
+    /// Records that `message`, admitted by `check`, was sent, so it blocks
+    /// conflicting lower-priority commands for the window.
+    pub fn claim(&mut self, message: &ClientMessage) {
+        let Some(control) = control(message.action) else {
+            return;
+        };
+        if self.blocking_claim(message).is_some() {
+            // The higher-priority command it agrees with keeps its claim.
+            return;
+        }
+        self.claims.insert(
+            control,
+            Claim {
+                priority: self.priority_of(message.origin.as_deref()),
+                origin: message
+                    .origin
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
+                action: message.action,
+                at: Instant::now(),
+            },
+        );
+    }
+
+    /// Returns the claim of a higher-priority source on the control of
+    /// `message` within the window, if any.
+    fn blocking_claim(&self, message: &ClientMessage) -> Option<&Claim> {
+        let claim = self.claims.get(&control(message.action)?)?;
+        let priority = self.priority_of(message.origin.as_deref());
+        (claim.at.elapsed() < self.window && claim.priority > priority).then_some(claim)
+    }
+}
+
+impl Default for Arbiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arbiter() {
+        let mut arbiter = Arbiter::new()
+            .priority("hotkeys", 100)
+            .priority("rules", 10)
+            .priority("rules:urgent", 200);
+        let message = |action, origin: &str| ClientMessage::new(action, None).with_origin(origin);
+        let send = |arbiter: &mut Arbiter, message: ClientMessage| {
+            arbiter.check(&message)?;
+            arbiter.claim(&message);
+            Ok::<_, TeamsWsError>(())
+        };
+
+        send(&mut arbiter, message(MeetingAction::Unmute, "hotkeys")).unwrap();
+        let e = send(&mut arbiter, message(MeetingAction::Mute, "rules:join")).unwrap_err();
+        assert!(matches!(e, TeamsWsError::Suppressed { by, .. } if by == "hotkeys"));
+        // Same action and other controls do not conflict.
+        send(&mut arbiter, message(MeetingAction::Unmute, "rules:join")).unwrap();
+        send(
+            &mut arbiter,
+            message(MeetingAction::BlurBackground, "rules:join"),
+        )
+        .unwrap();
+        send(&mut arbiter, message(MeetingAction::Mute, "rules:urgent")).unwrap();
+
+        // Commands that are checked but not sent claim nothing.
+        let arbiter = Arbiter::new().priority("hotkeys", 100);
+        arbiter
+            .check(&message(MeetingAction::Unmute, "hotkeys"))
+            .unwrap();
+        arbiter
+            .check(&message(MeetingAction::Mute, "rules"))
+            .unwrap();
+
+        let mut arbiter = Arbiter::new()
+            .priority("hotkeys", 100)
+            .window(Duration::ZERO);
+        send(&mut arbiter, message(MeetingAction::Unmute, "hotkeys")).unwrap();
+        send(&mut arbiter, message(MeetingAction::Mute, "rules")).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use crate::logging;
+use crate::transport::{Connector, Transport, TransportError};
+use async_std::sync::Mutex;
+use async_tungstenite::async_std::{connect_async, ConnectStream};
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+use futures_util::future::BoxFuture;
+use futures_util::StreamExt;
+
+/// Opens the connections to Teams with async-tungstenite on the async-std
+/// runtime instead of tokio-tungstenite, so the socket I/O of a client
+/// runs on the reactor of an async-std or smol application.
+///
+/// Supports `ws://` URLs, like the local Teams API. As with every
+/// `Connector`, the extra handshake headers are not sent. Needs the
+/// `async-std` feature.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier)
+///     .connector(AsyncStdConnector)
+///     .build()?;
+/// let client = TeamsClient::run_on_thread(websocket)?;
+/// async_std::task::block_on(client.handle().send(ClientMessage::new(MeetingAction::ToggleMute, None)))?;
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdConnector;
+
+impl Connector for AsyncStdConnector {
+    fn connect<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> BoxFuture<'a, Result<Box<dyn Transport>, TransportError>> {
+        Box::pin(async move {
+            let (stream, _) = connect_async(url).await?;
+            debug!(target: logging::CONNECTION, "Connected through async-tungstenite");
+            Ok(Box::new(AsyncStdTransport(Mutex::new(stream))) as Box<dyn Transport>)
+        })
+    }
+}
+
+/// A connection opened by `AsyncStdConnector`.
+///
+/// The stream is behind a mutex since a `Transport` has to be `Sync`, it
+/// is never contended.
+struct AsyncStdTransport(Mutex<WebSocketStream<ConnectStream>>);
+
+impl Transport for AsyncStdTransport {
+    fn send_text(&mut self, text: String) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async move { Ok(self.0.get_mut().send(Message::text(text)).await?) })
+    }
+
+    fn recv_text(&mut self) -> BoxFuture<'_, Option<Result<String, TransportError>>> {
+        Box::pin(async move {
+            loop {
+                // Pings are answered by async-tungstenite while reading.
+                match self.0.get_mut().next().await? {
+                    Ok(Message::Text(text)) => return Some(Ok(text.to_string())),
+                    Ok(Message::Close(_)) => return None,
+                    Ok(_) => {}
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+        })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async move { Ok(self.0.get_mut().close(None).await?) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TeamsClient;
+    use crate::messages::{ClientMessage, MeetingAction, MeetingState, MeetingUpdate};
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_async_std_connector() {
+        // The mock server runs on tokio, the client is driven by async-std.
+        let runtime = Runtime::new().unwrap();
+        let server = runtime
+            .block_on(
+                MockTeamsServer::builder()
+                    .meeting_updates([MeetingUpdate {
+                        meeting_permissions: None,
+                        meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+                    }])
+                    .start(),
+            )
+            .unwrap();
+        let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+            .ignore_environment()
+            .url(server.url())
+            .connector(AsyncStdConnector)
+            .build()
+            .unwrap();
+        let client = TeamsClient::run_on_thread(websocket).unwrap();
+        async_std::task::block_on(async {
+            let handle = client.handle();
+            handle
+                .wait_until_in_meeting(Duration::from_secs(5))
+                .await
+                .unwrap();
+            handle
+                .send(ClientMessage::new(MeetingAction::ToggleMute, None))
+                .await
+                .unwrap();
+            while server.received().is_empty() {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            client.shutdown().await.unwrap();
+        });
+        server.assert_actions(&[MeetingAction::ToggleMute]);
+    }
+}
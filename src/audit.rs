@@ -0,0 +1,237 @@
+use crate::logging;
+use crate::messages::ClientMessage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The hash preceding the first entry of a log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log.
+///
+/// # Fields
+///
+/// * `sequence` - The position of the entry in the log, starting at 0.
+/// * `timestamp_ms` - Milliseconds since the unix epoch when the action was sent.
+/// * `origin` - The integration that issued the action, taken from `ClientMessage::origin`.
+/// * `request_id` - The request id assigned to the message.
+/// * `message` - The text of the frame sent to Teams.
+/// * `previous_hash` - The hash of the previous entry.
+/// * `hash` - SHA-256 over `previous_hash` and the other fields of this entry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp_ms: u128,
+    pub origin: String,
+    pub request_id: Option<u32>,
+    pub message: String,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let content = format!(
+            "{}\n{}\n{}\n{}\n{:?}\n{}",
+            self.previous_hash,
+            self.sequence,
+            self.timestamp_ms,
+            self.origin,
+            self.request_id,
+            self.message
+        );
+        Sha256::digest(content.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// An append-only, hash-chained log of every action sent.
+///
+/// Each entry includes the hash of its predecessor, so altering, reordering
+/// or removing an entry other than the last ones breaks the chain, which
+/// `AuditLog::verify` detects. Entries cut from the end leave a valid
+/// chain; keep the `head` hash elsewhere, e.g. in a monitoring system, and
+/// check it with `AuditLog::verify_head` to detect those too.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    sequence: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, continuing the chain of an existing log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or an existing log fails verification.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let (sequence, last_hash) = match Self::verify(path) {
+            Ok(Some(last)) => (last.sequence + 1, last.hash),
+            Ok(None) => (0, GENESIS_HASH.to_string()),
+            Err(e) => {
+                warn!(
+                    target: logging::CONNECTION,
+                    "Error verifying audit log {}: {}",
+                    path.display(),
+                    e
+                );
+                return Err(e);
+            }
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            sequence,
+            last_hash,
+        })
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the hash of the last entry, which `verify_head` checks.
+    pub fn head(&self) -> &str {
+        &self.last_hash
+    }
+
+    /// Appends an entry for `message`, sent to Teams as the frame `text`,
+    /// and flushes it to disk.
+    pub fn record(
+        &mut self,
+        message: &ClientMessage,
+        text: &str,
+    ) -> Result<AuditEntry, Box<dyn Error>> {
+        let mut entry = AuditEntry {
+            sequence: self.sequence,
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            origin: message
+                .origin
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+            request_id: message.request_id,
+            message: text.to_string(),
+            previous_hash: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        writeln!(self.file, "{}", serde_json::to_string(&entry)?)?;
+        self.file.sync_data()?;
+        self.sequence += 1;
+        self.last_hash = entry.hash.clone();
+        Ok(entry)
+    }
+
+    /// Verifies the chain of the log at `path` and returns its last entry.
+    ///
+    /// A missing file is an empty log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first entry that was altered, removed or reordered.
+    /// Entries removed from the end are not detected, see `verify_head`.
+    pub fn verify(path: impl AsRef<Path>) -> Result<Option<AuditEntry>, Box<dyn Error>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Box::new(e)),
+        };
+        let mut last: Option<AuditEntry> = None;
+        for (line_number, line) in BufReader::new(file).lines().enumerate() {
+            let entry: AuditEntry = serde_json::from_str(&line?)?;
+            let (expected_sequence, expected_previous) = match &last {
+                Some(last) => (last.sequence + 1, last.hash.as_str()),
+                None => (0, GENESIS_HASH),
+            };
+            if entry.sequence != expected_sequence
+                || entry.previous_hash != expected_previous
+                || entry.hash != entry.compute_hash()
+            {
+                return Err(Box::from(format!(
+                    "audit log chain broken at line {}",
+                    line_number + 1
+                )));
+            }
+            last = Some(entry);
+        }
+        Ok(last)
+    }
+
+    /// Verifies the chain of the log at `path` like `verify` and that it
+    /// ends with the entry hashed `head`, as returned by `AuditLog::head`
+    /// when it was last written, so entries cut from the end are detected.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `verify`, or an error if the log does not end
+    /// with `head`.
+    pub fn verify_head(
+        path: impl AsRef<Path>,
+        head: &str,
+    ) -> Result<Option<AuditEntry>, Box<dyn Error>> {
+        let last = Self::verify(path)?;
+        let last_hash = last
+            .as_ref()
+            .map_or(GENESIS_HASH, |entry| entry.hash.as_str());
+        if last_hash != head {
+            return Err(Box::from("audit log does not end with the expected entry"));
+        }
+        Ok(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingAction;
+
+    #[test]
+    fn test_audit_log_chain() {
+        let path =
+            std::env::temp_dir().join(format!("teams-ws-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut log = AuditLog::open(&path).unwrap();
+        let message = ClientMessage::new(MeetingAction::Mute, None).with_origin("hotkeys");
+        let first = log.record(&message, r#"{"action":"mute"}"#).unwrap();
+        assert_eq!(first.origin, "hotkeys");
+        assert_eq!(first.message, r#"{"action":"mute"}"#);
+        drop(log);
+
+        let mut log = AuditLog::open(&path).unwrap();
+        let leave = ClientMessage::new(MeetingAction::LeaveCall, None);
+        let second = log.record(&leave, r#"{"action":"leave-call"}"#).unwrap();
+        assert_eq!(second.sequence, 1);
+        assert_eq!(second.previous_hash, first.hash);
+        assert_eq!(log.head(), second.hash);
+        assert_eq!(AuditLog::verify(&path).unwrap(), Some(second.clone()));
+        assert_eq!(
+            AuditLog::verify_head(&path, &second.hash).unwrap(),
+            Some(second.clone())
+        );
+
+        // Cutting the last entry leaves a valid chain but another head.
+        let content = std::fs::read_to_string(&path).unwrap();
+        let first_line = content.lines().next().unwrap();
+        std::fs::write(&path, format!("{}\n", first_line)).unwrap();
+        assert!(AuditLog::verify(&path).is_ok());
+        assert!(AuditLog::verify_head(&path, &second.hash).is_err());
+        std::fs::write(&path, &content).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replace("hotkeys", "rules")).unwrap();
+        assert!(AuditLog::verify(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
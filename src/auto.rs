@@ -0,0 +1,127 @@
+use crate::lifecycle::LifecycleTrigger;
+use crate::messages::{MeetingAction, Reaction};
+use crate::rules::{RuleAction, RuleSet, RulesEngine, Trigger};
+use crate::state::MeetingStateDelta;
+use serde::{Deserialize, Serialize};
+
+/// Rule name of `AutoActions::mute_on_join`.
+pub const MUTE_ON_JOIN: &str = "auto:mute_on_join";
+/// Rule name of `AutoActions::blur_on_video`.
+pub const BLUR_ON_VIDEO: &str = "auto:blur_on_video";
+/// Rule name of `AutoActions::react`.
+pub const REACT: &str = "auto:react";
+
+/// Reacting periodically.
+///
+/// # Fields
+///
+/// * `seconds` - The interval between reactions.
+/// * `reaction` - The reaction to send, e.g. `like`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutoReact {
+    pub seconds: u64,
+    pub reaction: Reaction,
+}
+
+/// Built-in automatic behaviors, each individually toggleable.
+///
+/// They run as rules named `auto:<field>` in a `RulesEngine`, so firing
+/// emits `Event::RuleFired` to the sender given to `RulesEngine::notify`.
+///
+/// # Fields
+///
+/// * `mute_on_join` - Mute when joining a meeting. On by default.
+/// * `blur_on_video` - Blur the background when the camera turns on. On by default.
+/// * `react` - React at an interval. Off by default.
+///
+/// # Example
+/// ```rust
+/// let auto: AutoActions = serde_json::from_str(r#"{"blur_on_video": false}"#)?;
+/// let (sender, mut fired) = tokio::sync::mpsc::unbounded_channel();
+/// let mut engine = auto.engine().notify(sender);
+/// engine.run(&mut websocket).await?;
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AutoActions {
+    pub mute_on_join: bool,
+    pub blur_on_video: bool,
+    pub react: Option<AutoReact>,
+}
+
+impl Default for AutoActions {
+    fn default() -> Self {
+        Self {
+            mute_on_join: true,
+            blur_on_video: true,
+            react: None,
+        }
+    }
+}
+
+impl AutoActions {
+    /// Returns the rules implementing the enabled behaviors.
+    pub fn to_rules(&self) -> RuleSet {
+        let mut rules = RuleSet::new();
+        if self.mute_on_join {
+            rules = rules.rule(
+                MUTE_ON_JOIN,
+                Trigger::Lifecycle(LifecycleTrigger::Joined),
+                vec![RuleAction::Send(MeetingAction::Mute)],
+            );
+        }
+        if self.blur_on_video {
+            rules = rules.rule(
+                BLUR_ON_VIDEO,
+                Trigger::State(MeetingStateDelta::VideoOn(true)),
+                vec![RuleAction::Send(MeetingAction::BlurBackground)],
+            );
+        }
+        if let Some(react) = &self.react {
+            rules = rules.rule(
+                REACT,
+                Trigger::Every {
+                    seconds: react.seconds,
+                },
+                vec![RuleAction::SendWith {
+                    action: MeetingAction::React,
+                    parameter: react.reaction.into(),
+                }],
+            );
+        }
+        rules
+    }
+
+    /// Returns a `RulesEngine` running the enabled behaviors.
+    pub fn engine(&self) -> RulesEngine {
+        RulesEngine::new(self.to_rules())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+
+    #[test]
+    fn test_auto_actions() {
+        let auto: AutoActions = serde_json::from_str(r#"{"blur_on_video": false}"#).unwrap();
+        assert!(auto.mute_on_join);
+        assert!(auto.react.is_none());
+        let rules = auto.to_rules();
+        assert_eq!(rules.rules.len(), 1);
+        assert_eq!(rules.rules[0].name, MUTE_ON_JOIN);
+
+        let auto = AutoActions {
+            react: Some(AutoReact {
+                seconds: 300,
+                reaction: Reaction::Applause,
+            }),
+            ..AutoActions::default()
+        };
+        let engine = auto.engine();
+        assert_eq!(engine.rules().rules.len(), 3);
+        let triggered = engine.handle(&Event::StateChanged(MeetingStateDelta::VideoOn(true)));
+        assert_eq!(triggered[0].0.name, BLUR_ON_VIDEO);
+    }
+}
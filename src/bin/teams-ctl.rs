@@ -0,0 +1,220 @@
+use futures_util::StreamExt;
+use ms_teams_ws::client::TeamsClient;
+use ms_teams_ws::config::Config;
+use ms_teams_ws::discovery;
+use ms_teams_ws::exit::{report_error, CommandLine, ExitStatus};
+use ms_teams_ws::messages::{ClientMessage, MeetingAction, Reaction, ServerMessage, UiPanel};
+use ms_teams_ws::token::FileTokenStore;
+use ms_teams_ws::types::AppIdentifiers;
+use ms_teams_ws::TeamsWebsocket;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::error::Error;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: teams-ctl <command> [options]
+
+Commands:
+  state               Print the meeting state
+  watch               Print the meeting state and every change of it
+  react <reaction>    Send a reaction: applause, laugh, like, love, wow
+  toggle-ui <panel>   Toggle a panel: chat, sharing-tray
+  <action>            Send an action, e.g. toggle-mute, mute, unmute,
+                      toggle-video, raise-hand, lower-hand, leave-call
+
+Options:
+  --url <url>          Teams websocket URL (default ws://127.0.0.1:8124)
+  --discover           Look up the URL of the Teams local API
+  --token <token>      Token from a previous pairing
+  --token-file <file>  Store the token in this file instead of the user config
+  --config <file>      JSON config file with url and token
+  --timeout <seconds>  How long to wait for connections and answers (default 3)
+  --json               Print the result as JSON
+  --compact            Print the whole meeting state as one line of JSON, with
+                       watch on every change, e.g. for waybar or polybar
+
+Settings are also read from TEAMS_WS_URL and TEAMS_WS_TOKEN and from the
+user config, ms-teams-ws/config.json in the user config directory, which
+also keeps the token between runs.
+
+The first action asks Teams to pair, accept it in Teams within the timeout.
+
+Exit codes:
+  0  ok
+  1  failed
+  2  usage: invalid command line
+  3  not_connected: Teams is not running or refused the connection
+  4  not_in_meeting: the command needs a meeting
+  5  not_permitted: the token or Teams refused
+  6  timeout: Teams did not answer in time";
+
+const IDENTIFIER: AppIdentifiers = AppIdentifiers {
+    protocol_version: Cow::Borrowed("2.0.0"),
+    manufacturer: Cow::Borrowed("ms-teams-ws"),
+    device: Cow::Borrowed("cli"),
+    app: Cow::Borrowed("teams-ctl"),
+    app_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+};
+
+/// Connects to Teams and runs a client for the connection.
+async fn client(args: &CommandLine) -> Result<TeamsClient, Box<dyn Error>> {
+    let identifier = Config::load_or_default().identifiers.unwrap_or(IDENTIFIER);
+    let mut builder = TeamsWebsocket::builder(identifier)
+        .user_config()
+        .connect_timeout(args.timeout);
+    if let Some(config) = &args.config {
+        builder = builder.config_file(config);
+    }
+    let url = match &args.url {
+        Some(url) => Some(url.clone()),
+        None if args.flag("--discover") => Some(discovery::discover().await?),
+        None => None,
+    };
+    if let Some(url) = url {
+        builder = builder.url(url);
+    }
+    if let Some(token) = &args.token {
+        builder = builder.token(token);
+    }
+    if let Some(token_file) = args.value("--token-file") {
+        builder = builder.token_store(FileTokenStore::new(token_file));
+    }
+    let mut websocket = builder.build()?;
+    websocket.connect().await?;
+    Ok(TeamsClient::run(websocket))
+}
+
+/// Returns the message for the `command` line, or `None` if unknown.
+fn message(command: &str, argument: Option<&str>) -> Result<Option<ClientMessage>, Box<dyn Error>> {
+    let argument = || argument.ok_or(format!("{} needs an argument", command));
+    Ok(match command {
+        "react" => Some(ClientMessage::reaction(parse::<Reaction>(
+            "reaction",
+            argument()?,
+        )?)),
+        "toggle-ui" => Some(ClientMessage::toggle_ui(parse::<UiPanel>(
+            "panel",
+            argument()?,
+        )?)),
+        action => parse::<MeetingAction>("action", action)
+            .ok()
+            .filter(|action| {
+                !matches!(
+                    action,
+                    MeetingAction::None | MeetingAction::React | MeetingAction::ToggleUI
+                )
+            })
+            .map(|action| ClientMessage::new(action, None)),
+    })
+}
+
+fn parse<T: DeserializeOwned>(kind: &str, name: &str) -> Result<T, Box<dyn Error>> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| Box::from(format!("unknown {} {}", kind, name)))
+}
+
+async fn action(args: CommandLine, message: ClientMessage) -> Result<ExitStatus, Box<dyn Error>> {
+    let client = client(&args).await?;
+    let answer: ServerMessage =
+        tokio::time::timeout(args.timeout, client.handle().send_and_wait(message)).await??;
+    client.shutdown().await?;
+    let status = match answer.error_kind() {
+        None => ExitStatus::Ok,
+        Some(kind) => ExitStatus::from_error(&kind),
+    };
+    if args.json {
+        println!("{}", serde_json::to_string(&answer)?);
+    } else if let Some(error) = &answer.error_msg {
+        eprintln!("{}", error);
+    } else {
+        println!("ok");
+    }
+    Ok(status)
+}
+
+async fn state(args: CommandLine) -> Result<ExitStatus, Box<dyn Error>> {
+    let client = client(&args).await?;
+    let handle = client.handle();
+    let state = handle.wait_for(|_| true, args.timeout).await?;
+    if args.flag("--compact") {
+        println!("{}", state.to_json());
+    } else if args.json {
+        let state = serde_json::json!({
+            "meetingState": state,
+            "meetingPermissions": handle.permissions(),
+        });
+        println!("{}", state);
+    } else if let serde_json::Value::Object(fields) = serde_json::to_value(&state)? {
+        for (field, value) in fields {
+            println!("{}: {}", field, value);
+        }
+    }
+    client.shutdown().await?;
+    Ok(ExitStatus::Ok)
+}
+
+async fn watch(args: CommandLine) -> Result<ExitStatus, Box<dyn Error>> {
+    let client = client(&args).await?;
+    let mut changes = client.subscribe_state_changes();
+    let handle = client.handle();
+    let state = handle.wait_for(|_| true, args.timeout).await?;
+    if args.flag("--compact") {
+        println!("{}", state.to_json());
+    } else if args.json {
+        println!("{}", serde_json::json!({ "meetingState": state }));
+    } else {
+        println!("{}", state);
+    }
+    while let Some(delta) = changes.next().await {
+        if args.flag("--compact") {
+            if let Some(state) = handle.meeting_state() {
+                println!("{}", state.to_json());
+            }
+        } else if args.json {
+            println!("{}", serde_json::to_string(&delta)?);
+        } else if let serde_json::Value::Object(fields) = serde_json::to_value(delta)? {
+            for (field, value) in fields {
+                println!("{}: {}", field, value);
+            }
+        }
+    }
+    client.join().await?;
+    Ok(ExitStatus::Ok)
+}
+
+fn main() -> ExitCode {
+    let json = std::env::args().any(|arg| arg == "--json");
+    let args = match CommandLine::parse(
+        std::env::args().skip(1),
+        &["--discover", "--compact"],
+        &["--token-file"],
+        true,
+    ) {
+        Ok(args) => args,
+        Err(e) => return report_error(json, ExitStatus::Usage, &e, USAGE),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create tokio runtime");
+    let result = match args.command.as_str() {
+        "state" => runtime.block_on(state(args)),
+        "watch" => runtime.block_on(watch(args)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        command => match message(command, args.argument.as_deref()) {
+            Ok(Some(message)) => runtime.block_on(action(args, message)),
+            Ok(None) => {
+                let e = format!("unknown command {}", command);
+                return report_error(json, ExitStatus::Usage, &e, USAGE);
+            }
+            Err(e) => return report_error(json, ExitStatus::Usage, &e, USAGE),
+        },
+    };
+    match result {
+        Ok(status) => status.into(),
+        Err(e) => report_error(json, ExitStatus::from_error(e.as_ref()), &e, USAGE),
+    }
+}
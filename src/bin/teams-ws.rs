@@ -0,0 +1,105 @@
+use ms_teams_ws::discovery;
+use ms_teams_ws::doctor::Doctor;
+use ms_teams_ws::exit::{report_error, CommandLine, ExitStatus};
+use ms_teams_ws::types::AppIdentifiers;
+use ms_teams_ws::TeamsWebsocket;
+use std::borrow::Cow;
+use std::error::Error;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: teams-ws <command> [options]
+
+Commands:
+  doctor    Diagnose why the connection to Teams does not work
+  discover  Print the URL of the Teams local API on this machine
+
+Options:
+  --url <url>          Teams websocket URL (default ws://127.0.0.1:8124)
+  --token <token>      Token from a previous pairing
+  --config <file>      JSON config file with url and token
+  --timeout <seconds>  How long to wait for connections and answers (default 3)
+  --pair               Wait for Teams to pair if there is no valid token
+  --json               Print the result as JSON
+
+Settings are also read from TEAMS_WS_URL and TEAMS_WS_TOKEN.
+
+Exit codes:
+  0  ok
+  1  failed
+  2  usage: invalid command line
+  3  not_connected: Teams is not running or refused the connection
+  4  not_in_meeting: the command needs a meeting
+  5  not_permitted: the token, confirmation, sandbox or arbiter refused
+  6  timeout: Teams did not answer in time";
+
+const IDENTIFIER: AppIdentifiers = AppIdentifiers {
+    protocol_version: Cow::Borrowed("2.0.0"),
+    manufacturer: Cow::Borrowed("ms-teams-ws"),
+    device: Cow::Borrowed("cli"),
+    app: Cow::Borrowed("teams-ws"),
+    app_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+};
+
+async fn doctor(args: CommandLine) -> Result<ExitStatus, Box<dyn Error>> {
+    let pair = args.flag("--pair");
+    let mut builder = TeamsWebsocket::builder(IDENTIFIER);
+    if let Some(config) = args.config {
+        builder = builder.config_file(config);
+    }
+    if let Some(url) = args.url {
+        builder = builder.url(url);
+    }
+    if let Some(token) = args.token {
+        builder = builder.token(token);
+    }
+    let diagnosis = Doctor::new(builder.build()?)
+        .timeout(args.timeout)
+        .pair(pair)
+        .run()
+        .await;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diagnosis)?);
+    } else {
+        print!("{}", diagnosis);
+    }
+    Ok(diagnosis.exit_status)
+}
+
+async fn discover(args: CommandLine) -> Result<ExitStatus, Box<dyn Error>> {
+    let url =
+        discovery::discover_on("127.0.0.1", &discovery::CANDIDATE_PORTS, args.timeout).await?;
+    if args.json {
+        println!("{}", serde_json::json!({ "url": url }));
+    } else {
+        println!("{}", url);
+    }
+    Ok(ExitStatus::Ok)
+}
+
+fn main() -> ExitCode {
+    let json = std::env::args().any(|arg| arg == "--json");
+    let args = match CommandLine::parse(std::env::args().skip(1), &["--pair"], &[], false) {
+        Ok(args) => args,
+        Err(e) => return report_error(json, ExitStatus::Usage, &e, USAGE),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create tokio runtime");
+    let result = match args.command.as_str() {
+        "doctor" => runtime.block_on(doctor(args)),
+        "discover" => runtime.block_on(discover(args)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        command => {
+            let e = format!("unknown command {}", command);
+            return report_error(json, ExitStatus::Usage, &e, USAGE);
+        }
+    };
+    match result {
+        Ok(status) => status.into(),
+        Err(e) => report_error(json, ExitStatus::from_error(e.as_ref()), &e, USAGE),
+    }
+}
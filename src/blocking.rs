@@ -0,0 +1,178 @@
+//! A blocking `TeamsWebsocket` for programs that are not async, e.g. CLI
+//! tools and GUI apps.
+
+use crate::messages::{ClientMessage, MeetingPermissions, MeetingState, ServerMessage};
+use crate::state::StateSnapshot;
+use std::error::Error;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Wraps a `crate::TeamsWebsocket` and runs its calls to completion on an
+/// internal single-threaded runtime.
+///
+/// The calls must not be made from within an async runtime, they panic
+/// there; use the async `TeamsWebsocket` instead. Nothing runs between
+/// calls, so keepalives, state refreshes and reconnects only happen while
+/// receiving.
+///
+/// # Example
+/// ```rust
+/// let mut websocket = TeamsWebsocket::builder(identifier).token(token).build_blocking()?;
+/// websocket.connect()?;
+/// websocket.send(ClientMessage::new(MeetingAction::ToggleMute, None))?;
+/// let answer = websocket.receive()?;
+/// ```
+pub struct TeamsWebsocket {
+    inner: crate::TeamsWebsocket,
+    runtime: Runtime,
+}
+
+impl TeamsWebsocket {
+    /// Wraps `websocket`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime cannot be created.
+    pub fn new(websocket: crate::TeamsWebsocket) -> Result<Self, Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner: websocket,
+            runtime,
+        })
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::connect`.
+    pub fn connect(&mut self) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.inner.connect())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::ready`.
+    pub fn ready(&mut self) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.inner.ready())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::pair`.
+    pub fn pair(&mut self) -> Result<String, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.pair())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::send`.
+    pub fn send(&mut self, message: ClientMessage) -> Result<u32, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.send(message))
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::send_and_wait`, giving up
+    /// after `timeout`.
+    pub fn send_and_wait(
+        &mut self,
+        message: ClientMessage,
+        timeout: Duration,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        let inner = &mut self.inner;
+        self.runtime.block_on(async {
+            match tokio::time::timeout(timeout, inner.send_and_wait(message)).await {
+                Ok(answer) => answer,
+                Err(_) => Err(Box::from(format!("no answer within {:?}", timeout))),
+            }
+        })
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::receive`.
+    pub fn receive(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.receive())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::receive_timeout`.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<ServerMessage, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.receive_timeout(timeout))
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::try_receive`.
+    pub fn try_receive(&mut self) -> Result<Option<ServerMessage>, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.try_receive())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::ping`.
+    pub fn ping(&mut self) -> Result<Duration, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.ping())
+    }
+
+    /// Blocking version of `crate::TeamsWebsocket::close`.
+    pub fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.inner.close())
+    }
+
+    pub fn is_in_meeting(&self) -> Option<bool> {
+        self.inner.is_in_meeting()
+    }
+
+    pub fn meeting_state(&self) -> Option<&MeetingState> {
+        self.inner.meeting_state()
+    }
+
+    pub fn state_snapshot(&self) -> Option<StateSnapshot> {
+        self.inner.state_snapshot()
+    }
+
+    pub fn permissions(&self) -> Option<&MeetingPermissions> {
+        self.inner.permissions()
+    }
+
+    /// Returns the async websocket, e.g. for its other getters.
+    pub fn get_ref(&self) -> &crate::TeamsWebsocket {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut crate::TeamsWebsocket {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> crate::TeamsWebsocket {
+        self.inner
+    }
+}
+
+impl std::fmt::Debug for TeamsWebsocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TeamsWebsocket").field(&self.inner).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingAction;
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+
+    #[test]
+    fn test_blocking_websocket() {
+        let server_runtime = Runtime::new().unwrap();
+        let server = server_runtime.block_on(MockTeamsServer::start()).unwrap();
+        let mut websocket = crate::TeamsWebsocket::builder(AppIdentifiers::default())
+            .ignore_environment()
+            .url(server.url())
+            .build_blocking()
+            .unwrap();
+        assert!(websocket
+            .send(ClientMessage::new(MeetingAction::Mute, None))
+            .is_err());
+        websocket.connect().unwrap();
+        websocket
+            .send(ClientMessage::new(MeetingAction::Mute, None))
+            .unwrap();
+        assert_eq!(websocket.receive().unwrap().request_id, Some(0));
+        let answer = websocket
+            .send_and_wait(
+                ClientMessage::new(MeetingAction::RaiseHand, None),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(answer.response.as_deref(), Some("Success"));
+        assert!(websocket.try_receive().unwrap().is_none());
+        websocket.close().unwrap();
+        server.assert_actions(&[MeetingAction::Mute, MeetingAction::RaiseHand]);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::client::ClientHandle;
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction, MeetingState};
+use crate::state::MeetingStateDelta;
+use futures_util::StreamExt;
+use std::error::Error;
+use zbus::object_server::{InterfaceRef, SignalEmitter};
+use zbus::{connection, Connection, MessageStream};
+
+/// The interface of the exported object.
+pub const INTERFACE: &str = "org.teams.MeetingControl";
+
+/// The path of the exported object.
+pub const OBJECT_PATH: &str = "/org/teams/MeetingControl";
+
+/// The well-known name requested on the bus by default.
+pub const DEFAULT_BUS_NAME: &str = "org.teams.MeetingControl";
+
+/// Exports the `org.teams.MeetingControl` interface on the D-Bus session
+/// bus, backed by a `TeamsClient`, so desktop widgets and keybinding
+/// daemons can control Teams natively:
+///
+/// ```text
+/// busctl --user call org.teams.MeetingControl /org/teams/MeetingControl \
+///     org.teams.MeetingControl ToggleMute
+/// ```
+///
+/// The object at `OBJECT_PATH` has the methods `Mute`, `Unmute`,
+/// `ToggleMute`, `ShowVideo`, `HideVideo`, `ToggleVideo`, `RaiseHand`,
+/// `LowerHand`, `ToggleHand`, `ToggleBackgroundBlur`, `StopSharing` and
+/// `LeaveCall`, failing with `org.teams.MeetingControl.Error` if the action
+/// cannot be sent. The read-only boolean properties `IsMuted`,
+/// `IsInMeeting`, `IsVideoOn`, `IsHandRaised`, `IsBackgroundBlurred`,
+/// `IsSharing`, `IsRecordingOn`, `HasUnreadMessages`, `IsOnHold` and
+/// `IsRecordingPaused` follow the meeting state and emit
+/// `PropertiesChanged` when it changes.
+///
+/// The bus is spoken to through zbus. Needs the `bridge-dbus` feature and
+/// Linux.
+///
+/// # Example
+/// ```rust
+/// let client = TeamsClient::run(websocket);
+/// tokio::spawn(DbusBridge::new(client.handle()).run());
+/// ```
+#[derive(Debug, Clone)]
+pub struct DbusBridge {
+    handle: ClientHandle,
+    address: Option<String>,
+    bus_name: String,
+}
+
+impl DbusBridge {
+    pub fn new(handle: ClientHandle) -> Self {
+        Self {
+            handle,
+            address: None,
+            bus_name: DEFAULT_BUS_NAME.to_string(),
+        }
+    }
+
+    /// Connects to the bus at `address`, e.g. `unix:path=/run/dbus/bus` or
+    /// `unix:abstract=/tmp/dbus-1234`, instead of the session bus.
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Requests `bus_name` instead of `org.teams.MeetingControl`.
+    pub fn bus_name(mut self, bus_name: impl Into<String>) -> Self {
+        self.bus_name = bus_name.into();
+        self
+    }
+
+    /// Connects to the bus and answers calls until the client stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bus cannot be reached, authentication fails,
+    /// the bus name is taken or the bus closes the connection.
+    pub async fn run(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let builder = match &self.address {
+            Some(address) => connection::Builder::address(address.as_str())?,
+            None => connection::Builder::session()?,
+        };
+        let connection = builder
+            .serve_at(OBJECT_PATH, MeetingControl::new(self.handle.clone()))?
+            .name(self.bus_name.as_str())?
+            .replace_existing_names(false)
+            .build()
+            .await?;
+        info!(target: logging::BRIDGE, "Exported {} as {}", INTERFACE, self.bus_name);
+        self.follow(&connection).await
+    }
+
+    /// Emits `PropertiesChanged` for the changes of the meeting state until
+    /// the client stops.
+    async fn follow(&self, connection: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let interface: InterfaceRef<MeetingControl> =
+            connection.object_server().interface(OBJECT_PATH).await?;
+        let mut changes = self.handle.subscribe_state_changes();
+        // Ends when the bus closes the connection.
+        let mut messages = MessageStream::from(connection);
+        loop {
+            tokio::select! {
+                change = changes.next() => match change {
+                    Some(delta) => properties_changed(&interface, delta).await?,
+                    None => break,
+                },
+                message = messages.next() => match message {
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err("bus closed the connection".into()),
+                },
+            }
+        }
+        info!(target: logging::BRIDGE, "Client stopped, leaving the bus");
+        Ok(())
+    }
+}
+
+/// The error of a method whose action could not be sent.
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.teams.MeetingControl")]
+enum MeetingControlError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Error(String),
+}
+
+/// The object exported at `OBJECT_PATH`.
+struct MeetingControl {
+    handle: ClientHandle,
+}
+
+impl MeetingControl {
+    fn new(handle: ClientHandle) -> Self {
+        Self { handle }
+    }
+
+    async fn send(&self, action: MeetingAction) -> Result<(), MeetingControlError> {
+        debug!(target: logging::BRIDGE, "D-Bus call {:?}", action);
+        self.handle
+            .send(ClientMessage::new(action, None))
+            .await
+            .map_err(|e| MeetingControlError::Error(e.to_string()))
+    }
+
+    fn state(&self) -> MeetingState {
+        self.handle.meeting_state().unwrap_or_default()
+    }
+}
+
+#[zbus::interface(name = "org.teams.MeetingControl")]
+impl MeetingControl {
+    async fn mute(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::Mute).await
+    }
+
+    async fn unmute(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::Unmute).await
+    }
+
+    async fn toggle_mute(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::ToggleMute).await
+    }
+
+    async fn show_video(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::ShowVideo).await
+    }
+
+    async fn hide_video(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::HideVideo).await
+    }
+
+    async fn toggle_video(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::ToggleVideo).await
+    }
+
+    async fn raise_hand(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::RaiseHand).await
+    }
+
+    async fn lower_hand(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::LowerHand).await
+    }
+
+    async fn toggle_hand(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::ToggleHand).await
+    }
+
+    async fn toggle_background_blur(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::ToggleBlurBackground).await
+    }
+
+    async fn stop_sharing(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::StopSharing).await
+    }
+
+    async fn leave_call(&self) -> Result<(), MeetingControlError> {
+        self.send(MeetingAction::LeaveCall).await
+    }
+
+    #[zbus(property)]
+    fn is_muted(&self) -> bool {
+        self.state().is_muted
+    }
+
+    #[zbus(property)]
+    fn is_in_meeting(&self) -> bool {
+        self.state().is_in_meeting
+    }
+
+    #[zbus(property)]
+    fn is_video_on(&self) -> bool {
+        self.state().is_video_on
+    }
+
+    #[zbus(property)]
+    fn is_hand_raised(&self) -> bool {
+        self.state().is_hand_raised
+    }
+
+    #[zbus(property)]
+    fn is_background_blurred(&self) -> bool {
+        self.state().is_background_blurred
+    }
+
+    #[zbus(property)]
+    fn is_sharing(&self) -> bool {
+        self.state().is_sharing
+    }
+
+    #[zbus(property)]
+    fn is_recording_on(&self) -> bool {
+        self.state().is_recording_on
+    }
+
+    #[zbus(property)]
+    fn has_unread_messages(&self) -> bool {
+        self.state().has_unread_messages
+    }
+
+    #[zbus(property)]
+    fn is_on_hold(&self) -> bool {
+        self.state().is_on_hold
+    }
+
+    #[zbus(property)]
+    fn is_recording_paused(&self) -> bool {
+        self.state().is_recording_paused
+    }
+}
+
+/// Emits `PropertiesChanged` for the property changed by `delta`.
+async fn properties_changed(
+    interface: &InterfaceRef<MeetingControl>,
+    delta: MeetingStateDelta,
+) -> zbus::Result<()> {
+    let emitter: &SignalEmitter<'_> = interface.signal_emitter();
+    let control = interface.get().await;
+    match delta {
+        MeetingStateDelta::Muted(_) => control.is_muted_changed(emitter).await,
+        MeetingStateDelta::InMeeting(_) => control.is_in_meeting_changed(emitter).await,
+        MeetingStateDelta::VideoOn(_) => control.is_video_on_changed(emitter).await,
+        MeetingStateDelta::HandRaised(_) => control.is_hand_raised_changed(emitter).await,
+        MeetingStateDelta::BackgroundBlurred(_) => {
+            control.is_background_blurred_changed(emitter).await
+        }
+        MeetingStateDelta::Sharing(_) => control.is_sharing_changed(emitter).await,
+        MeetingStateDelta::RecordingOn(_) => control.is_recording_on_changed(emitter).await,
+        MeetingStateDelta::UnreadMessages(_) => control.has_unread_messages_changed(emitter).await,
+        MeetingStateDelta::OnHold(_) => control.is_on_hold_changed(emitter).await,
+        MeetingStateDelta::RecordingPaused(_) => control.is_recording_paused_changed(emitter).await,
+        // Not a property, `HasUnreadMessages` changes alongside.
+        MeetingStateDelta::UnreadMessageCount(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TeamsClient;
+    use crate::messages::MeetingUpdate;
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use std::time::Duration;
+    use tokio::net::UnixStream;
+    use tokio::runtime::Runtime;
+    use zbus::fdo::PropertiesProxy;
+    use zbus::names::InterfaceName;
+    use zbus::Proxy;
+
+    #[test]
+    fn test_dbus_bridge() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::builder()
+                .meeting_updates([MeetingUpdate {
+                    meeting_permissions: None,
+                    meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+                }])
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            client
+                .handle()
+                .wait_until_in_meeting(Duration::from_secs(5))
+                .await
+                .unwrap();
+            // A peer-to-peer connection stands in for the bus.
+            let (bridge_end, caller_end) = UnixStream::pair().unwrap();
+            let guid = zbus::Guid::generate();
+            let bridge = DbusBridge::new(client.handle());
+            let exported = connection::Builder::unix_stream(bridge_end)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .serve_at(OBJECT_PATH, MeetingControl::new(client.handle()))
+                .unwrap()
+                .build();
+            let caller = connection::Builder::unix_stream(caller_end).p2p().build();
+            let (exported, caller) = tokio::join!(exported, caller);
+            let (exported, caller) = (exported.unwrap(), caller.unwrap());
+            let bridge = tokio::spawn(async move { bridge.follow(&exported).await });
+
+            let proxy = Proxy::new(&caller, DEFAULT_BUS_NAME, OBJECT_PATH, INTERFACE)
+                .await
+                .unwrap();
+            let in_meeting: bool = proxy.get_property("IsInMeeting").await.unwrap();
+            assert!(in_meeting);
+            let _: () = proxy.call("ToggleMute", &()).await.unwrap();
+            let error = proxy.call::<_, _, ()>("Explode", &()).await.unwrap_err();
+            assert!(
+                matches!(&error, zbus::Error::MethodError(name, ..) if name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod"),
+                "{:?}",
+                error
+            );
+            server.assert_actions(&[MeetingAction::ToggleMute]);
+
+            let properties = PropertiesProxy::builder(&caller)
+                .destination(DEFAULT_BUS_NAME)
+                .unwrap()
+                .path(OBJECT_PATH)
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let mut changed = properties.receive_properties_changed().await.unwrap();
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true).with_muted(true)),
+            });
+            let signal = changed.next().await.unwrap();
+            let args = signal.args().unwrap();
+            assert_eq!(
+                args.interface_name,
+                InterfaceName::from_static_str(INTERFACE).unwrap()
+            );
+            let muted = args.changed_properties.get("IsMuted").unwrap();
+            assert!(bool::try_from(muted).unwrap());
+
+            client.shutdown().await.unwrap();
+            bridge.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_address() {
+        assert!(connection::Builder::address("unix:abstract=/tmp/dbus-test").is_ok());
+        assert!(connection::Builder::address("unix:path=/run/dbus/bus").is_ok());
+    }
+}
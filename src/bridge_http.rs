@@ -0,0 +1,332 @@
+use crate::client::ClientHandle;
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction, Reaction, ServerMessage, UiPanel};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// How long the bridge waits for Teams to answer an action by default.
+pub const DEFAULT_ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client may take to send the request head before the
+/// connection is closed.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The header a request has to carry if the bridge has no token, e.g.
+/// `X-Teams-Ws: 1`. Browsers do not send custom headers to other sites
+/// without asking first, so web pages cannot control Teams through it.
+pub const BRIDGE_HEADER: &str = "x-teams-ws";
+
+/// An HTTP server proxying requests to a `TeamsClient`, so Stream Deck,
+/// Home Assistant or shell scripts can control Teams with plain HTTP.
+///
+/// | Request                      | Sends                                    |
+/// |------------------------------|------------------------------------------|
+/// | `GET /state`                 | nothing, returns the last meeting state and permissions |
+/// | `POST /react/<reaction>`     | `ClientMessage::reaction`, e.g. `/react/like` |
+/// | `POST /toggle-ui/<panel>`    | `ClientMessage::toggle_ui`, e.g. `/toggle-ui/chat` |
+/// | `POST /<action>`             | the `MeetingAction` with this wire name, e.g. `/mute` |
+///
+/// Actions answer with Teams' answer as JSON: `200` if Teams succeeded,
+/// `502` if it answered with an error, `504` if it did not answer in time
+/// and `503` if the action could not be sent. Unknown paths get a `404`.
+///
+/// Every request needs `Authorization: Bearer <token>` if the bridge has a
+/// `token`, or the `BRIDGE_HEADER` otherwise, and requests with an `Origin`
+/// header are refused, so web pages cannot send requests to it. There is
+/// no TLS, bind it to a loopback address. Needs the `bridge-http` feature.
+///
+/// # Example
+/// ```rust
+/// let client = TeamsClient::run(websocket);
+/// let listener = TcpListener::bind("127.0.0.1:8125").await?;
+/// tokio::spawn(HttpBridge::new(client.handle()).token("secret").serve(listener));
+/// // curl -X POST -H "Authorization: Bearer secret" http://127.0.0.1:8125/toggle-mute
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpBridge {
+    handle: ClientHandle,
+    answer_timeout: Duration,
+    token: Option<String>,
+}
+
+impl HttpBridge {
+    pub fn new(handle: ClientHandle) -> Self {
+        Self {
+            handle,
+            answer_timeout: DEFAULT_ANSWER_TIMEOUT,
+            token: None,
+        }
+    }
+
+    /// Waits at most `timeout` for Teams to answer an action.
+    pub fn answer_timeout(mut self, timeout: Duration) -> Self {
+        self.answer_timeout = timeout;
+        self
+    }
+
+    /// Requires `Authorization: Bearer <token>` instead of the
+    /// `BRIDGE_HEADER` on every request.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Returns the routes of the bridge, to nest them into an axum
+    /// application of its own.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/state", get(state))
+            .route("/react/{reaction}", post(react))
+            .route("/toggle-ui/{panel}", post(toggle_ui))
+            .route("/{action}", post(action))
+            .fallback(|| async { error(StatusCode::NOT_FOUND, "unknown path") })
+            .method_not_allowed_fallback(|| async {
+                error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed")
+            })
+            .layer(axum::middleware::from_fn_with_state(
+                self.clone(),
+                authorize,
+            ))
+            .with_state(self)
+    }
+
+    /// Answers the requests on `listener` until accepting fails.
+    ///
+    /// Connections are closed after one request, or if the request head
+    /// did not arrive within `REQUEST_TIMEOUT`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let router = self.router();
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let service = TowerToHyperService::new(router.clone());
+            tokio::spawn(async move {
+                let connection = hyper::server::conn::http1::Builder::new()
+                    .timer(TokioTimer::new())
+                    .header_read_timeout(REQUEST_TIMEOUT)
+                    .keep_alive(false)
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+                if let Err(e) = connection {
+                    debug!(target: logging::BRIDGE, "Error answering an HTTP request: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Sends `message` and answers with Teams' answer to it.
+    async fn forward(&self, message: ClientMessage) -> Response {
+        let answer = self.handle.send_and_wait(message);
+        let answer: Result<ServerMessage, String> =
+            match tokio::time::timeout(self.answer_timeout, answer).await {
+                Ok(answer) => answer.map_err(|e| e.to_string()),
+                Err(_) => return error(StatusCode::GATEWAY_TIMEOUT, "Teams did not answer"),
+            };
+        match answer {
+            Ok(answer) => {
+                let status = match answer.error_msg {
+                    Some(_) => StatusCode::BAD_GATEWAY,
+                    None => StatusCode::OK,
+                };
+                (status, Json(answer)).into_response()
+            }
+            Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, &e),
+        }
+    }
+}
+
+/// Refuses requests from web pages and ones without the token or the
+/// `BRIDGE_HEADER`.
+async fn authorize(State(bridge): State<HttpBridge>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    if headers.contains_key(header::ORIGIN) {
+        return error(
+            StatusCode::FORBIDDEN,
+            "cross-origin requests are not allowed",
+        );
+    }
+    if !authorized(bridge.token.as_deref(), headers) {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong credentials");
+    }
+    debug!(target: logging::BRIDGE, "Bridging {} {}", request.method(), request.uri().path());
+    next.run(request).await
+}
+
+fn authorized(token: Option<&str>, headers: &HeaderMap) -> bool {
+    match token {
+        Some(token) => headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| given == token),
+        None => headers.contains_key(BRIDGE_HEADER),
+    }
+}
+
+async fn state(State(bridge): State<HttpBridge>) -> Response {
+    Json(serde_json::json!({
+        "meetingState": bridge.handle.meeting_state(),
+        "meetingPermissions": bridge.handle.permissions(),
+    }))
+    .into_response()
+}
+
+async fn react(State(bridge): State<HttpBridge>, Path(reaction): Path<String>) -> Response {
+    match parse::<Reaction>(&reaction) {
+        Some(reaction) => bridge.forward(ClientMessage::reaction(reaction)).await,
+        None => unknown(&reaction),
+    }
+}
+
+async fn toggle_ui(State(bridge): State<HttpBridge>, Path(panel): Path<String>) -> Response {
+    match parse::<UiPanel>(&panel) {
+        Some(panel) => bridge.forward(ClientMessage::toggle_ui(panel)).await,
+        None => unknown(&panel),
+    }
+}
+
+async fn action(State(bridge): State<HttpBridge>, Path(action): Path<String>) -> Response {
+    let parsed = parse::<MeetingAction>(&action).filter(|action| {
+        !matches!(
+            action,
+            MeetingAction::None | MeetingAction::React | MeetingAction::ToggleUI
+        )
+    });
+    match parsed {
+        Some(parsed) => bridge.forward(ClientMessage::new(parsed, None)).await,
+        None => unknown(&action),
+    }
+}
+
+/// Parses a path segment by the serialized name of `T`.
+fn parse<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+fn unknown(name: &str) -> Response {
+    error(StatusCode::NOT_FOUND, &format!("unknown {}", name))
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TeamsClient;
+    use crate::messages::{MeetingState, MeetingUpdate};
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_http_bridge() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::builder()
+                .meeting_updates([MeetingUpdate {
+                    meeting_permissions: None,
+                    meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+                }])
+                .reject(MeetingAction::LeaveCall, "No active call")
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            client
+                .handle()
+                .wait_until_in_meeting(Duration::from_secs(5))
+                .await
+                .unwrap();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                HttpBridge::new(client.handle())
+                    .token("secret")
+                    .serve(listener),
+            );
+            let send = |request: String| async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                response
+            };
+            let request = |method: &str, path: &str| {
+                send(format!(
+                    "{} {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\r\n",
+                    method, path
+                ))
+            };
+
+            let response = request("POST", "/mute").await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+            assert!(response.contains(r#""response":"Success""#));
+            let response = request("POST", "/react/like").await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+            let response = request("POST", "/leave-call").await;
+            assert!(response.starts_with("HTTP/1.1 502"), "{}", response);
+            let response = request("POST", "/react/frown").await;
+            assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+            let response = request("GET", "/mute").await;
+            assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+            let response = request("GET", "/state").await;
+            assert!(response.contains(r#""isInMeeting":true"#), "{}", response);
+
+            let response = send("POST /mute HTTP/1.1\r\nHost: localhost\r\n\r\n".to_string()).await;
+            assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+            let response = send(
+                "POST /mute HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer wrong\r\n\r\n"
+                    .to_string(),
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+            let response = send(
+                "POST /mute HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\
+                 Origin: https://example.com\r\n\r\n"
+                    .to_string(),
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+            server.assert_actions(&[
+                MeetingAction::Mute,
+                MeetingAction::React,
+                MeetingAction::LeaveCall,
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(None, &headers));
+        assert!(!authorized(Some("secret"), &headers));
+        headers.insert(BRIDGE_HEADER, "1".parse().unwrap());
+        assert!(authorized(None, &headers));
+        assert!(!authorized(Some("secret"), &headers));
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorized(Some("secret"), &headers));
+        assert!(!authorized(Some("other"), &headers));
+    }
+}
@@ -0,0 +1,575 @@
+use crate::client::ClientHandle;
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction, MeetingState, Reaction, UiPanel};
+use crate::state::MeetingStateDelta;
+use futures_util::StreamExt;
+use rumqttc::{
+    AsyncClient, ConnectionError, Event, Incoming, LastWill, MqttOptions, Outgoing, QoS,
+};
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The topic prefix used by default, e.g. `teams/muted`.
+pub const DEFAULT_PREFIX: &str = "teams";
+
+/// The MQTT client id and Home Assistant node id used by default.
+pub const DEFAULT_CLIENT_ID: &str = "ms-teams-ws";
+
+/// The port of the broker if its address has none.
+const DEFAULT_PORT: u16 = 1883;
+
+/// The actions offered as Home Assistant buttons.
+const BUTTONS: [MeetingAction; 5] = [
+    MeetingAction::ToggleMute,
+    MeetingAction::ToggleVideo,
+    MeetingAction::ToggleHand,
+    MeetingAction::ToggleBlurBackground,
+    MeetingAction::LeaveCall,
+];
+
+/// Publishes the meeting state of a `TeamsClient` to an MQTT broker and
+/// sends the commands published to it, e.g. for busy lights driven by
+/// Home Assistant.
+///
+/// With the default prefix `teams` it publishes:
+/// - `teams/status`: `online`, or `offline` when the bridge or its
+///   connection to the broker stops (as last will);
+/// - `teams/<field>`: `ON` or `OFF` for every `MeetingState` field, named
+///   like `MeetingStateDelta`, e.g. `teams/muted` and `teams/in_meeting`;
+/// - `teams/unread_message_count`: the number of unread messages, empty if
+///   Teams does not report it.
+///
+/// It subscribes to `teams/command/#`:
+/// - `teams/command/<action>` sends the `MeetingAction` with this wire name,
+///   e.g. `teams/command/toggle-mute`, whatever the payload;
+/// - `teams/command/react` sends the reaction in the payload, e.g. `like`;
+/// - `teams/command/toggle-ui` toggles the panel in the payload, e.g. `chat`.
+///
+/// With `home_assistant_discovery`, the binary sensors of the state and
+/// buttons for the common actions are announced to Home Assistant.
+///
+/// The bridge speaks MQTT 3.1.1 with QoS 0 over plain TCP through the
+/// rumqttc client. Needs the `bridge-mqtt` feature.
+///
+/// # Example
+/// ```rust
+/// let client = TeamsClient::run(websocket);
+/// let bridge = MqttBridge::new(client.handle(), "192.168.1.10:1883")
+///     .credentials("teams", "secret")
+///     .home_assistant_discovery("homeassistant");
+/// tokio::spawn(bridge.run());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MqttBridge {
+    handle: ClientHandle,
+    broker: String,
+    client_id: String,
+    prefix: String,
+    credentials: Option<(String, String)>,
+    keep_alive: Duration,
+    discovery_prefix: Option<String>,
+}
+
+impl MqttBridge {
+    /// Creates a bridge for the client of `handle` and the broker at
+    /// `broker`, e.g. `localhost:1883`. The port defaults to 1883.
+    pub fn new(handle: ClientHandle, broker: impl Into<String>) -> Self {
+        Self {
+            handle,
+            broker: broker.into(),
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            credentials: None,
+            keep_alive: Duration::from_secs(30),
+            discovery_prefix: None,
+        }
+    }
+
+    /// Connects with `client_id`, also the Home Assistant node id.
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = client_id.into();
+        self
+    }
+
+    /// Publishes and subscribes below `prefix` instead of `teams`.
+    pub fn topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Logs in to the broker with `username` and `password`.
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Pings the broker every `interval`, in whole seconds between one
+    /// second and 65535 seconds; other intervals are clamped.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        let seconds = interval.as_secs().clamp(1, u16::MAX as u64);
+        self.keep_alive = Duration::from_secs(seconds);
+        self
+    }
+
+    /// Announces the state and buttons to Home Assistant below `prefix`,
+    /// usually `homeassistant`.
+    pub fn home_assistant_discovery(mut self, prefix: impl Into<String>) -> Self {
+        self.discovery_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Connects to the broker and bridges until the client stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the broker address is invalid, the broker cannot
+    /// be reached, refuses the connection or closes it.
+    pub async fn run(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (client, mut eventloop) = AsyncClient::new(self.options()?, 16);
+        // The event loop sends the requests of `client`, so it runs in its
+        // own task while this one waits for room in the request channel.
+        let (events, mut incoming) = mpsc::unbounded_channel();
+        let polling = tokio::spawn(async move {
+            loop {
+                let event = eventloop.poll().await;
+                let failed = event.is_err();
+                if events.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        let result = self.session(&client, &mut incoming).await;
+        polling.abort();
+        result
+    }
+
+    async fn session(
+        &self,
+        client: &AsyncClient,
+        incoming: &mut mpsc::UnboundedReceiver<Result<Event, ConnectionError>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            match incoming.recv().await {
+                Some(Ok(Event::Incoming(Incoming::ConnAck(_)))) => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err("broker closed the connection".into()),
+            }
+        }
+        info!(target: logging::BRIDGE, "Connected to MQTT broker {}", self.broker);
+        let mut changes = self.handle.subscribe_state_changes();
+        let status = self.topic("status");
+        client
+            .publish(&status, QoS::AtMostOnce, true, "online")
+            .await?;
+        for (topic, config) in self.discovery() {
+            client.publish(topic, QoS::AtMostOnce, true, config).await?;
+        }
+        if let Some(state) = self.handle.meeting_state() {
+            for delta in fields(&state) {
+                self.publish_state(client, delta).await?;
+            }
+            let count = MeetingStateDelta::UnreadMessageCount(state.unread_message_count);
+            self.publish_state(client, count).await?;
+        }
+        client
+            .subscribe(self.topic("command/#"), QoS::AtMostOnce)
+            .await?;
+        loop {
+            tokio::select! {
+                change = changes.next() => match change {
+                    Some(delta) => self.publish_state(client, delta).await?,
+                    None => break,
+                },
+                event = incoming.recv() => match event {
+                    Some(Ok(Event::Incoming(Incoming::Publish(publish)))) => {
+                        self.command(&publish.topic, &publish.payload).await
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err("broker closed the connection".into()),
+                },
+            }
+        }
+        info!(target: logging::BRIDGE, "Client stopped, disconnecting from MQTT broker");
+        client
+            .publish(&status, QoS::AtMostOnce, true, "offline")
+            .await?;
+        client.disconnect().await?;
+        // Wait until the event loop sent the requests before stopping it.
+        while let Some(event) = incoming.recv().await {
+            match event {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                Ok(_) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn options(&self) -> Result<MqttOptions, Box<dyn Error + Send + Sync>> {
+        let (host, port) = host_port(&self.broker)?;
+        let mut options = MqttOptions::new(&self.client_id, host, port);
+        options
+            .set_keep_alive(self.keep_alive)
+            .set_last_will(LastWill::new(
+                self.topic("status"),
+                "offline",
+                QoS::AtMostOnce,
+                true,
+            ));
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, password);
+        }
+        Ok(options)
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    async fn publish_state(
+        &self,
+        client: &AsyncClient,
+        delta: MeetingStateDelta,
+    ) -> Result<(), rumqttc::ClientError> {
+        let (field, on) = field(delta);
+        let payload = match delta {
+            MeetingStateDelta::UnreadMessageCount(count) => {
+                count.map(|count| count.to_string()).unwrap_or_default()
+            }
+            _ if on => "ON".to_string(),
+            _ => "OFF".to_string(),
+        };
+        client
+            .publish(self.topic(&field), QoS::AtMostOnce, true, payload)
+            .await
+    }
+
+    /// Sends the command published to `topic`, logging failures.
+    async fn command(&self, topic: &str, payload: &[u8]) {
+        let prefix = self.topic("command/");
+        let Some(name) = topic.strip_prefix(&prefix) else {
+            return;
+        };
+        let argument = String::from_utf8_lossy(payload);
+        let argument = argument.trim();
+        let message = match name {
+            "react" => parse::<Reaction>(argument).map(ClientMessage::reaction),
+            "toggle-ui" => parse::<UiPanel>(argument).map(ClientMessage::toggle_ui),
+            action => parse::<MeetingAction>(action)
+                .filter(|action| {
+                    !matches!(
+                        action,
+                        MeetingAction::None | MeetingAction::React | MeetingAction::ToggleUI
+                    )
+                })
+                .map(|action| ClientMessage::new(action, None)),
+        };
+        let Some(message) = message else {
+            warn!(
+                target: logging::BRIDGE,
+                "Ignoring unknown MQTT command {} {:?}",
+                topic,
+                argument
+            );
+            return;
+        };
+        debug!(target: logging::BRIDGE, "MQTT command {}", message);
+        if let Err(e) = self.handle.send(message).await {
+            warn!(target: logging::BRIDGE, "Error sending MQTT command {}: {}", topic, e);
+        }
+    }
+
+    /// Returns the retained Home Assistant discovery configs by topic.
+    fn discovery(&self) -> Vec<(String, String)> {
+        let Some(discovery_prefix) = &self.discovery_prefix else {
+            return Vec::new();
+        };
+        let node_id: String = self
+            .client_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let device = serde_json::json!({
+            "identifiers": [node_id],
+            "name": "Microsoft Teams",
+            "manufacturer": "Microsoft",
+        });
+        let mut configs = Vec::new();
+        for delta in fields(&MeetingState::new()) {
+            let (field, _) = field(delta);
+            let config = serde_json::json!({
+                "name": title(&field),
+                "unique_id": format!("{}_{}", node_id, field),
+                "state_topic": self.topic(&field),
+                "availability_topic": self.topic("status"),
+                "device": device,
+            });
+            let topic = format!(
+                "{}/binary_sensor/{}/{}/config",
+                discovery_prefix, node_id, field
+            );
+            configs.push((topic, config.to_string()));
+        }
+        for action in BUTTONS {
+            let name = crate::action::Action::wire_name(&action);
+            let object_id = name.replace('-', "_");
+            let config = serde_json::json!({
+                "name": title(&object_id),
+                "unique_id": format!("{}_{}", node_id, object_id),
+                "command_topic": self.topic(&format!("command/{}", name)),
+                "availability_topic": self.topic("status"),
+                "device": device,
+            });
+            let topic = format!(
+                "{}/button/{}/{}/config",
+                discovery_prefix, node_id, object_id
+            );
+            configs.push((topic, config.to_string()));
+        }
+        configs
+    }
+}
+
+/// Splits `broker` into its host and port, e.g. `[::1]:1883`.
+fn host_port(broker: &str) -> Result<(String, u16), Box<dyn Error + Send + Sync>> {
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => {
+            let port = port
+                .parse()
+                .map_err(|_| format!("invalid port in MQTT broker {}", broker))?;
+            (host, port)
+        }
+        _ => (broker, DEFAULT_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("missing host in MQTT broker {}", broker).into());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Returns every flag of `state` as a change.
+fn fields(state: &MeetingState) -> [MeetingStateDelta; 10] {
+    [
+        MeetingStateDelta::InMeeting(state.is_in_meeting),
+        MeetingStateDelta::Muted(state.is_muted),
+        MeetingStateDelta::VideoOn(state.is_video_on),
+        MeetingStateDelta::HandRaised(state.is_hand_raised),
+        MeetingStateDelta::BackgroundBlurred(state.is_background_blurred),
+        MeetingStateDelta::Sharing(state.is_sharing),
+        MeetingStateDelta::RecordingOn(state.is_recording_on),
+        MeetingStateDelta::UnreadMessages(state.has_unread_messages),
+        MeetingStateDelta::OnHold(state.is_on_hold),
+        MeetingStateDelta::RecordingPaused(state.is_recording_paused),
+    ]
+}
+
+/// Returns the serialized name and value of `delta`, e.g. `("muted", true)`.
+fn field(delta: MeetingStateDelta) -> (String, bool) {
+    match serde_json::to_value(delta) {
+        Ok(serde_json::Value::Object(fields)) => fields
+            .into_iter()
+            .next()
+            .map(|(name, value)| (name, value == true))
+            .unwrap_or_default(),
+        _ => Default::default(),
+    }
+}
+
+/// Turns `in_meeting` into `In meeting`.
+fn title(name: &str) -> String {
+    let name = name.replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+fn parse<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TeamsClient;
+    use crate::messages::MeetingUpdate;
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, ConnectReturnCode, Packet, Publish};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::runtime::Runtime;
+
+    /// Reads the next packet the bridge sent to the broker.
+    async fn read_packet(stream: &mut TcpStream, buffer: &mut BytesMut) -> Packet {
+        loop {
+            match rumqttc::mqttbytes::v4::read(buffer, 1 << 20) {
+                Ok(packet) => return packet,
+                Err(rumqttc::mqttbytes::Error::InsufficientBytes(_)) => {}
+                Err(e) => panic!("invalid packet: {:?}", e),
+            }
+            assert_ne!(
+                stream.read_buf(buffer).await.unwrap(),
+                0,
+                "connection closed"
+            );
+        }
+    }
+
+    async fn write_publish(stream: &mut TcpStream, topic: &str, payload: &[u8]) {
+        let mut buffer = BytesMut::new();
+        Publish::new(topic, QoS::AtMostOnce, payload)
+            .write(&mut buffer)
+            .unwrap();
+        stream.write_all(&buffer).await.unwrap();
+    }
+
+    fn topic_payload(packet: &Packet) -> Option<(&str, &[u8])> {
+        match packet {
+            Packet::Publish(publish) => Some((&publish.topic, &publish.payload)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_mqtt_bridge() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::builder()
+                .meeting_updates([MeetingUpdate {
+                    meeting_permissions: None,
+                    meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+                }])
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            client
+                .handle()
+                .wait_until_in_meeting(Duration::from_secs(5))
+                .await
+                .unwrap();
+            let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let bridge = MqttBridge::new(client.handle(), broker.local_addr().unwrap().to_string())
+                .home_assistant_discovery("homeassistant");
+            let bridge = tokio::spawn(bridge.run());
+
+            let (mut stream, _) = broker.accept().await.unwrap();
+            let mut buffer = BytesMut::new();
+            match read_packet(&mut stream, &mut buffer).await {
+                Packet::Connect(connect) => {
+                    assert_eq!(connect.client_id, DEFAULT_CLIENT_ID);
+                    let will = connect.last_will.unwrap();
+                    assert_eq!(
+                        (will.topic.as_str(), &will.message[..]),
+                        ("teams/status", &b"offline"[..])
+                    );
+                }
+                packet => panic!("unexpected {:?}", packet),
+            }
+            let mut connack = BytesMut::new();
+            ConnAck::new(ConnectReturnCode::Success, false)
+                .write(&mut connack)
+                .unwrap();
+            stream.write_all(&connack).await.unwrap();
+            let mut publishes = Vec::new();
+            loop {
+                match read_packet(&mut stream, &mut buffer).await {
+                    Packet::Publish(publish) => publishes.push(publish),
+                    Packet::Subscribe(subscribe) => {
+                        assert_eq!(subscribe.filters[0].path, "teams/command/#");
+                        break;
+                    }
+                    packet => panic!("unexpected {:?}", packet),
+                }
+            }
+            let published = |topic: &str| {
+                let publish = publishes.iter().find(|p| p.topic == topic).unwrap();
+                assert!(publish.retain);
+                String::from_utf8(publish.payload.to_vec()).unwrap()
+            };
+            assert_eq!(published("teams/status"), "online");
+            assert_eq!(published("teams/in_meeting"), "ON");
+            assert_eq!(published("teams/muted"), "OFF");
+            let config = published("homeassistant/button/ms_teams_ws/toggle_mute/config");
+            assert!(config.contains(r#""command_topic":"teams/command/toggle-mute""#));
+
+            write_publish(&mut stream, "teams/command/toggle-mute", b"PRESS").await;
+            write_publish(&mut stream, "teams/command/react", b"like").await;
+            while server.received().len() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            server.assert_actions(&[MeetingAction::ToggleMute, MeetingAction::React]);
+
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true).with_muted(true)),
+            });
+            let muted = Some(("teams/muted", &b"ON"[..]));
+            while topic_payload(&read_packet(&mut stream, &mut buffer).await) != muted {}
+
+            client.shutdown().await.unwrap();
+            let offline = Some(("teams/status", &b"offline"[..]));
+            while topic_payload(&read_packet(&mut stream, &mut buffer).await) != offline {}
+            assert_eq!(
+                read_packet(&mut stream, &mut buffer).await,
+                Packet::Disconnect
+            );
+            bridge.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(
+            host_port("localhost:1884").unwrap(),
+            ("localhost".to_string(), 1884)
+        );
+        assert_eq!(
+            host_port("localhost").unwrap(),
+            ("localhost".to_string(), 1883)
+        );
+        assert_eq!(host_port("[::1]:1884").unwrap(), ("::1".to_string(), 1884));
+        assert_eq!(host_port("[::1]").unwrap(), ("::1".to_string(), 1883));
+        assert!(host_port("localhost:mqtt").is_err());
+        assert!(host_port(":1883").is_err());
+
+        let runtime = Runtime::new().unwrap();
+        let handle = runtime.block_on(async {
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url("ws://127.0.0.1:1")
+                .build()
+                .unwrap();
+            TeamsClient::run(websocket).handle()
+        });
+        let bridge = MqttBridge::new(handle, "localhost");
+        assert_eq!(
+            bridge.clone().keep_alive(Duration::ZERO).keep_alive,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            bridge
+                .clone()
+                .keep_alive(Duration::from_millis(1500))
+                .keep_alive,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            bridge.keep_alive(Duration::from_secs(1 << 20)).keep_alive,
+            Duration::from_secs(u16::MAX as u64)
+        );
+    }
+}
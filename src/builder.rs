@@ -0,0 +1,406 @@
+use crate::arbitration::Arbiter;
+#[cfg(feature = "audit")]
+use crate::audit::AuditLog;
+use crate::config::{Config, ConfigTokenStore};
+use crate::confirm::ConfirmationHook;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::queue::CommandQueue;
+use crate::ratelimit::RateLimiter;
+use crate::reconnect::ReconnectPolicy;
+use crate::recording::{Direction, Recorder};
+use crate::settings::{SettingKey, SettingsResolver};
+use crate::shutdown::ShutdownSignal;
+#[cfg(feature = "rustls")]
+use crate::tls::CertificatePin;
+use crate::token::TokenStore;
+use crate::transport::Connector;
+use crate::types::AppIdentifiers;
+use crate::{
+    AlreadyConnected, ConnectionOptions, MalformedFrame, MessageObserver, RawFrameHook,
+    RequestIdStart, TeamsWebsocket,
+};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// A builder for `TeamsWebsocket`.
+///
+/// Settings are resolved with the precedence defaults < config file <
+/// environment < explicit builder calls. The effective values and where they
+/// came from are available through `TeamsWebsocket::settings()`.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier)
+///     .config_file("teams.json")
+///     .token("secret")
+///     .build()?;
+/// println!("{}", websocket.settings());
+/// ```
+pub struct TeamsWebsocketBuilder {
+    identifier: AppIdentifiers,
+    config_file: Option<PathBuf>,
+    user_config: bool,
+    use_environment: bool,
+    url: Option<String>,
+    token: Option<String>,
+    options: ConnectionOptions,
+    #[cfg(feature = "audit")]
+    audit_log: Option<AuditLog>,
+    confirmation_hook: Option<ConfirmationHook>,
+    command_queue: Option<CommandQueue>,
+    arbiter: Option<Arbiter>,
+    rate_limiter: Option<RateLimiter>,
+    shutdown_signal: Option<ShutdownSignal>,
+    malformed_frames: Option<UnboundedSender<MalformedFrame>>,
+    token_store: Option<Box<dyn TokenStore>>,
+    raw_frame_hook: Option<RawFrameHook>,
+    connector: Option<Box<dyn Connector>>,
+    recorder: Option<Recorder>,
+    message_observer: Option<MessageObserver>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl TeamsWebsocketBuilder {
+    pub fn new(identifier: AppIdentifiers) -> Self {
+        Self {
+            identifier,
+            config_file: None,
+            user_config: false,
+            use_environment: true,
+            url: None,
+            token: None,
+            options: ConnectionOptions::default(),
+            #[cfg(feature = "audit")]
+            audit_log: None,
+            confirmation_hook: None,
+            command_queue: None,
+            arbiter: None,
+            rate_limiter: None,
+            shutdown_signal: None,
+            malformed_frames: None,
+            token_store: None,
+            raw_frame_hook: None,
+            connector: None,
+            recorder: None,
+            message_observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Reads settings from a JSON config file.
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// Reads settings from the user `Config` at `Config::default_path()`,
+    /// if it exists and no `config_file` is given, and stores refreshed
+    /// tokens there unless a `token_store` is set.
+    pub fn user_config(mut self) -> Self {
+        self.user_config = true;
+        self
+    }
+
+    /// Do not read settings from `TEAMS_WS_*` environment variables.
+    pub fn ignore_environment(mut self) -> Self {
+        self.use_environment = false;
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Replaces the `ConnectionOptions`.
+    pub fn options(mut self, options: ConnectionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Allows connecting to non-loopback hosts.
+    pub fn allow_remote(mut self, allow: bool) -> Self {
+        self.options.allow_remote = allow;
+        self
+    }
+
+    /// Retries the handshake with `versions`, in order, if Teams rejects the
+    /// protocol version of the identifiers, see `TeamsWebsocket::connection_info`.
+    pub fn fallback_protocol_versions(
+        mut self,
+        versions: impl IntoIterator<Item = &'static str>,
+    ) -> Self {
+        self.options.fallback_protocol_versions = versions.into_iter().collect();
+        self
+    }
+
+    /// Tries the protocol versions of classic and new Teams on connect, see
+    /// `TeamsWebsocket::flavor`.
+    pub fn detect_flavor(mut self, detect: bool) -> Self {
+        self.options.detect_flavor = detect;
+        self
+    }
+
+    /// Queries the meeting state every `interval` while receiving, see
+    /// `ConnectionOptions::state_refresh`.
+    pub fn state_refresh(mut self, interval: Duration) -> Self {
+        self.options.state_refresh = Some(interval);
+        self
+    }
+
+    /// Pings Teams every `interval` while receiving and drops connections
+    /// that stopped answering, see `ConnectionOptions::keepalive`.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.options.keepalive = Some(interval);
+        self
+    }
+
+    /// Makes `TeamsWebsocket::connect` give up on a handshake after `timeout`,
+    /// see `ConnectionOptions::connect_timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes `TeamsWebsocket::receive` wait at most `timeout`, see
+    /// `ConnectionOptions::receive_timeout`.
+    pub fn receive_timeout(mut self, timeout: Duration) -> Self {
+        self.options.receive_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends the HTTP header `name` with the handshake, see
+    /// `ConnectionOptions::headers`.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Appends the query parameter `name` to the URL of the handshake.
+    pub fn query_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.query_params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets what `TeamsWebsocket::connect` does while connected, see
+    /// `AlreadyConnected`.
+    pub fn already_connected(mut self, policy: AlreadyConnected) -> Self {
+        self.options.already_connected = policy;
+        self
+    }
+
+    /// Sets where the request ids assigned by `TeamsWebsocket::send` start,
+    /// see `RequestIdStart`.
+    pub fn request_id_start(mut self, start: RequestIdStart) -> Self {
+        self.options.request_id_start = start;
+        self
+    }
+
+    /// Re-establishes dropped connections while receiving, see `ReconnectPolicy`.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect = Some(policy);
+        self
+    }
+
+    /// Queries the meeting state after every connect and waits for it, see
+    /// `ConnectionOptions::query_state_on_connect`.
+    pub fn query_state_on_connect(mut self, query: bool) -> Self {
+        self.options.query_state_on_connect = query;
+        self
+    }
+
+    /// Refuses to send actions the meeting permissions do not allow, see
+    /// `ConnectionOptions::check_permissions`.
+    pub fn check_permissions(mut self, check: bool) -> Self {
+        self.options.check_permissions = check;
+        self
+    }
+
+    /// Logs the messages `send` would send instead of sending them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.options.dry_run = dry_run;
+        self
+    }
+
+    /// Only trusts `wss://` servers whose certificate matches one of the
+    /// added pins.
+    #[cfg(feature = "rustls")]
+    pub fn pin_certificate(mut self, pin: CertificatePin) -> Self {
+        self.options.certificate_pins.push(pin);
+        self
+    }
+
+    /// Trusts `wss://` servers without verifying their certificate, see
+    /// `ConnectionOptions::accept_invalid_certificates`.
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub fn accept_invalid_certificates(mut self, accept: bool) -> Self {
+        self.options.accept_invalid_certificates = accept;
+        self
+    }
+
+    /// Consults `hook` before sending dangerous actions.
+    pub fn confirmation_hook(mut self, hook: ConfirmationHook) -> Self {
+        self.confirmation_hook = Some(hook);
+        self
+    }
+
+    /// Resolves conflicting commands from several sources, see `Arbiter`.
+    pub fn arbiter(mut self, arbiter: Arbiter) -> Self {
+        self.arbiter = Some(arbiter);
+        self
+    }
+
+    /// Stops receiving and reconnecting once `signal` is triggered, see
+    /// `ShutdownSignal`.
+    pub fn shutdown_signal(mut self, signal: ShutdownSignal) -> Self {
+        self.shutdown_signal = Some(signal);
+        self
+    }
+
+    /// Limits how often commands are sent, see `RateLimiter`.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Delivers frames that could not be parsed to `sender`, see
+    /// `TeamsWebsocket::receive_resilient`.
+    pub fn malformed_frames(mut self, sender: UnboundedSender<MalformedFrame>) -> Self {
+        self.malformed_frames = Some(sender);
+        self
+    }
+
+    /// Queues messages sent while not connected and sends them on `connect`.
+    pub fn command_queue(mut self, queue: CommandQueue) -> Self {
+        self.command_queue = Some(queue);
+        self
+    }
+
+    /// Records every sent action in a hash-chained audit log.
+    #[cfg(feature = "audit")]
+    pub fn audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Persists the tokens Teams sends in `store` and uses the stored token,
+    /// unless one is set with `token`.
+    pub fn token_store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.token_store = Some(Box::new(store));
+        self
+    }
+
+    /// Passes the raw text of every frame Teams sends to `hook`, for
+    /// debugging, see `TeamsWebsocket::set_raw_frame_hook`.
+    pub fn raw_frame_hook(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.raw_frame_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Opens the connections with `connector` instead of tokio-tungstenite,
+    /// e.g. to use another WebSocket stack, see `transport::Transport`.
+    pub fn connector(mut self, connector: impl Connector + 'static) -> Self {
+        self.connector = Some(Box::new(connector));
+        self
+    }
+
+    /// Writes every frame sent and received to `recorder`, see
+    /// `recording::ReplayTransport` to play it back.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Passes the raw text of every frame sent and received to `observer`,
+    /// see `TeamsWebsocket::set_message_observer`.
+    pub fn message_observer(
+        mut self,
+        observer: impl Fn(Direction, &str) + Send + Sync + 'static,
+    ) -> Self {
+        self.message_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Records messages, reconnects, errors and the meeting state with
+    /// `metrics`, e.g. for a Prometheus exporter.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Resolves the settings and creates the `TeamsWebsocket`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config file or the stored token cannot be loaded.
+    pub fn build(mut self) -> Result<TeamsWebsocket, Box<dyn Error>> {
+        let mut resolver = SettingsResolver::new();
+        let user_config = match self.user_config {
+            true => Config::default_path(),
+            false => None,
+        };
+        if let Some(path) = &user_config {
+            if self.token_store.is_none() {
+                self.token_store = Some(Box::new(ConfigTokenStore::new(path)));
+            }
+        }
+        let user_config = user_config.filter(|path| path.exists());
+        if let Some(path) = self.config_file.as_ref().or(user_config.as_ref()) {
+            resolver = resolver.config_file(path)?;
+        }
+        if self.use_environment {
+            resolver = resolver.environment();
+        }
+        if let Some(url) = self.url {
+            resolver = resolver.explicit(SettingKey::Url, url);
+        }
+        let stored = match &self.token_store {
+            Some(store) if self.token.is_none() => store.load()?,
+            _ => None,
+        };
+        if let Some(token) = self.token.or(stored) {
+            resolver = resolver.explicit(SettingKey::Token, token);
+        }
+        let mut websocket =
+            TeamsWebsocket::from_settings(self.identifier, resolver.resolve(), self.options);
+        websocket.set_confirmation_hook(self.confirmation_hook);
+        websocket.set_command_queue(self.command_queue);
+        websocket.set_arbiter(self.arbiter);
+        websocket.set_rate_limiter(self.rate_limiter);
+        if let Some(signal) = self.shutdown_signal {
+            websocket.set_shutdown_signal(signal);
+        }
+        websocket.set_malformed_frames(self.malformed_frames);
+        websocket.set_token_store(self.token_store);
+        websocket.set_raw_frame_hook(self.raw_frame_hook);
+        websocket.set_connector(self.connector);
+        websocket.set_recorder(self.recorder);
+        websocket.set_message_observer(self.message_observer);
+        #[cfg(feature = "metrics")]
+        websocket.set_metrics(self.metrics);
+        #[cfg(feature = "audit")]
+        websocket.set_audit_log(self.audit_log);
+        Ok(websocket)
+    }
+
+    /// Builds a `blocking::TeamsWebsocket`, for programs that are not async.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `build`, or an error if the runtime of the
+    /// blocking websocket cannot be created.
+    pub fn build_blocking(self) -> Result<crate::blocking::TeamsWebsocket, Box<dyn Error>> {
+        crate::blocking::TeamsWebsocket::new(self.build()?)
+    }
+}
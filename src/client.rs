@@ -0,0 +1,1454 @@
+use crate::event::{ConnectionStatus, DisconnectInitiator, DisconnectReport, Event};
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingPermissions, MeetingState, ServerMessage};
+use crate::state::{MeetingStateDelta, StateSnapshot, StateTracker};
+use crate::{TeamsWebsocket, TeamsWsError};
+use futures_util::stream::BoxStream;
+use futures_util::{Sink, Stream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// The number of events kept for subscribers that fall behind, unless set
+/// with `TeamsClient::run_with_capacity`.
+pub const EVENT_CAPACITY: usize = 256;
+
+/// A subscriber fell more than the event capacity behind and missed the
+/// given number of events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl std::fmt::Display for Lagged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "subscriber lagged behind, skipped {} events", self.0)
+    }
+}
+
+impl Error for Lagged {}
+
+/// What a `TeamsClient` reports to its subscribers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// A message Teams sent, including the answers to requests.
+    Message(ServerMessage),
+    /// Teams sent a new token.
+    TokenRefreshed(String),
+    /// A state change or connection event derived from the messages.
+    Event(Event),
+    /// Receiving failed with the given message, the client stopped.
+    Error(String),
+}
+
+enum Command {
+    Send(ClientMessage, oneshot::Sender<Result<(), String>>),
+    Request(
+        ClientMessage,
+        oneshot::Sender<Result<ServerMessage, String>>,
+    ),
+    /// Forgets the `Request`s whose caller stopped waiting for the answer.
+    Forget,
+    Close,
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Send(message, _) => write!(f, "Send({})", message),
+            Command::Request(message, _) => write!(f, "Request({})", message),
+            Command::Forget => write!(f, "Forget"),
+            Command::Close => write!(f, "Close"),
+        }
+    }
+}
+
+impl Command {
+    fn message(&self) -> Option<&ClientMessage> {
+        match self {
+            Command::Send(message, _) | Command::Request(message, _) => Some(message),
+            Command::Forget | Command::Close => None,
+        }
+    }
+
+    /// Replies to the command that the confirmation hook refused it.
+    fn refuse(self) {
+        let Some(action) = self.message().map(|message| message.action) else {
+            return;
+        };
+        let e = TeamsWsError::NotConfirmed { action };
+        info!(target: logging::CONNECTION, "{}", e);
+        match self {
+            Command::Send(_, reply) => {
+                let _ = reply.send(Err(e.to_string()));
+            }
+            Command::Request(_, reply) => {
+                let _ = reply.send(Err(e.to_string()));
+            }
+            Command::Forget | Command::Close => {}
+        }
+    }
+}
+
+/// The replies of `ClientHandle::send_and_wait` waiting for Teams' answer,
+/// by request id.
+type Waiting = HashMap<u32, oneshot::Sender<Result<ServerMessage, String>>>;
+
+/// Sends the message of `command` and replies to it, or adds the reply to
+/// `waiting` for Teams' answer. A `confirmed` message skips the
+/// confirmation hook.
+async fn dispatch(
+    websocket: &mut TeamsWebsocket,
+    command: Command,
+    waiting: &mut Waiting,
+    confirmed: bool,
+) {
+    match command {
+        Command::Send(message, reply) => {
+            let result = websocket
+                .send_checked(message, confirmed)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string());
+            let _ = reply.send(result);
+        }
+        Command::Request(message, reply) => {
+            let name = message.wire_name().into_owned();
+            match websocket.send_checked(message, confirmed).await {
+                // The caller stopped waiting while e.g. the confirmation hook ran.
+                Ok(_) if reply.is_closed() => {}
+                Ok(id) if websocket.pending_requests().any(|request| request.id == id) => {
+                    // Left over if Teams never answered the request that used the id before.
+                    if let Some(stale) = waiting.insert(id, reply) {
+                        let _ = stale.send(Err(format!("request {} was not answered", id)));
+                    }
+                }
+                Ok(_) => {
+                    let _ =
+                        reply.send(Err(format!("{} was not sent, no answer to wait for", name)));
+                }
+                Err(e) => {
+                    let _ = reply.send(Err(e.to_string()));
+                }
+            }
+        }
+        Command::Forget | Command::Close => {}
+    }
+}
+
+/// What Teams last reported, as published by the client task.
+#[derive(Debug, Clone, Default)]
+struct Snapshot {
+    state: Option<StateSnapshot>,
+    permissions: Option<MeetingPermissions>,
+}
+
+impl Snapshot {
+    fn of(websocket: &TeamsWebsocket) -> Self {
+        Self {
+            state: websocket.state_snapshot(),
+            permissions: websocket.permissions().cloned(),
+        }
+    }
+}
+
+/// Sends `Command::Forget` when a `send_and_wait` is dropped before the
+/// answer arrived, e.g. by a timeout, so its reply does not stay waiting.
+struct ForgetOnDrop<'a>(Option<&'a mpsc::UnboundedSender<Command>>);
+
+impl ForgetOnDrop<'_> {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ForgetOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(commands) = self.0 {
+            let _ = commands.send(Command::Forget);
+        }
+    }
+}
+
+/// A cloneable handle sending commands to a running `TeamsClient`.
+///
+/// Handles are `Send` and `Sync` and all methods take `&self`, so e.g. a
+/// hotkey listener, a tray icon and an HTTP endpoint can each keep a clone
+/// without a mutex. The client task handles the commands in the order they
+/// were sent; awaiting an answer does not block the other handles.
+///
+/// # Example
+/// ```rust
+/// let handle = client.handle();
+/// tokio::spawn(async move { hotkeys.run(handle).await });
+/// let handle = client.handle();
+/// tokio::spawn(async move { tray.run(handle).await });
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientHandle {
+    commands: mpsc::UnboundedSender<Command>,
+    events: broadcast::Sender<ClientEvent>,
+    snapshot: watch::Receiver<Snapshot>,
+    status: watch::Receiver<ConnectionStatus>,
+    /// The runtime of the client task, whose timers `wait_for` uses.
+    runtime: tokio::runtime::Handle,
+}
+
+impl ClientHandle {
+    /// Sends `message` through the client and waits until it was sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `TeamsWebsocket::send`, as a message, or an
+    /// error if the client stopped.
+    pub async fn send(&self, message: ClientMessage) -> Result<(), Box<dyn Error>> {
+        let (reply, result) = oneshot::channel();
+        self.commands
+            .send(Command::Send(message, reply))
+            .map_err(|_| "client stopped")?;
+        result.await.map_err(|_| "client stopped")??;
+        Ok(())
+    }
+
+    /// Sends `message` through the client and waits for Teams' answer to
+    /// it, which is also broadcast to the subscribers.
+    ///
+    /// Teams does not answer if it hangs, so wrap the call in a timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `TeamsWebsocket::send`, as a message, an error
+    /// if the message was not sent because of dry-run mode or the command
+    /// queue, or if the client stopped before the answer arrived.
+    pub async fn send_and_wait(
+        &self,
+        message: ClientMessage,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        let (reply, answer) = oneshot::channel();
+        self.commands
+            .send(Command::Request(message, reply))
+            .map_err(|_| "client stopped")?;
+        let forget = ForgetOnDrop(Some(&self.commands));
+        let answer = answer.await;
+        forget.disarm();
+        Ok(answer.map_err(|_| "client stopped")??)
+    }
+
+    /// Like `send_and_wait`, but fails if Teams answered with an error, so
+    /// "no meeting" can be told from other failures without parsing text.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `send_and_wait`, or the `TeamsErrorKind` of
+    /// Teams' answer.
+    ///
+    /// # Example
+    /// ```rust
+    /// match handle.request(ClientMessage::new(MeetingAction::Mute, None)).await {
+    ///     Err(e) if e.downcast_ref() == Some(&TeamsErrorKind::NoActiveCall) => {}
+    ///     answer => answer.map(drop)?,
+    /// }
+    /// ```
+    pub async fn request(&self, message: ClientMessage) -> Result<ServerMessage, Box<dyn Error>> {
+        Ok(self.send_and_wait(message).await?.into_result()?)
+    }
+
+    /// Returns a receiver for the events from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns the changes of the meeting state from now on, e.g.
+    /// `Muted(true)`, for integrations that only care about transitions.
+    ///
+    /// The client keeps tracking the state across reconnects, so a
+    /// reconnect only yields the fields that changed meanwhile. The stream
+    /// ends when the client stopped; changes a slow consumer falls
+    /// more than the event capacity behind on are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut changes = client.handle().subscribe_state_changes();
+    /// while let Some(change) = changes.next().await {
+    ///     if let MeetingStateDelta::Muted(muted) = change {
+    ///         busy_light.set(muted);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe_state_changes(&self) -> BoxStream<'static, MeetingStateDelta> {
+        self.subscribe_filtered(|event| match event {
+            ClientEvent::Event(Event::StateChanged(delta)) => Some(delta),
+            _ => None,
+        })
+        .filter_map(|delta| async move {
+            delta
+                .map_err(|e| warn!(target: logging::CONNECTION, "State change subscriber {}", e))
+                .ok()
+        })
+        .boxed()
+    }
+
+    /// Returns a copy of every message Teams sends from now on, including
+    /// the answers to requests, e.g. to log all traffic while another
+    /// subscriber reacts to meeting updates.
+    ///
+    /// A subscriber falling more than the event capacity behind misses the
+    /// oldest messages and gets `Lagged` with their number instead; it may
+    /// keep receiving or give up. The stream ends when the client stopped.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut messages = client.handle().subscribe_messages();
+    /// while let Some(message) = messages.next().await {
+    ///     match message {
+    ///         Ok(message) => log.write(&message)?,
+    ///         Err(lagged) => warn!("Traffic log incomplete: {}", lagged),
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe_messages(&self) -> BoxStream<'static, Result<ServerMessage, Lagged>> {
+        self.subscribe_filtered(|event| match event {
+            ClientEvent::Message(message) => Some(message),
+            _ => None,
+        })
+    }
+
+    /// Returns the events `filter` maps to `Some` from now on, and `Lagged`
+    /// where events were skipped. The stream ends when the client stopped.
+    fn subscribe_filtered<T: Send + 'static>(
+        &self,
+        filter: fn(ClientEvent) -> Option<T>,
+    ) -> BoxStream<'static, Result<T, Lagged>> {
+        let state = (self.subscribe(), self.commands.clone());
+        futures_util::stream::unfold(state, move |(mut events, commands)| async move {
+            loop {
+                // The handles keep the channel open, the stopped client
+                // shows in the commands channel closing.
+                let event = tokio::select! {
+                    biased;
+                    event = events.recv() => event,
+                    _ = commands.closed() => events.try_recv().map_err(|e| match e {
+                        broadcast::error::TryRecvError::Lagged(skipped) => {
+                            broadcast::error::RecvError::Lagged(skipped)
+                        }
+                        _ => broadcast::error::RecvError::Closed,
+                    }),
+                };
+                match event {
+                    Ok(event) => {
+                        if let Some(item) = filter(event) {
+                            return Some((Ok(item), (events, commands)));
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        return Some((Err(Lagged(skipped)), (events, commands)))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Returns the meeting state Teams last reported, or `None` before it
+    /// reported anything.
+    pub fn meeting_state(&self) -> Option<MeetingState> {
+        self.snapshot
+            .borrow()
+            .state
+            .as_ref()
+            .map(|snapshot| snapshot.state.clone())
+    }
+
+    /// Returns the meeting state Teams last reported with when it did, or
+    /// `None` before it reported anything. The snapshot keeps aging while
+    /// the client is disconnected, see `StateSnapshot::is_stale`.
+    pub fn state_snapshot(&self) -> Option<StateSnapshot> {
+        self.snapshot.borrow().state.clone()
+    }
+
+    /// Returns the meeting permissions Teams last reported, or `None`
+    /// before it reported anything.
+    pub fn permissions(&self) -> Option<MeetingPermissions> {
+        self.snapshot.borrow().permissions.clone()
+    }
+
+    /// Returns the state of the connection of the client.
+    pub fn status(&self) -> ConnectionStatus {
+        *self.status.borrow()
+    }
+
+    /// Returns a channel following the state of the connection, see
+    /// `TeamsWebsocket::watch_status`.
+    pub fn watch_status(&self) -> watch::Receiver<ConnectionStatus> {
+        self.status.clone()
+    }
+
+    /// Returns whether Teams is in a meeting, as last reported by Teams.
+    pub fn is_in_meeting(&self) -> Option<bool> {
+        let snapshot = self.snapshot.borrow();
+        snapshot
+            .state
+            .as_ref()
+            .map(|snapshot| snapshot.state.is_in_meeting)
+    }
+
+    /// Waits until the meeting state Teams reports satisfies `condition`
+    /// and returns that state, at once if the last reported one does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `tokio::time::error::Elapsed` after `timeout`, or an error if
+    /// the client stopped first.
+    ///
+    /// # Example
+    /// ```rust
+    /// handle.wait_for(|state| state.is_in_meeting && !state.is_muted, timeout).await?;
+    /// recording_light.on();
+    /// ```
+    pub async fn wait_for<F>(
+        &self,
+        mut condition: F,
+        timeout: Duration,
+    ) -> Result<MeetingState, Box<dyn Error>>
+    where
+        F: FnMut(&MeetingState) -> bool,
+    {
+        let mut snapshot = self.snapshot.clone();
+        let reported = snapshot.wait_for(|snapshot| {
+            snapshot
+                .state
+                .as_ref()
+                .is_some_and(|snapshot| condition(&snapshot.state))
+        });
+        let reported = {
+            let _context = self.runtime.enter();
+            tokio::time::timeout(timeout, reported)
+        };
+        let snapshot = reported.await?.map_err(|_| "client stopped")?;
+        Ok(snapshot
+            .state
+            .as_ref()
+            .map(|snapshot| snapshot.state.clone())
+            .unwrap_or_default())
+    }
+
+    /// Waits until Teams is in a meeting, see `wait_for`.
+    pub async fn wait_until_in_meeting(
+        &self,
+        timeout: Duration,
+    ) -> Result<MeetingState, Box<dyn Error>> {
+        self.wait_for(|state| state.is_in_meeting, timeout).await
+    }
+
+    /// Waits until Teams left the meeting, see `wait_for`.
+    pub async fn wait_until_meeting_ended(
+        &self,
+        timeout: Duration,
+    ) -> Result<MeetingState, Box<dyn Error>> {
+        self.wait_for(|state| !state.is_in_meeting, timeout).await
+    }
+
+    /// Waits until the user is muted, see `wait_for`.
+    pub async fn wait_until_muted(
+        &self,
+        timeout: Duration,
+    ) -> Result<MeetingState, Box<dyn Error>> {
+        self.wait_for(|state| state.is_muted, timeout).await
+    }
+
+    /// Waits until the user is unmuted, see `wait_for`.
+    pub async fn wait_until_unmuted(
+        &self,
+        timeout: Duration,
+    ) -> Result<MeetingState, Box<dyn Error>> {
+        self.wait_for(|state| !state.is_muted, timeout).await
+    }
+
+    /// Closes the connection and stops the client.
+    pub fn close(&self) {
+        let _ = self.commands.send(Command::Close);
+    }
+}
+
+/// A `TeamsWebsocket` owned by a background task, so updates can be
+/// listened to while commands are sent from other tasks.
+///
+/// Events are broadcast to every subscriber; subscribers that fall more
+/// than `EVENT_CAPACITY` events behind miss the oldest ones, see
+/// `run_with_capacity` and `ClientHandle::subscribe_messages`. The task
+/// stops when the connection ends for good, see `ConnectionOptions::reconnect`,
+/// or when closed.
+///
+/// # Example
+/// ```rust
+/// let client = TeamsClient::run(websocket);
+/// let mut events = client.subscribe();
+/// let handle = client.handle();
+/// tokio::spawn(async move {
+///     while let Ok(event) = events.recv().await {
+///         println!("{:?}", event);
+///     }
+/// });
+/// handle.send(ClientMessage::new(MeetingAction::ToggleMute, None)).await?;
+/// ```
+pub struct TeamsClient {
+    handle: ClientHandle,
+    task: JoinHandle<TeamsWebsocket>,
+}
+
+impl TeamsClient {
+    /// Spawns the task owning `websocket`, which connects it unless connected.
+    pub fn run(websocket: TeamsWebsocket) -> Self {
+        Self::spawn(websocket, None, EVENT_CAPACITY)
+    }
+
+    /// Like `run`, keeping `capacity` events for subscribers that fall
+    /// behind instead of `EVENT_CAPACITY`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn run_with_capacity(websocket: TeamsWebsocket, capacity: usize) -> Self {
+        Self::spawn(websocket, None, capacity)
+    }
+
+    /// Like `run`, but runs the task on a tokio runtime of its own on a new
+    /// thread, for applications built on another runtime such as async-std
+    /// or smol, or on none.
+    ///
+    /// The client, its handles and their futures do not need a tokio
+    /// context, so they can be used from any executor. The thread ends
+    /// when the client stopped. With the `async-std` feature,
+    /// `AsyncStdConnector` moves the socket I/O onto async-std as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime or the thread cannot be created.
+    ///
+    /// # Example
+    /// ```rust
+    /// let client = TeamsClient::run_on_thread(websocket)?;
+    /// async_std::task::block_on(async {
+    ///     client.handle().send(ClientMessage::new(MeetingAction::ToggleMute, None)).await
+    /// })?;
+    /// ```
+    pub fn run_on_thread(websocket: TeamsWebsocket) -> Result<Self, Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Self::run_on_runtime(websocket, runtime)
+    }
+
+    /// Spawns the task on `runtime` and drives it on a new thread, see
+    /// `run_on_thread`.
+    pub(crate) fn run_on_runtime(
+        websocket: TeamsWebsocket,
+        runtime: tokio::runtime::Runtime,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (stopped, finished) = oneshot::channel::<()>();
+        let client = {
+            let _context = runtime.enter();
+            Self::spawn_with(websocket, None, EVENT_CAPACITY, Some(stopped))
+        };
+        std::thread::Builder::new()
+            .name("teams-client".to_string())
+            .spawn(move || {
+                let _ = runtime.block_on(finished);
+            })?;
+        Ok(client)
+    }
+
+    /// Spawns the task, which also delivers the messages to `messages`.
+    fn spawn(websocket: TeamsWebsocket, messages: Option<MessageSender>, capacity: usize) -> Self {
+        Self::spawn_with(websocket, messages, capacity, None)
+    }
+
+    /// Spawns the task, which drops `stopped` when it is done.
+    fn spawn_with(
+        websocket: TeamsWebsocket,
+        messages: Option<MessageSender>,
+        capacity: usize,
+        stopped: Option<oneshot::Sender<()>>,
+    ) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(capacity);
+        let (published, snapshot) = watch::channel(Snapshot::of(&websocket));
+        let status = websocket.watch_status();
+        let run = run_loop(websocket, receiver, events.clone(), published, messages);
+        let task = tokio::spawn(async move {
+            let websocket = run.await;
+            // Without an await in between the task is complete when the
+            // runtime thread notices.
+            drop(stopped);
+            websocket
+        });
+        Self {
+            handle: ClientHandle {
+                commands,
+                events,
+                snapshot,
+                status,
+                runtime: tokio::runtime::Handle::current(),
+            },
+            task,
+        }
+    }
+
+    pub fn handle(&self) -> ClientHandle {
+        self.handle.clone()
+    }
+
+    /// Returns a receiver for the events from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.handle.subscribe()
+    }
+
+    /// Returns the changes of the meeting state from now on, see
+    /// `ClientHandle::subscribe_state_changes`.
+    pub fn subscribe_state_changes(&self) -> BoxStream<'static, MeetingStateDelta> {
+        self.handle.subscribe_state_changes()
+    }
+
+    /// Returns a copy of every message Teams sends from now on, see
+    /// `ClientHandle::subscribe_messages`.
+    pub fn subscribe_messages(&self) -> BoxStream<'static, Result<ServerMessage, Lagged>> {
+        self.handle.subscribe_messages()
+    }
+
+    /// Waits until the client stopped and returns the websocket, e.g. to
+    /// inspect its `history`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the task panicked.
+    pub async fn join(self) -> Result<TeamsWebsocket, Box<dyn Error>> {
+        Ok(self.task.await?)
+    }
+
+    /// Stops the client gracefully and returns the websocket: the commands
+    /// sent before are still handled, then the connection is shut down, see
+    /// `TeamsWebsocket::shutdown`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the task panicked.
+    pub async fn shutdown(self) -> Result<TeamsWebsocket, Box<dyn Error>> {
+        self.handle.close();
+        self.join().await
+    }
+}
+
+/// Sends `TeamsReceiver` the messages, or the error the client stopped with.
+type MessageSender = mpsc::UnboundedSender<Result<ServerMessage, TeamsWsError>>;
+
+/// The half of a split `TeamsWebsocket` sending commands, see
+/// `TeamsWebsocket::split`.
+///
+/// It can be cloned to send from several tasks. As a `Sink`, flushing waits
+/// until the messages were sent and closing closes the connection.
+#[derive(Debug)]
+pub struct TeamsSender {
+    handle: ClientHandle,
+    /// The results of the messages started with `Sink::start_send`.
+    sent: VecDeque<oneshot::Receiver<Result<(), String>>>,
+}
+
+impl Clone for TeamsSender {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            sent: VecDeque::new(),
+        }
+    }
+}
+
+impl TeamsSender {
+    /// Sends `message` and waits until it was sent, see `ClientHandle::send`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `ClientHandle::send`.
+    pub async fn send(&self, message: ClientMessage) -> Result<(), Box<dyn Error>> {
+        self.handle.send(message).await
+    }
+
+    /// Sends `message` and waits for Teams' answer to it, see
+    /// `ClientHandle::send_and_wait`. The answer is also received by the
+    /// `TeamsReceiver`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `ClientHandle::send_and_wait`.
+    pub async fn send_and_wait(
+        &self,
+        message: ClientMessage,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        self.handle.send_and_wait(message).await
+    }
+
+    /// Returns a receiver for the events derived from the messages.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.handle.subscribe()
+    }
+
+    /// Returns the changes of the meeting state from now on, see
+    /// `ClientHandle::subscribe_state_changes`.
+    pub fn subscribe_state_changes(&self) -> BoxStream<'static, MeetingStateDelta> {
+        self.handle.subscribe_state_changes()
+    }
+
+    /// Closes the connection, after which the `TeamsReceiver` ends.
+    pub fn close(&self) {
+        self.handle.close()
+    }
+}
+
+/// The half of a split `TeamsWebsocket` receiving the messages Teams sends,
+/// see `TeamsWebsocket::split`.
+///
+/// Unlike subscribers of `ClientEvent`s it does not miss messages when it
+/// falls behind, they are kept until received.
+pub struct TeamsReceiver {
+    messages: mpsc::UnboundedReceiver<Result<ServerMessage, TeamsWsError>>,
+    task: JoinHandle<TeamsWebsocket>,
+}
+
+impl TeamsReceiver {
+    /// Receives the next message, skipping malformed frames and
+    /// re-establishing dropped connections like `receive_resilient`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the connection ended with, then an error that the
+    /// client stopped.
+    pub async fn receive(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        match self.messages.recv().await {
+            Some(message) => Ok(message?),
+            None => Err(Box::from("client stopped")),
+        }
+    }
+
+    /// Waits until the client stopped and returns the websocket, e.g. to
+    /// inspect its `history`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the task panicked.
+    pub async fn join(self) -> Result<TeamsWebsocket, Box<dyn Error>> {
+        Ok(self.task.await?)
+    }
+}
+
+impl Sink<ClientMessage> for TeamsSender {
+    type Error = TeamsWsError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.handle.commands.is_closed() {
+            return Poll::Ready(Err(TeamsWsError::Send("client stopped".to_string())));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: ClientMessage) -> Result<(), Self::Error> {
+        let (reply, result) = oneshot::channel();
+        self.handle
+            .commands
+            .send(Command::Send(message, reply))
+            .map_err(|_| TeamsWsError::Send("client stopped".to_string()))?;
+        self.sent.push_back(result);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        while let Some(result) = self.sent.front_mut() {
+            let result = ready!(Pin::new(result).poll(cx));
+            self.sent.pop_front();
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Poll::Ready(Err(TeamsWsError::Send(e))),
+                Err(_) => {
+                    return Poll::Ready(Err(TeamsWsError::Send("client stopped".to_string())))
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.handle.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Ends after the connection ended for good, which is reported as
+/// `TeamsWsError::ConnectionClosed` first, or after the client was closed.
+impl Stream for TeamsReceiver {
+    type Item = Result<ServerMessage, TeamsWsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+/// Spawns the task owning `websocket` and returns the halves talking to it.
+pub(crate) fn split(websocket: TeamsWebsocket) -> (TeamsSender, TeamsReceiver) {
+    let (sender, messages) = mpsc::unbounded_channel();
+    let client = TeamsClient::spawn(websocket, Some(sender), EVENT_CAPACITY);
+    let sender = TeamsSender {
+        handle: client.handle,
+        sent: VecDeque::new(),
+    };
+    (
+        sender,
+        TeamsReceiver {
+            messages,
+            task: client.task,
+        },
+    )
+}
+
+async fn run_loop(
+    mut websocket: TeamsWebsocket,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: broadcast::Sender<ClientEvent>,
+    snapshot: watch::Sender<Snapshot>,
+    messages: Option<MessageSender>,
+) -> TeamsWebsocket {
+    // Sending only fails without subscribers, which is not an error.
+    let emit = |event| {
+        let _ = events.send(event);
+    };
+    let deliver = |message| {
+        if let Some(messages) = &messages {
+            let _ = messages.send(message);
+        }
+    };
+    if websocket.status() != ConnectionStatus::Connected {
+        if let Err(e) = websocket.connect().await {
+            emit(ClientEvent::Error(e.to_string()));
+            deliver(Err(match e.downcast::<TeamsWsError>() {
+                Ok(e) => *e,
+                Err(e) => TeamsWsError::ConnectionClosed(DisconnectReport::from_error(e.as_ref())),
+            }));
+            return websocket;
+        }
+    }
+    snapshot.send_replace(Snapshot::of(&websocket));
+    let mut tracker = StateTracker::new();
+    // The answers awaited by `ClientHandle::send_and_wait`, by request id.
+    let mut waiting: Waiting = HashMap::new();
+    // Commands back from the confirmation hook, which runs in its own task
+    // so receiving and other commands go on while the user decides.
+    let (confirmations, mut confirmed) = mpsc::unbounded_channel::<(Command, bool)>();
+    emit(ClientEvent::Event(Event::Connected));
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(command @ (Command::Send(..) | Command::Request(..))) => {
+                    let hook = command
+                        .message()
+                        .zip(websocket.confirmation_hook())
+                        .filter(|(message, hook)| hook.requires_confirmation(message.action))
+                        .map(|(_, hook)| hook.clone());
+                    match hook {
+                        Some(hook) => {
+                            let confirmations = confirmations.clone();
+                            tokio::spawn(async move {
+                                let confirmed = match command.message() {
+                                    Some(message) => hook.confirm(message).await,
+                                    None => false,
+                                };
+                                let _ = confirmations.send((command, confirmed));
+                            });
+                        }
+                        None => dispatch(&mut websocket, command, &mut waiting, false).await,
+                    }
+                }
+                Some(Command::Forget) => {
+                    waiting.retain(|_, reply| !reply.is_closed());
+                }
+                Some(Command::Close) | None => {
+                    if let Err(e) = websocket.shutdown().await {
+                        warn!(target: logging::CONNECTION, "Error closing client: {}", e);
+                    }
+                    if let Some(report) = websocket.disconnect_report() {
+                        emit(ClientEvent::Event(Event::Disconnected(report.clone())));
+                    }
+                    return websocket;
+                }
+            },
+            Some((command, confirmed)) = confirmed.recv() => {
+                if confirmed {
+                    dispatch(&mut websocket, command, &mut waiting, true).await;
+                } else {
+                    command.refuse();
+                }
+            }
+            // Errors are not `Send`, only their message leaves the branch.
+            message = async { websocket.receive_resilient().await.map_err(|e| e.to_string()) } => match message {
+                Ok(message) => {
+                    if let Some(token) = &message.token_refresh {
+                        emit(ClientEvent::TokenRefreshed(token.clone()));
+                    }
+                    let derived = match &message.meeting_update {
+                        Some(update) => {
+                            snapshot.send_replace(Snapshot::of(&websocket));
+                            tracker.events(update)
+                        }
+                        None => Vec::new(),
+                    };
+                    let failed = message.error_kind().map(Event::TeamsError);
+                    if let Some(reply) = message.request_id.and_then(|id| waiting.remove(&id)) {
+                        let _ = reply.send(Ok(message.clone()));
+                    }
+                    deliver(Ok(message.clone()));
+                    emit(ClientEvent::Message(message));
+                    for event in derived.into_iter().chain(failed) {
+                        emit(ClientEvent::Event(event));
+                    }
+                }
+                Err(_) if websocket.shutdown_signal().is_triggered() => {
+                    if let Err(e) = websocket.shutdown().await {
+                        warn!(target: logging::CONNECTION, "Error closing client: {}", e);
+                    }
+                    if let Some(report) = websocket.disconnect_report() {
+                        emit(ClientEvent::Event(Event::Disconnected(report.clone())));
+                    }
+                    return websocket;
+                }
+                Err(e) => {
+                    let report = websocket.disconnect_report().cloned().unwrap_or_else(|| {
+                        DisconnectReport::new(DisconnectInitiator::Network, e.as_str(), None)
+                    });
+                    deliver(Err(TeamsWsError::ConnectionClosed(report.clone())));
+                    emit(ClientEvent::Event(Event::Disconnected(report)));
+                    emit(ClientEvent::Error(e));
+                    return websocket;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{MeetingAction, MeetingState, MeetingUpdate, TeamsErrorKind};
+    use crate::mock::MockTeamsServer;
+    use crate::reconnect::ReconnectPolicy;
+    use crate::recording::Direction;
+    use crate::types::AppIdentifiers;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[test]
+    fn test_teams_client() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // Answers every command with a meeting update that unmutes.
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws_stream.next().await {
+                    let request: ClientMessage = serde_json::from_str(&text).unwrap();
+                    let mut state = MeetingState::new();
+                    state.is_in_meeting = true;
+                    let update = ServerMessage {
+                        request_id: request.request_id,
+                        response: None,
+                        error_msg: None,
+                        token_refresh: None,
+                        meeting_update: Some(MeetingUpdate {
+                            meeting_permissions: None,
+                            meeting_state: Some(state),
+                        }),
+                        extra: serde_json::Map::new(),
+                    };
+                    let update = serde_json::to_string(&update).unwrap();
+                    ws_stream.send(Message::Text(update)).await.unwrap();
+                }
+            });
+            let websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            let mut events = client.subscribe();
+            let handle = client.handle();
+
+            let answer = client
+                .handle()
+                .send_and_wait(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            assert_eq!(answer.request_id, Some(0));
+
+            // Commands are sent from another task while the client listens.
+            tokio::spawn(async move {
+                handle
+                    .send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+                    .await
+                    .unwrap();
+            })
+            .await
+            .unwrap();
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                match events.recv().await.unwrap() {
+                    ClientEvent::Event(Event::Connected) => {}
+                    event => received.push(event),
+                }
+            }
+            assert!(
+                matches!(&received[0], ClientEvent::Message(message) if message.request_id == Some(0))
+            );
+            assert_eq!(
+                received[1],
+                ClientEvent::Event(Event::StateChanged(MeetingStateDelta::InMeeting(true)))
+            );
+
+            client.handle().close();
+            let websocket = client.join().await.unwrap();
+            assert!(websocket.disconnect_report().is_some());
+        });
+    }
+
+    #[test]
+    fn test_client_handle_shared() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<ClientHandle>();
+
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::builder()
+                .meeting_updates([MeetingUpdate {
+                    meeting_permissions: None,
+                    meeting_state: Some(MeetingState {
+                        is_in_meeting: true,
+                        ..MeetingState::new()
+                    }),
+                }])
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            let tasks: Vec<_> = [MeetingAction::Mute, MeetingAction::RaiseHand]
+                .into_iter()
+                .map(|action| {
+                    let handle = client.handle();
+                    tokio::spawn(async move {
+                        handle
+                            .send_and_wait(ClientMessage::new(action, None))
+                            .await
+                            .unwrap()
+                    })
+                })
+                .collect();
+            for task in tasks {
+                assert_eq!(task.await.unwrap().response.as_deref(), Some("Success"));
+            }
+            let handle = client.handle();
+            while handle.is_in_meeting().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(handle.is_in_meeting(), Some(true));
+            assert!(handle.permissions().is_none());
+            client.shutdown().await.unwrap();
+            assert_eq!(server.received().len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_client_message_observer() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::start().await.unwrap();
+            let frames = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let websocket = {
+                let frames = frames.clone();
+                TeamsWebsocket::builder(AppIdentifiers::default())
+                    .ignore_environment()
+                    .url(server.url())
+                    .message_observer(move |direction, text| {
+                        frames.lock().unwrap().push((direction, text.to_string()))
+                    })
+                    .build()
+                    .unwrap()
+            };
+            let client = TeamsClient::run(websocket);
+            let answer = client
+                .handle()
+                .send_and_wait(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            client.shutdown().await.unwrap();
+            let frames = frames.lock().unwrap();
+            assert!(frames.iter().any(|(direction, text)| {
+                *direction == Direction::Sent && text.contains(r#""action":"mute""#)
+            }));
+            assert!(frames.iter().any(|(direction, text)| {
+                *direction == Direction::Received
+                    && serde_json::from_str::<ServerMessage>(text)
+                        .is_ok_and(|message| message == answer)
+            }));
+        });
+    }
+
+    #[test]
+    fn test_client_handle_wait_for() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::start().await.unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            let handle = client.handle();
+            let timeout = Duration::from_millis(50);
+            let error = handle.wait_until_in_meeting(timeout).await.unwrap_err();
+            assert!(error.is::<tokio::time::error::Elapsed>());
+
+            let waiting = tokio::spawn({
+                let handle = client.handle();
+                async move {
+                    let muted = handle.wait_until_muted(Duration::from_secs(5)).await;
+                    muted.map_err(|e| e.to_string())
+                }
+            });
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true).with_muted(true)),
+            });
+            assert!(waiting.await.unwrap().unwrap().is_in_meeting);
+            // Satisfied by the cached state without waiting.
+            handle.wait_until_in_meeting(timeout).await.unwrap();
+            assert_eq!(handle.status(), ConnectionStatus::Connected);
+
+            client.shutdown().await.unwrap();
+            assert_eq!(handle.status(), ConnectionStatus::Closed);
+            let error = handle.wait_until_unmuted(timeout).await.unwrap_err();
+            assert_eq!(error.to_string(), "client stopped");
+        });
+    }
+
+    #[test]
+    fn test_client_handle_request() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::builder()
+                .reject(MeetingAction::LeaveCall, "No active call")
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            let handle = client.handle();
+            let mut events = client.subscribe();
+            let answer = handle
+                .request(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            assert_eq!(answer.response.as_deref(), Some("Success"));
+            let error = handle
+                .request(ClientMessage::new(MeetingAction::LeaveCall, None))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<TeamsErrorKind>(),
+                Some(&TeamsErrorKind::NoActiveCall)
+            );
+            let failed = ClientEvent::Event(Event::TeamsError(TeamsErrorKind::NoActiveCall));
+            while events.recv().await.unwrap() != failed {}
+            client.shutdown().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_client_confirmation_does_not_block() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            // The user answers the confirmation dialog later.
+            let (answer, dialog) = oneshot::channel::<bool>();
+            let dialog = std::sync::Mutex::new(Some(dialog));
+            let asked = std::sync::Arc::new(tokio::sync::Notify::new());
+            let notify = asked.clone();
+            websocket.set_confirmation_hook(Some(crate::confirm::ConfirmationHook::new(
+                move |_| {
+                    notify.notify_one();
+                    let dialog = dialog.lock().unwrap().take();
+                    Box::pin(async move {
+                        match dialog {
+                            Some(dialog) => dialog.await.unwrap_or(false),
+                            None => false,
+                        }
+                    })
+                },
+            )));
+            let client = TeamsClient::run(websocket);
+            let handle = client.handle();
+            let leave = {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    handle
+                        .request(ClientMessage::new(MeetingAction::LeaveCall, None))
+                        .await
+                        .map_err(|e| e.to_string())
+                })
+            };
+            asked.notified().await;
+            handle
+                .request(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            answer.send(true).unwrap();
+            leave.await.unwrap().unwrap();
+            server.assert_actions(&[MeetingAction::Mute, MeetingAction::LeaveCall]);
+
+            let error = handle
+                .send(ClientMessage::new(MeetingAction::LeaveCall, None))
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains("not confirmed"));
+            client.shutdown().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_subscribe_state_changes() {
+        // The client task only runs once the test awaits, after subscribing.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let in_meeting = MeetingState {
+                is_in_meeting: true,
+                ..MeetingState::new()
+            };
+            let server = MockTeamsServer::builder()
+                .meeting_updates([MeetingUpdate {
+                    meeting_permissions: None,
+                    meeting_state: Some(in_meeting.clone()),
+                }])
+                .start()
+                .await
+                .unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .reconnect(ReconnectPolicy {
+                    initial_delay: Duration::from_millis(10),
+                    ..ReconnectPolicy::default()
+                })
+                .build()
+                .unwrap();
+            let client = TeamsClient::run(websocket);
+            let mut changes = client.subscribe_state_changes();
+            assert_eq!(
+                changes.next().await,
+                Some(MeetingStateDelta::InMeeting(true))
+            );
+
+            // The update sent after reconnecting changes nothing.
+            server.disconnect();
+            while server.tokens().len() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState {
+                    is_muted: true,
+                    ..in_meeting
+                }),
+            });
+            assert_eq!(changes.next().await, Some(MeetingStateDelta::Muted(true)));
+
+            client.handle().close();
+            assert_eq!(changes.next().await, None);
+        });
+    }
+
+    #[test]
+    fn test_subscribe_messages() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::start().await.unwrap();
+            let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let client = TeamsClient::run_with_capacity(websocket, 8);
+            let mut status = client.handle().watch_status();
+            status
+                .wait_for(|status| *status == ConnectionStatus::Connected)
+                .await
+                .unwrap();
+            let mut traffic = client.subscribe_messages();
+            let mut lagging = client.subscribe_messages();
+            let update = |muted| MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true).with_muted(muted)),
+            };
+            // Each update also yields state change events.
+            for muted in [true, false, true, false, true, false] {
+                server.send_update(update(muted));
+                let message = traffic.next().await.unwrap().unwrap();
+                assert_eq!(message.meeting_update, Some(update(muted)));
+            }
+            assert!(matches!(lagging.next().await, Some(Err(Lagged(_)))));
+            let mut last = None;
+            client.handle().close();
+            while let Some(message) = lagging.next().await {
+                last = Some(message.unwrap());
+            }
+            assert_eq!(last.unwrap().meeting_update, Some(update(false)));
+            assert_eq!(traffic.next().await, None);
+        });
+    }
+
+    /// Runs `future` to completion without a tokio runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(std::thread::Thread);
+
+        impl std::task::Wake for Unpark {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::sync::Arc::new(Unpark(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_on_thread() {
+        let rt = Runtime::new().unwrap();
+        let server = rt.block_on(MockTeamsServer::start()).unwrap();
+        let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+            .ignore_environment()
+            .url(server.url())
+            .build()
+            .unwrap();
+        let client = TeamsClient::run_on_thread(websocket).unwrap();
+        let handle = client.handle();
+        block_on(async {
+            handle
+                .send(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            let timeout = Duration::from_millis(20);
+            assert!(handle.wait_until_in_meeting(timeout).await.is_err());
+            let websocket = client.shutdown().await.unwrap();
+            assert_eq!(websocket.status(), ConnectionStatus::Closed);
+        });
+        server.assert_actions(&[MeetingAction::Mute]);
+    }
+
+    #[test]
+    fn test_split() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // Answers every command.
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws_stream.next().await {
+                    let request: ClientMessage = serde_json::from_str(&text).unwrap();
+                    let answer = ServerMessage {
+                        request_id: request.request_id,
+                        response: Some("Success".to_string()),
+                        error_msg: None,
+                        token_refresh: None,
+                        meeting_update: None,
+                        extra: serde_json::Map::new(),
+                    };
+                    let answer = serde_json::to_string(&answer).unwrap();
+                    ws_stream.send(Message::Text(answer)).await.unwrap();
+                }
+            });
+            let websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            let (sender, mut receiver) = websocket.split();
+            let commands = sender.clone();
+            tokio::spawn(async move {
+                for action in [MeetingAction::Mute, MeetingAction::Unmute] {
+                    commands
+                        .send(ClientMessage::new(action, None))
+                        .await
+                        .unwrap();
+                }
+            });
+            for id in 0..2 {
+                let message = receiver.receive().await.unwrap();
+                assert_eq!(message.request_id, Some(id));
+            }
+
+            // The halves compose as `Sink` and `Stream`.
+            let mut sink = sender.clone();
+            for action in [MeetingAction::HideVideo, MeetingAction::ShowVideo] {
+                sink.feed(ClientMessage::new(action, None)).await.unwrap();
+            }
+            sink.flush().await.unwrap();
+            let ids: Vec<_> = (&mut receiver)
+                .take(2)
+                .map(|message| message.unwrap().request_id)
+                .collect()
+                .await;
+            assert_eq!(ids, [Some(2), Some(3)]);
+
+            sender.close();
+            assert!(receiver.receive().await.is_err());
+            let websocket = receiver.join().await.unwrap();
+            assert!(websocket.disconnect_report().is_some());
+        });
+    }
+}
@@ -0,0 +1,183 @@
+use crate::messages::{
+    ClientMessage, ClientMessageParameter, ClientMessageParameterType, MeetingAction, Reaction,
+    UiPanel,
+};
+use crate::TeamsWebsocket;
+use std::error::Error;
+
+/// Builds the `ClientMessage`s of a sequence of `Command`s, e.g. a scene
+/// sent by `TeamsWebsocket::send_all` when switching from a break to being
+/// on camera.
+///
+/// # Example
+/// ```rust
+/// use ms_teams_ws::messages::Command;
+///
+/// let on_air = ms_teams_ws::scene![Command::Unmute, Command::ShowVideo, Command::UnblurBackground];
+/// websocket.send_all_and_wait(on_air).await?;
+/// ```
+#[macro_export]
+macro_rules! scene {
+    ($($command:expr),* $(,)?) => {
+        ::std::vec![$($crate::messages::ClientMessage::from($command)),*]
+    };
+}
+
+/// Shorthands for sending every `MeetingAction`.
+///
+/// Each returns the request id assigned to the message, to match it with
+/// Teams' answer, or `None` if the message was queued while not connected.
+/// They fail like `TeamsWebsocket::send`.
+///
+/// # Example
+/// ```rust
+/// websocket.toggle_mute().await?;
+/// let id = websocket.send_reaction(Reaction::Like).await?;
+/// ```
+impl TeamsWebsocket {
+    async fn request(
+        &mut self,
+        action: MeetingAction,
+        parameter: Option<ClientMessageParameterType>,
+    ) -> Result<Option<u32>, Box<dyn Error>> {
+        let connected = self.link.is_connected();
+        let parameters = parameter.map(ClientMessageParameter::new);
+        let id = self.send(ClientMessage::new(action, parameters)).await?;
+        Ok(connected.then_some(id))
+    }
+
+    pub async fn query_state(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::QueryMeetingState, None).await
+    }
+
+    pub async fn mute(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::Mute, None).await
+    }
+
+    pub async fn unmute(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::Unmute, None).await
+    }
+
+    pub async fn toggle_mute(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ToggleMute, None).await
+    }
+
+    pub async fn hide_video(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::HideVideo, None).await
+    }
+
+    pub async fn show_video(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ShowVideo, None).await
+    }
+
+    pub async fn toggle_video(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ToggleVideo, None).await
+    }
+
+    pub async fn blur_background(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::BlurBackground, None).await
+    }
+
+    pub async fn unblur_background(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::UnblurBackground, None).await
+    }
+
+    pub async fn toggle_background_blur(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ToggleBlurBackground, None)
+            .await
+    }
+
+    pub async fn raise_hand(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::RaiseHand, None).await
+    }
+
+    pub async fn lower_hand(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::LowerHand, None).await
+    }
+
+    pub async fn toggle_hand(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ToggleHand, None).await
+    }
+
+    pub async fn leave_call(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::LeaveCall, None).await
+    }
+
+    pub async fn stop_sharing(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::StopSharing, None).await
+    }
+
+    pub async fn send_reaction(
+        &mut self,
+        reaction: Reaction,
+    ) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::React, Some(reaction.into()))
+            .await
+    }
+
+    pub async fn toggle_ui(&mut self, panel: UiPanel) -> Result<Option<u32>, Box<dyn Error>> {
+        self.request(MeetingAction::ToggleUI, Some(panel.into()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppIdentifiers;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[test]
+    fn test_commands() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            let server = tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                let mut received = Vec::new();
+                while let Some(Ok(Message::Text(text))) = ws_stream.next().await {
+                    received.push(text);
+                }
+                let _ = ws_stream.close(None).await;
+                received
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            assert!(websocket.toggle_mute().await.is_err());
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.toggle_mute().await.unwrap(), Some(0));
+            assert_eq!(
+                websocket.send_reaction(Reaction::Like).await.unwrap(),
+                Some(1)
+            );
+            assert_eq!(websocket.toggle_ui(UiPanel::Chat).await.unwrap(), Some(2));
+            websocket.close().await.unwrap();
+
+            let received = server.await.unwrap();
+            assert_eq!(
+                received,
+                vec![
+                    r#"{"action":"toggle-mute","parameters":null,"requestId":0}"#,
+                    r#"{"action":"send-reaction","parameters":{"type":"like"},"requestId":1}"#,
+                    r#"{"action":"toggle-ui","parameters":{"type":"chat"},"requestId":2}"#,
+                ]
+            );
+        });
+    }
+}
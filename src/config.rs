@@ -0,0 +1,207 @@
+use crate::logging;
+use crate::token::TokenStore;
+use crate::types::AppIdentifiers;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// The name of the directory in the user config directory.
+pub const DIRECTORY: &str = "ms-teams-ws";
+
+/// The name of the config file in `DIRECTORY`.
+pub const FILE_NAME: &str = "config.json";
+
+/// The per-user settings shared by every program using this crate, so a
+/// token paired once is reused by all of them.
+///
+/// Stored as JSON in `default_path()`, a superset of the config files read
+/// by `TeamsWebsocketBuilder::config_file`:
+///
+/// ```json
+/// {"url": "ws://127.0.0.1:8124", "token": "...", "identifiers": {"app": "teams-ctl", ...}}
+/// ```
+///
+/// `TeamsWebsocketBuilder::user_config` reads the url and token from it and
+/// stores refreshed tokens back. `Debug` masks the token.
+///
+/// # Fields
+///
+/// * `url` - The URL of the Teams local API.
+/// * `token` - The token from the last pairing.
+/// * `identifiers` - The identifiers the token was paired with.
+///
+/// # Example
+/// ```rust
+/// let config = Config::load_or_default();
+/// let identifier = config.identifiers.unwrap_or_default();
+/// let websocket = TeamsWebsocket::builder(identifier).user_config().build()?;
+/// ```
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifiers: Option<AppIdentifiers>,
+}
+
+impl Config {
+    /// Returns `ms-teams-ws/config.json` in the config directory of the
+    /// user: `%APPDATA%` on Windows, `~/Library/Application Support` on macOS
+    /// and `$XDG_CONFIG_HOME` or `~/.config` elsewhere. `None` if the
+    /// variables naming it are not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let home = || std::env::var_os("HOME").map(PathBuf::from);
+        let config_dir = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            home().map(|home| home.join("Library").join("Application Support"))
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .or_else(|| home().map(|home| home.join(".config")))
+        };
+        config_dir.map(|dir| dir.join(DIRECTORY).join(FILE_NAME))
+    }
+
+    /// Loads the config at `path`, or the default one if there is no file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid config.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        match std::fs::read_to_string(path.as_ref()) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Loads the config at `default_path()`, falling back to the default
+    /// one with a warning if it cannot be loaded.
+    pub fn load_or_default() -> Self {
+        let Some(path) = Self::default_path() else {
+            return Self::default();
+        };
+        Self::load(&path).unwrap_or_else(|e| {
+            warn!(target: logging::CONFIG, "Ignoring config {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Writes the config to `path`, readable only by the user, creating
+    /// the directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or file cannot be written.
+    pub fn store(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        std::io::Write::write_all(&mut file, serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_data()?;
+        debug!(target: logging::CONFIG, "Stored config in {}", path.display());
+        Ok(())
+    }
+
+    /// Writes the config to `default_path()`, see `store`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no config directory or writing fails.
+    pub fn store_default(&self) -> Result<(), Box<dyn Error>> {
+        self.store(Self::default_path().ok_or("no user config directory")?)
+    }
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("url", &self.url)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("identifiers", &self.identifiers)
+            .finish()
+    }
+}
+
+/// A `TokenStore` keeping the token in the `token` of a `Config` file,
+/// leaving its other settings as they are.
+#[derive(Clone, Debug)]
+pub struct ConfigTokenStore {
+    path: PathBuf,
+}
+
+impl ConfigTokenStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TokenStore for ConfigTokenStore {
+    fn load(&self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(Config::load(&self.path)?.token)
+    }
+
+    fn store(&mut self, token: &str) -> Result<(), Box<dyn Error>> {
+        let mut config = Config::load(&self.path)?;
+        config.token = Some(token.to_string());
+        config.store(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingKey;
+    use crate::TeamsWebsocket;
+
+    #[test]
+    fn test_config() {
+        let path = std::env::temp_dir()
+            .join(format!("teams-ws-config-{}", std::process::id()))
+            .join(FILE_NAME);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        let config = Config {
+            url: Some("ws://127.0.0.1:1".to_string()),
+            token: None,
+            identifiers: Some(AppIdentifiers::builder().app("teams-ctl").build()),
+        };
+        config.store(&path).unwrap();
+        let mut store = ConfigTokenStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+        store.store("paired").unwrap();
+        let stored = Config::load(&path).unwrap();
+        assert_eq!(stored.token.as_deref(), Some("paired"));
+        assert_eq!(stored.identifiers, config.identifiers);
+        assert!(!format!("{:?}", stored).contains("paired"));
+
+        let websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+            .ignore_environment()
+            .config_file(&path)
+            .build()
+            .unwrap();
+        assert_eq!(
+            websocket.settings().get(SettingKey::Url),
+            Some("ws://127.0.0.1:1")
+        );
+        assert_eq!(websocket.settings().get(SettingKey::Token), Some("paired"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
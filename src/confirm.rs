@@ -0,0 +1,114 @@
+use crate::messages::{ClientMessage, MeetingAction};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Actions that require confirmation unless configured otherwise.
+pub const DANGEROUS_ACTIONS: [MeetingAction; 2] =
+    [MeetingAction::LeaveCall, MeetingAction::StopSharing];
+
+/// The future returned by a confirmation callback, resolving to `true` if the
+/// action may be sent.
+pub type ConfirmationFuture = Pin<Box<dyn Future<Output = bool> + Send>>;
+
+type Callback = dyn Fn(&ClientMessage) -> ConfirmationFuture + Send + Sync;
+
+/// A callback consulted before dangerous actions are sent.
+///
+/// The callback may ask the user through the host application's UI, as it
+/// returns a future. Sending an action that is not confirmed fails with
+/// `TeamsWsError::NotConfirmed`.
+///
+/// `TeamsWebsocket::send` awaits the callback, so nothing is received or
+/// sent on that websocket until it resolves. A `TeamsClient` runs it in a
+/// task of its own and keeps receiving and sending other commands, which
+/// may therefore overtake the action waiting for confirmation.
+///
+/// # Example
+/// ```rust
+/// // Require a second press within two seconds before leaving the call.
+/// websocket.set_confirmation_hook(Some(ConfirmationHook::repeat_within(Duration::from_secs(2))));
+/// ```
+#[derive(Clone)]
+pub struct ConfirmationHook {
+    actions: HashSet<MeetingAction>,
+    callback: Arc<Callback>,
+}
+
+impl ConfirmationHook {
+    /// Creates a hook consulted for the `DANGEROUS_ACTIONS`.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&ClientMessage) -> ConfirmationFuture + Send + Sync + 'static,
+    {
+        Self {
+            actions: DANGEROUS_ACTIONS.into_iter().collect(),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Creates a hook that confirms an action only if the same action was
+    /// requested before within `window`, so it takes two presses to send it.
+    pub fn repeat_within(window: Duration) -> Self {
+        let last_requests: Mutex<HashMap<MeetingAction, Instant>> = Mutex::new(HashMap::new());
+        Self::new(move |message| {
+            let now = Instant::now();
+            let mut last_requests = last_requests.lock().unwrap();
+            let confirmed = match last_requests.remove(&message.action) {
+                Some(previous) => now.duration_since(previous) <= window,
+                None => false,
+            };
+            if !confirmed {
+                last_requests.insert(message.action, now);
+            }
+            Box::pin(std::future::ready(confirmed))
+        })
+    }
+
+    /// Replaces the set of actions requiring confirmation.
+    pub fn actions(mut self, actions: impl IntoIterator<Item = MeetingAction>) -> Self {
+        self.actions = actions.into_iter().collect();
+        self
+    }
+
+    /// Returns whether `action` requires confirmation.
+    pub fn requires_confirmation(&self, action: MeetingAction) -> bool {
+        self.actions.contains(&action)
+    }
+
+    /// Returns whether `message` may be sent.
+    pub(crate) async fn confirm(&self, message: &ClientMessage) -> bool {
+        if !self.requires_confirmation(message.action) {
+            return true;
+        }
+        (self.callback)(message).await
+    }
+}
+
+impl std::fmt::Debug for ConfirmationHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConfirmationHook {{ actions: {:?} }}", self.actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_repeat_within() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let hook = ConfirmationHook::repeat_within(Duration::from_secs(60));
+            let leave = ClientMessage::new(MeetingAction::LeaveCall, None);
+            let mute = ClientMessage::new(MeetingAction::Mute, None);
+            assert!(hook.confirm(&mute).await);
+            assert!(!hook.confirm(&leave).await);
+            assert!(hook.confirm(&leave).await);
+            assert!(!hook.confirm(&leave).await);
+        });
+    }
+}
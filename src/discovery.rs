@@ -0,0 +1,102 @@
+use crate::logging;
+use crate::types::AppIdentifiers;
+use crate::TeamsWebsocket;
+use std::error::Error;
+use std::time::Duration;
+
+/// Ports the Teams local API listens on: the default 8124, and the ports
+/// after it, which Teams moves to when another process holds the default.
+pub const CANDIDATE_PORTS: [u16; 3] = [8124, 8125, 8126];
+
+/// How long `discover` waits for each port to complete the handshake.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Finds the URL of the Teams local API on this machine, see `discover_on`.
+///
+/// # Errors
+///
+/// Returns an error if none of the `CANDIDATE_PORTS` answers.
+///
+/// # Example
+/// ```rust
+/// let url = discover().await?;
+/// let websocket = TeamsWebsocket::builder(identifier).url(url).build()?;
+/// ```
+pub async fn discover() -> Result<String, Box<dyn Error>> {
+    discover_on("127.0.0.1", &CANDIDATE_PORTS, PROBE_TIMEOUT).await
+}
+
+/// Returns the URL of the first of `ports` on `host` that accepts a
+/// WebSocket handshake with the protocol version of classic or new Teams.
+///
+/// The probes connect without a token and close again without sending
+/// commands, so Teams does not ask to pair.
+///
+/// # Errors
+///
+/// Returns an error if no port answers within `timeout`.
+pub async fn discover_on(
+    host: &str,
+    ports: &[u16],
+    timeout: Duration,
+) -> Result<String, Box<dyn Error>> {
+    for port in ports {
+        let url = if host.contains(':') {
+            format!("ws://[{}]:{}", host, port)
+        } else {
+            format!("ws://{}:{}", host, port)
+        };
+        let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+            .ignore_environment()
+            .url(url.as_str())
+            .detect_flavor(true)
+            .connect_timeout(timeout)
+            .build()?;
+        match websocket.connect().await {
+            Ok(()) => {
+                info!(target: logging::CONNECTION, "Found the Teams local API at {}", url);
+                let _ = websocket.close().await;
+                return Ok(url);
+            }
+            Err(e) => debug!(target: logging::CONNECTION, "No Teams local API at {}: {}", url, e),
+        }
+    }
+    Err(Box::from(format!(
+        "no Teams local API found on {} ports {:?}, enable \"Manage API\" in the Teams privacy settings",
+        host, ports
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+    use tokio_tungstenite::accept_async;
+
+    #[test]
+    fn test_discover_on() {
+        Runtime::new().unwrap().block_on(async {
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let closed_port = closed.local_addr().unwrap().port();
+            drop(closed);
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while ws_stream.next().await.is_some() {}
+            });
+
+            let timeout = Duration::from_secs(1);
+            let url = discover_on("127.0.0.1", &[closed_port, port], timeout)
+                .await
+                .unwrap();
+            assert_eq!(url, format!("ws://127.0.0.1:{}", port));
+            assert!(discover_on("127.0.0.1", &[closed_port], timeout)
+                .await
+                .is_err());
+        });
+    }
+}
@@ -0,0 +1,425 @@
+use crate::exit::ExitStatus;
+use crate::messages::{ClientMessage, MeetingAction, TeamsErrorKind};
+use crate::settings::SettingKey;
+use crate::{options, settings, TeamsWebsocket};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+pub use crate::discovery::CANDIDATE_PORTS;
+
+/// The outcome of a single check.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+    Skipped,
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "ok"),
+            CheckStatus::Warning => write!(f, "warn"),
+            CheckStatus::Failed => write!(f, "FAIL"),
+            CheckStatus::Skipped => write!(f, "skip"),
+        }
+    }
+}
+
+/// A single check of a `Diagnosis`.
+///
+/// # Fields
+///
+/// * `name` - What was checked.
+/// * `status` - The outcome.
+/// * `detail` - What was found and, for problems, what to do about it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// The result of `Doctor::run`.
+///
+/// # Fields
+///
+/// * `checks` - The checks in the order they ran.
+/// * `token` - A token received while pairing, to be stored in the settings.
+/// * `exit_status` - The `ExitStatus` of the first failed check.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Diagnosis {
+    pub checks: Vec<Check>,
+    pub token: Option<String>,
+    pub exit_status: ExitStatus,
+}
+
+impl Default for Diagnosis {
+    fn default() -> Self {
+        Self {
+            checks: Vec::new(),
+            token: None,
+            exit_status: ExitStatus::Ok,
+        }
+    }
+}
+
+impl Diagnosis {
+    fn add(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    fn fail(&mut self, name: &'static str, detail: impl Into<String>, exit_status: ExitStatus) {
+        self.add(name, CheckStatus::Failed, detail);
+        if self.exit_status == ExitStatus::Ok {
+            self.exit_status = exit_status;
+        }
+    }
+
+    /// Returns whether no check failed.
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed)
+    }
+}
+
+impl std::fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            writeln!(
+                f,
+                "[{:>4}] {}: {}",
+                check.status.to_string(),
+                check.name,
+                check.detail
+            )?;
+        }
+        if let Some(token) = &self.token {
+            writeln!(
+                f,
+                "New token received, store it as {}: {}",
+                SettingKey::Token.env_var(),
+                token
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnoses why a `TeamsWebsocket` does not connect.
+///
+/// Checks whether Teams is running, probes the configured and the candidate
+/// ports, connects, validates the token and, if enabled, waits for Teams to
+/// pair and hand out a new token.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier).build()?;
+/// let diagnosis = Doctor::new(websocket).pair(true).run().await;
+/// print!("{}", diagnosis);
+/// ```
+pub struct Doctor {
+    websocket: TeamsWebsocket,
+    timeout: Duration,
+    pair: bool,
+    pair_timeout: Duration,
+}
+
+impl Doctor {
+    pub fn new(websocket: TeamsWebsocket) -> Self {
+        Self {
+            websocket,
+            timeout: Duration::from_secs(3),
+            pair: false,
+            pair_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets how long to wait for connections and answers.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits for Teams to pair if there is no valid token.
+    pub fn pair(mut self, pair: bool) -> Self {
+        self.pair = pair;
+        self
+    }
+
+    /// Sets how long to wait for the user to allow pairing in Teams.
+    pub fn pair_timeout(mut self, pair_timeout: Duration) -> Self {
+        self.pair_timeout = pair_timeout;
+        self
+    }
+
+    /// Runs all checks.
+    pub async fn run(mut self) -> Diagnosis {
+        let mut diagnosis = Diagnosis::default();
+        let settings = self.websocket.settings();
+        let url = self.websocket.url.clone();
+        let source = |key| {
+            settings
+                .source(key)
+                .map(|source| source.to_string())
+                .unwrap_or_else(|| "not set".to_string())
+        };
+        diagnosis.add(
+            "settings",
+            CheckStatus::Ok,
+            format!(
+                "url {} ({}), token {}",
+                crate::redact::redact_url(&url),
+                source(SettingKey::Url),
+                source(SettingKey::Token)
+            ),
+        );
+
+        match teams_running() {
+            Some(true) => diagnosis.add("teams", CheckStatus::Ok, "Teams is running"),
+            Some(false) => diagnosis.fail(
+                "teams",
+                "Teams is not running, start Teams",
+                ExitStatus::NotConnected,
+            ),
+            None => diagnosis.add(
+                "teams",
+                CheckStatus::Skipped,
+                "cannot list processes on this system",
+            ),
+        }
+
+        let host = options::url_host(&url).unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = options::url_port(&url);
+        let mut open = Vec::new();
+        for candidate in port.iter().chain(CANDIDATE_PORTS.iter()) {
+            if open.contains(candidate) {
+                continue;
+            }
+            let address = (host.as_str(), *candidate);
+            if let Ok(Ok(_)) = timeout(self.timeout, TcpStream::connect(address)).await {
+                open.push(*candidate);
+            }
+        }
+        match (port, open.first()) {
+            (Some(port), Some(_)) if open.contains(&port) => {
+                diagnosis.add("port", CheckStatus::Ok, format!("{} is open", port))
+            }
+            (_, Some(other)) => diagnosis.fail(
+                "port",
+                format!(
+                    "the configured port is closed but {} is open, set {} to ws://{}:{}",
+                    other,
+                    SettingKey::Url.env_var(),
+                    host,
+                    other
+                ),
+                ExitStatus::NotConnected,
+            ),
+            (_, None) => diagnosis.fail(
+                "port",
+                format!(
+                    "no port open on {}, enable \"Manage API\" in the Teams privacy settings (default {})",
+                    host,
+                    settings::DEFAULT_URL
+                ),
+                ExitStatus::NotConnected,
+            ),
+        }
+
+        self.websocket.options.detect_flavor = true;
+        match timeout(self.timeout, self.websocket.connect()).await {
+            Ok(Ok(())) => {
+                let detail = match self.websocket.flavor() {
+                    Some(flavor) => format!("connected to {}", flavor),
+                    None => "connected".to_string(),
+                };
+                diagnosis.add("connection", CheckStatus::Ok, detail)
+            }
+            Ok(Err(e)) => {
+                let exit_status = ExitStatus::from_error(e.as_ref());
+                diagnosis.fail("connection", e.to_string(), exit_status);
+                diagnosis.add("token", CheckStatus::Skipped, "not connected");
+                return diagnosis;
+            }
+            Err(_) => {
+                diagnosis.fail("connection", "timed out", ExitStatus::Timeout);
+                diagnosis.add("token", CheckStatus::Skipped, "not connected");
+                return diagnosis;
+            }
+        }
+
+        let token_valid = self.check_token(&mut diagnosis).await;
+        if !token_valid && self.pair {
+            self.wait_for_pairing(&mut diagnosis).await;
+        }
+        let _ = self.websocket.close().await;
+        diagnosis
+    }
+
+    /// Queries the meeting state and returns whether Teams accepted the token.
+    async fn check_token(&mut self, diagnosis: &mut Diagnosis) -> bool {
+        if self.websocket.token.is_none() {
+            diagnosis.add(
+                "token",
+                CheckStatus::Warning,
+                "no token configured, pairing is required",
+            );
+            return false;
+        }
+        let query = ClientMessage::new(MeetingAction::QueryMeetingState, None);
+        if let Err(e) = self.websocket.send(query).await {
+            diagnosis.fail("token", e.to_string(), ExitStatus::from_error(e.as_ref()));
+            return false;
+        }
+        match timeout(self.timeout, self.websocket.receive()).await {
+            Ok(Ok(message)) => match message.error_kind() {
+                // Teams only complains about the meeting if it accepted the token.
+                Some(TeamsErrorKind::NoActiveCall) => {
+                    diagnosis.add("token", CheckStatus::Ok, "Teams answered, no active call");
+                    true
+                }
+                Some(_) => {
+                    let error = message.error_msg.unwrap_or_default();
+                    diagnosis.fail("token", error, ExitStatus::NotPermitted);
+                    false
+                }
+                None => {
+                    diagnosis.add("token", CheckStatus::Ok, "Teams answered");
+                    true
+                }
+            },
+            Ok(Err(e)) => {
+                diagnosis.fail("token", e.to_string(), ExitStatus::from_error(e.as_ref()));
+                false
+            }
+            Err(_) => {
+                diagnosis.add(
+                    "token",
+                    CheckStatus::Warning,
+                    "no answer, Teams may reject the token or not be in a meeting",
+                );
+                false
+            }
+        }
+    }
+
+    /// Waits for Teams to send a token after the user allowed pairing.
+    async fn wait_for_pairing(&mut self, diagnosis: &mut Diagnosis) {
+        let query = ClientMessage::new(MeetingAction::QueryMeetingState, None);
+        if self.websocket.send(query).await.is_err() {
+            diagnosis.fail("pairing", "not connected", ExitStatus::NotConnected);
+            return;
+        }
+        let websocket = &mut self.websocket;
+        let wait = async {
+            loop {
+                match websocket.receive().await {
+                    Ok(message) if message.token_refresh.is_some() => {
+                        return message.token_refresh;
+                    }
+                    Ok(_) => continue,
+                    Err(_) => return None,
+                }
+            }
+        };
+        match timeout(self.pair_timeout, wait).await {
+            Ok(Some(token)) => {
+                diagnosis.add("pairing", CheckStatus::Ok, "paired");
+                diagnosis.token = Some(token);
+            }
+            _ => diagnosis.fail(
+                "pairing",
+                "no token received, join a meeting and allow the app in Teams",
+                ExitStatus::NotPermitted,
+            ),
+        }
+    }
+}
+
+/// Returns whether a Teams process is running, if processes can be listed.
+fn teams_running() -> Option<bool> {
+    let is_teams = |name: &str| {
+        let name = name.to_lowercase();
+        name.contains("teams") && !name.contains("teams-ws")
+    };
+    if cfg!(target_os = "linux") {
+        let processes = std::fs::read_dir("/proc").ok()?;
+        return Some(processes.flatten().any(|process| {
+            // Skips the `self` links, which name this process.
+            let pid = process.file_name();
+            pid.to_str()
+                .is_some_and(|pid| pid.chars().all(|c| c.is_ascii_digit()))
+                && std::fs::read_to_string(process.path().join("comm"))
+                    .map(|name| is_teams(&name))
+                    .unwrap_or(false)
+        }));
+    }
+    let output = if cfg!(windows) {
+        std::process::Command::new("tasklist").arg("/NH").output()
+    } else {
+        std::process::Command::new("ps")
+            .args(["-A", "-o", "comm="])
+            .output()
+    };
+    let output = output.ok().filter(|output| output.status.success())?;
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(is_teams),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppIdentifiers;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_doctor_closed_port() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            drop(listener);
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(format!("ws://127.0.0.1:{}", port))
+                .build()
+                .unwrap();
+            let diagnosis = Doctor::new(websocket)
+                .timeout(Duration::from_millis(200))
+                .run()
+                .await;
+            let status = |name| {
+                diagnosis
+                    .checks
+                    .iter()
+                    .find(|check| check.name == name)
+                    .unwrap()
+                    .status
+            };
+            assert_eq!(status("settings"), CheckStatus::Ok);
+            assert_eq!(status("connection"), CheckStatus::Failed);
+            assert_eq!(status("token"), CheckStatus::Skipped);
+            assert!(!diagnosis.is_healthy());
+            assert!(diagnosis.to_string().contains("[FAIL] connection"));
+        });
+    }
+}
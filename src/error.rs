@@ -0,0 +1,195 @@
+use crate::event::DisconnectReport;
+use crate::lifecycle::{LifecycleTrigger, MeetingPhase};
+use crate::messages::MeetingAction;
+use crate::redact::SecretUrl;
+use std::time::Duration;
+
+/// A frame received from Teams that is not a valid `ServerMessage`.
+///
+/// # Fields
+///
+/// * `payload` - The raw frame, lossily decoded as UTF-8.
+/// * `reason` - Why the frame could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedFrame {
+    pub payload: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for MalformedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed frame ({}): {}", self.reason, self.payload)
+    }
+}
+
+/// Errors reported by `TeamsWebsocket`.
+///
+/// Functions return them boxed as `Box<dyn Error>`, use `downcast_ref` to
+/// inspect them.
+#[derive(Debug)]
+pub enum TeamsWsError {
+    /// The connection to `url` could not be established.
+    Connect {
+        url: SecretUrl,
+        source: Box<tungstenite::Error>,
+    },
+    /// The URL points to a non-loopback host but `allow_remote` is not set.
+    RemoteNotAllowed { host: String },
+    /// The confirmation hook did not confirm sending `action`.
+    NotConfirmed { action: MeetingAction },
+    /// The `Sandbox` of an automation does not allow sending `action`.
+    SandboxViolation { action: MeetingAction },
+    /// The `Arbiter` suppressed `action`, which conflicts with a recent
+    /// command of the higher-priority source `by`.
+    Suppressed { action: MeetingAction, by: String },
+    /// The `RateLimiter` refused `action`, which may be sent again after
+    /// `retry_after`.
+    RateLimited {
+        action: MeetingAction,
+        retry_after: Duration,
+    },
+    /// The action with the wire name `action` needs a meeting, but Teams is
+    /// not in one.
+    NotInMeeting { action: String },
+    /// The latest `MeetingPermissions` do not allow `action`, see
+    /// `ConnectionOptions::check_permissions`.
+    PermissionDenied { action: MeetingAction },
+    /// No account of an `Aggregator` is in a meeting to route a command to.
+    NoActiveMeeting,
+    /// `trigger` is not allowed in the meeting lifecycle phase `from`.
+    InvalidTransition {
+        from: MeetingPhase,
+        trigger: LifecycleTrigger,
+    },
+    /// Teams sent a frame that could not be parsed.
+    Malformed(MalformedFrame),
+    /// The connection ended while receiving, as described by the report.
+    ConnectionClosed(DisconnectReport),
+    /// Sending through a `TeamsSender` failed with the given message.
+    Send(String),
+    /// The command queue is full and refused to queue `action`, see
+    /// `OverflowPolicy::Error`.
+    QueueFull { action: MeetingAction },
+    /// A message with the request id `id` is already waiting for Teams'
+    /// answer or queued.
+    DuplicateRequestId { id: u32 },
+    /// `connect` was called while connected, see
+    /// `ConnectionOptions::already_connected`.
+    AlreadyConnected,
+    /// The `ShutdownSignal` was triggered while waiting.
+    Cancelled,
+}
+
+impl TeamsWsError {
+    /// Returns whether sending may succeed later, e.g. once the rate limit
+    /// allows it or Teams is in a meeting, so a queued command is kept.
+    pub(crate) fn is_temporary(&self) -> bool {
+        match self {
+            TeamsWsError::Connect { .. }
+            | TeamsWsError::Suppressed { .. }
+            | TeamsWsError::RateLimited { .. }
+            | TeamsWsError::NotInMeeting { .. }
+            | TeamsWsError::PermissionDenied { .. }
+            | TeamsWsError::NoActiveMeeting
+            | TeamsWsError::ConnectionClosed(_)
+            | TeamsWsError::Send(_)
+            | TeamsWsError::Cancelled => true,
+            TeamsWsError::RemoteNotAllowed { .. }
+            | TeamsWsError::NotConfirmed { .. }
+            | TeamsWsError::SandboxViolation { .. }
+            | TeamsWsError::InvalidTransition { .. }
+            | TeamsWsError::Malformed(_)
+            | TeamsWsError::QueueFull { .. }
+            | TeamsWsError::DuplicateRequestId { .. }
+            | TeamsWsError::AlreadyConnected => false,
+        }
+    }
+}
+
+impl std::fmt::Display for TeamsWsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeamsWsError::Connect { url, source } => {
+                write!(f, "failed to connect to {}: {}", url, source)
+            }
+            TeamsWsError::RemoteNotAllowed { host } => write!(
+                f,
+                "refusing to connect to non-loopback host {} without allow_remote",
+                host
+            ),
+            TeamsWsError::NotConfirmed { action } => {
+                write!(f, "sending {:?} was not confirmed", action)
+            }
+            TeamsWsError::SandboxViolation { action } => {
+                write!(f, "sending {:?} is not allowed by the sandbox", action)
+            }
+            TeamsWsError::Suppressed { action, by } => {
+                write!(
+                    f,
+                    "suppressed {:?} conflicting with a command from {}",
+                    action, by
+                )
+            }
+            TeamsWsError::RateLimited {
+                action,
+                retry_after,
+            } => {
+                write!(
+                    f,
+                    "rate limited {:?}, retry after {} ms",
+                    action,
+                    retry_after.as_millis()
+                )
+            }
+            TeamsWsError::NotInMeeting { action } => {
+                write!(f, "cannot send {}, not in a meeting", action)
+            }
+            TeamsWsError::PermissionDenied { action } => {
+                write!(f, "{:?} is not permitted in this meeting", action)
+            }
+            TeamsWsError::NoActiveMeeting => write!(f, "no account is in a meeting"),
+            TeamsWsError::InvalidTransition { from, trigger } => {
+                write!(
+                    f,
+                    "{:?} is not allowed in meeting phase {:?}",
+                    trigger, from
+                )
+            }
+            TeamsWsError::Malformed(frame) => write!(f, "{}", frame),
+            TeamsWsError::ConnectionClosed(report) => write!(f, "socket closed, {}", report),
+            TeamsWsError::Send(message) => write!(f, "sending failed: {}", message),
+            TeamsWsError::QueueFull { action } => {
+                write!(f, "command queue full, not queueing {:?}", action)
+            }
+            TeamsWsError::DuplicateRequestId { id } => {
+                write!(f, "request id {} is already in use", id)
+            }
+            TeamsWsError::AlreadyConnected => write!(f, "already connected"),
+            TeamsWsError::Cancelled => write!(f, "cancelled by shutdown"),
+        }
+    }
+}
+
+impl std::error::Error for TeamsWsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TeamsWsError::Connect { source, .. } => Some(source.as_ref()),
+            TeamsWsError::RemoteNotAllowed { .. }
+            | TeamsWsError::NotConfirmed { .. }
+            | TeamsWsError::SandboxViolation { .. }
+            | TeamsWsError::Suppressed { .. }
+            | TeamsWsError::RateLimited { .. }
+            | TeamsWsError::NotInMeeting { .. }
+            | TeamsWsError::PermissionDenied { .. }
+            | TeamsWsError::NoActiveMeeting
+            | TeamsWsError::InvalidTransition { .. }
+            | TeamsWsError::Malformed(_)
+            | TeamsWsError::ConnectionClosed(_)
+            | TeamsWsError::Send(_)
+            | TeamsWsError::QueueFull { .. }
+            | TeamsWsError::DuplicateRequestId { .. }
+            | TeamsWsError::AlreadyConnected
+            | TeamsWsError::Cancelled => None,
+        }
+    }
+}
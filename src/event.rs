@@ -0,0 +1,135 @@
+use crate::lifecycle::Transition;
+use crate::messages::TeamsErrorKind;
+use crate::state::{MeetingStateDelta, SessionEvent};
+use crate::TeamsWebsocket;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Who ended a connection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectInitiator {
+    /// This side called `TeamsWebsocket::close`.
+    Client,
+    /// Teams closed the connection, e.g. because it exited.
+    Server,
+    /// The connection failed without a closing handshake, e.g. because
+    /// the network dropped or Teams crashed.
+    Network,
+}
+
+/// The state of the link to Teams, published by `TeamsWebsocket::watch_status`
+/// so user interfaces can show it live.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// Not connected yet, or the connection was lost and is not re-established.
+    #[default]
+    Disconnected,
+    /// `connect` is opening the socket.
+    Connecting,
+    /// The socket is open.
+    Connected,
+    /// The connection was lost and `ConnectionOptions::reconnect` is retrying.
+    Reconnecting,
+    /// This side closed the connection.
+    Closed,
+}
+
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectionStatus::Disconnected => "disconnected",
+            ConnectionStatus::Connecting => "connecting",
+            ConnectionStatus::Connected => "connected",
+            ConnectionStatus::Reconnecting => "reconnecting",
+            ConnectionStatus::Closed => "closed",
+        })
+    }
+}
+
+/// Why and when a connection ended.
+///
+/// # Fields
+///
+/// * `reason` - The close reason sent by Teams or the error that ended the connection.
+/// * `close_code` - The WebSocket close code, if a close frame was exchanged.
+/// * `initiated_by` - Who ended the connection.
+/// * `at_ms` - Milliseconds since the unix epoch when the connection ended.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DisconnectReport {
+    pub reason: String,
+    pub close_code: Option<u16>,
+    pub initiated_by: DisconnectInitiator,
+    pub at_ms: u128,
+}
+
+impl DisconnectReport {
+    pub fn new(
+        initiated_by: DisconnectInitiator,
+        reason: impl Into<String>,
+        close_code: Option<u16>,
+    ) -> Self {
+        Self {
+            reason: reason.into(),
+            close_code,
+            initiated_by,
+            at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_millis())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns a report for a connection that ended with `error`.
+    pub fn from_error(error: &dyn Error) -> Self {
+        Self::new(DisconnectInitiator::Network, error.to_string(), None)
+    }
+}
+
+impl std::fmt::Display for DisconnectReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let by = match self.initiated_by {
+            DisconnectInitiator::Client => "client",
+            DisconnectInitiator::Server => "Teams",
+            DisconnectInitiator::Network => "network error",
+        };
+        write!(f, "disconnected by {}: {}", by, self.reason)?;
+        if let Some(code) = self.close_code {
+            write!(f, " (code {})", code)?;
+        }
+        Ok(())
+    }
+}
+
+/// A high-level event observed on a connection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The connection to Teams was established.
+    Connected,
+    /// The connection to Teams ended.
+    Disconnected(DisconnectReport),
+    /// A field of the meeting state changed.
+    StateChanged(MeetingStateDelta),
+    /// A meeting, recording or screen share started or ended, or a hand
+    /// was raised or lowered. Follows the `StateChanged` it is derived from.
+    Session(SessionEvent),
+    /// The meeting lifecycle moved to another phase.
+    PhaseChanged(Transition),
+    /// A rule of a `RulesEngine` fired, with the rule name.
+    RuleFired(String),
+    /// Teams answered a request with an error, e.g. because there is no
+    /// meeting.
+    TeamsError(TeamsErrorKind),
+}
+
+impl Event {
+    /// Returns the `Disconnected` event for a connection of `websocket`
+    /// that ended with `error`.
+    pub(crate) fn disconnected(websocket: &TeamsWebsocket, error: &dyn Error) -> Self {
+        let report = websocket.disconnect_report().cloned();
+        Event::Disconnected(report.unwrap_or_else(|| DisconnectReport::from_error(error)))
+    }
+}
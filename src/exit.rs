@@ -0,0 +1,275 @@
+use crate::messages::TeamsErrorKind;
+use crate::TeamsWsError;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// The exit codes of the command line tools, so scripts can branch on the
+/// outcome instead of parsing log output.
+///
+/// | Code | Status          | Meaning                                          |
+/// |------|-----------------|--------------------------------------------------|
+/// | 0    | `ok`            | Success                                          |
+/// | 1    | `failed`        | Any other error                                  |
+/// | 2    | `usage`         | Invalid command line                             |
+/// | 3    | `not_connected` | Teams is not running or refused the connection   |
+/// | 4    | `not_in_meeting`| The command needs a meeting                      |
+/// | 5    | `not_permitted` | Refused by token, confirmation, policy or limits |
+/// | 6    | `timeout`       | Teams did not answer in time                     |
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    Ok = 0,
+    Failed = 1,
+    Usage = 2,
+    NotConnected = 3,
+    NotInMeeting = 4,
+    NotPermitted = 5,
+    Timeout = 6,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the status for an error returned by this crate.
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        if error.is::<tokio::time::error::Elapsed>() {
+            return ExitStatus::Timeout;
+        }
+        match error.downcast_ref::<TeamsErrorKind>() {
+            Some(TeamsErrorKind::NoActiveCall) => return ExitStatus::NotInMeeting,
+            Some(TeamsErrorKind::NotPermitted | TeamsErrorKind::InvalidToken) => {
+                return ExitStatus::NotPermitted
+            }
+            Some(TeamsErrorKind::Unknown(_)) => return ExitStatus::Failed,
+            None => {}
+        }
+        match error.downcast_ref::<TeamsWsError>() {
+            Some(
+                TeamsWsError::Connect { .. }
+                | TeamsWsError::RemoteNotAllowed { .. }
+                | TeamsWsError::ConnectionClosed(_),
+            ) => ExitStatus::NotConnected,
+            Some(TeamsWsError::NotInMeeting { .. } | TeamsWsError::NoActiveMeeting) => {
+                ExitStatus::NotInMeeting
+            }
+            Some(
+                TeamsWsError::NotConfirmed { .. }
+                | TeamsWsError::PermissionDenied { .. }
+                | TeamsWsError::SandboxViolation { .. }
+                | TeamsWsError::Suppressed { .. }
+                | TeamsWsError::RateLimited { .. },
+            ) => ExitStatus::NotPermitted,
+            Some(
+                TeamsWsError::InvalidTransition { .. }
+                | TeamsWsError::Malformed(_)
+                | TeamsWsError::Send(_)
+                | TeamsWsError::QueueFull { .. }
+                | TeamsWsError::DuplicateRequestId { .. }
+                | TeamsWsError::AlreadyConnected
+                | TeamsWsError::Cancelled,
+            )
+            | None => {
+                if error.to_string() == crate::SOCKET_NOT_CONNECTED {
+                    ExitStatus::NotConnected
+                } else {
+                    ExitStatus::Failed
+                }
+            }
+        }
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ExitStatus::Ok => "ok",
+            ExitStatus::Failed => "failed",
+            ExitStatus::Usage => "usage",
+            ExitStatus::NotConnected => "not_connected",
+            ExitStatus::NotInMeeting => "not_in_meeting",
+            ExitStatus::NotPermitted => "not_permitted",
+            ExitStatus::Timeout => "timeout",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The command line of a command line tool.
+///
+/// `--url`, `--token`, `--config`, `--timeout` and `--json` are known to
+/// every tool, the others are passed to `CommandLine::parse`.
+///
+/// # Fields
+///
+/// * `command` - The first argument, e.g. `toggle-mute`.
+/// * `argument` - The first argument after the command that is not an option.
+/// * `url` - The value of `--url`.
+/// * `token` - The value of `--token`.
+/// * `config` - The value of `--config`.
+/// * `timeout` - The value of `--timeout` in seconds, 3 by default.
+/// * `json` - Whether `--json` was given.
+///
+/// # Example
+/// ```rust
+/// let args = CommandLine::parse(std::env::args().skip(1), &["--pair"], &[], false)?;
+/// let pair = args.flag("--pair");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandLine {
+    pub command: String,
+    pub argument: Option<String>,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub config: Option<String>,
+    pub timeout: Duration,
+    pub json: bool,
+    flags: BTreeSet<String>,
+    values: BTreeMap<String, String>,
+}
+
+impl CommandLine {
+    /// Parses `args`, the arguments without the program name.
+    ///
+    /// Besides the options of every tool, `flags` are accepted without and
+    /// `options` with a value. With `argument`, one argument after the
+    /// command is accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for a missing command or value, an invalid timeout
+    /// or an unknown option.
+    pub fn parse(
+        mut args: impl Iterator<Item = String>,
+        flags: &[&str],
+        options: &[&str],
+        argument: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Self {
+            command: args.next().ok_or("missing command")?,
+            argument: None,
+            url: None,
+            token: None,
+            config: None,
+            timeout: Duration::from_secs(3),
+            json: false,
+            flags: BTreeSet::new(),
+            values: BTreeMap::new(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--url" => parsed.url = Some(value()?),
+                "--token" => parsed.token = Some(value()?),
+                "--config" => parsed.config = Some(value()?),
+                "--timeout" => parsed.timeout = Duration::from_secs(value()?.parse()?),
+                "--json" => parsed.json = true,
+                name if flags.contains(&name) => {
+                    parsed.flags.insert(arg);
+                }
+                name if options.contains(&name) => {
+                    let value = value()?;
+                    parsed.values.insert(arg, value);
+                }
+                _ if argument && !arg.starts_with("--") && parsed.argument.is_none() => {
+                    parsed.argument = Some(arg)
+                }
+                _ => return Err(Box::from(format!("unknown option {}", arg))),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Returns whether the flag `name`, e.g. `--pair`, was given.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    /// Returns the value of the option `name`, e.g. `--token-file`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// Reports a failure of a command line tool on stderr, followed by `usage`
+/// for `ExitStatus::Usage`, or as JSON on stdout with `--json`.
+pub fn report_error(
+    json: bool,
+    status: ExitStatus,
+    error: &dyn std::fmt::Display,
+    usage: &str,
+) -> ExitCode {
+    if json {
+        let report = serde_json::json!({
+            "exit_status": status,
+            "error": error.to_string(),
+        });
+        println!("{}", report);
+    } else if status == ExitStatus::Usage {
+        eprintln!("{}\n\n{}", error, usage);
+    } else {
+        eprintln!("{}", error);
+    }
+    status.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingAction;
+
+    #[test]
+    fn test_exit_status_from_error() {
+        let error: Box<dyn Error> = Box::new(TeamsWsError::SandboxViolation {
+            action: MeetingAction::LeaveCall,
+        });
+        assert_eq!(
+            ExitStatus::from_error(error.as_ref()),
+            ExitStatus::NotPermitted
+        );
+        let error: Box<dyn Error> = Box::from(crate::SOCKET_NOT_CONNECTED);
+        assert_eq!(
+            ExitStatus::from_error(error.as_ref()),
+            ExitStatus::NotConnected
+        );
+        let error: Box<dyn Error> = Box::new(TeamsErrorKind::NoActiveCall);
+        assert_eq!(
+            ExitStatus::from_error(error.as_ref()),
+            ExitStatus::NotInMeeting
+        );
+        assert_eq!(ExitStatus::NotInMeeting.code(), 4);
+    }
+
+    #[test]
+    fn test_command_line() {
+        let args = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>();
+        let parsed = CommandLine::parse(
+            args("react like --json --compact --token-file t --timeout 5").into_iter(),
+            &["--compact"],
+            &["--token-file"],
+            true,
+        )
+        .unwrap();
+        assert_eq!(parsed.command, "react");
+        assert_eq!(parsed.argument.as_deref(), Some("like"));
+        assert!(parsed.json);
+        assert!(parsed.flag("--compact"));
+        assert!(!parsed.flag("--pair"));
+        assert_eq!(parsed.value("--token-file"), Some("t"));
+        assert_eq!(parsed.timeout, Duration::from_secs(5));
+
+        assert!(CommandLine::parse(args("doctor like").into_iter(), &[], &[], false).is_err());
+        assert!(CommandLine::parse(args("doctor --pair").into_iter(), &[], &[], false).is_err());
+        assert!(CommandLine::parse(args("doctor --url").into_iter(), &[], &[], false).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! A C ABI for C and C++ integrations such as Stream Deck plugins and OBS
+//! scripts, declared in `include/ms_teams_ws.h`.
+//!
+//! Build the library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib` (or
+//! `staticlib`). Functions returning an `int` return the `ExitStatus` code,
+//! 0 on success; `teams_ws_last_error` describes the last failure on the
+//! calling thread. A panic does not unwind into C, the function fails with
+//! `ExitStatus::Failed` or `NULL` instead. The functions must not be called
+//! from within a tokio runtime or a state callback.
+//!
+//! The header is generated by cbindgen with `cbindgen.toml`;
+//! `TEAMS_WS_UPDATE_HEADER=1 cargo test --features ffi ffi` rewrites it
+//! after the functions changed, the test fails while it is out of date.
+
+use crate::client::{ClientHandle, TeamsClient};
+use crate::exit::ExitStatus;
+use crate::messages::{ClientMessage, MeetingAction};
+use crate::types::AppIdentifiers;
+use crate::TeamsWebsocket;
+use futures_util::StreamExt;
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+/// How long the functions sending actions wait for Teams to answer.
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Called with the meeting state as JSON, e.g.
+/// `{"isMuted":true,"isInMeeting":true,...}`, and the `user_data` given
+/// on registration. The string is only valid during the call.
+pub type TeamsWsStateCallback =
+    Option<extern "C" fn(state_json: *const c_char, user_data: *mut c_void)>;
+
+/// A connected client, created by `teams_ws_connect` and released by
+/// `teams_ws_free`.
+pub struct TeamsWsClient {
+    client: Option<TeamsClient>,
+    handle: ClientHandle,
+    runtime: tokio::runtime::Handle,
+    /// The task calling the state callback, replaced on registration.
+    callback: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// The `user_data` of a callback, passed back to C unchanged.
+struct UserData(*mut c_void);
+
+// The caller of `teams_ws_set_state_callback` promises it may be used
+// from the client thread.
+unsafe impl Send for UserData {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `error` for `teams_ws_last_error` and returns its status code.
+fn fail(error: &(dyn Error + 'static)) -> c_int {
+    set_last_error(&error.to_string());
+    c_int::from(ExitStatus::from_error(error).code())
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f` and returns its result, or records the panic for
+/// `teams_ws_last_error` and returns `failed` if it panicked.
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(&format!("panicked: {}", message));
+            failed
+        }
+    }
+}
+
+/// The result of an `int` function that panicked.
+fn panicked() -> c_int {
+    c_int::from(ExitStatus::Failed.code())
+}
+
+/// Aborts the task calling the state callback of `client`, if any, and
+/// waits until it stopped, so the callback is not called anymore.
+fn stop_callback(client: &mut TeamsWsClient) {
+    if let Some(task) = client.callback.take() {
+        task.abort();
+        let _ = client.runtime.block_on(task);
+    }
+}
+
+/// Returns the string at `ptr`, or `None` if it is `NULL`.
+///
+/// # Safety
+///
+/// `ptr` must be `NULL` or point to a NUL-terminated string.
+unsafe fn optional_str<'a>(ptr: *const c_char) -> Result<Option<&'a str>, Box<dyn Error>> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(ptr).to_str()?))
+}
+
+fn connect(
+    url: Option<&str>,
+    token: Option<&str>,
+    app: Option<&str>,
+) -> Result<TeamsWsClient, Box<dyn Error>> {
+    let mut identifier = AppIdentifiers::builder().device("ffi");
+    if let Some(app) = app {
+        identifier = identifier.app(app.to_string());
+    }
+    let mut builder = TeamsWebsocket::builder(identifier.build()).user_config();
+    if let Some(url) = url {
+        builder = builder.url(url);
+    }
+    if let Some(token) = token {
+        builder = builder.token(token);
+    }
+    let mut websocket = builder.build()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(websocket.connect())?;
+    let handle = runtime.handle().clone();
+    let client = TeamsClient::run_on_runtime(websocket, runtime)?;
+    Ok(TeamsWsClient {
+        handle: client.handle(),
+        client: Some(client),
+        runtime: handle,
+        callback: None,
+    })
+}
+
+/// Connects to Teams and returns the client, or `NULL` on failure.
+///
+/// `url`, `token` and `app` may be `NULL` for the URL and token of the
+/// environment and the user config, and the default app name shown when
+/// Teams asks to pair. Tokens Teams grants are stored in the user config.
+///
+/// # Safety
+///
+/// The arguments must be `NULL` or NUL-terminated UTF-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_connect(
+    url: *const c_char,
+    token: *const c_char,
+    app: *const c_char,
+) -> *mut TeamsWsClient {
+    guard(std::ptr::null_mut(), || {
+        let connected =
+            (|| connect(optional_str(url)?, optional_str(token)?, optional_str(app)?))();
+        match connected {
+            Ok(client) => Box::into_raw(Box::new(client)),
+            Err(e) => {
+                fail(e.as_ref());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Sends `message` and waits for Teams' answer.
+fn request(client: &TeamsWsClient, message: ClientMessage) -> c_int {
+    let answered = client.runtime.block_on(async {
+        let answer = tokio::time::timeout(ANSWER_TIMEOUT, client.handle.request(message)).await;
+        answer.map_err(Box::<dyn Error>::from)?
+    });
+    match answered {
+        Ok(_) => 0,
+        Err(e) => fail(e.as_ref()),
+    }
+}
+
+/// Sends the action named `action`, e.g. `"toggle-mute"` or
+/// `"raise-hand"`, and waits for Teams' answer.
+///
+/// # Safety
+///
+/// `client` must come from `teams_ws_connect` and `action` must be a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_send_action(
+    client: *mut TeamsWsClient,
+    action: *const c_char,
+) -> c_int {
+    guard(panicked(), || {
+        let Some(client) = client.as_ref() else {
+            set_last_error("client is NULL");
+            return c_int::from(ExitStatus::Usage.code());
+        };
+        let action = match optional_str(action) {
+            Ok(Some(action)) => serde_json::from_value::<MeetingAction>(action.into()),
+            _ => {
+                set_last_error("action is not a string");
+                return c_int::from(ExitStatus::Usage.code());
+            }
+        };
+        match action {
+            Ok(action) => request(client, ClientMessage::new(action, None)),
+            Err(e) => {
+                set_last_error(&format!("unknown action: {}", e));
+                c_int::from(ExitStatus::Usage.code())
+            }
+        }
+    })
+}
+
+/// Toggles the microphone and waits for Teams' answer.
+///
+/// # Safety
+///
+/// `client` must come from `teams_ws_connect`.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_toggle_mute(client: *mut TeamsWsClient) -> c_int {
+    guard(panicked(), || {
+        let Some(client) = client.as_ref() else {
+            set_last_error("client is NULL");
+            return c_int::from(ExitStatus::Usage.code());
+        };
+        request(client, ClientMessage::new(MeetingAction::ToggleMute, None))
+    })
+}
+
+/// Returns the meeting state Teams last reported as JSON, or `NULL` before
+/// it reported one. Release the string with `teams_ws_string_free`.
+///
+/// # Safety
+///
+/// `client` must come from `teams_ws_connect`.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_state(client: *mut TeamsWsClient) -> *mut c_char {
+    guard(std::ptr::null_mut(), || {
+        let Some(state) = client
+            .as_ref()
+            .and_then(|client| client.handle.meeting_state())
+        else {
+            return std::ptr::null_mut();
+        };
+        let json = serde_json::to_string(&state).unwrap_or_default();
+        CString::new(json).map_or(std::ptr::null_mut(), CString::into_raw)
+    })
+}
+
+/// Calls `callback` with the meeting state after every change, on the
+/// thread of the client, until another callback is registered or the
+/// client is released. A `NULL` callback unregisters it. The previous
+/// callback is not called anymore once this returns.
+///
+/// # Safety
+///
+/// `client` must come from `teams_ws_connect`, and `callback` and
+/// `user_data` must stay usable from another thread until then.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_set_state_callback(
+    client: *mut TeamsWsClient,
+    callback: TeamsWsStateCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(panicked(), || {
+        let Some(client) = client.as_mut() else {
+            set_last_error("client is NULL");
+            return c_int::from(ExitStatus::Usage.code());
+        };
+        stop_callback(client);
+        let Some(callback) = callback else {
+            return 0;
+        };
+        let user_data = UserData(user_data);
+        let handle = client.handle.clone();
+        let mut changes = handle.subscribe_state_changes();
+        client.callback = Some(client.runtime.spawn(async move {
+            let user_data = user_data;
+            while changes.next().await.is_some() {
+                let Some(state) = handle.meeting_state() else {
+                    continue;
+                };
+                let Ok(json) = CString::new(serde_json::to_string(&state).unwrap_or_default())
+                else {
+                    continue;
+                };
+                callback(json.as_ptr(), user_data.0);
+            }
+        }));
+        0
+    })
+}
+
+/// Returns the last error on the calling thread, or `NULL`. The string is
+/// valid until the next call failing on the thread.
+#[no_mangle]
+pub extern "C" fn teams_ws_last_error() -> *const c_char {
+    guard(std::ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(std::ptr::null(), |e| e.as_ptr())
+        })
+    })
+}
+
+/// Releases a string returned by this library.
+///
+/// # Safety
+///
+/// `string` must be `NULL` or come from this library and not be released yet.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_string_free(string: *mut c_char) {
+    guard((), || {
+        if !string.is_null() {
+            drop(CString::from_raw(string));
+        }
+    })
+}
+
+/// Closes the connection and releases `client`, after the state callback
+/// returned if it is running.
+///
+/// # Safety
+///
+/// `client` must be `NULL` or come from `teams_ws_connect` and not be
+/// released yet.
+#[no_mangle]
+pub unsafe extern "C" fn teams_ws_free(client: *mut TeamsWsClient) {
+    guard((), || {
+        if client.is_null() {
+            return;
+        }
+        let mut client = Box::from_raw(client);
+        stop_callback(&mut client);
+        if let Some(teams_client) = client.client.take() {
+            let _ = client.runtime.block_on(teams_client.shutdown());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingState;
+    use crate::messages::MeetingUpdate;
+    use crate::mock::MockTeamsServer;
+    use std::sync::mpsc;
+    use tokio::runtime::Runtime;
+
+    extern "C" fn on_state(state_json: *const c_char, user_data: *mut c_void) {
+        let states = unsafe { &*(user_data as *const mpsc::Sender<String>) };
+        let state = unsafe { CStr::from_ptr(state_json) };
+        let _ = states.send(state.to_str().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_ffi() {
+        let rt = Runtime::new().unwrap();
+        let server = rt.block_on(MockTeamsServer::start()).unwrap();
+        let url = CString::new(server.url()).unwrap();
+        let token = CString::new("token").unwrap();
+        unsafe {
+            let client = teams_ws_connect(url.as_ptr(), token.as_ptr(), std::ptr::null());
+            assert!(!client.is_null());
+            let (sender, states) = mpsc::channel::<String>();
+            let user_data = &sender as *const mpsc::Sender<String> as *mut c_void;
+            assert_eq!(
+                teams_ws_set_state_callback(client, Some(on_state), user_data),
+                0
+            );
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+            });
+            let state = states.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(state.contains("\"isInMeeting\":true"));
+            let json = teams_ws_state(client);
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), state);
+            teams_ws_string_free(json);
+            assert_eq!(teams_ws_set_state_callback(client, None, user_data), 0);
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true).with_muted(true)),
+            });
+            assert!(states.recv_timeout(Duration::from_millis(100)).is_err());
+
+            assert_eq!(teams_ws_toggle_mute(client), 0);
+            let unknown = CString::new("dance").unwrap();
+            assert_eq!(teams_ws_send_action(client, unknown.as_ptr()), 2);
+            let error = CStr::from_ptr(teams_ws_last_error()).to_str().unwrap();
+            assert!(error.starts_with("unknown action"));
+            teams_ws_free(client);
+        }
+        server.assert_actions(&[MeetingAction::ToggleMute]);
+    }
+
+    #[test]
+    fn test_panic() {
+        let status = guard(panicked(), || panic!("boom"));
+        assert_eq!(status, 1);
+        let error = unsafe { CStr::from_ptr(teams_ws_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "panicked: boom");
+    }
+
+    #[test]
+    fn test_header() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+        let mut generated = Vec::new();
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(root.join("src/ffi.rs"))
+            .generate()
+            .unwrap()
+            .write(&mut generated);
+        let path = root.join("include/ms_teams_ws.h");
+        if std::env::var_os("TEAMS_WS_UPDATE_HEADER").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        let header = std::fs::read(&path).unwrap();
+        assert!(
+            header == generated,
+            "{} is out of date, run TEAMS_WS_UPDATE_HEADER=1 cargo test --features ffi ffi",
+            path.display()
+        );
+    }
+}
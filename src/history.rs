@@ -0,0 +1,182 @@
+use crate::event::DisconnectReport;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of entries kept by default, older ones are dropped first.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// What happened to a connection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum ConnectionEventKind {
+    /// Connecting with `protocol_version` was attempted, `reconnect` if the
+    /// websocket was connected before.
+    Connecting {
+        protocol_version: String,
+        reconnect: bool,
+    },
+    /// The connection was established with `protocol_version`.
+    Connected { protocol_version: String },
+    /// Connecting failed with `reason`.
+    ConnectFailed { reason: String },
+    /// The connection ended.
+    Disconnected(DisconnectReport),
+    /// Teams sent a new token.
+    TokenRefreshed,
+}
+
+impl std::fmt::Display for ConnectionEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionEventKind::Connecting {
+                protocol_version,
+                reconnect,
+            } => {
+                let verb = if *reconnect {
+                    "reconnecting"
+                } else {
+                    "connecting"
+                };
+                write!(f, "{} with protocol version {}", verb, protocol_version)
+            }
+            ConnectionEventKind::Connected { protocol_version } => {
+                write!(f, "connected with protocol version {}", protocol_version)
+            }
+            ConnectionEventKind::ConnectFailed { reason } => {
+                write!(f, "connecting failed: {}", reason)
+            }
+            ConnectionEventKind::Disconnected(report) => write!(f, "{}", report),
+            ConnectionEventKind::TokenRefreshed => write!(f, "token refreshed"),
+        }
+    }
+}
+
+/// An entry of a `ConnectionHistory`.
+///
+/// # Fields
+///
+/// * `at_ms` - Milliseconds since the unix epoch when it happened.
+/// * `kind` - What happened.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConnectionEvent {
+    pub at_ms: u128,
+    #[serde(flatten)]
+    pub kind: ConnectionEventKind,
+}
+
+impl std::fmt::Display for ConnectionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.at_ms, self.kind)
+    }
+}
+
+/// The recent connection-level events of a `TeamsWebsocket`, for
+/// diagnostics like showing why a bridge was offline without its logs.
+///
+/// # Example
+/// ```rust
+/// for event in websocket.history().events() {
+///     println!("{}", event);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectionHistory {
+    events: VecDeque<ConnectionEvent>,
+    capacity: usize,
+    connections: usize,
+}
+
+impl ConnectionHistory {
+    /// Creates a history keeping at most `capacity` events, dropping the oldest.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::new(),
+            capacity,
+            connections: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the number of connections established, including those
+    /// whose events were dropped.
+    pub fn connections(&self) -> usize {
+        self.connections
+    }
+
+    /// Returns the events, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &ConnectionEvent> {
+        self.events.iter()
+    }
+
+    /// Returns the last time the connection ended.
+    pub fn last_disconnect(&self) -> Option<&DisconnectReport> {
+        self.events
+            .iter()
+            .rev()
+            .find_map(|event| match &event.kind {
+                ConnectionEventKind::Disconnected(report) => Some(report),
+                _ => None,
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub(crate) fn push(&mut self, kind: ConnectionEventKind) {
+        if matches!(kind, ConnectionEventKind::Connected { .. }) {
+            self.connections += 1;
+        }
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis())
+            .unwrap_or_default();
+        self.events.push_back(ConnectionEvent { at_ms, kind });
+        while self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
+    }
+}
+
+impl Default for ConnectionHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::DisconnectInitiator;
+
+    #[test]
+    fn test_connection_history() {
+        let mut history = ConnectionHistory::new(2);
+        history.push(ConnectionEventKind::Connected {
+            protocol_version: "2.0.0".to_string(),
+        });
+        let report = DisconnectReport::new(DisconnectInitiator::Server, "bye", Some(1000));
+        history.push(ConnectionEventKind::Disconnected(report.clone()));
+        history.push(ConnectionEventKind::TokenRefreshed);
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.connections(), 1);
+        assert_eq!(history.last_disconnect(), Some(&report));
+        let kinds: Vec<String> = history
+            .events()
+            .map(|event| event.kind.to_string())
+            .collect();
+        assert_eq!(kinds[1], "token refreshed");
+
+        let json = serde_json::to_value(history.events().last().unwrap()).unwrap();
+        assert_eq!(json["kind"], "token_refreshed");
+    }
+}
@@ -1,43 +1,183 @@
-mod messages;
-mod types;
+#[macro_use]
+mod logging;
 
-use crate::messages::{ClientMessage, ServerMessage};
-use crate::types::AppIdentifiers;
-use futures_util::SinkExt;
-use futures_util::StreamExt;
+pub mod action;
+pub mod aggregate;
+pub mod arbitration;
+#[cfg(feature = "async-std")]
+pub mod async_std_transport;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod auto;
+pub mod blocking;
+#[cfg(all(target_os = "linux", feature = "bridge-dbus"))]
+pub mod bridge_dbus;
+#[cfg(feature = "bridge-http")]
+pub mod bridge_http;
+#[cfg(feature = "bridge-mqtt")]
+pub mod bridge_mqtt;
+mod builder;
+pub mod client;
+mod commands;
+pub mod config;
+pub mod confirm;
+pub mod discovery;
+pub mod doctor;
+mod error;
+pub mod event;
+pub mod exit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod history;
+pub mod lifecycle;
+pub mod messages;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+mod options;
+pub mod pending;
+pub mod plugin;
+mod query;
+pub mod queue;
+pub mod ratelimit;
+pub mod reconnect;
+pub mod recording;
+pub mod redact;
+pub mod rules;
+pub mod sandbox;
+#[cfg(feature = "scenario")]
+pub mod scenario;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod settings;
+pub mod shutdown;
+pub mod spool;
+pub mod state;
+#[cfg(feature = "rustls")]
+pub mod tls;
+pub mod token;
+pub mod transport;
+pub mod types;
+#[cfg(feature = "typescript")]
+pub mod typescript;
+pub mod webhook;
+
+use crate::action::Action;
+use crate::arbitration::Arbiter;
+pub use crate::builder::TeamsWebsocketBuilder;
+use crate::confirm::ConfirmationHook;
+pub use crate::error::{MalformedFrame, TeamsWsError};
+use crate::event::{ConnectionStatus, DisconnectInitiator, DisconnectReport};
+use crate::history::{ConnectionEventKind, ConnectionHistory};
+use crate::logging::Instrument;
+use crate::messages::{
+    ClientMessage, MeetingAction, MeetingPermissions, MeetingState, ProtocolVersion, ServerMessage,
+    TeamsErrorKind,
+};
+pub use crate::options::{AlreadyConnected, ConnectionOptions, RequestIdStart};
+use crate::pending::{PendingRequest, PendingRequests};
+use crate::queue::CommandQueue;
+use crate::ratelimit::RateLimiter;
+use crate::recording::{Direction, Recorder};
+use crate::redact::SecretUrl;
+use crate::settings::{ResolvedSettings, SettingKey, SettingsResolver};
+use crate::shutdown::ShutdownSignal;
+use crate::state::StateSnapshot;
+use crate::token::TokenStore;
+use crate::transport::{Connector, Socket};
+use crate::types::{AppIdentifiers, ConnectionInfo, HandshakeResponse, TeamsFlavor};
+use futures_util::FutureExt;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio_tungstenite::connect_async;
-use url::Url;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
 
 /// A struct representing a WebSocket connection to a Microsoft Teams server.
 ///
 /// # Fields
 /// - `identifier`: An `AppIdentifiers` struct containing information about the app.
-/// - `socket`: An optional WebSocket stream.
+/// - `link`: The state of the connection, holding the WebSocket stream while open.
+/// - `status`: Publishes the `ConnectionStatus` of `link` to `watch_status`.
 /// - `token`: An optional authentication token.
-/// - `request_id`: A counter for request IDs.
+/// - `request_id`: The next request ID `send` assigns, wrapping around.
+/// - `ping_id`: A counter for the payloads of pings sent by `ping`.
+/// - `buffered`: Frames received while waiting for a pong or an answer, with the messages they were handled as.
+/// - `replayed`: The number of messages at the front of `buffered` that were handled already.
+/// - `requests`: The sent requests Teams did not answer yet.
+/// - `disconnect_report`: Why the last connection ended.
+/// - `history`: The recent connects, disconnects and token refreshes.
+/// - `in_meeting`: Whether Teams last reported being in a meeting.
+/// - `meeting_state`: The meeting state Teams last reported.
+/// - `state_updated`: When Teams last reported `meeting_state`.
+/// - `permissions`: The meeting permissions Teams last reported.
+/// - `next_state_refresh`: When `receive` queries the meeting state next, see `ConnectionOptions::state_refresh`.
+/// - `state_refresh_id`: The request id of the last state query sent by `receive`.
+/// - `next_keepalive`: When `receive` pings Teams next, see `ConnectionOptions::keepalive`.
+/// - `keepalive_ping`: The payload of the keepalive ping Teams did not answer yet.
+/// - `protocol_version`: The protocol version Teams accepted on connect.
+/// - `handshake_response`: The HTTP response the connection was upgraded with.
 /// - `url`: The URL of the WebSocket server.
+/// - `settings`: The resolved settings and where each value came from.
+/// - `options`: The `ConnectionOptions` used when connecting.
+/// - `command_queue`: An optional `CommandQueue` for messages sent while not connected.
+/// - `arbiter`: An optional `Arbiter` resolving conflicting commands of several sources.
+/// - `rate_limiter`: An optional `RateLimiter` limiting how often commands are sent.
+/// - `shutdown_signal`: The `ShutdownSignal` stopping receiving and reconnecting.
+/// - `malformed_frames`: An optional channel receiving frames that could not be parsed.
+/// - `token_store`: An optional `TokenStore` persisting the tokens Teams sends.
+/// - `raw_frame_hook`: An optional callback receiving the raw text of every frame Teams sends.
+/// - `connector`: An optional `Connector` opening the connections instead of tokio-tungstenite.
+/// - `recorder`: An optional `Recorder` writing every frame sent and received to a file.
+/// - `message_observer`: An optional callback receiving the raw text of every frame sent and received.
+/// - `metrics`: Optional `Metrics` counting messages, reconnects and errors.
 ///
 /// # Methods
-/// - `new`: Creates a new `TeamsWebsocket` instance.
+/// - `new`: Creates a new `TeamsWebsocket` instance, deprecated in favour of `builder`.
+/// - `builder`: Creates a `TeamsWebsocketBuilder` resolving config file and environment settings.
 /// - `connect`: Connects to the WebSocket server.
-/// - `send`: Sends a `ClientMessage` to the server.
+/// - `reconnect`: Closes the connection and connects again with the latest token.
+/// - `ready`: Connects and waits until Teams reported its meeting state.
+/// - `pair`: Connects without a token and waits until Teams grants one.
+/// - `connection_info`: Returns the URL and negotiated protocol version.
+/// - `handshake_response`: Returns the status and headers of the HTTP upgrade response.
+/// - `status`, `watch_status`: Return the `ConnectionStatus` and a channel following it.
+/// - `protocol`: Returns the `ProtocolVersion` messages are encoded with.
+/// - `send`: Sends a `ClientMessage` or any other `Action`, including ones defined outside this crate.
+/// - `send_and_wait`: Sends an `Action` and returns Teams' answer to it.
+/// - `toggle_mute`, `raise_hand`, `send_reaction`, ...: Send a `MeetingAction` and return its request id.
 /// - `receive`: Receives a `ServerMessage` from the server.
+/// - `receive_timeout`, `receive_blocking`, `try_receive`: Receive with a timeout, without one or without waiting.
+/// - `meeting_state`, `permissions`: Return the state and permissions Teams last reported.
+/// - `pending_requests`: Lists the requests Teams did not answer yet.
+/// - `ping`: Measures the round-trip time to the server.
+/// - `shutdown_signal`: Returns the `ShutdownSignal` stopping receives and reconnects.
+/// - `receive_resilient`: Receives the next valid `ServerMessage`, skipping malformed frames.
 /// - `close`: Closes the WebSocket connection.
+/// - `shutdown`: Flushes, closes and waits for Teams to answer the Close frame.
+/// - `split`: Splits into a `TeamsSender` and a `TeamsReceiver` usable from different tasks.
 ///
 /// # Example
 /// ```rust
 /// let identifier = AppIdentifiers {
-///     protocol_version: "1.0",
-///     manufacturer: "TestManufacturer",
-///     device: "TestDevice",
-///     app: "TestApp",
-///     app_version: "1.0",
+///     protocol_version: "1.0".into(),
+///     manufacturer: "TestManufacturer".into(),
+///     device: "TestDevice".into(),
+///     app: "TestApp".into(),
+///     app_version: "1.0".into(),
 /// };
 /// use ms_teams_ws::messages;
 /// use ms_teams_ws::TeamsWebsocket;
 ///
-/// let mut websocket = TeamsWebsocket::new(identifier, None, None).await;
+/// let mut websocket = TeamsWebsocket::builder(identifier).build()?;
 /// websocket.connect().await.unwrap();
 /// let client_message = ClientMessage::new(messages::MeetingAction::BlurBackground, None);
 /// websocket.send(client_message).await.unwrap();
@@ -46,284 +186,3626 @@ use url::Url;
 /// ```
 pub struct TeamsWebsocket {
     identifier: AppIdentifiers,
-    socket: Option<
-        tokio_tungstenite::WebSocketStream<
-            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
-        >,
-    >,
+    link: Link,
+    status: tokio::sync::watch::Sender<ConnectionStatus>,
     token: Option<String>,
     request_id: u32,
+    ping_id: u32,
+    buffered: VecDeque<Buffered>,
+    replayed: usize,
+    requests: PendingRequests,
+    disconnect_report: Option<DisconnectReport>,
+    history: ConnectionHistory,
+    in_meeting: Option<bool>,
+    meeting_state: Option<MeetingState>,
+    state_updated: Option<Instant>,
+    permissions: Option<MeetingPermissions>,
+    next_state_refresh: Option<tokio::time::Instant>,
+    state_refresh_id: Option<u32>,
+    next_keepalive: Option<tokio::time::Instant>,
+    keepalive_ping: Option<Vec<u8>>,
+    protocol_version: Option<Cow<'static, str>>,
+    handshake_response: Option<HandshakeResponse>,
     url: String,
+    settings: ResolvedSettings,
+    options: ConnectionOptions,
+    #[cfg(feature = "audit")]
+    audit_log: Option<audit::AuditLog>,
+    confirmation_hook: Option<ConfirmationHook>,
+    command_queue: Option<CommandQueue>,
+    arbiter: Option<Arbiter>,
+    rate_limiter: Option<RateLimiter>,
+    shutdown_signal: ShutdownSignal,
+    malformed_frames: Option<UnboundedSender<MalformedFrame>>,
+    token_store: Option<Box<dyn TokenStore>>,
+    raw_frame_hook: Option<RawFrameHook>,
+    connector: Option<Box<dyn Connector>>,
+    recorder: Option<Recorder>,
+    message_observer: Option<MessageObserver>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<metrics::Metrics>,
+}
+
+/// Never prints the token, URLs are redacted.
+impl std::fmt::Debug for TeamsWebsocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TeamsWebsocket")
+            .field("identifier", &self.identifier)
+            .field("url", &redact::redact_url(&self.url))
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("status", &self.link.status())
+            .field("protocol_version", &self.protocol_version)
+            .field("request_id", &self.request_id)
+            .field("in_meeting", &self.in_meeting)
+            .field("options", &self.options)
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
 }
 
 const SOCKET_NOT_CONNECTED: &str = "socket not connected";
 
+/// The state machine of a `TeamsWebsocket` connection, the socket only exists
+/// in the states that can use it.
+enum Link {
+    Disconnected,
+    Connecting,
+    Connected(Socket),
+    Reconnecting,
+    /// Closed by this side, the socket is kept to read Teams' answer to the
+    /// Close frame until `shutdown` or the next `connect` releases it.
+    Closed(Option<Socket>),
+}
+
+impl Link {
+    fn status(&self) -> ConnectionStatus {
+        match self {
+            Link::Disconnected => ConnectionStatus::Disconnected,
+            Link::Connecting => ConnectionStatus::Connecting,
+            Link::Connected(_) => ConnectionStatus::Connected,
+            Link::Reconnecting => ConnectionStatus::Reconnecting,
+            Link::Closed(_) => ConnectionStatus::Closed,
+        }
+    }
+
+    /// Returns the socket, also after `close` until Teams answered.
+    fn socket(&mut self) -> Option<&mut Socket> {
+        match self {
+            Link::Connected(socket) | Link::Closed(Some(socket)) => Some(socket),
+            _ => None,
+        }
+    }
+
+    fn take_socket(&mut self) -> Option<Socket> {
+        match std::mem::replace(self, Link::Disconnected) {
+            Link::Connected(socket) | Link::Closed(Some(socket)) => Some(socket),
+            link => {
+                *self = link;
+                None
+            }
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        matches!(self, Link::Connected(_))
+    }
+}
+
+/// A frame kept for `receive` by `read_ahead` or `ping`.
+struct Buffered {
+    frame: Message,
+    /// The message `read_ahead` handled the frame as. Kept, as the meeting
+    /// state a partial update was merged into may have changed since.
+    handled: Option<ServerMessage>,
+}
+
+/// A callback receiving the raw text of every frame Teams sends, e.g. to
+/// debug fields this crate does not model, see `ServerMessage::extra`.
+pub type RawFrameHook = Box<dyn Fn(&str) + Send + Sync>;
+
+/// A callback receiving the raw text of every frame sent to and received
+/// from Teams, e.g. to keep a debugging or audit trail without enabling
+/// trace logging for the whole application.
+pub type MessageObserver = Box<dyn Fn(Direction, &str) + Send + Sync>;
+
+/// How long `shutdown` waits for Teams to answer the Close frame unless
+/// `ConnectionOptions::connect_timeout` is set.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns the next request id of `counter`, wrapping around after `u32::MAX`.
+fn next_request_id(counter: &mut u32) -> u32 {
+    let id = *counter;
+    *counter = counter.wrapping_add(1);
+    id
+}
+
+/// Returns the request id of `message`, assigning the next one of `counter`
+/// that is not `reserved` if the caller did not set one.
+fn assign_request_id(
+    counter: &mut u32,
+    message: &mut ClientMessage,
+    reserved: impl Fn(u32) -> bool,
+) -> u32 {
+    match message.request_id {
+        Some(id) => id,
+        None => {
+            let mut id = next_request_id(counter);
+            while reserved(id) {
+                id = next_request_id(counter);
+            }
+            *message.request_id.insert(id)
+        }
+    }
+}
+
+/// Returns whether `id` is used by a request waiting for Teams' answer or
+/// by a queued command.
+fn request_id_reserved(requests: &PendingRequests, queue: Option<&CommandQueue>, id: u32) -> bool {
+    requests.contains(id) || queue.is_some_and(|queue| queue.contains_request(id))
+}
+
+/// Writes a frame to `recorder` and passes it to `observer`, if set,
+/// logging failures.
+fn record_frame(
+    recorder: Option<&Recorder>,
+    observer: Option<&MessageObserver>,
+    direction: Direction,
+    text: &str,
+) {
+    if let Some(Err(e)) = recorder.map(|recorder| recorder.record(direction, text)) {
+        warn!(target: logging::CONNECTION, "Error writing recording: {}", e);
+    }
+    if let Some(observer) = observer {
+        observer(direction, text);
+    }
+}
+
+/// Returns whether `error` is Teams refusing the WebSocket handshake, as
+/// opposed to e.g. nothing listening on the port.
+fn is_handshake_rejected(error: &(dyn Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<TeamsWsError>(),
+        Some(TeamsWsError::Connect { source, .. }) if matches!(**source, tungstenite::Error::Http(_))
+    )
+}
+
 impl TeamsWebsocket {
+    /// Creates a websocket for `url`, or the default URL, authenticating
+    /// with `token`.
+    ///
+    /// Only covers the URL and the token; use `TeamsWebsocket::builder` for
+    /// timeouts, reconnecting, TLS pins and the other options.
+    #[deprecated(note = "use TeamsWebsocket::builder")]
     pub async fn new(
         identifier: AppIdentifiers,
         token: Option<String>,
         url: Option<String>,
+    ) -> Self {
+        let mut resolver = SettingsResolver::new();
+        if let Some(url) = url {
+            resolver = resolver.explicit(SettingKey::Url, url);
+        }
+        if let Some(token) = token {
+            resolver = resolver.explicit(SettingKey::Token, token);
+        }
+        Self::from_settings(identifier, resolver.resolve(), ConnectionOptions::default())
+    }
+
+    /// Splits the websocket into a half sending commands and a half
+    /// receiving messages, so they can be used from different tasks.
+    ///
+    /// The websocket moves into a background task like with `TeamsClient::run`,
+    /// which connects it unless connected; call this inside a tokio runtime.
+    ///
+    /// # Example
+    /// ```rust
+    /// let (sender, mut receiver) = websocket.split();
+    /// tokio::spawn(async move {
+    ///     while let Ok(message) = receiver.receive().await {
+    ///         println!("{}", message);
+    ///     }
+    /// });
+    /// sender.send(ClientMessage::new(MeetingAction::ToggleMute, None)).await?;
+    /// ```
+    pub fn split(self) -> (client::TeamsSender, client::TeamsReceiver) {
+        client::split(self)
+    }
+
+    /// Creates a `TeamsWebsocketBuilder` for the given app identifiers.
+    pub fn builder(identifier: AppIdentifiers) -> TeamsWebsocketBuilder {
+        TeamsWebsocketBuilder::new(identifier)
+    }
+
+    pub(crate) fn from_settings(
+        identifier: AppIdentifiers,
+        settings: ResolvedSettings,
+        options: ConnectionOptions,
     ) -> Self {
         Self {
             identifier,
-            socket: None,
-            token,
-            request_id: 0,
-            url: url.unwrap_or_else(|| "ws://127.0.0.1:8124".to_string()),
+            link: Link::Disconnected,
+            status: tokio::sync::watch::Sender::new(ConnectionStatus::Disconnected),
+            token: settings.get(SettingKey::Token).map(str::to_string),
+            request_id: options.request_id_start.first(),
+            ping_id: 0,
+            buffered: VecDeque::new(),
+            replayed: 0,
+            requests: PendingRequests::default(),
+            disconnect_report: None,
+            history: ConnectionHistory::default(),
+            in_meeting: None,
+            meeting_state: None,
+            state_updated: None,
+            permissions: None,
+            next_state_refresh: None,
+            state_refresh_id: None,
+            next_keepalive: None,
+            keepalive_ping: None,
+            protocol_version: None,
+            handshake_response: None,
+            url: settings
+                .get(SettingKey::Url)
+                .unwrap_or(settings::DEFAULT_URL)
+                .to_string(),
+            settings,
+            options,
+            #[cfg(feature = "audit")]
+            audit_log: None,
+            confirmation_hook: None,
+            command_queue: None,
+            arbiter: None,
+            rate_limiter: None,
+            shutdown_signal: ShutdownSignal::new(),
+            malformed_frames: None,
+            token_store: None,
+            raw_frame_hook: None,
+            connector: None,
+            recorder: None,
+            message_observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Returns the effective settings and the layer each value came from.
+    pub fn settings(&self) -> &ResolvedSettings {
+        &self.settings
+    }
+
+    /// Returns the `ConnectionOptions` used when connecting.
+    pub fn options(&self) -> &ConnectionOptions {
+        &self.options
+    }
+
+    /// Consults `hook` before sending the actions it covers.
+    pub fn set_confirmation_hook(&mut self, hook: Option<ConfirmationHook>) {
+        self.confirmation_hook = hook;
+    }
+
+    pub(crate) fn confirmation_hook(&self) -> Option<&ConfirmationHook> {
+        self.confirmation_hook.as_ref()
+    }
+
+    /// Switches dry-run mode, in which `send` only logs the messages it would send.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.options.dry_run = dry_run;
+    }
+
+    /// Resolves conflicting commands from several sources with `arbiter`.
+    pub fn set_arbiter(&mut self, arbiter: Option<Arbiter>) {
+        self.arbiter = arbiter;
+    }
+
+    /// Stops receiving and reconnecting once `signal` is triggered.
+    pub fn set_shutdown_signal(&mut self, signal: ShutdownSignal) {
+        self.shutdown_signal = signal;
+    }
+
+    /// Returns the signal stopping receiving and reconnecting, to trigger
+    /// it from another task, see `ShutdownSignal`.
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.shutdown_signal.clone()
+    }
+
+    /// Limits how often commands are sent with `rate_limiter`.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    /// Delivers frames that could not be parsed to `sender`, see `receive_resilient`.
+    pub fn set_malformed_frames(&mut self, sender: Option<UnboundedSender<MalformedFrame>>) {
+        self.malformed_frames = sender;
+    }
+
+    /// Persists the tokens Teams sends in `store`.
+    pub fn set_token_store(&mut self, store: Option<Box<dyn TokenStore>>) {
+        self.token_store = store;
+    }
+
+    /// Opens the connections with `connector` instead of tokio-tungstenite.
+    pub fn set_connector(&mut self, connector: Option<Box<dyn Connector>>) {
+        self.connector = connector;
+    }
+
+    /// Writes every frame sent and received to `recorder`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
+    /// Passes the raw text of every frame sent to and received from Teams to
+    /// `observer`, including the frames of the task of a `TeamsClient` and
+    /// of reconnects. Frames not sent in dry-run mode are not passed.
+    ///
+    /// # Example
+    /// ```rust
+    /// websocket.set_message_observer(Some(Box::new(|direction, text| {
+    ///     eprintln!("{:?} {}", direction, text);
+    /// })));
+    /// ```
+    pub fn set_message_observer(&mut self, observer: Option<MessageObserver>) {
+        self.message_observer = observer;
+    }
+
+    /// Passes the raw text of every frame Teams sends to `hook`, once, before
+    /// it is parsed. Binary frames are decoded lossily.
+    pub fn set_raw_frame_hook(&mut self, hook: Option<RawFrameHook>) {
+        self.raw_frame_hook = hook;
+    }
+
+    /// Returns why the last connection ended, or `None` while connected.
+    pub fn disconnect_report(&self) -> Option<&DisconnectReport> {
+        self.disconnect_report.as_ref()
+    }
+
+    /// Returns the recent connection-level events, see `ConnectionHistory`.
+    pub fn history(&self) -> &ConnectionHistory {
+        &self.history
+    }
+
+    /// Returns the current state of the connection.
+    pub fn status(&self) -> ConnectionStatus {
+        self.link.status()
+    }
+
+    /// Returns a channel following the state of the connection, e.g. to show
+    /// it in a user interface. It sees every change made while it is polled.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut status = websocket.watch_status();
+    /// tokio::spawn(async move {
+    ///     while status.changed().await.is_ok() {
+    ///         println!("Teams is {}", *status.borrow());
+    ///     }
+    /// });
+    /// ```
+    pub fn watch_status(&self) -> tokio::sync::watch::Receiver<ConnectionStatus> {
+        self.status.subscribe()
+    }
+
+    /// Moves the connection to `link` and publishes its status if it changed.
+    fn set_link(&mut self, link: Link) {
+        let status = link.status();
+        self.link = link;
+        self.status.send_if_modified(|current| {
+            let changed = *current != status;
+            if changed {
+                debug!(target: logging::CONNECTION, "Connection {} -> {}", current, status);
+                *current = status;
+            }
+            changed
+        });
+    }
+
+    /// Records why the connection ended, unless it is known already, and
+    /// leaves the connected state.
+    fn record_disconnect(&mut self, report: impl FnOnce() -> DisconnectReport) {
+        if self.disconnect_report.is_none() {
+            let report = report();
+            self.history
+                .push(ConnectionEventKind::Disconnected(report.clone()));
+            self.disconnect_report = Some(report);
+        }
+        if self.link.is_connected() {
+            let by_client = self
+                .disconnect_report
+                .as_ref()
+                .is_some_and(|report| report.initiated_by == DisconnectInitiator::Client);
+            let socket = self.link.take_socket();
+            self.set_link(match by_client {
+                true => Link::Closed(socket),
+                false => Link::Disconnected,
+            });
         }
     }
 
+    /// Returns whether Teams is in a meeting, as last reported by Teams, or
+    /// `None` before it reported anything on this connection.
+    pub fn is_in_meeting(&self) -> Option<bool> {
+        self.in_meeting
+    }
+
+    /// Returns the meeting state Teams last reported on this connection, or
+    /// `None` before it reported one. See `ready` to wait for it.
+    pub fn meeting_state(&self) -> Option<&MeetingState> {
+        self.meeting_state.as_ref()
+    }
+
+    /// Returns the meeting state Teams last reported on this connection
+    /// with when it did, or `None` before it reported one.
+    pub fn state_snapshot(&self) -> Option<StateSnapshot> {
+        let state = self.meeting_state.clone()?;
+        Some(StateSnapshot::new(state, self.state_updated?))
+    }
+
+    /// Returns the meeting permissions Teams last reported on this
+    /// connection, or `None` before it reported any.
+    pub fn permissions(&self) -> Option<&MeetingPermissions> {
+        self.permissions.as_ref()
+    }
+
+    /// Returns the sent requests Teams did not answer yet, oldest first.
+    pub fn pending_requests(&self) -> impl Iterator<Item = &PendingRequest> {
+        self.requests.iter()
+    }
+
+    /// Stops waiting for the answer to request `id` and returns it, or
+    /// `None` if it was answered already.
+    ///
+    /// Teams is not told, so the action may still take effect.
+    pub fn cancel_request(&mut self, id: u32) -> Option<PendingRequest> {
+        self.requests.resolve(id)
+    }
+
+    /// Stops waiting for the requests older than `max_age` and returns them,
+    /// so daemons can report commands Teams never answered.
+    pub fn expire_requests(&mut self, max_age: Duration) -> Vec<PendingRequest> {
+        self.requests.expire(max_age)
+    }
+
+    /// Queues messages sent while not connected in `queue` and sends them on `connect`.
+    pub fn set_command_queue(&mut self, queue: Option<CommandQueue>) {
+        self.command_queue = queue;
+    }
+
+    /// Returns the command queue, if set.
+    pub fn command_queue(&self) -> Option<&CommandQueue> {
+        self.command_queue.as_ref()
+    }
+
+    /// Records every message `send` sent in `audit_log`, as it was sent.
+    #[cfg(feature = "audit")]
+    pub fn set_audit_log(&mut self, audit_log: Option<audit::AuditLog>) {
+        self.audit_log = audit_log;
+    }
+
+    /// Records the activity of the connection in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Option<metrics::Metrics>) {
+        self.metrics = metrics;
+    }
+
     /// Connects to the WebSocket server using the provided URL and parameters.
     ///
     /// # Errors
     ///
     /// Returns an error if the URL cannot be parsed or if the connection attempt fails.
+    /// Connecting to a non-loopback host fails with `TeamsWsError::RemoteNotAllowed`
+    /// unless `ConnectionOptions::allow_remote` is set.
+    ///
+    /// While connected, the connection is kept, or with
+    /// `AlreadyConnected::Fail` the call fails with `TeamsWsError::AlreadyConnected`.
+    /// A connection closed with `close` is released.
+    ///
+    /// Once connected, the commands of the command queue are sent and, with
+    /// `ConnectionOptions::query_state_on_connect`, the meeting state is awaited.
     ///
     /// # Examples
     ///
     /// ```
     /// use ms_teams_ws::messages;
     /// use ms_teams_ws::TeamsWebsocket;
-    /// let mut websocket = TeamsWebsocket::new(identifier, token, url).await;
+    /// let mut websocket = TeamsWebsocket::builder(identifier).url(url).token(token).build()?;
     /// match websocket.connect().await {
     ///     Ok(_) => println!("Connected successfully"),
     ///     Err(e) => eprintln!("Failed to connect: {}", e),
     /// }
     /// ```
     pub async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        let url = Url::parse_with_params(
-            &self.url,
-            &[
-                ("protocol-version", self.identifier.protocol_version),
-                ("manufacturer", self.identifier.manufacturer),
-                ("device", self.identifier.device),
-                ("app", self.identifier.app),
-                ("app-version", self.identifier.app_version),
-                ("token", self.token.as_deref().unwrap_or("")),
-            ],
+        if self.link.is_connected() {
+            if self.options.already_connected == AlreadyConnected::Fail {
+                let e = TeamsWsError::AlreadyConnected;
+                warn!(target: logging::CONNECTION, "{}", e);
+                return Err(Box::new(e));
+            }
+            debug!(target: logging::CONNECTION, "Already connected");
+            return Ok(());
+        }
+        // Close was sent already, Teams' answer is not awaited anymore.
+        drop(self.link.take_socket());
+        let span = span!(
+            target: logging::CONNECTION,
+            "connect",
+            connection = self.history.connections() + 1
         );
-        if let Err(e) = url {
-            log::warn!("Error parsing url: {}", e);
-            return Err(Box::new(e));
+        if !matches!(self.link, Link::Reconnecting) {
+            self.set_link(Link::Connecting);
+        }
+        let connected = self.connect_inner().instrument(span).await;
+        if connected.is_err() && matches!(self.link, Link::Connecting) {
+            self.set_link(Link::Disconnected);
         }
-        let url = url.unwrap();
+        connected
+    }
 
-        let (socket, response) = match connect_async(url.as_str()).await {
-            Ok((socket, response)) => (socket, response),
-            Err(e) => {
-                log::warn!("Error: {}", e);
+    async fn connect_inner(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.options.allow_remote {
+            let host = options::url_host(&self.url);
+            if !host.as_deref().is_some_and(options::is_loopback_host) {
+                let e = TeamsWsError::RemoteNotAllowed {
+                    host: host.unwrap_or_default(),
+                };
+                warn!(target: logging::CONNECTION, "{}", e);
                 return Err(Box::new(e));
             }
-        };
+        }
+        let mut protocol_versions = vec![self.identifier.protocol_version.clone()];
+        let mut fallbacks = self.options.fallback_protocol_versions.clone();
+        if self.options.detect_flavor {
+            fallbacks.extend(TeamsFlavor::ALL.map(TeamsFlavor::protocol_version));
+        }
+        for version in fallbacks {
+            if !protocol_versions.iter().any(|known| known == version) {
+                protocol_versions.push(Cow::Borrowed(version));
+            }
+        }
+        let reconnect = self.history.connections() > 0;
+        self.protocol_version = None;
+        let mut protocol_versions = protocol_versions.into_iter().enumerate().peekable();
+        while let Some((attempt, protocol_version)) = protocol_versions.next() {
+            if attempt > 0 {
+                info!(
+                    target: logging::CONNECTION,
+                    "Retrying with protocol version {}",
+                    protocol_version
+                );
+            }
+            self.history.push(ConnectionEventKind::Connecting {
+                protocol_version: protocol_version.to_string(),
+                reconnect,
+            });
+            match self.connect_with(&protocol_version).await {
+                Ok(()) => {
+                    self.history.push(ConnectionEventKind::Connected {
+                        protocol_version: protocol_version.to_string(),
+                    });
+                    self.protocol_version = Some(protocol_version);
+                    break;
+                }
+                Err(e) => {
+                    self.history.push(ConnectionEventKind::ConnectFailed {
+                        reason: e.to_string(),
+                    });
+                    // Only a rejected handshake may be due to the protocol version.
+                    if !is_handshake_rejected(e.as_ref()) || protocol_versions.peek().is_none() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        self.buffered.clear();
+        self.replayed = 0;
+        self.requests.clear();
+        self.disconnect_report = None;
+        self.in_meeting = None;
+        self.meeting_state = None;
+        self.state_updated = None;
+        self.permissions = None;
+        self.next_state_refresh = self
+            .options
+            .state_refresh
+            .map(|interval| tokio::time::Instant::now() + interval);
+        self.state_refresh_id = None;
+        self.next_keepalive = self
+            .options
+            .keepalive
+            .map(|interval| tokio::time::Instant::now() + interval);
+        self.keepalive_ping = None;
+        self.replay_queue().await;
+        if self.options.query_state_on_connect {
+            let timeout = self.options.connect_timeout;
+            let query = self.await_state();
+            match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, query).await {
+                    Ok(result) => result?,
+                    Err(_) => return Err(Box::from("Teams did not answer the state query")),
+                },
+                None => query.await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Connects unless connected, queries the meeting state and waits until
+    /// Teams answered, so startup code can trust `meeting_state` and
+    /// `is_in_meeting` afterwards.
+    ///
+    /// Messages received while waiting, including the answer, are kept for
+    /// `receive`, so state trackers still see the initial update. If Teams
+    /// is not in a call it answers with an error and `meeting_state` stays
+    /// `None`. In dry-run mode the query is not sent and `ready` does not wait.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if connecting, sending the query or receiving fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// tokio::time::timeout(Duration::from_secs(5), websocket.ready()).await??;
+    /// if websocket.meeting_state().is_some_and(|state| state.is_muted) {
+    ///     println!("Muted");
+    /// }
+    /// ```
+    pub async fn ready(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.link.is_connected() {
+            self.connect().await?;
+            if self.options.query_state_on_connect {
+                return Ok(());
+            }
+        }
+        self.await_state().await
+    }
 
-        if log::log_enabled!(log::Level::Debug) {
-            log::debug!("Connected to the server");
-            log::debug!("Response HTTP code: {}", response.status());
-            log::debug!("Response contains the following headers:");
-            for (header, _value) in response.headers() {
-                log::trace!("* {header}");
+    /// Shuts the connection down, if any, like `shutdown` and connects again,
+    /// e.g. after changing networks or to apply a token granted elsewhere.
+    ///
+    /// The token is reloaded from the `TokenStore`, which holds the last
+    /// one Teams sent to any client sharing it. Without a store, or if it
+    /// is empty, the last token Teams sent this client is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `connect`. Errors shutting the old connection
+    /// down and reading the token store are logged.
+    pub async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.shutdown().await {
+            warn!(target: logging::CONNECTION, "Error shutting down before reconnecting: {}", e);
+        }
+        if let Some(store) = &self.token_store {
+            match store.load() {
+                Ok(Some(token)) => self.token = Some(token),
+                Ok(None) => {}
+                Err(e) => warn!(target: logging::CONNECTION, "Error loading token: {}", e),
             }
         }
-        self.socket = Some(socket);
+        self.connect().await
+    }
+
+    /// Queries the meeting state and waits for the answer, see `ready`.
+    async fn await_state(&mut self) -> Result<(), Box<dyn Error>> {
+        let id = self
+            .send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+            .await?;
+        if self.requests.iter().any(|request| request.id == id) {
+            self.read_ahead(true, |message| {
+                message.request_id == Some(id)
+                    || message
+                        .meeting_update
+                        .as_ref()
+                        .is_some_and(|update| update.meeting_state.is_some())
+            })
+            .await?;
+        }
+        info!(target: logging::CONNECTION, "Ready, in meeting: {:?}", self.in_meeting);
         Ok(())
     }
-    
-    /// Sends a `ClientMessage` to Teams.
+
+    /// Pairs with Teams: connects without a token and waits until Teams
+    /// grants one, which is returned and used for later connects.
     ///
-    /// # Arguments
+    /// Teams only pairs while the meeting permissions allow it (`can_pair`),
+    /// so the meeting state is queried and, once pairing is allowed, queried
+    /// again for Teams to ask the user to allow the app. This waits until the
+    /// user decided; wrap the call in a timeout. The granted token is stored
+    /// in the token store, if set. Messages received while waiting are kept
+    /// for `receive`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in dry-run mode and if closing, connecting, sending
+    /// or receiving fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// let token = timeout(Duration::from_secs(60), websocket.pair()).await??;
+    /// ```
+    pub async fn pair(&mut self) -> Result<String, Box<dyn Error>> {
+        if self.options.dry_run {
+            return Err(Box::from("cannot pair in dry-run mode"));
+        }
+        if self.link.is_connected() {
+            self.close().await?;
+        }
+        self.token = None;
+        self.connect().await?;
+        self.send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+            .await?;
+        let mut requested = false;
+        loop {
+            let message = self
+                .read_ahead(true, |message| {
+                    message.token_refresh.is_some()
+                        || (!requested
+                            && message
+                                .meeting_update
+                                .as_ref()
+                                .and_then(|update| update.meeting_permissions.as_ref())
+                                .is_some_and(|permissions| permissions.can_pair))
+                })
+                .await?;
+            if let Some(token) = message.token_refresh {
+                info!(target: logging::CONNECTION, "Paired with Teams");
+                return Ok(token);
+            }
+            info!(
+                target: logging::CONNECTION,
+                "Pairing allowed, waiting for the user to allow the app"
+            );
+            self.send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+                .await?;
+            requested = true;
+        }
+    }
+
+    /// Sends `message` and waits for Teams' answer to it, the message
+    /// carrying its request id.
     ///
-    /// * `message` - The `ClientMessage` to be sent.
+    /// Messages received while waiting, e.g. meeting updates, are kept for
+    /// `receive`. Teams does not answer if it hangs, so wrap the call in a
+    /// timeout; messages read until then stay kept.
     ///
     /// # Errors
     ///
-    /// Returns an error if the WebSocket connection is not established, if the message cannot be serialized, or if there is an error sending the message.
+    /// Returns the errors of `send`, an error if the message was not sent
+    /// because of dry-run mode or the command queue, and the errors of
+    /// receiving, except for malformed frames.
     ///
-    /// # Examples
+    /// # Example
+    /// ```rust
+    /// let query = ClientMessage::new(MeetingAction::QueryMeetingState, None);
+    /// let answer = timeout(Duration::from_secs(2), websocket.send_and_wait(query)).await??;
+    /// ```
+    pub async fn send_and_wait<A: Action>(
+        &mut self,
+        action: A,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        let message = action.into_message()?;
+        let name = message.wire_name();
+        let id = self.send(message).await?;
+        if !self.requests.iter().any(|request| request.id == id) {
+            return Err(Box::from(format!(
+                "{} was not sent, no answer to wait for",
+                name
+            )));
+        }
+        self.read_ahead(false, |message| message.request_id == Some(id))
+            .await
+    }
+
+    /// Sends `messages` in order, e.g. a `scene!` switched by one button of
+    /// a stream deck, and returns their request ids.
     ///
-    /// 
-    pub async fn send(&mut self, message: ClientMessage) -> Result<(), Box<dyn Error>> {
-        if let Some(socket) = &mut self.socket {
-            let mut message = message;
-            message.request_id = Some(self.request_id);
-            self.request_id += 1;
-            let serialized_message = serde_json::to_string(&message);
-            log::debug!("Sending message: {:?}", serialized_message);
-            match serialized_message {
-                Ok(msg) => {
-                    if let Err(e) = socket
-                    .send(tungstenite::Message::Text(msg))
-                    .await
-                    {
-                        log::warn!("Error sending message: {}", e);
-                        return Err(Box::new(e));
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Error serializing message: {}", e);
-                    return Err(Box::new(e));
-                }
-            } 
-            return Ok(());
+    /// Messages without a request id get consecutive ones. Sending stops at
+    /// the first message that fails, the ones before it stay sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first message `send` fails for.
+    ///
+    /// # Example
+    /// ```rust
+    /// let ids = websocket
+    ///     .send_all(scene![Command::Unmute, Command::ShowVideo, Command::UnblurBackground])
+    ///     .await?;
+    /// ```
+    pub async fn send_all(
+        &mut self,
+        messages: Vec<ClientMessage>,
+    ) -> Result<Vec<u32>, Box<dyn Error>> {
+        let mut ids = Vec::with_capacity(messages.len());
+        for message in messages {
+            ids.push(self.send(message).await?);
         }
-        log::warn!("{}", SOCKET_NOT_CONNECTED);
-        Err(Box::from(SOCKET_NOT_CONNECTED))
-        
+        Ok(ids)
     }
 
-    pub async fn receive(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
-        if let Some(socket) = &mut self.socket {
-            match socket.next().await {
-                Some(Ok(msg)) => {
-                    let server_message =
-                        serde_json::from_str::<ServerMessage>(msg.to_text().unwrap());
-                    match server_message {
-                        Ok(json) => {
-                            Ok(json)
-                        }
-                        Err(e) => {
-                            log::warn!("Error parsing json : {}", e);
-                            Err(Box::new(e))
+    /// Sends `messages` like `send_all` and waits for Teams' answers to all
+    /// of them, returned in the order of `messages`.
+    ///
+    /// Messages received while waiting are kept for `receive`, like with
+    /// `send_and_wait`; wrap the call in a timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `send_all`, an error if a message was not sent
+    /// because of dry-run mode or the command queue, and the errors of
+    /// receiving, except for malformed frames.
+    pub async fn send_all_and_wait(
+        &mut self,
+        messages: Vec<ClientMessage>,
+    ) -> Result<Vec<ServerMessage>, Box<dyn Error>> {
+        let names: Vec<_> = messages.iter().map(|message| message.wire_name()).collect();
+        let ids = self.send_all(messages).await?;
+        for (id, name) in ids.iter().zip(names) {
+            if !self.requests.iter().any(|request| request.id == *id) {
+                return Err(Box::from(format!(
+                    "{} was not sent, no answer to wait for",
+                    name
+                )));
+            }
+        }
+        let mut answers = Vec::with_capacity(ids.len());
+        for id in ids {
+            let answer = self
+                .read_ahead(false, |message| message.request_id == Some(id))
+                .await?;
+            answers.push(answer);
+        }
+        Ok(answers)
+    }
+
+    /// Handles the kept and then the incoming messages until `matches` one,
+    /// which is kept for `receive` too if `keep_match`. The other messages
+    /// stay kept, `receive` only repeats idempotent bookkeeping for them.
+    ///
+    /// Cancelling the future loses no messages, it fails with
+    /// `TeamsWsError::Cancelled` once the shutdown signal is triggered.
+    async fn read_ahead<F>(
+        &mut self,
+        keep_match: bool,
+        matches: F,
+    ) -> Result<ServerMessage, Box<dyn Error>>
+    where
+        F: FnMut(&ServerMessage) -> bool,
+    {
+        let signal = self.shutdown_signal.clone();
+        signal
+            .guard(self.read_ahead_inner(keep_match, matches))
+            .await
+    }
+
+    async fn read_ahead_inner<F>(
+        &mut self,
+        keep_match: bool,
+        mut matches: F,
+    ) -> Result<ServerMessage, Box<dyn Error>>
+    where
+        F: FnMut(&ServerMessage) -> bool,
+    {
+        let mut position = 0;
+        loop {
+            let (msg, handled) = match self.buffered.get(position) {
+                Some(buffered) => (buffered.frame.clone(), buffered.handled.clone()),
+                None => {
+                    let Some(socket) = self.link.socket() else {
+                        warn!(target: logging::CONNECTION, "{}", SOCKET_NOT_CONNECTED);
+                        return Err(Box::from(SOCKET_NOT_CONNECTED));
+                    };
+                    match socket.next().await {
+                        Some(Ok(msg)) => {
+                            self.buffered.push_back(Buffered {
+                                frame: msg.clone(),
+                                handled: None,
+                            });
+                            (msg, None)
                         }
+                        next => return self.handle_frame(next, false),
                     }
-                },
-                Some(Err(e)) => {
-                    log::warn!("Error reading from socket {}", e);
-                    Err(Box::new(e))
                 }
-                None => {
-                    log::info!("Socket closed");
-                    Err(Box::from("socket closed"))
+            };
+            let first_read = position >= self.replayed;
+            position += 1;
+            self.replayed = self.replayed.max(position);
+            if matches!(msg, Message::Ping(_) | Message::Pong(_)) {
+                continue;
+            }
+            if first_read {
+                self.observe_frame(&msg);
+            }
+            let handled = match handled {
+                Some(message) => Ok(message),
+                None => self.handle_frame(Some(Ok(msg)), !first_read),
+            };
+            match handled {
+                Ok(message) => {
+                    if first_read {
+                        if let Some(token) = &message.token_refresh {
+                            self.token_refreshed(token);
+                        }
+                        self.buffered[position - 1].handled = Some(message.clone());
+                    }
+                    if matches(&message) {
+                        if !keep_match {
+                            self.buffered.remove(position - 1);
+                            self.replayed -= 1;
+                        }
+                        return Ok(message);
+                    }
                 }
+                // Malformed frames are reported when `receive` reads them.
+                Err(e) if matches!(e.downcast_ref(), Some(TeamsWsError::Malformed(_))) => {}
+                Err(e) => return Err(e),
             }
-        } else {
-            log::warn!("{}", SOCKET_NOT_CONNECTED);
-            Err(Box::from(SOCKET_NOT_CONNECTED))
         }
     }
 
-    pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
-        if let Some(socket) = &mut self.socket {
-            if let Err(e) = socket.close(None).await {
-                log::warn!("Error closing socket: {}", e);
-                return Err(Box::new(e));
-            }
-            log::info!("Connection closed");
-            Ok(())
-        } else {
-            log::warn!("{}", SOCKET_NOT_CONNECTED);
-            Err(Box::from(SOCKET_NOT_CONNECTED))
-        }
+    /// Returns the wire format of the messages, derived from the protocol
+    /// version Teams accepted, or the one of the identifiers before connecting.
+    pub fn protocol(&self) -> ProtocolVersion {
+        ProtocolVersion::from_version(
+            self.protocol_version
+                .as_deref()
+                .unwrap_or(&self.identifier.protocol_version),
+        )
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand::Rng;
-    use std::net::SocketAddr;
+    /// Returns the flavor of the connected Teams client, derived from the
+    /// accepted protocol version, or `None` before connecting.
+    pub fn flavor(&self) -> Option<TeamsFlavor> {
+        self.protocol_version
+            .as_deref()
+            .map(TeamsFlavor::from_protocol_version)
+    }
+
+    /// Returns the URL and negotiated protocol version of the connection,
+    /// or `None` before connecting.
+    pub fn connection_info(&self) -> Option<ConnectionInfo> {
+        let protocol_version = self.protocol_version.clone()?;
+        Some(ConnectionInfo {
+            url: self.url.clone(),
+            flavor: TeamsFlavor::from_protocol_version(&protocol_version),
+            protocol_version,
+        })
+    }
+
+    /// Returns the status and headers of the HTTP response the connection
+    /// was upgraded with, or `None` before connecting and for connections
+    /// opened by a `Connector`.
+    pub fn handshake_response(&self) -> Option<&HandshakeResponse> {
+        self.handshake_response.as_ref()
+    }
+
+    /// Opens the socket advertising `protocol_version`.
+    async fn connect_with(&mut self, protocol_version: &str) -> Result<(), Box<dyn Error>> {
+        self.handshake_response = None;
+        let mut params = vec![
+            ("protocol-version", protocol_version),
+            ("manufacturer", &self.identifier.manufacturer),
+            ("device", &self.identifier.device),
+            ("app", &self.identifier.app),
+            ("app-version", &self.identifier.app_version),
+            ("token", self.token.as_deref().unwrap_or("")),
+        ];
+        params.extend(
+            self.options
+                .query_params
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        #[cfg(all(feature = "url", not(feature = "slim")))]
+        let url = url::Url::parse_with_params(&self.url, &params)
+            .map(String::from)
+            .map_err(Box::<dyn Error>::from);
+        #[cfg(any(feature = "slim", not(feature = "url")))]
+        let url = query::url_with_params(&self.url, &params);
+        if let Err(e) = url {
+            warn!(target: logging::CONNECTION, "Error parsing url: {}", e);
+            return Err(e);
+        }
+        let url = SecretUrl::new(url.unwrap());
+        debug!(target: logging::CONNECTION, "Connecting to {}", url);
+        if let Some(connector) = &self.connector {
+            let opened = match self.options.connect_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, connector.connect(url.expose()))
+                    .await
+                    .unwrap_or_else(|_| Err(Box::from("connecting timed out"))),
+                None => connector.connect(url.expose()).await,
+            };
+            let transport = match opened {
+                Ok(transport) => transport,
+                Err(e) => {
+                    let e = TeamsWsError::Connect {
+                        url,
+                        source: Box::new(tungstenite::Error::Io(std::io::Error::other(e))),
+                    };
+                    warn!(target: logging::CONNECTION, "Error: {}", e);
+                    return Err(Box::new(e));
+                }
+            };
+            debug!(target: logging::CONNECTION, "Connected through the connector");
+            self.set_link(Link::Connected(Socket::transport(transport)));
+            return Ok(());
+        }
+
+        let opened = match self.options.connect_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, self.open_socket(&url)).await {
+                Ok(opened) => opened,
+                Err(_) => Err(tungstenite::Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "handshake timed out",
+                ))),
+            },
+            None => self.open_socket(&url).await,
+        };
+        let (socket, response) = match opened {
+            Ok((socket, response)) => (socket, response),
+            Err(e) => {
+                let e = TeamsWsError::Connect {
+                    url,
+                    source: Box::new(e),
+                };
+                warn!(target: logging::CONNECTION, "Error: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        debug!(target: logging::CONNECTION, "Connected to the server");
+        debug!(target: logging::CONNECTION, "Response HTTP code: {}", response.status());
+        debug!(target: logging::CONNECTION, "Response contains the following headers:");
+        for (header, _value) in response.headers() {
+            trace!(target: logging::CONNECTION, "* {header}");
+        }
+        self.handshake_response = Some(HandshakeResponse {
+            status: response.status().as_u16(),
+            headers: response
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.to_string(), value)
+                })
+                .collect(),
+        });
+        self.set_link(Link::Connected(Socket::WebSocket(Box::new(socket))));
+        Ok(())
+    }
+
+    /// Sends the commands queued while not connected that are not stale.
+    ///
+    /// Commands refused for good, e.g. by the confirmation hook or the
+    /// sandbox, are dropped. On other errors, e.g. `TeamsWsError::RateLimited`
+    /// or `TeamsWsError::NotInMeeting`, the command and the following ones
+    /// are queued again.
+    async fn replay_queue(&mut self) {
+        let Some(queue) = &mut self.command_queue else {
+            return;
+        };
+        let commands = match queue.take_fresh_commands() {
+            Ok(commands) => commands,
+            Err(e) => {
+                warn!(target: logging::RECONNECT, "Error reading command queue: {}", e);
+                return;
+            }
+        };
+        if !commands.is_empty() {
+            info!(target: logging::RECONNECT, "Sending {} queued commands", commands.len());
+        }
+        let mut commands = commands.into_iter();
+        while let Some(command) = commands.next() {
+            if let Err(e) = self.send(command.clone().into_message()).await {
+                if e.downcast_ref::<TeamsWsError>()
+                    .is_some_and(|e| !e.is_temporary())
+                {
+                    warn!(
+                        target: logging::RECONNECT,
+                        "Dropping queued command {:?}: {}",
+                        command.message.action,
+                        e
+                    );
+                    continue;
+                }
+                warn!(
+                    target: logging::RECONNECT,
+                    "Error sending queued command, queueing it again: {}",
+                    e
+                );
+                let remaining = std::iter::once(command).chain(commands).collect();
+                if let Some(queue) = &mut self.command_queue {
+                    if let Err(e) = queue.requeue(remaining) {
+                        warn!(target: logging::RECONNECT, "Error writing command queue: {}", e);
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    async fn open_socket(
+        &self,
+        url: &SecretUrl,
+    ) -> Result<(WebSocketStream, tungstenite::handshake::client::Response), tungstenite::Error>
+    {
+        let mut request = url.expose().into_client_request()?;
+        for (name, value) in &self.options.headers {
+            request.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        #[cfg(feature = "rustls")]
+        if url.expose().starts_with("wss://")
+            && (!self.options.certificate_pins.is_empty()
+                || self.options.accept_invalid_certificates)
+        {
+            let config = tls::pinned_client_config(
+                &self.options.certificate_pins,
+                self.options.accept_invalid_certificates,
+            )
+            .map_err(|e| tungstenite::Error::Tls(e.into()))?;
+            return tokio_tungstenite::connect_async_tls_with_config(
+                request,
+                None,
+                false,
+                Some(tokio_tungstenite::Connector::Rustls(config)),
+            )
+            .await;
+        }
+        #[cfg(feature = "native-tls")]
+        if url.expose().starts_with("wss://") && self.options.accept_invalid_certificates {
+            let connector = native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .build()
+                .map_err(|e| tungstenite::Error::Tls(e.into()))?;
+            return tokio_tungstenite::connect_async_tls_with_config(
+                request,
+                None,
+                false,
+                Some(tokio_tungstenite::Connector::NativeTls(connector)),
+            )
+            .await;
+        }
+        connect_async(request).await
+    }
+
+    /// Sends a `ClientMessage`, or any other `Action`, to Teams and returns
+    /// its request id.
+    ///
+    /// Actions defined outside this crate are sent as a `ClientMessage::custom`
+    /// with the same checks as the known ones, though only those for all
+    /// actions apply, e.g. the overall rate limit but no debounce.
+    ///
+    /// The request id set on `message` is kept, otherwise the next one of the
+    /// counter is assigned, see `RequestIdStart`. Teams answers with the same
+    /// id, so ids set by the caller should not repeat those of the counter.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The `ClientMessage`, `MeetingAction`, `Command` or other `Action` to be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the WebSocket connection is not established, if the message cannot be serialized, or if there is an error sending the message.
+    /// Actions that need a meeting fail with `TeamsWsError::NotInMeeting` if Teams reported not being in one.
+    /// Actions covered by the confirmation hook fail with `TeamsWsError::NotConfirmed` unless confirmed.
+    /// Commands the arbiter suppresses fail with `TeamsWsError::Suppressed`.
+    /// Commands the rate limiter refuses fail with `TeamsWsError::RateLimited`.
+    /// A request id set by the caller that a request waiting for Teams' answer or a queued command uses fails with `TeamsWsError::DuplicateRequestId`,
+    /// assigned request ids skip those.
+    /// With the `audit` feature, a sent message that cannot be recorded in the audit log is logged as an error, it was sent already.
+    /// With a command queue, messages sent while not connected are queued instead of failing.
+    /// In dry-run mode the message is logged instead of sent or recorded in the audit log.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ms_teams_ws::messages::{Command, Reaction};
+    /// let id = websocket.send(Command::React(Reaction::Like)).await?;
+    /// println!("Sent request {}", id);
+    /// ```
+    pub async fn send<A: Action>(&mut self, message: A) -> Result<u32, Box<dyn Error>> {
+        self.send_checked(message.into_message()?, false).await
+    }
+
+    /// Sends `message` like `send`, skipping the confirmation hook if it
+    /// was `confirmed` already, see `TeamsClient`.
+    pub(crate) async fn send_checked(
+        &mut self,
+        message: ClientMessage,
+        confirmed: bool,
+    ) -> Result<u32, Box<dyn Error>> {
+        let span = span!(
+            target: logging::CONNECTION,
+            "send",
+            action = %message.wire_name(),
+            request_id = tracing::field::Empty
+        );
+        self.send_inner(message, confirmed).instrument(span).await
+    }
+
+    async fn send_inner(
+        &mut self,
+        message: ClientMessage,
+        confirmed: bool,
+    ) -> Result<u32, Box<dyn Error>> {
+        let protocol = self.protocol();
+        if let Some(id) = message
+            .request_id
+            .filter(|&id| request_id_reserved(&self.requests, self.command_queue.as_ref(), id))
+        {
+            let e = TeamsWsError::DuplicateRequestId { id };
+            info!(target: logging::CONNECTION, "{}", e);
+            return Err(Box::new(e));
+        }
+        // Checked in dry-run mode as well, so automations see the refusals
+        // they would get; commands queued while not connected are checked
+        // once sent.
+        if self.options.dry_run || self.link.socket().is_some() {
+            self.check_message(&message, confirmed).await?;
+        }
+        if self.options.dry_run {
+            // Never queued, a queued command would be sent for real once
+            // dry-run mode is switched off.
+            let mut message = message;
+            let id = assign_request_id(&mut self.request_id, &mut message, |id| {
+                request_id_reserved(&self.requests, self.command_queue.as_ref(), id)
+            });
+            logging::Span::current().record("request_id", id);
+            info!(
+                target: logging::CONNECTION,
+                "Dry run, not sending {} from {}",
+                message,
+                message.origin.as_deref().unwrap_or("unknown")
+            );
+            return Ok(id);
+        }
+        if let Some(socket) = self.link.socket() {
+            let mut message = message;
+            let id = assign_request_id(&mut self.request_id, &mut message, |id| {
+                request_id_reserved(&self.requests, self.command_queue.as_ref(), id)
+            });
+            logging::Span::current().record("request_id", id);
+            let serialized_message = message.to_wire().and_then(|wire| protocol.encode(&wire));
+            debug!(target: logging::CONNECTION, "Sending message: {:?}", serialized_message);
+            match serialized_message {
+                Ok(msg) => {
+                    record_frame(
+                        self.recorder.as_ref(),
+                        self.message_observer.as_ref(),
+                        Direction::Sent,
+                        &msg,
+                    );
+                    if let Err(e) = socket.send(tungstenite::Message::Text(msg.clone())).await {
+                        warn!(target: logging::CONNECTION, "Error sending message: {}", e);
+                        #[cfg(feature = "metrics")]
+                        if let Some(metrics) = &self.metrics {
+                            metrics.record_error(metrics::ErrorKind::Send);
+                        }
+                        return Err(Box::new(e));
+                    }
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_sent();
+                    }
+                    if let Some(arbiter) = &mut self.arbiter {
+                        arbiter.claim(&message);
+                    }
+                    if let Some(rate_limiter) = &mut self.rate_limiter {
+                        rate_limiter.record(&message);
+                    }
+                    #[cfg(feature = "audit")]
+                    if let Some(audit_log) = &mut self.audit_log {
+                        if let Err(e) = audit_log.record(&message, &msg) {
+                            warn!(
+                                target: logging::CONNECTION,
+                                "Error writing audit log for sent message {}: {}",
+                                id,
+                                e
+                            );
+                        }
+                    }
+                    self.requests.insert(id, message.action);
+                }
+                Err(e) => {
+                    warn!(target: logging::CONNECTION, "Error serializing message: {}", e);
+                    return Err(Box::new(e));
+                }
+            }
+            return Ok(id);
+        }
+        if self.command_queue.is_some() {
+            // Assigned now, so the id returned is the one sent once connected.
+            let mut message = message;
+            let id = assign_request_id(&mut self.request_id, &mut message, |id| {
+                request_id_reserved(&self.requests, self.command_queue.as_ref(), id)
+            });
+            info!(target: logging::CONNECTION, "Not connected, queueing {:?}", message.action);
+            if let Some(queue) = &mut self.command_queue {
+                queue.push(message)?;
+            }
+            return Ok(id);
+        }
+        warn!(target: logging::CONNECTION, "{}", SOCKET_NOT_CONNECTED);
+        Err(Box::from(SOCKET_NOT_CONNECTED))
+    }
+
+    /// Refuses `message` if it cannot be sent now, e.g. outside a meeting,
+    /// without permission or confirmation, or due to the arbiter or the
+    /// rate limiter.
+    async fn check_message(
+        &self,
+        message: &ClientMessage,
+        confirmed: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.in_meeting == Some(false) && message.requires_meeting() {
+            let e = TeamsWsError::NotInMeeting {
+                action: message.wire_name().into_owned(),
+            };
+            info!(target: logging::CONNECTION, "{}", e);
+            return Err(Box::new(e));
+        }
+        if self.options.check_permissions
+            && self
+                .permissions
+                .as_ref()
+                .is_some_and(|permissions| !permissions.allows_message(message))
+        {
+            let e = TeamsWsError::PermissionDenied {
+                action: message.action,
+            };
+            info!(target: logging::CONNECTION, "{}", e);
+            return Err(Box::new(e));
+        }
+        // Confirmed first, so a press the hook refuses neither claims
+        // the control nor uses up the rate limit for the confirming one.
+        if let Some(hook) = self.confirmation_hook.as_ref().filter(|_| !confirmed) {
+            if !hook.confirm(message).await {
+                let e = TeamsWsError::NotConfirmed {
+                    action: message.action,
+                };
+                info!(target: logging::CONNECTION, "{}", e);
+                return Err(Box::new(e));
+            }
+        }
+        if let Some(arbiter) = &self.arbiter {
+            arbiter.check(message)?;
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.check(message)?;
+        }
+        Ok(())
+    }
+
+    /// Receives the next message, waiting at most
+    /// `ConnectionOptions::receive_timeout` if set.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `tokio::time::error::Elapsed` error on timeout, the
+    /// errors of `receive_blocking` otherwise.
+    pub async fn receive(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        match self.options.receive_timeout {
+            Some(timeout) => self.receive_timeout(timeout).await,
+            None => self.receive_blocking().await,
+        }
+    }
+
+    /// Receives the next message, waiting at most `timeout`.
+    ///
+    /// Cancelling the wait loses no messages, so a timeout can be retried.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `tokio::time::error::Elapsed` error on timeout, the
+    /// errors of `receive_blocking` otherwise.
+    pub async fn receive_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        tokio::time::timeout(timeout, self.receive_blocking()).await?
+    }
+
+    /// Returns a message that already arrived, or `None` without waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `receive_blocking`.
+    pub async fn try_receive(&mut self) -> Result<Option<ServerMessage>, Box<dyn Error>> {
+        match tokio::time::timeout(Duration::ZERO, self.receive_blocking()).await {
+            Ok(message) => message.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Receives the next message, waiting as long as it takes, regardless
+    /// of `ConnectionOptions::receive_timeout`.
+    ///
+    /// Pings are answered and skipped like pongs, binary frames are parsed
+    /// like text frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket is not connected,
+    /// `TeamsWsError::ConnectionClosed` if the connection ends and is not
+    /// re-established, `TeamsWsError::Malformed` for frames that cannot
+    /// be parsed, or `TeamsWsError::Cancelled` once the shutdown signal is
+    /// triggered, also while reconnecting.
+    pub async fn receive_blocking(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        let span = span!(
+            target: logging::CONNECTION,
+            "receive",
+            request_id = tracing::field::Empty,
+            action = tracing::field::Empty
+        );
+        let signal = self.shutdown_signal.clone();
+        signal.guard(self.receive_inner()).instrument(span).await
+    }
+
+    async fn receive_inner(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        loop {
+            let Some(socket) = self.link.socket() else {
+                // Resumes a reconnect that was cancelled, e.g. by `select!`.
+                if self.may_reconnect() && self.reestablish("not reconnected yet").await {
+                    continue;
+                }
+                warn!(target: logging::CONNECTION, "{}", SOCKET_NOT_CONNECTED);
+                return Err(Box::from(SOCKET_NOT_CONNECTED));
+            };
+            let mut replayed = false;
+            let timer = [self.next_state_refresh, self.next_keepalive]
+                .into_iter()
+                .flatten()
+                .min();
+            let next = match (self.buffered.pop_front(), timer) {
+                (Some(buffered), _) => {
+                    replayed = self.replayed > 0;
+                    self.replayed = self.replayed.saturating_sub(1);
+                    if let Some(message) = buffered.handled {
+                        return Ok(message);
+                    }
+                    Some(Ok(buffered.frame))
+                }
+                (None, None) => socket.next().await,
+                (None, Some(at)) => {
+                    let next = tokio::select! {
+                        next = socket.next() => Some(next),
+                        _ = tokio::time::sleep_until(at) => None,
+                    };
+                    match next {
+                        Some(next) => next,
+                        None => {
+                            let reason = match self.run_timers().await {
+                                Ok(()) => continue,
+                                Err(e) if self.may_reconnect() => e.to_string(),
+                                Err(e) => return Err(e),
+                            };
+                            self.recover(&reason).await?;
+                            continue;
+                        }
+                    }
+                }
+            };
+            if let Some(Ok(Message::Pong(data))) = &next {
+                if self.keepalive_ping.as_ref() == Some(data) {
+                    self.keepalive_ping = None;
+                }
+            }
+            if let Some(Ok(msg)) = &next {
+                if !replayed {
+                    self.observe_frame(msg);
+                }
+            }
+            if !matches!(next, Some(Ok(Message::Ping(_) | Message::Pong(_)))) {
+                // Only the message of the error is kept across the reconnect,
+                // errors are not `Send`.
+                let reason = match self.handle_frame(next, replayed) {
+                    Ok(message) => {
+                        match &message.token_refresh {
+                            Some(token) if !replayed => self.token_refreshed(token),
+                            _ => {}
+                        }
+                        return Ok(message);
+                    }
+                    Err(e) if self.may_reconnect() => e.to_string(),
+                    Err(e) => return Err(e),
+                };
+                self.recover(&reason).await?;
+            }
+        }
+    }
+
+    /// Reconnects after the connection was lost because of `reason`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reconnecting failed.
+    async fn recover(&mut self, reason: &str) -> Result<(), Box<dyn Error>> {
+        if !self.reestablish(reason).await {
+            return Err(Box::from(format!(
+                "connection lost ({}), reconnecting failed",
+                reason
+            )));
+        }
+        Ok(())
+    }
+
+    /// Uses `token` Teams sent from now on, e.g. when reconnecting, and
+    /// persists it in the `TokenStore`.
+    fn token_refreshed(&mut self, token: &str) {
+        info!(target: logging::CONNECTION, "Teams refreshed the token");
+        self.token = Some(token.to_string());
+        self.history.push(ConnectionEventKind::TokenRefreshed);
+        if let Some(store) = &mut self.token_store {
+            if let Err(e) = store.store(token) {
+                warn!(target: logging::CONNECTION, "Error storing refreshed token: {}", e);
+            }
+        }
+    }
+
+    /// Returns whether the connection ended and `ConnectionOptions::reconnect`
+    /// asks to re-establish it. Connections closed by the client are not.
+    fn may_reconnect(&self) -> bool {
+        self.options.reconnect.is_some()
+            && matches!(
+                &self.disconnect_report,
+                Some(report) if report.initiated_by != DisconnectInitiator::Client
+            )
+    }
+
+    /// Re-establishes the connection that ended because of `reason` according
+    /// to `ConnectionOptions::reconnect` and returns whether it succeeded.
+    async fn reestablish(&mut self, reason: &str) -> bool {
+        let Some(policy) = self.options.reconnect.clone() else {
+            return false;
+        };
+        info!(target: logging::RECONNECT, "Connection lost ({}), reconnecting", reason);
+        self.set_link(Link::Reconnecting);
+        let mut attempt = 0;
+        while policy.allows(attempt) {
+            let delay = policy.jittered_delay(attempt);
+            debug!(target: logging::RECONNECT, "Reconnect attempt {} in {:?}", attempt + 1, delay);
+            tokio::time::sleep(delay).await;
+            let span = span!(target: logging::RECONNECT, "reconnect", attempt = attempt + 1);
+            match self.connect().instrument(span).await {
+                Ok(()) => {
+                    info!(target: logging::RECONNECT, "Reconnected after {} attempts", attempt + 1);
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_reconnect();
+                    }
+                    return true;
+                }
+                Err(e) => {
+                    warn!(
+                        target: logging::RECONNECT,
+                        "Reconnect attempt {} failed: {}",
+                        attempt + 1,
+                        e
+                    );
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_error(metrics::ErrorKind::Reconnect);
+                    }
+                }
+            }
+            attempt += 1;
+        }
+        warn!(target: logging::RECONNECT, "Giving up reconnecting after {} attempts", attempt);
+        self.set_link(Link::Disconnected);
+        false
+    }
+
+    /// Runs the keepalive and the state refresh that are due.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is stale or pinging fails.
+    async fn run_timers(&mut self) -> Result<(), Box<dyn Error>> {
+        let now = tokio::time::Instant::now();
+        if self.next_keepalive.is_some_and(|at| at <= now) {
+            self.keepalive().await?;
+        }
+        if self.next_state_refresh.is_some_and(|at| at <= now) {
+            self.refresh_state().await;
+        }
+        Ok(())
+    }
+
+    /// Pings Teams for `ConnectionOptions::keepalive`, or drops the connection
+    /// if the previous ping was not answered within the interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is stale or sending the ping fails.
+    async fn keepalive(&mut self) -> Result<(), Box<dyn Error>> {
+        let interval = self.options.keepalive.unwrap_or_default();
+        self.next_keepalive = Some(tokio::time::Instant::now() + interval);
+        if self.keepalive_ping.is_some() {
+            let report = DisconnectReport::new(
+                DisconnectInitiator::Network,
+                format!("no pong within {:?}", interval),
+                None,
+            );
+            warn!(target: logging::CONNECTION, "Connection is stale, {}", report);
+            self.record_disconnect(|| report);
+            return Err(Box::from("connection stale"));
+        }
+        let Some(socket) = self.link.socket() else {
+            return Ok(());
+        };
+        self.ping_id = self.ping_id.wrapping_add(1);
+        let payload = self.ping_id.to_be_bytes().to_vec();
+        trace!(target: logging::CONNECTION, "Sending keepalive ping");
+        if let Err(e) = socket.send(Message::Ping(payload.clone())).await {
+            warn!(target: logging::CONNECTION, "Error sending keepalive ping: {}", e);
+            self.record_disconnect(|| DisconnectReport::from_error(&e));
+            return Err(Box::new(e));
+        }
+        self.keepalive_ping = Some(payload);
+        Ok(())
+    }
+
+    /// Queries the meeting state for `ConnectionOptions::state_refresh`.
+    async fn refresh_state(&mut self) {
+        let interval = self.options.state_refresh.unwrap_or_default();
+        self.next_state_refresh = Some(tokio::time::Instant::now() + interval);
+        // After `close` the socket is only read until Teams answers.
+        if !self.link.is_connected() {
+            return;
+        }
+        match self
+            .send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+            .await
+        {
+            Ok(id) => {
+                trace!(target: logging::CONNECTION, "Refreshing meeting state");
+                self.state_refresh_id = Some(id);
+            }
+            Err(e) => warn!(target: logging::CONNECTION, "Error refreshing meeting state: {}", e),
+        }
+    }
+
+    /// Passes a data frame read for the first time to the recorder, the
+    /// message observer and the raw frame hook and counts it in the metrics.
+    fn observe_frame(&self, msg: &Message) {
+        #[cfg(feature = "metrics")]
+        if let (Some(metrics), Message::Text(_) | Message::Binary(_)) = (&self.metrics, msg) {
+            metrics.record_received();
+        }
+        let text = match msg {
+            Message::Text(text) => Cow::Borrowed(text.as_str()),
+            Message::Binary(data) => String::from_utf8_lossy(data),
+            _ => return,
+        };
+        record_frame(
+            self.recorder.as_ref(),
+            self.message_observer.as_ref(),
+            Direction::Received,
+            &text,
+        );
+        if let Some(hook) = &self.raw_frame_hook {
+            hook(&text);
+        }
+    }
+
+    /// Turns the next frame read from the socket into a `ServerMessage`,
+    /// tracking the meeting state, answered requests and disconnects.
+    ///
+    /// A `replayed` frame was handled by `read_ahead` already and newer
+    /// frames may have been read since, so it does not touch the meeting
+    /// state, the permissions or the metrics again.
+    fn handle_frame(
+        &mut self,
+        next: Option<Result<Message, tungstenite::Error>>,
+        replayed: bool,
+    ) -> Result<ServerMessage, Box<dyn Error>> {
+        match next {
+            Some(Ok(Message::Close(frame))) => {
+                let (code, reason) = match frame {
+                    Some(frame) => (Some(u16::from(frame.code)), frame.reason.into_owned()),
+                    None => (None, String::new()),
+                };
+                let report = DisconnectReport::new(DisconnectInitiator::Server, reason, code);
+                info!(target: logging::CONNECTION, "Socket closed, {}", report);
+                self.record_disconnect(|| report.clone());
+                Err(Box::new(TeamsWsError::ConnectionClosed(report)))
+            }
+            Some(Ok(msg)) => {
+                let server_message = match msg.to_text() {
+                    Ok(text) => self
+                        .protocol()
+                        .decode_update(text, self.meeting_state.as_ref(), self.permissions.as_ref())
+                        .map_err(|e| (text.to_string(), e.to_string())),
+                    Err(e) => Err((
+                        String::from_utf8_lossy(&msg.into_data()).into_owned(),
+                        e.to_string(),
+                    )),
+                };
+                match server_message {
+                    Ok(json) => {
+                        if let Some(id) = json.request_id {
+                            let span = logging::Span::current();
+                            span.record("request_id", id);
+                            if let Some(request) = self.requests.resolve(id) {
+                                span.record("action", &*request.action.wire_name());
+                            }
+                        }
+                        if replayed {
+                            return Ok(json);
+                        }
+                        if let Some(permissions) = json
+                            .meeting_update
+                            .as_ref()
+                            .and_then(|u| u.meeting_permissions.as_ref())
+                        {
+                            self.permissions = Some(permissions.clone());
+                        }
+                        if let Some(state) = json
+                            .meeting_update
+                            .as_ref()
+                            .and_then(|u| u.meeting_state.as_ref())
+                        {
+                            if json.request_id.is_some()
+                                && json.request_id == self.state_refresh_id
+                                && self.meeting_state.as_ref() != Some(state)
+                            {
+                                info!(
+                                    target: logging::CONNECTION,
+                                    "Meeting state refresh found missed updates"
+                                );
+                            }
+                            self.in_meeting = Some(state.is_in_meeting);
+                            self.meeting_state = Some(state.clone());
+                            self.state_updated = Some(Instant::now());
+                            #[cfg(feature = "metrics")]
+                            if let Some(metrics) = &self.metrics {
+                                metrics.record_state(state);
+                            }
+                        } else if json.error_kind() == Some(TeamsErrorKind::NoActiveCall) {
+                            self.in_meeting = Some(false);
+                        }
+                        Ok(json)
+                    }
+                    Err((payload, reason)) => {
+                        warn!(target: logging::CODEC, "Error parsing json : {}", reason);
+                        #[cfg(feature = "metrics")]
+                        if let (Some(metrics), false) = (&self.metrics, replayed) {
+                            metrics.record_error(metrics::ErrorKind::Malformed);
+                        }
+                        Err(Box::new(TeamsWsError::Malformed(MalformedFrame {
+                            payload,
+                            reason,
+                        })))
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                warn!(target: logging::CONNECTION, "Error reading from socket {}", e);
+                self.record_disconnect(|| DisconnectReport::from_error(&e));
+                Err(Box::new(e))
+            }
+            None => {
+                info!(target: logging::CONNECTION, "Socket closed");
+                let report =
+                    DisconnectReport::new(DisconnectInitiator::Network, "socket closed", None);
+                self.record_disconnect(|| report.clone());
+                Err(Box::new(TeamsWsError::ConnectionClosed(report)))
+            }
+        }
+    }
+
+    /// Sends a WebSocket ping and returns the round-trip time when the pong
+    /// arrives, to show the link quality or detect a wedged Teams client.
+    ///
+    /// Messages arriving before the pong are kept for `receive`. Teams does
+    /// not answer if it hangs, so wrap the call in a timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket is not connected or the connection fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// let rtt = tokio::time::timeout(Duration::from_secs(2), websocket.ping()).await??;
+    /// println!("Teams answered in {} ms", rtt.as_millis());
+    /// ```
+    pub async fn ping(&mut self) -> Result<Duration, Box<dyn Error>> {
+        let Some(socket) = self.link.socket() else {
+            warn!(target: logging::CONNECTION, "{}", SOCKET_NOT_CONNECTED);
+            return Err(Box::from(SOCKET_NOT_CONNECTED));
+        };
+        self.ping_id = self.ping_id.wrapping_add(1);
+        let payload = self.ping_id.to_be_bytes().to_vec();
+        let start = Instant::now();
+        socket.send(Message::Ping(payload.clone())).await?;
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Pong(data))) if data == payload => {
+                    let rtt = start.elapsed();
+                    debug!(target: logging::CONNECTION, "Ping answered in {:?}", rtt);
+                    return Ok(rtt);
+                }
+                // A pong of an earlier, abandoned ping or of a keepalive ping.
+                Some(Ok(Message::Pong(data))) => {
+                    if self.keepalive_ping.as_ref() == Some(&data) {
+                        self.keepalive_ping = None;
+                    }
+                }
+                Some(Ok(msg)) => self.buffered.push_back(Buffered {
+                    frame: msg,
+                    handled: None,
+                }),
+                Some(Err(e)) => {
+                    warn!(target: logging::CONNECTION, "Error reading from socket {}", e);
+                    self.record_disconnect(|| DisconnectReport::from_error(&e));
+                    return Err(Box::new(e));
+                }
+                None => {
+                    info!(target: logging::CONNECTION, "Socket closed");
+                    let report =
+                        DisconnectReport::new(DisconnectInitiator::Network, "socket closed", None);
+                    self.record_disconnect(|| report.clone());
+                    return Err(Box::new(TeamsWsError::ConnectionClosed(report)));
+                }
+            }
+        }
+    }
+
+    /// Receives the next valid message, for long-running consumers that
+    /// should not stop on a bad frame or an idle meeting.
+    ///
+    /// Malformed frames are delivered to the channel set with
+    /// `set_malformed_frames`, or logged without one, and receiving continues.
+    /// `ConnectionOptions::receive_timeout` does not apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `receive_blocking` other than `TeamsWsError::Malformed`.
+    pub async fn receive_resilient(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        loop {
+            let e = match self.receive_blocking().await {
+                Ok(message) => return Ok(message),
+                Err(e) => e,
+            };
+            let Some(TeamsWsError::Malformed(frame)) = e.downcast_ref::<TeamsWsError>() else {
+                return Err(e);
+            };
+            match &self.malformed_frames {
+                Some(sender) if sender.send(frame.clone()).is_ok() => {}
+                _ => warn!(target: logging::CODEC, "Skipping {}", frame),
+            }
+        }
+    }
+
+    pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.link.is_connected() {
+            self.record_disconnect(|| {
+                DisconnectReport::new(DisconnectInitiator::Client, "closed by client", None)
+            });
+        }
+        if let Some(socket) = self.link.socket() {
+            if let Err(e) = socket.close(None).await {
+                warn!(target: logging::CONNECTION, "Error closing socket: {}", e);
+                return Err(Box::new(e));
+            }
+            info!(target: logging::CONNECTION, "Connection closed");
+            Ok(())
+        } else {
+            warn!(target: logging::CONNECTION, "{}", SOCKET_NOT_CONNECTED);
+            Err(Box::from(SOCKET_NOT_CONNECTED))
+        }
+    }
+
+    /// Shuts the connection down gracefully: flushes the frames still being
+    /// sent, sends a Close frame and waits until Teams answered it, at most
+    /// `connect_timeout` or `SHUTDOWN_TIMEOUT`. Frames Teams sends meanwhile
+    /// are discarded.
+    ///
+    /// Unlike `close`, the socket is released, and shutting down without a
+    /// connection does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if flushing or sending the Close frame fails, the
+    /// socket is released anyway.
+    pub async fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(mut socket) = self.link.take_socket() else {
+            return Ok(());
+        };
+        self.set_link(Link::Closed(None));
+        self.record_disconnect(|| {
+            DisconnectReport::new(DisconnectInitiator::Client, "closed by client", None)
+        });
+        socket.flush().await?;
+        socket.close(None).await?;
+        let timeout = self.options.connect_timeout.unwrap_or(SHUTDOWN_TIMEOUT);
+        let answered = async { while let Some(Ok(_)) = socket.next().await {} };
+        if tokio::time::timeout(timeout, answered).await.is_err() {
+            debug!(target: logging::CONNECTION, "Teams did not answer the Close frame");
+        }
+        info!(target: logging::CONNECTION, "Connection shut down");
+        Ok(())
+    }
+}
+
+/// Best effort for websockets dropped while connected: sends a Close frame
+/// if it can go out without waiting and closes the TCP connection, so Teams
+/// notices right away. Use `shutdown` to close gracefully.
+impl Drop for TeamsWebsocket {
+    fn drop(&mut self) {
+        if let Some(mut socket) = self.link.take_socket() {
+            debug!(target: logging::CONNECTION, "Dropped while connected, closing the socket");
+            let _ = socket.close(None).now_or_never();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
     use tokio::net::TcpListener;
     use tokio::runtime::Runtime;
     use tokio_tungstenite::accept_async;
     use tokio_tungstenite::tungstenite::protocol::Message;
 
     #[test]
-    fn test_teams_websocket_new() {
+    #[allow(deprecated)]
+    fn test_teams_websocket_new() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let websocket = TeamsWebsocket::new(identifier.clone(), None, None).await;
+            assert_eq!(websocket.identifier, identifier);
+            assert_eq!(websocket.status(), ConnectionStatus::Disconnected);
+            assert!(websocket.token.is_none());
+            assert_eq!(websocket.request_id, 0);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_builder() {
+        let identifier = AppIdentifiers {
+            protocol_version: "1.0".into(),
+            manufacturer: "TestManufacturer".into(),
+            device: "TestDevice".into(),
+            app: "TestApp".into(),
+            app_version: "1.0".into(),
+        };
+        let websocket = TeamsWebsocket::builder(identifier)
+            .ignore_environment()
+            .token("secret")
+            .build()
+            .unwrap();
+        assert_eq!(websocket.token.as_deref(), Some("secret"));
+        assert_eq!(websocket.url, settings::DEFAULT_URL);
+        assert_eq!(
+            websocket.settings().source(SettingKey::Url),
+            Some(&settings::SettingSource::Default)
+        );
+        assert_eq!(
+            websocket.settings().source(SettingKey::Token),
+            Some(&settings::SettingSource::Explicit)
+        );
+    }
+
+    #[test]
+    fn test_teams_websocket_connect() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            let result = websocket.connect().await;
+            assert!(result.is_ok());
+            assert_eq!(websocket.status(), ConnectionStatus::Connected);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_connect_twice() {
+        Runtime::new().unwrap().block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let path =
+                std::env::temp_dir().join(format!("teams-ws-reconnect-{}", std::process::id()));
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .token("old")
+                .token_store(token::FileTokenStore::new(&path))
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket.connect().await.unwrap();
+            token::FileTokenStore::new(&path).store("granted").unwrap();
+            websocket.reconnect().await.unwrap();
+            let mute = ClientMessage::new(messages::MeetingAction::Mute, None);
+            websocket.send_and_wait(mute).await.unwrap();
+            assert_eq!(server.tokens(), ["old", "granted"]);
+            std::fs::remove_file(&path).unwrap();
+
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .already_connected(AlreadyConnected::Fail)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let error = websocket.connect().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(TeamsWsError::AlreadyConnected)
+            ));
+            assert_eq!(websocket.status(), ConnectionStatus::Connected);
+            websocket.close().await.unwrap();
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.status(), ConnectionStatus::Connected);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_request_ids() {
+        Runtime::new().unwrap().block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .request_id_start(RequestIdStart::At(u32::MAX))
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let mute = ClientMessage::new(messages::MeetingAction::Mute, None);
+            assert_eq!(websocket.send(mute.clone()).await.unwrap(), u32::MAX);
+            assert_eq!(websocket.send(mute.clone()).await.unwrap(), 0);
+            let mut own = mute;
+            own.request_id = Some(42);
+            assert_eq!(websocket.send(own.clone()).await.unwrap(), 42);
+            let error = websocket.send(own.clone()).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(TeamsWsError::DuplicateRequestId { id: 42 })
+            ));
+            // 42 is waiting for an answer, so the counter skips it.
+            websocket.request_id = 42;
+            let unmute = ClientMessage::new(messages::MeetingAction::Unmute, None);
+            assert_eq!(websocket.send(unmute).await.unwrap(), 43);
+            own.request_id = Some(1);
+            let answer = websocket.send_and_wait(own).await.unwrap();
+            assert_eq!(answer.request_id, Some(1));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_send_all() {
+        Runtime::new().unwrap().block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let scene = scene![messages::Command::Unmute, messages::Command::ShowVideo];
+            assert_eq!(websocket.send_all(scene.clone()).await.unwrap(), [0, 1]);
+            let answers = websocket.send_all_and_wait(scene).await.unwrap();
+            let ids: Vec<_> = answers.iter().map(|answer| answer.request_id).collect();
+            assert_eq!(ids, [Some(2), Some(3)]);
+            server.assert_actions(&[
+                messages::MeetingAction::Unmute,
+                messages::MeetingAction::ShowVideo,
+                messages::MeetingAction::Unmute,
+                messages::MeetingAction::ShowVideo,
+            ]);
+
+            websocket.set_dry_run(true);
+            let captions = ClientMessage::custom(messages::CustomAction {
+                name: "toggle-captions".to_string(),
+                parameters: None,
+                requires_meeting: false,
+            });
+            let error = websocket
+                .send_all_and_wait(vec![captions])
+                .await
+                .unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "toggle-captions was not sent, no answer to wait for"
+            );
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_handshake() {
+        Runtime::new().unwrap().block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .header("Proxy-Authorization", "Bearer proxy")
+                .query_param("tenant", "a b")
+                .build()
+                .unwrap();
+            assert!(websocket.handshake_response().is_none());
+            websocket.connect().await.unwrap();
+            let response = websocket.handshake_response().unwrap();
+            assert_eq!(response.status, 101);
+            assert_eq!(response.header("upgrade"), Some("websocket"));
+
+            let handshake = &server.handshakes()[0];
+            assert_eq!(
+                handshake.header("proxy-authorization"),
+                Some("Bearer proxy")
+            );
+            assert!(handshake.query.contains("&tenant=a"));
+
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .header("Bad Header", "value")
+                .build()
+                .unwrap();
+            assert!(websocket.connect().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_watch_status() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            let mut status = websocket.watch_status();
+            assert_eq!(*status.borrow_and_update(), ConnectionStatus::Disconnected);
+            websocket.connect().await.unwrap();
+            assert!(status.has_changed().unwrap());
+            assert_eq!(*status.borrow_and_update(), ConnectionStatus::Connected);
+
+            server.disconnect();
+            while websocket.receive().await.is_ok() {}
+            assert_eq!(*status.borrow_and_update(), ConnectionStatus::Disconnected);
+
+            websocket.connect().await.unwrap();
+            websocket.close().await.unwrap();
+            assert_eq!(*status.borrow_and_update(), ConnectionStatus::Closed);
+            assert!(websocket.receive().await.is_err());
+            assert_eq!(websocket.status(), ConnectionStatus::Closed);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_connect_error_masks_token() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            drop(listener);
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .token("secret")
+                .build()
+                .unwrap();
+            let error = websocket.connect().await.unwrap_err();
+            assert!(error.downcast_ref::<TeamsWsError>().is_some());
+            assert!(error.to_string().contains("token=***"));
+            assert!(!error.to_string().contains("secret"));
+            assert!(!format!("{:?}", websocket).contains("secret"));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_refuses_remote_host() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url("ws://192.0.2.1:8124")
+                .token("secret")
+                .build()
+                .unwrap();
+            let error = websocket.connect().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::RemoteNotAllowed { host }) if host == "192.0.2.1"
+            ));
+            assert_eq!(websocket.status(), ConnectionStatus::Disconnected);
+
+            // The url crate connects to evil.example, the backslash ends its host.
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url("ws://evil.example\\@127.0.0.1:8124")
+                .token("secret")
+                .build()
+                .unwrap();
+            let error = websocket.connect().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::RemoteNotAllowed { .. })
+            ));
+        });
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn test_teams_websocket_wss() {
+        use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use std::sync::Arc;
+
+        // A server whose certificate is issued by a CA of the platform store.
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "ms-teams-ws test CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let certificate = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        let ca_file = std::env::temp_dir().join(format!("teams-ws-ca-{}.pem", std::process::id()));
+        std::fs::write(&ca_file, ca.pem()).unwrap();
+        std::env::set_var("SSL_CERT_FILE", &ca_file);
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(certificate.der().to_vec())],
+            PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let stream = acceptor.accept(stream).await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while ws_stream.next().await.is_some() {}
+            });
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(format!("wss://localhost:{}", port))
+                .token("secret")
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.status(), ConnectionStatus::Connected);
+        });
+        std::fs::remove_file(ca_file).unwrap();
+    }
+
+    #[test]
+    fn test_teams_websocket_connect_timeout() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            // Accepts the connection but never answers the handshake.
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (_stream, _) = listener.accept().await.unwrap();
+                std::future::pending::<()>().await;
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .connect_timeout(Duration::from_millis(50))
+                .build()
+                .unwrap();
+            let error = websocket.connect().await.unwrap_err();
+            assert!(error.to_string().contains("handshake timed out"));
+            assert_eq!(websocket.status(), ConnectionStatus::Disconnected);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_confirmation_hook() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket.set_confirmation_hook(Some(ConfirmationHook::new(|_| {
+                Box::pin(std::future::ready(false))
+            })));
+
+            let error = websocket
+                .send(ClientMessage::new(messages::MeetingAction::LeaveCall, None))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::NotConfirmed { .. })
+            ));
+
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(0));
+
+            // The refused first press does not use up the rate limit.
+            websocket.set_confirmation_hook(Some(ConfirmationHook::repeat_within(
+                Duration::from_secs(60),
+            )));
+            websocket.set_rate_limiter(Some(
+                RateLimiter::new()
+                    .debounce(messages::MeetingAction::LeaveCall, Duration::from_secs(60)),
+            ));
+            let leave = || ClientMessage::new(messages::MeetingAction::LeaveCall, None);
+            assert!(websocket.send(leave()).await.is_err());
+            websocket.send(leave()).await.unwrap();
+            let error = websocket.send(leave()).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::NotConfirmed { .. })
+            ));
+            let error = websocket.send(leave()).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::RateLimited { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_send_receive() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let client_message = ClientMessage::new(messages::MeetingAction::BlurBackground, None);
+            websocket.send(client_message).await.unwrap();
+            assert_eq!(websocket.pending_requests().count(), 1);
+
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(websocket.pending_requests().count(), 0);
+            assert_eq!(server_message.response.as_deref(), Some("Success"));
+            server.assert_actions(&[messages::MeetingAction::BlurBackground]);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_command_queue() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
             let identifier = AppIdentifiers {
-                protocol_version: "1.0",
-                manufacturer: "TestManufacturer",
-                device: "TestDevice",
-                app: "TestApp",
-                app_version: "1.0",
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
             };
-            let websocket = TeamsWebsocket::new(identifier.clone(), None, None).await;
-            assert_eq!(websocket.identifier, identifier);
-            assert!(websocket.socket.is_none());
-            assert!(websocket.token.is_none());
-            assert_eq!(websocket.request_id, 0);
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(server.url())
+                .command_queue(CommandQueue::new())
+                .build()
+                .unwrap();
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            assert_eq!(websocket.command_queue().unwrap().len(), 1);
+
+            websocket.connect().await.unwrap();
+            assert!(websocket.command_queue().unwrap().is_empty());
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(0));
+
+            // A command refused for now stays queued.
+            websocket.shutdown().await.unwrap();
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::RaiseHand, None))
+                .await
+                .unwrap();
+            websocket.set_rate_limiter(Some(RateLimiter::new().limit(0, Duration::from_secs(60))));
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.command_queue().unwrap().len(), 1);
         });
     }
-    async fn start_test_server() -> SocketAddr {
-        let mut rng = rand::thread_rng();
-        let port: u16 = rng.gen_range(1024..65535);
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
-            .await
-            .unwrap();
-        let addr = listener.local_addr().unwrap();
-        tokio::spawn(async move {
-            while let Ok((stream, _)) = listener.accept().await {
-                let ws_stream = accept_async(stream).await.unwrap();
-                let (mut write, mut read) = ws_stream.split();
-                tokio::spawn(async move {
-                    while let Some(Ok(msg)) = read.next().await {
-                        if let Message::Text(text) = msg {
-                            let client_message: ClientMessage =
-                                serde_json::from_str(&text).unwrap();
-                            let server_message = ServerMessage {
-                                request_id: client_message.request_id,
-                                response: Some(format!("Echo: {}", text)),
-                                error_msg: None,
-                                token_refresh: None,
-                                meeting_update: None,
-                            };
-                            let response = serde_json::to_string(&server_message).unwrap();
-                            write.send(Message::Text(response)).await.unwrap();
+
+    #[test]
+    fn test_teams_websocket_dry_run() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(server.url())
+                .dry_run(true)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::LeaveCall, None))
+                .await
+                .unwrap();
+
+            websocket.set_dry_run(false);
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(1));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_dry_run_disconnected() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .command_queue(CommandQueue::new())
+                .dry_run(true)
+                .build()
+                .unwrap();
+            let id = websocket
+                .send(ClientMessage::new(messages::MeetingAction::LeaveCall, None))
+                .await
+                .unwrap();
+            assert_eq!(id, 0);
+            assert!(websocket.command_queue().unwrap().is_empty());
+
+            // Nothing is sent for real once dry-run mode is switched off.
+            websocket.set_dry_run(false);
+            websocket.connect().await.unwrap();
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(1));
+            server.assert_actions(&[messages::MeetingAction::Mute]);
+
+            // Without a queue, dry-run sends are simulated as well.
+            websocket.shutdown().await.unwrap();
+            websocket.set_command_queue(None);
+            websocket.set_dry_run(true);
+            let id = websocket
+                .send(ClientMessage::new(messages::MeetingAction::Unmute, None))
+                .await
+                .unwrap();
+            assert_eq!(id, 2);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_receive_resilient() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                for frame in ["{\"response\":", "not json", "{\"response\":\"ok\"}"] {
+                    ws_stream
+                        .send(Message::Text(frame.to_string()))
+                        .await
+                        .unwrap();
+                }
+                ws_stream.next().await;
+            });
+            let (sender, mut malformed) = tokio::sync::mpsc::unbounded_channel();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .malformed_frames(sender)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let error = websocket.receive().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::Malformed(frame)) if frame.payload == "{\"response\":"
+            ));
+            let server_message = websocket.receive_resilient().await.unwrap();
+            assert_eq!(server_message.response, Some("ok".to_string()));
+            assert_eq!(malformed.recv().await.unwrap().payload, "not json");
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_ping() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            assert!(websocket.ping().await.is_err());
+            websocket.connect().await.unwrap();
+
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            let rtt = websocket.ping().await.unwrap();
+            assert!(rtt < Duration::from_secs(5));
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(0));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_disconnect_report() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                // Other frame types than text are no reason to fail.
+                ws_stream.send(Message::Ping(vec![1])).await.unwrap();
+                let binary = br#"{"requestId":7,"response":"Success"}"#.to_vec();
+                ws_stream.send(Message::Binary(binary)).await.unwrap();
+                ws_stream
+                    .close(Some(tungstenite::protocol::CloseFrame {
+                        code: tungstenite::protocol::frame::coding::CloseCode::Away,
+                        reason: "Teams is quitting".into(),
+                    }))
+                    .await
+                    .unwrap();
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            assert!(websocket.disconnect_report().is_none());
+            assert_eq!(websocket.receive().await.unwrap().request_id, Some(7));
+            let error = websocket.receive().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(TeamsWsError::ConnectionClosed(report)) if report.close_code == Some(1001)
+            ));
+            let report = websocket.disconnect_report().unwrap();
+            assert_eq!(report.initiated_by, DisconnectInitiator::Server);
+            assert_eq!(report.close_code, Some(1001));
+            assert_eq!(report.reason, "Teams is quitting");
+            let kinds: Vec<_> = websocket
+                .history()
+                .events()
+                .map(|event| &event.kind)
+                .collect();
+            assert!(matches!(
+                kinds.as_slice(),
+                [
+                    ConnectionEventKind::Connecting {
+                        reconnect: false,
+                        ..
+                    },
+                    ConnectionEventKind::Connected { .. },
+                    ConnectionEventKind::Disconnected(_)
+                ]
+            ));
+            assert_eq!(websocket.history().last_disconnect(), Some(report));
+
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket.close().await.unwrap();
+            let report = websocket.disconnect_report().unwrap();
+            assert_eq!(report.initiated_by, DisconnectInitiator::Client);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_not_in_meeting() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                let update = ServerMessage {
+                    request_id: None,
+                    response: None,
+                    error_msg: None,
+                    token_refresh: None,
+                    meeting_update: Some(messages::MeetingUpdate {
+                        meeting_permissions: None,
+                        meeting_state: Some(messages::MeetingState::new()),
+                    }),
+                    extra: serde_json::Map::new(),
+                };
+                let update = serde_json::to_string(&update).unwrap();
+                ws_stream.send(Message::Text(update)).await.unwrap();
+                while ws_stream.next().await.is_some() {}
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.is_in_meeting(), None);
+            websocket.receive().await.unwrap();
+            assert_eq!(websocket.is_in_meeting(), Some(false));
+
+            let error = websocket
+                .send(ClientMessage::new(messages::MeetingAction::Mute, None))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::NotInMeeting { .. })
+            ));
+            websocket
+                .send(ClientMessage::new(
+                    messages::MeetingAction::QueryMeetingState,
+                    None,
+                ))
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_partial_update() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "2.0.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // The new Teams client only sends the fields that changed.
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                for update in [
+                    r#"{"meetingUpdate":{"meetingState":{"isInMeeting":true,"isVideoOn":true},"meetingPermissions":{"canToggleMute":true}},"apiVersion":"2.0.0"}"#,
+                    r#"{"meetingUpdate":{"meetingState":{"isMuted":true}},"apiVersion":"2.0.0"}"#,
+                ] {
+                    ws_stream.send(Message::Text(update.into())).await.unwrap();
+                }
+                while ws_stream.next().await.is_some() {}
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket.receive().await.unwrap();
+            let update = websocket.receive().await.unwrap();
+            let state = update.meeting_update.unwrap().meeting_state.unwrap();
+            assert!(state.is_muted && state.is_in_meeting && state.is_video_on);
+            assert_eq!(websocket.is_in_meeting(), Some(true));
+            assert_eq!(websocket.meeting_state(), Some(&state));
+            assert!(websocket.permissions().unwrap().can_toggle_mute);
+
+            websocket
+                .send(ClientMessage::new(messages::MeetingAction::Unmute, None))
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_check_permissions() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let update = messages::MeetingUpdate {
+                meeting_permissions: Some(messages::MeetingPermissions {
+                    can_toggle_mute: true,
+                    can_toggle_chat: true,
+                    ..messages::MeetingPermissions::new()
+                }),
+                meeting_state: Some(messages::MeetingState {
+                    is_in_meeting: true,
+                    ..messages::MeetingState::new()
+                }),
+            };
+            let server = mock::MockTeamsServer::builder()
+                .meeting_updates([update])
+                .start()
+                .await
+                .unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .check_permissions(true)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket.receive().await.unwrap();
+
+            websocket.mute().await.unwrap();
+            websocket.toggle_ui(messages::UiPanel::Chat).await.unwrap();
+            for denied in [
+                ClientMessage::new(messages::MeetingAction::ToggleVideo, None),
+                ClientMessage::toggle_ui(messages::UiPanel::SharingTray),
+            ] {
+                let error = websocket.send(denied).await.unwrap_err();
+                assert!(matches!(
+                    error.downcast_ref::<TeamsWsError>(),
+                    Some(TeamsWsError::PermissionDenied { .. })
+                ));
+            }
+            websocket.receive().await.unwrap();
+            websocket.receive().await.unwrap();
+            server.assert_actions(&[
+                messages::MeetingAction::Mute,
+                messages::MeetingAction::ToggleUI,
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_detect_flavor() {
+        use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "2.0.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            // Accepts classic Teams connections only.
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    // The error type is given by tungstenite.
+                    #[allow(clippy::result_large_err)]
+                    let callback = |request: &Request, response: Response| {
+                        let query = request.uri().query().unwrap_or_default();
+                        if query.contains("protocol-version=1.0.0") {
+                            Ok(response)
+                        } else {
+                            let mut error = ErrorResponse::new(Some("unsupported".to_string()));
+                            *error.status_mut() = tungstenite::http::StatusCode::BAD_REQUEST;
+                            Err(error)
                         }
+                    };
+                    if let Ok(mut ws_stream) =
+                        tokio_tungstenite::accept_hdr_async(stream, callback).await
+                    {
+                        tokio::spawn(async move { while ws_stream.next().await.is_some() {} });
                     }
-                });
+                }
+            });
+
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(url.clone())
+                .build()
+                .unwrap();
+            assert!(websocket.connect().await.is_err());
+            assert_eq!(websocket.flavor(), None);
+
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(url.clone())
+                .detect_flavor(true)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.flavor(), Some(TeamsFlavor::Classic));
+            assert_eq!(websocket.protocol(), ProtocolVersion::V1);
+
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .fallback_protocol_versions(["1.5.0", "1.0.0"])
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let info = websocket.connection_info().unwrap();
+            assert_eq!(info.protocol_version, "1.0.0");
+            assert_eq!(info.flavor, TeamsFlavor::Classic);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_send_action() {
+        struct Applause;
+
+        impl Action for Applause {
+            fn wire_name(&self) -> std::borrow::Cow<'static, str> {
+                "send-reaction".into()
+            }
+
+            fn parameters(&self) -> Option<serde_json::Value> {
+                Some(serde_json::json!({"type": "applause"}))
+            }
+        }
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let url = server.url();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            websocket.send(Applause).await.unwrap();
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.response.as_deref(), Some("Success"));
+            let received = server.received();
+            assert_eq!(received[0].action, messages::MeetingAction::React);
+            assert_eq!(
+                received[0].parameters,
+                Some(messages::ClientMessageParameter::new(
+                    messages::ClientMessageParameterType::ReactApplause
+                ))
+            );
+
+            let id = websocket.send(messages::MeetingAction::Mute).await.unwrap();
+            let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.request_id, Some(id));
+
+            // Custom actions get the checks for all actions and are answered.
+            struct Captions;
+
+            impl Action for Captions {
+                fn wire_name(&self) -> std::borrow::Cow<'static, str> {
+                    "toggle-captions".into()
+                }
             }
+
+            let answer = websocket.send_and_wait(&Captions).await.unwrap();
+            assert_eq!(answer.request_id, Some(2));
+            websocket.set_rate_limiter(Some(RateLimiter::new().limit(0, Duration::from_secs(60))));
+            let error = websocket.send(Captions).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TeamsWsError>(),
+                Some(TeamsWsError::RateLimited { .. })
+            ));
+            websocket.set_rate_limiter(None);
+            websocket.shutdown().await.unwrap();
+            websocket.set_command_queue(Some(CommandQueue::new()));
+            websocket.send(Captions).await.unwrap();
+            let queued = websocket
+                .command_queue()
+                .unwrap()
+                .commands()
+                .next()
+                .unwrap();
+            assert_eq!(queued.message.wire_name(), "toggle-captions");
         });
-        addr
     }
 
     #[test]
-    fn test_teams_websocket_connect() {
+    fn test_teams_websocket_ready() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
             let identifier = AppIdentifiers {
-                protocol_version: "1.0",
-                manufacturer: "TestManufacturer",
-                device: "TestDevice",
-                app: "TestApp",
-                app_version: "1.0",
-            };
-            let addr = start_test_server().await;
-            let url = format!("ws://{}", addr);
-            let mut websocket = TeamsWebsocket::new(identifier.clone(), None, Some(url)).await;
-            let result = websocket.connect().await;
-            assert!(result.is_ok());
-            assert!(websocket.socket.is_some());
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                // Answer the state query with a token refresh first.
+                let Some(Ok(Message::Text(text))) = ws_stream.next().await else {
+                    return;
+                };
+                let query: ClientMessage = serde_json::from_str(&text).unwrap();
+                assert_eq!(query.action, messages::MeetingAction::QueryMeetingState);
+                let refresh = ServerMessage {
+                    request_id: None,
+                    response: None,
+                    error_msg: None,
+                    token_refresh: Some("token".to_string()),
+                    meeting_update: None,
+                    extra: serde_json::Map::new(),
+                };
+                let mut state = messages::MeetingState::new();
+                state.is_in_meeting = true;
+                state.is_muted = true;
+                let update = ServerMessage {
+                    request_id: query.request_id,
+                    response: None,
+                    error_msg: None,
+                    token_refresh: None,
+                    meeting_update: Some(messages::MeetingUpdate {
+                        meeting_permissions: Some(messages::MeetingPermissions {
+                            can_toggle_mute: true,
+                            ..messages::MeetingPermissions::new()
+                        }),
+                        meeting_state: Some(state),
+                    }),
+                    extra: serde_json::Map::new(),
+                };
+                for message in [refresh, update] {
+                    let message = serde_json::to_string(&message).unwrap();
+                    ws_stream.send(Message::Text(message)).await.unwrap();
+                }
+                while ws_stream.next().await.is_some() {}
+            });
+            let path = std::env::temp_dir().join(format!("teams-ws-ready-{}", std::process::id()));
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.set_token_store(Some(Box::new(token::FileTokenStore::new(&path))));
+            assert!(websocket.meeting_state().is_none());
+            assert!(websocket.state_snapshot().is_none());
+            websocket.ready().await.unwrap();
+            assert!(websocket.meeting_state().unwrap().is_muted);
+            let snapshot = websocket.state_snapshot().unwrap();
+            assert!(snapshot.state.is_muted);
+            assert!(!snapshot.is_stale(Duration::from_secs(60)));
+            assert!(websocket.permissions().unwrap().can_toggle_mute);
+            assert_eq!(websocket.is_in_meeting(), Some(true));
+
+            // The messages read while waiting are still received.
+            let refresh = websocket.receive().await.unwrap();
+            assert_eq!(refresh.token_refresh.as_deref(), Some("token"));
+            let update = websocket.receive().await.unwrap();
+            assert!(update.meeting_update.is_some());
+            assert_eq!(websocket.pending_requests().count(), 0);
+            let refreshes = websocket
+                .history()
+                .events()
+                .filter(|event| event.kind == ConnectionEventKind::TokenRefreshed)
+                .count();
+            assert_eq!(refreshes, 1);
+            assert_eq!(websocket.token.as_deref(), Some("token"));
+            let stored = token::FileTokenStore::new(&path).load().unwrap();
+            assert_eq!(stored.as_deref(), Some("token"));
+            std::fs::remove_file(&path).unwrap();
         });
     }
 
     #[test]
-    fn test_teams_websocket_send_receive() {
+    fn test_teams_websocket_unknown_fields() {
+        Runtime::new().unwrap().block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let frames = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut websocket = {
+                let frames = frames.clone();
+                TeamsWebsocket::builder(AppIdentifiers::default())
+                    .ignore_environment()
+                    .url(server.url())
+                    .raw_frame_hook(move |text| frames.lock().unwrap().push(text.to_string()))
+                    .build()
+                    .unwrap()
+            };
+            websocket.connect().await.unwrap();
+            let frame = r#"{"meetingUpdate":{"meetingState":{"isMuted":true,"isHandRaised":false,"isInMeeting":true,"isRecordingOn":false,"isBackgroundBlurred":false,"isSharing":false,"hasUnreadMessages":false,"isVideoOn":false,"isCaptionsOn":true}},"sessionId":"abc"}"#;
+            server.send(serde_json::from_str(frame).unwrap());
+            let message = websocket.receive().await.unwrap();
+            assert_eq!(message.extra["sessionId"], "abc");
+            let state = websocket.meeting_state().unwrap();
+            assert!(state.is_muted);
+            assert_eq!(state.extra["isCaptionsOn"], true);
+            let frames = frames.lock().unwrap();
+            assert_eq!(frames.len(), 1);
+            assert!(frames[0].contains(r#""isCaptionsOn":true"#));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_shutdown() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // Reports whether each connection ended with a Close frame.
+            let (closed, mut close_frames) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let mut ws_stream = accept_async(stream).await.unwrap();
+                    let closed = closed.clone();
+                    tokio::spawn(async move {
+                        let mut close_frame = false;
+                        while let Some(Ok(message)) = ws_stream.next().await {
+                            close_frame |= message.is_close();
+                        }
+                        let _ = closed.send(close_frame);
+                    });
+                }
+            });
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.shutdown().await.unwrap();
+            websocket.connect().await.unwrap();
+            websocket
+                .send(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            websocket.shutdown().await.unwrap();
+            assert!(close_frames.recv().await.unwrap());
+            assert_eq!(websocket.status(), ConnectionStatus::Closed);
+            assert!(websocket.disconnect_report().is_some());
+
+            websocket.connect().await.unwrap();
+            drop(websocket);
+            assert!(close_frames.recv().await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_query_state_on_connect() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .query_state_on_connect(true)
+                .reconnect(reconnect::ReconnectPolicy {
+                    initial_delay: Duration::from_millis(10),
+                    ..reconnect::ReconnectPolicy::default()
+                })
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            server.assert_actions(&[MeetingAction::QueryMeetingState]);
+            assert_eq!(websocket.receive().await.unwrap().request_id, Some(0));
+
+            // The reconnect queries again before receive returns.
+            server.disconnect();
+            let answer = websocket.receive().await.unwrap();
+            assert_eq!(answer.request_id, Some(1));
+            server.assert_actions(&[
+                MeetingAction::QueryMeetingState,
+                MeetingAction::QueryMeetingState,
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_keepalive() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
             let identifier = AppIdentifiers {
-                protocol_version: "1.0",
-                manufacturer: "TestManufacturer",
-                device: "TestDevice",
-                app: "TestApp",
-                app_version: "1.0",
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
             };
-            let addr = start_test_server().await;
-            let url = format!("ws://{}", addr);
-            let mut websocket = TeamsWebsocket::new(identifier.clone(), None, Some(url)).await;
+            // Answered pings keep the connection alive.
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier.clone())
+                .ignore_environment()
+                .url(server.url())
+                .keepalive(Duration::from_millis(20))
+                .build()
+                .unwrap();
             websocket.connect().await.unwrap();
+            let idle = websocket.receive_timeout(Duration::from_millis(150)).await;
+            assert!(idle.is_err());
+            assert!(websocket.disconnect_report().is_none());
 
-            let client_message = ClientMessage::new(messages::MeetingAction::BlurBackground, None);
-            websocket.send(client_message).await.unwrap();
+            // A server that stopped reading never answers.
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let _ws_stream = accept_async(stream).await.unwrap();
+                std::future::pending::<()>().await;
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .keepalive(Duration::from_millis(20))
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let error = websocket
+                .receive_timeout(Duration::from_secs(5))
+                .await
+                .unwrap_err();
+            assert_eq!(error.to_string(), "connection stale");
+            let report = websocket.disconnect_report().unwrap();
+            assert_eq!(report.initiated_by, DisconnectInitiator::Network);
+            assert!(report.reason.starts_with("no pong"));
+            assert_eq!(websocket.status(), ConnectionStatus::Disconnected);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_pair() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                let permissions = |can_pair| ServerMessage {
+                    request_id: None,
+                    response: None,
+                    error_msg: None,
+                    token_refresh: None,
+                    meeting_update: Some(messages::MeetingUpdate {
+                        meeting_permissions: Some(messages::MeetingPermissions {
+                            can_pair,
+                            ..messages::MeetingPermissions::new()
+                        }),
+                        meeting_state: None,
+                    }),
+                    extra: serde_json::Map::new(),
+                };
+                // Pairing is not allowed until the user joined a meeting.
+                ws_stream.next().await.unwrap().unwrap();
+                for message in [permissions(false), permissions(true)] {
+                    let message = serde_json::to_string(&message).unwrap();
+                    ws_stream.send(Message::Text(message)).await.unwrap();
+                }
+                ws_stream.next().await.unwrap().unwrap();
+                let granted = ServerMessage {
+                    request_id: None,
+                    response: None,
+                    error_msg: None,
+                    token_refresh: Some("granted".to_string()),
+                    meeting_update: None,
+                    extra: serde_json::Map::new(),
+                };
+                let granted = serde_json::to_string(&granted).unwrap();
+                ws_stream.send(Message::Text(granted)).await.unwrap();
+                while ws_stream.next().await.is_some() {}
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .token("revoked")
+                .build()
+                .unwrap();
+            let token = websocket.pair().await.unwrap();
+            assert_eq!(token, "granted");
+            assert_eq!(websocket.token.as_deref(), Some("granted"));
+            assert!(websocket.permissions().unwrap().can_pair);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_state_refresh() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(server.url())
+                .state_refresh(Duration::from_millis(20))
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            // Nothing was sent, the answered queries come from the refresh.
+            for request_id in 0..2 {
+                let server_message = websocket.receive().await.unwrap();
+                assert_eq!(server_message.request_id, Some(request_id));
+            }
+            server.assert_actions(&[
+                messages::MeetingAction::QueryMeetingState,
+                messages::MeetingAction::QueryMeetingState,
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_reconnect() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                // Teams restarts: the first connection is dropped.
+                let (stream, _) = listener.accept().await.unwrap();
+                drop(accept_async(stream).await.unwrap());
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                let update = ServerMessage {
+                    request_id: None,
+                    response: Some("back".to_string()),
+                    error_msg: None,
+                    token_refresh: None,
+                    meeting_update: None,
+                    extra: serde_json::Map::new(),
+                };
+                let update = serde_json::to_string(&update).unwrap();
+                ws_stream.send(Message::Text(update)).await.unwrap();
+                while ws_stream.next().await.is_some() {}
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .reconnect(reconnect::ReconnectPolicy {
+                    initial_delay: Duration::from_millis(10),
+                    max_attempts: Some(3),
+                    ..reconnect::ReconnectPolicy::default()
+                })
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
 
             let server_message = websocket.receive().await.unwrap();
+            assert_eq!(server_message.response.as_deref(), Some("back"));
+            assert_eq!(websocket.history().connections(), 2);
+            assert!(websocket.history().last_disconnect().is_some());
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_send_and_wait() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // An unsolicited update arrives before every answer.
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws_stream.next().await {
+                    let request: ClientMessage = serde_json::from_str(&text).unwrap();
+                    let update = ServerMessage {
+                        request_id: None,
+                        response: None,
+                        error_msg: None,
+                        token_refresh: None,
+                        meeting_update: Some(messages::MeetingUpdate {
+                            meeting_permissions: None,
+                            meeting_state: Some(messages::MeetingState::new()),
+                        }),
+                        extra: serde_json::Map::new(),
+                    };
+                    let answer = ServerMessage {
+                        request_id: request.request_id,
+                        response: Some("Success".to_string()),
+                        ..update.clone()
+                    };
+                    for message in [update, answer] {
+                        let message = serde_json::to_string(&message).unwrap();
+                        ws_stream.send(Message::Text(message)).await.unwrap();
+                    }
+                }
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let query = ClientMessage::new(messages::MeetingAction::QueryMeetingState, None);
+            let answer = websocket.send_and_wait(query).await.unwrap();
+            assert_eq!(answer.request_id, Some(0));
+            assert_eq!(answer.response.as_deref(), Some("Success"));
+            assert_eq!(websocket.pending_requests().count(), 0);
+
+            let update = websocket.receive().await.unwrap();
+            assert_eq!(update.request_id, None);
+            assert!(update.meeting_update.is_some());
+
+            websocket.set_dry_run(true);
+            let query = ClientMessage::new(messages::MeetingAction::QueryMeetingState, None);
+            assert!(websocket.send_and_wait(query).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_read_ahead_keeps_latest_state() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            // Two updates arrive before the answer, the meeting ends in between.
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws_stream.next().await {
+                    let request: ClientMessage = serde_json::from_str(&text).unwrap();
+                    let update = |in_meeting| ServerMessage {
+                        request_id: None,
+                        response: None,
+                        error_msg: None,
+                        token_refresh: None,
+                        meeting_update: Some(messages::MeetingUpdate {
+                            meeting_permissions: None,
+                            meeting_state: Some(
+                                messages::MeetingState::new().with_in_meeting(in_meeting),
+                            ),
+                        }),
+                        extra: serde_json::Map::new(),
+                    };
+                    let answer = ServerMessage {
+                        request_id: request.request_id,
+                        response: Some("Success".to_string()),
+                        meeting_update: None,
+                        ..update(false)
+                    };
+                    for message in [update(true), update(false), answer] {
+                        let message = serde_json::to_string(&message).unwrap();
+                        ws_stream.send(Message::Text(message)).await.unwrap();
+                    }
+                }
+            });
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(url)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let query = ClientMessage::new(messages::MeetingAction::QueryMeetingState, None);
+            websocket.send_and_wait(query).await.unwrap();
+            assert_eq!(websocket.is_in_meeting(), Some(false));
+
+            let update = websocket.receive().await.unwrap();
+            assert!(
+                update
+                    .meeting_update
+                    .unwrap()
+                    .meeting_state
+                    .unwrap()
+                    .is_in_meeting
+            );
+            assert_eq!(websocket.is_in_meeting(), Some(false));
+            websocket.receive().await.unwrap();
+            assert_eq!(websocket.is_in_meeting(), Some(false));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_state_snapshot_read_ahead() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let update = messages::MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(messages::MeetingState::new().with_in_meeting(true)),
+            };
+            let server = mock::MockTeamsServer::builder()
+                .meeting_updates([update])
+                .start()
+                .await
+                .unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let query = ClientMessage::new(messages::MeetingAction::QueryMeetingState, None);
+            websocket.send_and_wait(query).await.unwrap();
+            let reported = websocket.state_snapshot().unwrap().last_updated;
+
+            // Receiving the update read ahead does not make the state look fresh.
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let update = websocket.receive().await.unwrap();
+            assert!(update.meeting_update.is_some());
+            let snapshot = websocket.state_snapshot().unwrap();
+            assert_eq!(snapshot.last_updated, reported);
+            assert!(snapshot.is_stale(Duration::from_millis(10)));
+        });
+    }
+
+    #[test]
+    fn test_teams_websocket_receive_timeout() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let identifier = AppIdentifiers {
+                protocol_version: "1.0".into(),
+                manufacturer: "TestManufacturer".into(),
+                device: "TestDevice".into(),
+                app: "TestApp".into(),
+                app_version: "1.0".into(),
+            };
+            let server = mock::MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(identifier)
+                .ignore_environment()
+                .url(server.url())
+                .receive_timeout(Duration::from_millis(20))
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+
+            let error = websocket.receive().await.unwrap_err();
             assert_eq!(
-                server_message.response,
-                Some(
-                    "Echo: {\"action\":\"blur-background\",\"parameters\":null,\"requestId\":0}"
-                        .to_string()
-                )
+                exit::ExitStatus::from_error(error.as_ref()),
+                exit::ExitStatus::Timeout
             );
+            assert!(websocket.try_receive().await.unwrap().is_none());
+
+            websocket.mute().await.unwrap();
+            let server_message = websocket
+                .receive_timeout(Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(server_message.request_id, Some(0));
         });
     }
 }
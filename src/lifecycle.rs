@@ -0,0 +1,184 @@
+use crate::logging;
+use crate::messages::MeetingState;
+use crate::TeamsWsError;
+use serde::{Deserialize, Serialize};
+
+/// The phase of the meeting lifecycle.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MeetingPhase {
+    /// Not in a meeting.
+    #[default]
+    Idle,
+    /// Joining was requested but Teams does not report the meeting yet.
+    Joining,
+    /// In a meeting.
+    InMeeting,
+    /// In a meeting and sharing the screen.
+    Presenting,
+    /// Leaving was requested but Teams still reports the meeting.
+    Leaving,
+}
+
+/// What moves the lifecycle from one phase to another.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleTrigger {
+    /// Joining a meeting was requested, e.g. by a calendar integration.
+    Join,
+    /// Teams reports being in a meeting.
+    Joined,
+    /// Teams reports sharing started.
+    StartPresenting,
+    /// Teams reports sharing stopped.
+    StopPresenting,
+    /// Leaving the meeting was requested, e.g. by sending `LeaveCall`.
+    Leave,
+    /// Teams reports no meeting.
+    Left,
+}
+
+/// A transition of the meeting lifecycle.
+///
+/// # Fields
+///
+/// * `from` - The phase before the transition.
+/// * `to` - The phase after the transition.
+/// * `trigger` - What caused the transition.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Transition {
+    pub from: MeetingPhase,
+    pub to: MeetingPhase,
+    pub trigger: LifecycleTrigger,
+}
+
+/// The meeting lifecycle as an explicit state machine.
+///
+/// Only the transitions below are allowed, anything else is rejected with
+/// `TeamsWsError::InvalidTransition`:
+///
+/// * `Idle` -> `Joining` on `Join`
+/// * `Idle`, `Joining` -> `InMeeting` on `Joined`
+/// * `InMeeting` -> `Presenting` on `StartPresenting`
+/// * `Presenting` -> `InMeeting` on `StopPresenting`
+/// * `Joining`, `InMeeting`, `Presenting` -> `Leaving` on `Leave`
+/// * `Joining`, `InMeeting`, `Presenting`, `Leaving` -> `Idle` on `Left`
+///
+/// `Joined`, `StartPresenting`, `StopPresenting` and `Left` are derived from
+/// meeting states by `MeetingLifecycle::observe`, `Join` and `Leave` are
+/// fired by the code requesting them.
+///
+/// # Example
+/// ```rust
+/// let mut lifecycle = MeetingLifecycle::new();
+/// lifecycle.fire(LifecycleTrigger::Join)?;
+/// assert_eq!(lifecycle.phase(), MeetingPhase::Joining);
+/// assert!(lifecycle.fire(LifecycleTrigger::StartPresenting).is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MeetingLifecycle {
+    phase: MeetingPhase,
+}
+
+impl MeetingLifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn phase(&self) -> MeetingPhase {
+        self.phase
+    }
+
+    /// Returns the phase `trigger` leads to from `from`, if allowed.
+    pub fn target(from: MeetingPhase, trigger: LifecycleTrigger) -> Option<MeetingPhase> {
+        use LifecycleTrigger::*;
+        use MeetingPhase::*;
+        match (from, trigger) {
+            (Idle, Join) => Some(Joining),
+            (Idle | Joining, Joined) => Some(InMeeting),
+            (InMeeting, StartPresenting) => Some(Presenting),
+            (Presenting, StopPresenting) => Some(InMeeting),
+            (Joining | InMeeting | Presenting, Leave) => Some(Leaving),
+            (Joining | InMeeting | Presenting | Leaving, Left) => Some(Idle),
+            _ => None,
+        }
+    }
+
+    /// Moves to the phase `trigger` leads to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeamsWsError::InvalidTransition` if `trigger` is not allowed
+    /// in the current phase, the phase is unchanged then.
+    pub fn fire(&mut self, trigger: LifecycleTrigger) -> Result<Transition, TeamsWsError> {
+        let from = self.phase;
+        let to =
+            Self::target(from, trigger).ok_or(TeamsWsError::InvalidTransition { from, trigger })?;
+        self.phase = to;
+        debug!(target: logging::STATE, "Meeting lifecycle {:?} -> {:?} on {:?}", from, to, trigger);
+        Ok(Transition { from, to, trigger })
+    }
+
+    /// Applies the triggers implied by `state` and returns the transitions made.
+    pub fn observe(&mut self, state: &MeetingState) -> Vec<Transition> {
+        let mut triggers = Vec::new();
+        if !state.is_in_meeting {
+            triggers.push(LifecycleTrigger::Left);
+        } else {
+            triggers.push(LifecycleTrigger::Joined);
+            triggers.push(if state.is_sharing {
+                LifecycleTrigger::StartPresenting
+            } else {
+                LifecycleTrigger::StopPresenting
+            });
+        }
+        triggers
+            .into_iter()
+            .filter_map(|trigger| self.fire(trigger).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meeting_lifecycle() {
+        let mut lifecycle = MeetingLifecycle::new();
+        lifecycle.fire(LifecycleTrigger::Join).unwrap();
+        assert!(lifecycle.fire(LifecycleTrigger::StopPresenting).is_err());
+        assert_eq!(lifecycle.phase(), MeetingPhase::Joining);
+
+        let mut state = MeetingState {
+            is_in_meeting: true,
+            is_sharing: true,
+            ..MeetingState::default()
+        };
+        let transitions = lifecycle.observe(&state);
+        assert_eq!(
+            transitions,
+            vec![
+                Transition {
+                    from: MeetingPhase::Joining,
+                    to: MeetingPhase::InMeeting,
+                    trigger: LifecycleTrigger::Joined
+                },
+                Transition {
+                    from: MeetingPhase::InMeeting,
+                    to: MeetingPhase::Presenting,
+                    trigger: LifecycleTrigger::StartPresenting
+                }
+            ]
+        );
+        assert!(lifecycle.observe(&state).is_empty());
+
+        lifecycle.fire(LifecycleTrigger::Leave).unwrap();
+        state.is_sharing = false;
+        assert!(lifecycle.observe(&state).is_empty());
+        assert_eq!(lifecycle.phase(), MeetingPhase::Leaving);
+
+        state.is_in_meeting = false;
+        assert_eq!(lifecycle.observe(&state)[0].to, MeetingPhase::Idle);
+    }
+}
@@ -0,0 +1,107 @@
+//! Logging through `log`, or through `tracing` with the `tracing` feature.
+//!
+//! The macros take the same arguments as those of `log`. Without a target
+//! they log to the module path, e.g. `ms_teams_ws::rules`.
+//!
+//! With `tracing`, connecting, sending, receiving and reconnect attempts
+//! also run in spans carrying the request id, the action and the attempt
+//! number, so a command can be followed through the client.
+
+/// Target of connection handling: connecting, sending and closing.
+pub(crate) const CONNECTION: &str = "ms_teams_ws::connection";
+/// Target of encoding and decoding messages.
+pub(crate) const CODEC: &str = "ms_teams_ws::codec";
+/// Target of restoring state after connecting, e.g. replaying queued commands.
+pub(crate) const RECONNECT: &str = "ms_teams_ws::reconnect";
+/// Target of the command queue and the event spool.
+pub(crate) const QUEUE: &str = "ms_teams_ws::queue";
+/// Target of refusing commands, e.g. by the rate limiter, the arbiter or a sandbox.
+pub(crate) const POLICY: &str = "ms_teams_ws::policy";
+/// Target of tracking the meeting, e.g. its lifecycle phases.
+pub(crate) const STATE: &str = "ms_teams_ws::state";
+/// Target of settings, config files and stored tokens.
+pub(crate) const CONFIG: &str = "ms_teams_ws::config";
+/// Target of rules, scripts and plugins.
+pub(crate) const AUTOMATION: &str = "ms_teams_ws::automation";
+/// Target of the D-Bus, HTTP and MQTT bridges and of webhooks.
+pub(crate) const BRIDGE: &str = "ms_teams_ws::bridge";
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! emit {
+    ($level:ident, $target:expr, $($arg:tt)+) => {
+        log::$level!(target: $target, $($arg)+)
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! emit {
+    ($level:ident, $target:expr, $($arg:tt)+) => {
+        tracing::$level!(target: $target, $($arg)+)
+    };
+}
+
+macro_rules! warn {
+    (target: $target:expr, $($arg:tt)+) => { emit!(warn, $target, $($arg)+) };
+    ($($arg:tt)+) => { emit!(warn, module_path!(), $($arg)+) };
+}
+
+macro_rules! info {
+    (target: $target:expr, $($arg:tt)+) => { emit!(info, $target, $($arg)+) };
+    ($($arg:tt)+) => { emit!(info, module_path!(), $($arg)+) };
+}
+
+macro_rules! debug {
+    (target: $target:expr, $($arg:tt)+) => { emit!(debug, $target, $($arg)+) };
+    ($($arg:tt)+) => { emit!(debug, module_path!(), $($arg)+) };
+}
+
+macro_rules! trace {
+    (target: $target:expr, $($arg:tt)+) => { emit!(trace, $target, $($arg)+) };
+    ($($arg:tt)+) => { emit!(trace, module_path!(), $($arg)+) };
+}
+
+/// Creates a span like `tracing::info_span!`, or a `Span` doing nothing
+/// without the `tracing` feature.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    (target: $target:expr, $($arg:tt)+) => {
+        tracing::info_span!(target: $target, $($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::logging::Span
+    };
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{Instrument, Span};
+
+/// Stands in for `tracing::Span` without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn current() -> Self {
+        Span
+    }
+
+    pub(crate) fn record<V>(&self, _field: &str, _value: V) -> &Self {
+        self
+    }
+}
+
+/// Stands in for `tracing::Instrument` without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) trait Instrument: Sized {
+    fn instrument(self, _span: Span) -> Self {
+        self
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl<F: std::future::Future> Instrument for F {}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+pub mod v1;
+
 /// Represents a message sent from the server.
 ///
 /// # Fields
@@ -9,15 +11,22 @@ use serde::{Deserialize, Serialize};
 /// * `error_msg` - An optional error message from the server.
 /// * `token_refresh` - An optional token refresh message.
 /// * `meeting_update` - An optional update about the meeting.
+/// * `extra` - The fields Teams sent that this crate does not know.
+///
+/// `Display` and `Debug` mask the token of `token_refresh`.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
 pub struct ServerMessage {
     pub request_id: Option<u32>,
     pub response: Option<String>,
     pub error_msg: Option<String>,
     pub token_refresh: Option<String>,
     pub meeting_update: Option<MeetingUpdate>,
+    #[serde(flatten)]
+    #[cfg_attr(feature = "typescript", ts(skip))]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl std::fmt::Display for ServerMessage {
@@ -25,11 +34,99 @@ impl std::fmt::Display for ServerMessage {
         write!(
             f,
             "ServerMessage {{ request_id: {:?}, response: {:?}, error_msg: {:?}, token_refresh: {:?}, meeting_update: {:?} }}",
-            self.request_id, self.response, self.error_msg, self.token_refresh, self.meeting_update
+            self.request_id,
+            self.response,
+            self.error_msg,
+            self.token_refresh.as_ref().map(|_| "***"),
+            self.meeting_update
         )
     }
 }
 
+impl std::fmt::Debug for ServerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerMessage")
+            .field("request_id", &self.request_id)
+            .field("response", &self.response)
+            .field("error_msg", &self.error_msg)
+            .field("token_refresh", &self.token_refresh.as_ref().map(|_| "***"))
+            .field("meeting_update", &self.meeting_update)
+            .field("extra", &self.extra)
+            .finish()
+    }
+}
+
+impl ServerMessage {
+    /// Returns the kind of the error Teams reported, if any.
+    pub fn error_kind(&self) -> Option<TeamsErrorKind> {
+        self.error_msg.as_deref().map(TeamsErrorKind::parse)
+    }
+
+    /// Returns the message, or the kind of the error Teams reported in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `TeamsErrorKind` of `error_msg` if it is set.
+    pub fn into_result(self) -> Result<Self, TeamsErrorKind> {
+        match self.error_kind() {
+            Some(kind) => Err(kind),
+            None => Ok(self),
+        }
+    }
+}
+
+/// The kind of an error reported by Teams in `ServerMessage::error_msg`.
+///
+/// Teams sends free text, `TeamsErrorKind::parse` maps the known messages
+/// and keeps others as `Unknown`. It is an `Error`, returned by
+/// `ServerMessage::into_result` and `ClientHandle::request`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamsErrorKind {
+    /// The action needs a meeting, but there is none.
+    NoActiveCall,
+    /// The action is not allowed in the current meeting, see `MeetingPermissions`.
+    NotPermitted,
+    /// Teams rejected the token, pairing is required.
+    InvalidToken,
+    /// Any other error, with the original message.
+    Unknown(String),
+}
+
+impl TeamsErrorKind {
+    pub fn parse(error_msg: &str) -> Self {
+        let message = error_msg.to_ascii_lowercase();
+        let contains = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        if contains(&[
+            "no active call",
+            "no active meeting",
+            "not in a meeting",
+            "no meeting",
+        ]) {
+            TeamsErrorKind::NoActiveCall
+        } else if contains(&["token", "unauthorized", "not paired", "pairing"]) {
+            TeamsErrorKind::InvalidToken
+        } else if contains(&["not permitted", "not allowed", "permission"]) {
+            TeamsErrorKind::NotPermitted
+        } else {
+            TeamsErrorKind::Unknown(error_msg.to_string())
+        }
+    }
+}
+
+impl std::fmt::Display for TeamsErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeamsErrorKind::NoActiveCall => write!(f, "no active call"),
+            TeamsErrorKind::NotPermitted => write!(f, "not permitted"),
+            TeamsErrorKind::InvalidToken => write!(f, "invalid token"),
+            TeamsErrorKind::Unknown(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TeamsErrorKind {}
+
 /// Represents an update about the meeting.
 ///
 /// # Fields
@@ -38,7 +135,8 @@ impl std::fmt::Display for ServerMessage {
 /// * `meeting_state` - Optional state of the meeting.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
 pub struct MeetingUpdate {
     pub meeting_permissions: Option<MeetingPermissions>,
     pub meeting_state: Option<MeetingState>,
@@ -68,9 +166,20 @@ impl std::fmt::Display for MeetingUpdate {
 /// * `can_toggle_chat` - Whether the user can toggle chat.
 /// * `can_stop_sharing` - Whether the user can stop sharing.
 /// * `can_pair` - Whether the user can pair devices.
+/// * `extra` - The permissions Teams sent that this crate does not know.
+///
+/// Permissions Teams leaves out are read as `false`. New fields may be added,
+/// so build the permissions with `new` and the `with_` methods.
+///
+/// # Example
+/// ```rust
+/// let permissions = MeetingPermissions::new().with_can_toggle_mute(true).with_can_leave(true);
+/// ```
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[serde(rename_all = "camelCase", default)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[non_exhaustive]
 pub struct MeetingPermissions {
     pub can_toggle_mute: bool,
     pub can_toggle_video: bool,
@@ -82,6 +191,9 @@ pub struct MeetingPermissions {
     pub can_toggle_chat: bool,
     pub can_stop_sharing: bool,
     pub can_pair: bool,
+    #[serde(flatten)]
+    #[cfg_attr(feature = "typescript", ts(skip))]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl MeetingPermissions {
@@ -97,6 +209,106 @@ impl MeetingPermissions {
             can_toggle_chat: false,
             can_stop_sharing: false,
             can_pair: false,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    pub fn with_can_toggle_mute(mut self, allowed: bool) -> Self {
+        self.can_toggle_mute = allowed;
+        self
+    }
+
+    pub fn with_can_toggle_video(mut self, allowed: bool) -> Self {
+        self.can_toggle_video = allowed;
+        self
+    }
+
+    pub fn with_can_toggle_hand(mut self, allowed: bool) -> Self {
+        self.can_toggle_hand = allowed;
+        self
+    }
+
+    pub fn with_can_toggle_blur(mut self, allowed: bool) -> Self {
+        self.can_toggle_blur = allowed;
+        self
+    }
+
+    pub fn with_can_leave(mut self, allowed: bool) -> Self {
+        self.can_leave = allowed;
+        self
+    }
+
+    pub fn with_can_react(mut self, allowed: bool) -> Self {
+        self.can_react = allowed;
+        self
+    }
+
+    pub fn with_can_toggle_share_tray(mut self, allowed: bool) -> Self {
+        self.can_toggle_share_tray = allowed;
+        self
+    }
+
+    pub fn with_can_toggle_chat(mut self, allowed: bool) -> Self {
+        self.can_toggle_chat = allowed;
+        self
+    }
+
+    pub fn with_can_stop_sharing(mut self, allowed: bool) -> Self {
+        self.can_stop_sharing = allowed;
+        self
+    }
+
+    pub fn with_can_pair(mut self, allowed: bool) -> Self {
+        self.can_pair = allowed;
+        self
+    }
+
+    /// Returns whether the permissions allow `action`.
+    ///
+    /// `MeetingAction::ToggleUI` is allowed if the chat or the share tray
+    /// can be toggled.
+    pub fn allows(&self, action: MeetingAction) -> bool {
+        match action {
+            MeetingAction::None | MeetingAction::QueryMeetingState => true,
+            MeetingAction::Mute | MeetingAction::Unmute | MeetingAction::ToggleMute => {
+                self.can_toggle_mute
+            }
+            MeetingAction::HideVideo | MeetingAction::ShowVideo | MeetingAction::ToggleVideo => {
+                self.can_toggle_video
+            }
+            MeetingAction::BlurBackground
+            | MeetingAction::UnblurBackground
+            | MeetingAction::ToggleBlurBackground => self.can_toggle_blur,
+            MeetingAction::RaiseHand | MeetingAction::LowerHand | MeetingAction::ToggleHand => {
+                self.can_toggle_hand
+            }
+            MeetingAction::LeaveCall => self.can_leave,
+            MeetingAction::React => self.can_react,
+            MeetingAction::ToggleUI => self.can_toggle_chat || self.can_toggle_share_tray,
+            MeetingAction::StopSharing => self.can_stop_sharing,
+        }
+    }
+
+    /// Returns whether the permissions allow sending `message`, taking the
+    /// panel of `MeetingAction::ToggleUI` into account.
+    pub fn allows_message(&self, message: &ClientMessage) -> bool {
+        let parameter = message.parameters.as_ref().map(|p| &p.type_);
+        match (message.action, parameter) {
+            (MeetingAction::ToggleUI, Some(ClientMessageParameterType::ToggleUiChat)) => {
+                self.allows_ui(UiPanel::Chat)
+            }
+            (MeetingAction::ToggleUI, Some(ClientMessageParameterType::ToggleUiSharing)) => {
+                self.allows_ui(UiPanel::SharingTray)
+            }
+            (action, _) => self.allows(action),
+        }
+    }
+
+    /// Returns whether the permissions allow toggling `panel`.
+    pub fn allows_ui(&self, panel: UiPanel) -> bool {
+        match panel {
+            UiPanel::Chat => self.can_toggle_chat,
+            UiPanel::SharingTray => self.can_toggle_share_tray,
         }
     }
 }
@@ -129,9 +341,24 @@ impl std::fmt::Display for MeetingPermissions {
 /// * `is_sharing` - Whether the user is sharing their screen.
 /// * `has_unread_messages` - Whether there are unread messages.
 /// * `is_video_on` - Whether the video is on.
+/// * `is_on_hold` - Whether the call is on hold.
+/// * `is_recording_paused` - Whether the recording is paused.
+/// * `unread_message_count` - How many messages are unread, if Teams says.
+/// * `extra` - The state fields Teams sent that this crate does not know.
+///
+/// Fields Teams leaves out are read as `false`, or `None` for
+/// `unread_message_count` which only current Teams clients send. New fields may be added, so
+/// build states with `new` and the `with_` methods.
+///
+/// # Example
+/// ```rust
+/// let state = MeetingState::new().with_in_meeting(true).with_muted(true);
+/// ```
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[serde(rename_all = "camelCase", default)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[non_exhaustive]
 pub struct MeetingState {
     pub is_muted: bool,
     pub is_hand_raised: bool,
@@ -141,6 +368,12 @@ pub struct MeetingState {
     pub is_sharing: bool,
     pub has_unread_messages: bool,
     pub is_video_on: bool,
+    pub is_on_hold: bool,
+    pub is_recording_paused: bool,
+    pub unread_message_count: Option<u32>,
+    #[serde(flatten)]
+    #[cfg_attr(feature = "typescript", ts(skip))]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl MeetingState {
@@ -154,7 +387,112 @@ impl MeetingState {
             is_sharing: false,
             has_unread_messages: false,
             is_video_on: false,
+            is_on_hold: false,
+            is_recording_paused: false,
+            unread_message_count: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    pub fn with_muted(mut self, value: bool) -> Self {
+        self.is_muted = value;
+        self
+    }
+
+    pub fn with_hand_raised(mut self, value: bool) -> Self {
+        self.is_hand_raised = value;
+        self
+    }
+
+    pub fn with_in_meeting(mut self, value: bool) -> Self {
+        self.is_in_meeting = value;
+        self
+    }
+
+    pub fn with_recording_on(mut self, value: bool) -> Self {
+        self.is_recording_on = value;
+        self
+    }
+
+    pub fn with_background_blurred(mut self, value: bool) -> Self {
+        self.is_background_blurred = value;
+        self
+    }
+
+    pub fn with_sharing(mut self, value: bool) -> Self {
+        self.is_sharing = value;
+        self
+    }
+
+    pub fn with_unread_messages(mut self, value: bool) -> Self {
+        self.has_unread_messages = value;
+        self
+    }
+
+    pub fn with_video_on(mut self, value: bool) -> Self {
+        self.is_video_on = value;
+        self
+    }
+
+    pub fn with_on_hold(mut self, value: bool) -> Self {
+        self.is_on_hold = value;
+        self
+    }
+
+    pub fn with_recording_paused(mut self, value: bool) -> Self {
+        self.is_recording_paused = value;
+        self
+    }
+
+    pub fn with_unread_message_count(mut self, value: Option<u32>) -> Self {
+        self.unread_message_count = value;
+        self
+    }
+
+    /// Reads the fields older Teams builds send under other names into the
+    /// known ones: `isCameraOn` of classic Teams is `is_video_on`.
+    pub fn upgrade_legacy_fields(&mut self) {
+        if let Some(camera_on) = self.extra.remove("isCameraOn") {
+            self.is_video_on |= camera_on.as_bool().unwrap_or_default();
+        }
+    }
+
+    /// The version of the schema of `to_json`, raised when fields change
+    /// incompatibly.
+    pub const JSON_VERSION: u64 = 1;
+
+    /// Returns the state as one line of JSON for status bars such as waybar
+    /// or polybar, e.g. `{"hasUnreadMessages":false,...,"version":1}`.
+    ///
+    /// Only the fields of this struct are included, not `extra`, so the
+    /// schema only changes with `JSON_VERSION`.
+    pub fn to_json(&self) -> String {
+        let known = MeetingState {
+            extra: serde_json::Map::new(),
+            ..self.clone()
+        };
+        let mut json = serde_json::to_value(known).unwrap_or_default();
+        if let serde_json::Value::Object(fields) = &mut json {
+            fields.insert("version".to_string(), Self::JSON_VERSION.into());
+        }
+        json.to_string()
+    }
+
+    /// Reads a state written by `to_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` is not a state or was written by a newer
+    /// schema version.
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)?;
+        let version = fields
+            .remove("version")
+            .and_then(|version| version.as_u64());
+        if let Some(version) = version.filter(|version| *version > Self::JSON_VERSION) {
+            return Err(format!("unsupported meeting state version {}", version).into());
         }
+        Ok(serde_json::from_value(fields.into())?)
     }
 }
 
@@ -168,8 +506,8 @@ impl std::fmt::Display for MeetingState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "MeetingState {{ is_muted: {}, is_hand_raised: {}, is_in_meeting: {}, is_recording_on: {}, is_background_blurred: {}, is_sharing: {}, has_unread_messages: {}, is_video_on: {} }}",
-            self.is_muted, self.is_hand_raised, self.is_in_meeting, self.is_recording_on, self.is_background_blurred, self.is_sharing, self.has_unread_messages, self.is_video_on
+            "MeetingState {{ is_muted: {}, is_hand_raised: {}, is_in_meeting: {}, is_recording_on: {}, is_background_blurred: {}, is_sharing: {}, has_unread_messages: {}, is_video_on: {}, is_on_hold: {}, is_recording_paused: {}, unread_message_count: {:?} }}",
+            self.is_muted, self.is_hand_raised, self.is_in_meeting, self.is_recording_on, self.is_background_blurred, self.is_sharing, self.has_unread_messages, self.is_video_on, self.is_on_hold, self.is_recording_paused, self.unread_message_count
         )
     }
 }
@@ -181,7 +519,8 @@ impl std::fmt::Display for MeetingState {
 /// * `type_` - The type of the client message parameter.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
 pub struct ClientMessageParameter {
     #[serde(rename = "type")]
     pub type_: ClientMessageParameterType,
@@ -196,7 +535,8 @@ impl ClientMessageParameter {
 /// Represents the type of a client message parameter.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
 pub enum ClientMessageParameterType {
     #[serde(rename = "applause")]
     ReactApplause,
@@ -214,21 +554,95 @@ pub enum ClientMessageParameterType {
     ToggleUiSharing,
 }
 
+/// A reaction sent with `TeamsWebsocket::send_reaction`.
+///
+/// Unlike `ClientMessageParameterType` it only has the values valid for
+/// `MeetingAction::React`, and is (de)serialized by their names, e.g. `like`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reaction {
+    Applause,
+    Laugh,
+    Like,
+    Love,
+    Wow,
+}
+
+impl Reaction {
+    /// All reactions, in the order Teams shows them.
+    pub const ALL: [Reaction; 5] = [
+        Reaction::Like,
+        Reaction::Love,
+        Reaction::Applause,
+        Reaction::Laugh,
+        Reaction::Wow,
+    ];
+}
+
+impl From<Reaction> for ClientMessageParameterType {
+    fn from(reaction: Reaction) -> Self {
+        match reaction {
+            Reaction::Applause => ClientMessageParameterType::ReactApplause,
+            Reaction::Laugh => ClientMessageParameterType::ReactLaugh,
+            Reaction::Like => ClientMessageParameterType::ReactLike,
+            Reaction::Love => ClientMessageParameterType::ReactLove,
+            Reaction::Wow => ClientMessageParameterType::ReactWow,
+        }
+    }
+}
+
+/// A part of the Teams UI toggled with `TeamsWebsocket::toggle_ui`.
+///
+/// Only has the values valid for `MeetingAction::ToggleUI`, (de)serialized
+/// by their names `chat` and `sharing-tray`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UiPanel {
+    Chat,
+    SharingTray,
+}
+
+impl UiPanel {
+    pub const ALL: [UiPanel; 2] = [UiPanel::Chat, UiPanel::SharingTray];
+}
+
+impl From<UiPanel> for ClientMessageParameterType {
+    fn from(panel: UiPanel) -> Self {
+        match panel {
+            UiPanel::Chat => ClientMessageParameterType::ToggleUiChat,
+            UiPanel::SharingTray => ClientMessageParameterType::ToggleUiSharing,
+        }
+    }
+}
+
 /// Represents a message sent from the client.
 ///
 /// # Fields
 ///
 /// * `action` - The action to be performed.
 /// * `parameters` - Optional parameters for the action.
-/// * `request_id` - An optional identifier for the request.
+/// * `request_id` - An optional identifier for the request, assigned by
+///   `TeamsWebsocket::send` unless set.
+/// * `origin` - The integration that issued the message. Not sent to Teams, used for attribution.
+/// * `custom` - An action defined outside this crate, sent instead of
+///   `action` and `parameters`, see `Action`.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 #[serde(rename = "none")]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript", ts(rename = "ClientMessage"))]
 pub struct ClientMessage {
     pub action: MeetingAction,
     pub parameters: Option<ClientMessageParameter>,
     pub request_id: Option<u32>,
+    #[serde(skip)]
+    pub origin: Option<String>,
+    #[serde(skip)]
+    #[cfg_attr(feature = "typescript", ts(skip))]
+    pub custom: Option<CustomAction>,
 }
 
 impl ClientMessage {
@@ -237,12 +651,92 @@ impl ClientMessage {
             action,
             parameters,
             request_id: None,
+            origin: None,
+            custom: None,
+        }
+    }
+
+    /// Creates a message sending `custom`, an action this crate does not know.
+    pub fn custom(custom: CustomAction) -> Self {
+        Self {
+            custom: Some(custom),
+            ..Self::new(MeetingAction::None, None)
         }
     }
+
+    /// Returns the name of the action on the wire, e.g. `toggle-mute`.
+    pub fn wire_name(&self) -> std::borrow::Cow<'static, str> {
+        match &self.custom {
+            Some(custom) => custom.name.clone().into(),
+            None => crate::action::Action::wire_name(&self.action),
+        }
+    }
+
+    /// Returns whether Teams only performs the action in a meeting.
+    pub fn requires_meeting(&self) -> bool {
+        match &self.custom {
+            Some(custom) => custom.requires_meeting,
+            None => self.action.requires_meeting(),
+        }
+    }
+
+    /// Returns the message as sent to Teams, with `custom` in place of
+    /// `action` and `parameters`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message cannot be serialized.
+    pub fn to_wire(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        if let (Some(custom), serde_json::Value::Object(fields)) = (&self.custom, &mut value) {
+            fields.insert("action".to_string(), custom.name.clone().into());
+            fields.insert(
+                "parameters".to_string(),
+                custom.parameters.clone().unwrap_or_default(),
+            );
+        }
+        Ok(value)
+    }
+
+    /// Creates a `MeetingAction::React` message sending `reaction`.
+    pub fn reaction(reaction: Reaction) -> Self {
+        Self::new(
+            MeetingAction::React,
+            Some(ClientMessageParameter::new(reaction.into())),
+        )
+    }
+
+    /// Creates a `MeetingAction::ToggleUI` message toggling `panel`.
+    pub fn toggle_ui(panel: UiPanel) -> Self {
+        Self::new(
+            MeetingAction::ToggleUI,
+            Some(ClientMessageParameter::new(panel.into())),
+        )
+    }
+
+    /// Creates the message of `command`, see `Command`.
+    pub fn command(command: Command) -> Self {
+        command.into()
+    }
+
+    /// Attributes the message to the integration that issued it.
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
 }
 
 impl std::fmt::Display for ClientMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(custom) = &self.custom {
+            return write!(
+                f,
+                "ClientMessage {{ action: {:?}, parameters: {:?}, request_id: {} }}",
+                custom.name,
+                custom.parameters,
+                self.request_id.unwrap_or(0)
+            );
+        }
         write!(
             f,
             "ClientMessage {{ action: {:?}, parameters: {:?}, request_id: {} }}",
@@ -253,11 +747,101 @@ impl std::fmt::Display for ClientMessage {
     }
 }
 
+/// An action defined outside this crate, carried by `ClientMessage::custom`.
+///
+/// # Fields
+///
+/// * `name` - The name of the action on the wire.
+/// * `parameters` - The parameters sent with the action.
+/// * `requires_meeting` - Whether Teams only performs the action in a meeting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomAction {
+    pub name: String,
+    pub parameters: Option<serde_json::Value>,
+    pub requires_meeting: bool,
+}
+
+/// A `MeetingAction` together with the parameter it takes, so combinations
+/// Teams would answer with an `errorMsg`, e.g. a reaction attached to
+/// `ToggleMute`, do not compile.
+///
+/// # Example
+/// ```rust
+/// websocket.send(Command::React(Reaction::Wow)).await?;
+/// websocket.send(ClientMessage::command(Command::ToggleUi(UiPanel::Chat))).await?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    QueryMeetingState,
+    Mute,
+    Unmute,
+    ToggleMute,
+    HideVideo,
+    ShowVideo,
+    ToggleVideo,
+    UnblurBackground,
+    BlurBackground,
+    ToggleBlurBackground,
+    LowerHand,
+    RaiseHand,
+    ToggleHand,
+    LeaveCall,
+    React(Reaction),
+    ToggleUi(UiPanel),
+    StopSharing,
+}
+
+impl Command {
+    pub fn action(self) -> MeetingAction {
+        match self {
+            Command::QueryMeetingState => MeetingAction::QueryMeetingState,
+            Command::Mute => MeetingAction::Mute,
+            Command::Unmute => MeetingAction::Unmute,
+            Command::ToggleMute => MeetingAction::ToggleMute,
+            Command::HideVideo => MeetingAction::HideVideo,
+            Command::ShowVideo => MeetingAction::ShowVideo,
+            Command::ToggleVideo => MeetingAction::ToggleVideo,
+            Command::UnblurBackground => MeetingAction::UnblurBackground,
+            Command::BlurBackground => MeetingAction::BlurBackground,
+            Command::ToggleBlurBackground => MeetingAction::ToggleBlurBackground,
+            Command::LowerHand => MeetingAction::LowerHand,
+            Command::RaiseHand => MeetingAction::RaiseHand,
+            Command::ToggleHand => MeetingAction::ToggleHand,
+            Command::LeaveCall => MeetingAction::LeaveCall,
+            Command::React(_) => MeetingAction::React,
+            Command::ToggleUi(_) => MeetingAction::ToggleUI,
+            Command::StopSharing => MeetingAction::StopSharing,
+        }
+    }
+
+    /// Returns the parameter sent with the action, if it takes one.
+    pub fn parameter(self) -> Option<ClientMessageParameter> {
+        match self {
+            Command::React(reaction) => Some(ClientMessageParameter::new(reaction.into())),
+            Command::ToggleUi(panel) => Some(ClientMessageParameter::new(panel.into())),
+            _ => None,
+        }
+    }
+}
+
+impl From<Command> for ClientMessage {
+    fn from(command: Command) -> Self {
+        ClientMessage::new(command.action(), command.parameter())
+    }
+}
+
 /// Represents an action that can be performed in a meeting.
+///
+/// Actions are (de)serialized only by their kebab-case wire names, e.g.
+/// `toggle-mute`, the only form the local API accepts. The variants have no
+/// assigned numeric codes; their implicit discriminants follow declaration
+/// order and are not part of the protocol.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename = "none")]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript", ts(rename = "MeetingAction"))]
 pub enum MeetingAction {
     None,
     #[serde(rename = "query-state")]
@@ -295,3 +879,395 @@ pub enum MeetingAction {
     #[serde(rename = "stop-sharing")]
     StopSharing,
 }
+
+impl MeetingAction {
+    /// Returns whether Teams only performs the action in a meeting.
+    pub fn requires_meeting(self) -> bool {
+        !matches!(self, MeetingAction::None | MeetingAction::QueryMeetingState)
+    }
+}
+
+/// The wire format of the local API, which depends on the protocol version
+/// Teams accepted on connect.
+///
+/// Protocol version 2.0.0, spoken by the new Teams client, tags client
+/// messages with an `apiVersion` and leaves out the meeting state and
+/// permission fields that did not change. `decode_update` merges such a
+/// partial update into the state and permissions reported before.
+///
+/// # Example
+/// ```rust
+/// let protocol = ProtocolVersion::from_version("2.0.0");
+/// let text = protocol.encode(&ClientMessage::new(MeetingAction::ToggleMute, None))?;
+/// let message = protocol.decode(r#"{"requestId":1,"response":"Success"}"#)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    /// Protocol version 1.0.0 of classic Teams.
+    #[default]
+    V1,
+    /// Protocol version 2.0.0 of the new Teams client.
+    V2,
+}
+
+impl ProtocolVersion {
+    /// Returns the wire format of `version`, e.g. "1.0.0" or "2.0.0".
+    pub fn from_version(version: &str) -> Self {
+        if version.starts_with("1.") || version == "1" {
+            ProtocolVersion::V1
+        } else {
+            ProtocolVersion::V2
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::V1 => "1.0.0",
+            ProtocolVersion::V2 => "2.0.0",
+        }
+    }
+
+    /// Serializes `message`, usually a `ClientMessage`, for sending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `message` does not serialize to a JSON object.
+    pub fn encode<T: Serialize>(self, message: &T) -> Result<String, serde_json::Error> {
+        let mut value = serde_json::to_value(message)?;
+        if self == ProtocolVersion::V2 {
+            match &mut value {
+                serde_json::Value::Object(fields) => {
+                    fields.insert("apiVersion".to_string(), self.as_str().into());
+                }
+                _ => return Err(serde::ser::Error::custom("message is not an object")),
+            }
+        }
+        serde_json::to_string(&value)
+    }
+
+    /// Parses a message received from Teams.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `text` is not a `ServerMessage` in this format.
+    ///
+    /// Meeting updates of version 1.0.0 are read as a `v1::MeetingUpdate`
+    /// and converted into the canonical `MeetingUpdate`. In both versions the
+    /// fields missing from a meeting state or permissions are read as
+    /// `false`, unknown ones are kept in `extra`, and fields of older Teams
+    /// builds are upgraded, see `MeetingState::upgrade_legacy_fields`. Use
+    /// `decode_update` for the partial updates of version 2.0.0.
+    pub fn decode(self, text: &str) -> Result<ServerMessage, serde_json::Error> {
+        self.decode_update(text, None, None)
+    }
+
+    /// Parses a message received from Teams like `decode`, reading the
+    /// meeting state and permission fields a version 2.0.0 update leaves out
+    /// from `state` and `permissions`, the ones Teams reported before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `text` is not a `ServerMessage` in this format.
+    ///
+    /// # Example
+    /// ```rust
+    /// let message = ProtocolVersion::V2.decode_update(
+    ///     r#"{"meetingUpdate":{"meetingState":{"isMuted":true}}}"#,
+    ///     websocket.meeting_state(),
+    ///     websocket.permissions(),
+    /// )?;
+    /// ```
+    pub fn decode_update(
+        self,
+        text: &str,
+        state: Option<&MeetingState>,
+        permissions: Option<&MeetingPermissions>,
+    ) -> Result<ServerMessage, serde_json::Error> {
+        let mut value: serde_json::Value = serde_json::from_str(text)?;
+        let legacy = match self {
+            ProtocolVersion::V1 => value
+                .as_object_mut()
+                .and_then(|fields| fields.remove("meetingUpdate"))
+                .filter(|update| !update.is_null())
+                .map(serde_json::from_value::<v1::MeetingUpdate>)
+                .transpose()?,
+            ProtocolVersion::V2 => {
+                if let Some(update) = value.get_mut("meetingUpdate") {
+                    merge_fields(update.get_mut("meetingState"), state)?;
+                    merge_fields(update.get_mut("meetingPermissions"), permissions)?;
+                }
+                None
+            }
+        };
+        let mut message: ServerMessage = serde_json::from_value(value)?;
+        if let Some(update) = legacy {
+            message.meeting_update = Some(update.into());
+        }
+        if let Some(state) = message
+            .meeting_update
+            .as_mut()
+            .and_then(|update| update.meeting_state.as_mut())
+        {
+            state.upgrade_legacy_fields();
+        }
+        Ok(message)
+    }
+}
+
+/// Fills the fields missing from the JSON object `partial` with the ones of
+/// `previous`.
+fn merge_fields<T: Serialize>(
+    partial: Option<&mut serde_json::Value>,
+    previous: Option<&T>,
+) -> Result<(), serde_json::Error> {
+    let (Some(serde_json::Value::Object(fields)), Some(previous)) = (partial, previous) else {
+        return Ok(());
+    };
+    if let serde_json::Value::Object(previous) = serde_json::to_value(previous)? {
+        for (name, value) in previous {
+            fields.entry(name).or_insert(value);
+        }
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_teams_error_kind() {
+        let message: ServerMessage =
+            serde_json::from_str(r#"{"requestId":1,"errorMsg":"No active call"}"#).unwrap();
+        assert_eq!(message.error_kind(), Some(TeamsErrorKind::NoActiveCall));
+        assert_eq!(
+            TeamsErrorKind::parse("Invalid token"),
+            TeamsErrorKind::InvalidToken
+        );
+        assert_eq!(
+            TeamsErrorKind::parse("Action not permitted"),
+            TeamsErrorKind::NotPermitted
+        );
+        assert_eq!(
+            TeamsErrorKind::parse("Something broke"),
+            TeamsErrorKind::Unknown("Something broke".to_string())
+        );
+        assert_eq!(
+            message.into_result().unwrap_err(),
+            TeamsErrorKind::NoActiveCall
+        );
+    }
+
+    #[test]
+    fn test_reaction() {
+        let message = ClientMessage::reaction(Reaction::Wow);
+        assert_eq!(message.action, MeetingAction::React);
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"action":"send-reaction","parameters":{"type":"wow"},"requestId":null}"#
+        );
+        for reaction in Reaction::ALL {
+            let name = serde_json::to_value(reaction).unwrap();
+            let parameter = ClientMessageParameterType::from(reaction);
+            assert_eq!(name, serde_json::to_value(parameter).unwrap());
+        }
+        assert!(serde_json::from_str::<Reaction>(r#""chat""#).is_err());
+    }
+
+    #[test]
+    fn test_toggle_ui() {
+        let message = ClientMessage::toggle_ui(UiPanel::SharingTray);
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"action":"toggle-ui","parameters":{"type":"sharing-tray"},"requestId":null}"#
+        );
+        for panel in UiPanel::ALL {
+            let name = serde_json::to_value(panel).unwrap();
+            let parameter = ClientMessageParameterType::from(panel);
+            assert_eq!(name, serde_json::to_value(parameter).unwrap());
+        }
+        let permissions = MeetingPermissions {
+            can_toggle_chat: true,
+            ..MeetingPermissions::new()
+        };
+        assert!(permissions.allows(MeetingAction::ToggleUI));
+        assert!(permissions.allows_ui(UiPanel::Chat));
+        assert!(!permissions.allows_ui(UiPanel::SharingTray));
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            ClientMessage::command(Command::React(Reaction::Like)),
+            ClientMessage::reaction(Reaction::Like)
+        );
+        assert_eq!(
+            ClientMessage::from(Command::ToggleUi(UiPanel::Chat)),
+            ClientMessage::toggle_ui(UiPanel::Chat)
+        );
+        let message = ClientMessage::from(Command::ToggleMute);
+        assert_eq!(message, ClientMessage::new(MeetingAction::ToggleMute, None));
+    }
+
+    #[test]
+    fn test_meeting_state_json() {
+        let state = MeetingState::new().with_in_meeting(true).with_muted(true);
+        let mut with_extra = state.clone();
+        with_extra.extra.insert("isCaptionsOn".into(), true.into());
+        let json = with_extra.to_json();
+        assert!(!json.contains('\n'));
+        assert!(!json.contains("isCaptionsOn"));
+        assert!(json.contains(r#""isMuted":true"#));
+        assert!(json.contains(r#""version":1"#));
+        assert_eq!(MeetingState::from_json(&json).unwrap(), state);
+        assert!(MeetingState::from_json(r#"{"version":2}"#).is_err());
+        assert!(MeetingState::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_protocol_version() {
+        let message = ClientMessage::new(MeetingAction::ToggleMute, None);
+        let v1: serde_json::Value =
+            serde_json::from_str(&ProtocolVersion::V1.encode(&message).unwrap()).unwrap();
+        assert!(v1.get("apiVersion").is_none());
+        let v2: serde_json::Value = serde_json::from_str(
+            &ProtocolVersion::from_version("2.0.0")
+                .encode(&message)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(v2["apiVersion"], "2.0.0");
+        assert_eq!(v2["action"], "toggle-mute");
+
+        let update = r#"{"meetingUpdate":{"meetingState":{"isMuted":true,"isInMeeting":true},"meetingPermissions":{"canLeave":true}}}"#;
+        let message = ProtocolVersion::V2.decode(update).unwrap();
+        let update = message.meeting_update.unwrap();
+        let answer = r#"{"requestId":1,"response":"Success","meetingUpdate":null}"#;
+        assert!(ProtocolVersion::V1
+            .decode(answer)
+            .unwrap()
+            .meeting_update
+            .is_none());
+        assert_eq!(
+            update.meeting_state,
+            Some(MeetingState::new().with_muted(true).with_in_meeting(true))
+        );
+        assert_eq!(
+            update.meeting_permissions,
+            Some(MeetingPermissions::new().with_can_leave(true))
+        );
+    }
+
+    #[test]
+    fn test_protocol_version_partial_update() {
+        let state = MeetingState::new()
+            .with_in_meeting(true)
+            .with_video_on(true);
+        let permissions = MeetingPermissions::new().with_can_toggle_mute(true);
+        let update = r#"{"meetingUpdate":{"meetingState":{"isMuted":true},"meetingPermissions":{"canLeave":true}}}"#;
+        let message = ProtocolVersion::V2
+            .decode_update(update, Some(&state), Some(&permissions))
+            .unwrap();
+        let update = message.meeting_update.unwrap();
+        assert_eq!(update.meeting_state, Some(state.clone().with_muted(true)));
+        assert_eq!(
+            update.meeting_permissions,
+            Some(permissions.clone().with_can_leave(true))
+        );
+
+        // Version 1.0.0 always sends the full state.
+        let update = r#"{"meetingUpdate":{"meetingState":{"isMuted":true}}}"#;
+        let message = ProtocolVersion::V1
+            .decode_update(update, Some(&state), Some(&permissions))
+            .unwrap();
+        let state = message.meeting_update.unwrap().meeting_state.unwrap();
+        assert!(state.is_muted && !state.is_in_meeting);
+    }
+
+    /// Decodes the frames of `fixture`, one per line, as the payload shapes
+    /// sent by a Teams release.
+    fn decode_fixture(protocol: ProtocolVersion, fixture: &str) -> Vec<ServerMessage> {
+        fixture
+            .lines()
+            .map(|line| protocol.decode(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_release_fixtures() {
+        let classic = decode_fixture(
+            ProtocolVersion::V1,
+            include_str!("../tests/fixtures/classic-1.0.0.jsonl"),
+        );
+        let update = classic[0].meeting_update.as_ref().unwrap();
+        let state = update.meeting_state.as_ref().unwrap();
+        assert!(state.is_muted && state.is_video_on && !state.is_sharing);
+        assert!(state.extra.is_empty());
+        let permissions = update.meeting_permissions.as_ref().unwrap();
+        assert!(permissions.can_toggle_mute && !permissions.can_pair);
+        assert_eq!(permissions.extra["canToggleRecord"], false);
+        assert_eq!(classic[2].error_kind(), Some(TeamsErrorKind::NoActiveCall));
+
+        let early = decode_fixture(
+            ProtocolVersion::V1,
+            include_str!("../tests/fixtures/classic-1.0.0-early.jsonl"),
+        );
+        let update = early[0].meeting_update.as_ref().unwrap();
+        let state = update.meeting_state.as_ref().unwrap();
+        assert!(state.is_in_meeting && !state.is_video_on && !state.is_sharing);
+        let permissions = update.meeting_permissions.as_ref().unwrap();
+        assert!(permissions.can_leave && !permissions.can_react && !permissions.can_pair);
+        assert!(permissions.extra.is_empty());
+
+        let sharing = decode_fixture(
+            ProtocolVersion::V1,
+            include_str!("../tests/fixtures/classic-1.0.0-sharing.jsonl"),
+        );
+        let update = sharing[0].meeting_update.as_ref().unwrap();
+        let state = update.meeting_state.as_ref().unwrap();
+        assert!(state.is_sharing && state.is_video_on && state.extra.is_empty());
+        let permissions = update.meeting_permissions.as_ref().unwrap();
+        assert!(permissions.can_stop_sharing && permissions.can_pair);
+
+        let new = decode_fixture(
+            ProtocolVersion::V2,
+            include_str!("../tests/fixtures/new-2.0.0.jsonl"),
+        );
+        let update = new[0].meeting_update.as_ref().unwrap();
+        assert!(update.meeting_state.as_ref().unwrap().is_video_on);
+        assert!(update.meeting_permissions.as_ref().unwrap().can_pair);
+        assert!(new[2].token_refresh.is_some());
+
+        let hold = decode_fixture(
+            ProtocolVersion::V2,
+            include_str!("../tests/fixtures/new-2.0.0-hold.jsonl"),
+        );
+        let update = hold[0].meeting_update.as_ref().unwrap();
+        let state = update.meeting_state.as_ref().unwrap();
+        assert!(state.is_on_hold && !state.is_recording_paused);
+        assert_eq!(state.unread_message_count, Some(3));
+        assert_eq!(hold[0].extra["apiVersion"], "2.0.0");
+
+        // Updates after the first only carry the fields that changed.
+        let mut state = None;
+        let mut permissions = None;
+        for line in include_str!("../tests/fixtures/new-2.0.0-partial.jsonl").lines() {
+            let message = ProtocolVersion::V2
+                .decode_update(line, state.as_ref(), permissions.as_ref())
+                .unwrap();
+            let update = message.meeting_update.unwrap();
+            state = update.meeting_state.or(state);
+            permissions = update.meeting_permissions.or(permissions);
+        }
+        let state = state.unwrap();
+        assert!(state.is_in_meeting && state.is_muted && state.is_video_on && state.is_hand_raised);
+        let permissions = permissions.unwrap();
+        assert!(permissions.can_toggle_mute && !permissions.can_toggle_video);
+    }
+}
@@ -0,0 +1,123 @@
+//! The meeting updates of protocol version 1.0.0, sent by classic Teams.
+//!
+//! `ProtocolVersion::V1` decodes meeting updates into these types and
+//! converts them into the canonical `MeetingUpdate`, which is the one of
+//! protocol version 2.0.0. Fields classic Teams names differently are
+//! renamed by the conversion, e.g. `isCameraOn` becomes `is_video_on`.
+
+use serde::{Deserialize, Serialize};
+
+/// A meeting update of classic Teams.
+///
+/// # Fields
+///
+/// * `meeting_permissions` - Optional permissions for the meeting.
+/// * `meeting_state` - Optional state of the meeting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MeetingUpdate {
+    pub meeting_permissions: Option<MeetingPermissions>,
+    pub meeting_state: Option<MeetingState>,
+}
+
+/// The meeting state of classic Teams.
+///
+/// # Fields
+///
+/// * `is_camera_on` - Whether the camera is on, `is_video_on` of the canonical state.
+/// * `extra` - The fields Teams sent that this crate does not know.
+///
+/// The other fields are those of the canonical `MeetingState`. Classic
+/// Teams does not send `isOnHold`, `isRecordingPaused` or
+/// `unreadMessageCount`, they are read as not set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MeetingState {
+    pub is_muted: bool,
+    pub is_camera_on: bool,
+    pub is_hand_raised: bool,
+    pub is_in_meeting: bool,
+    pub is_recording_on: bool,
+    pub is_background_blurred: bool,
+    pub is_sharing: bool,
+    pub has_unread_messages: bool,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The meeting permissions of classic Teams.
+///
+/// # Fields
+///
+/// * `can_toggle_record` - Whether the user can toggle recording, kept in
+///   `extra` of the canonical permissions as `canToggleRecord`.
+/// * `extra` - The permissions Teams sent that this crate does not know.
+///
+/// The other fields are those of the canonical `MeetingPermissions`. Early
+/// classic builds leave out `canPair` and the sharing and chat permissions,
+/// they are read as `false`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MeetingPermissions {
+    pub can_toggle_mute: bool,
+    pub can_toggle_video: bool,
+    pub can_toggle_hand: bool,
+    pub can_toggle_blur: bool,
+    pub can_toggle_record: Option<bool>,
+    pub can_leave: bool,
+    pub can_react: bool,
+    pub can_toggle_share_tray: bool,
+    pub can_toggle_chat: bool,
+    pub can_stop_sharing: bool,
+    pub can_pair: bool,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<MeetingUpdate> for super::MeetingUpdate {
+    fn from(update: MeetingUpdate) -> Self {
+        Self {
+            meeting_permissions: update.meeting_permissions.map(Into::into),
+            meeting_state: update.meeting_state.map(Into::into),
+        }
+    }
+}
+
+impl From<MeetingState> for super::MeetingState {
+    fn from(state: MeetingState) -> Self {
+        Self {
+            is_muted: state.is_muted,
+            is_hand_raised: state.is_hand_raised,
+            is_in_meeting: state.is_in_meeting,
+            is_recording_on: state.is_recording_on,
+            is_background_blurred: state.is_background_blurred,
+            is_sharing: state.is_sharing,
+            has_unread_messages: state.has_unread_messages,
+            is_video_on: state.is_camera_on,
+            extra: state.extra,
+            ..Self::new()
+        }
+    }
+}
+
+impl From<MeetingPermissions> for super::MeetingPermissions {
+    fn from(permissions: MeetingPermissions) -> Self {
+        let mut extra = permissions.extra;
+        if let Some(can_toggle_record) = permissions.can_toggle_record {
+            extra.insert("canToggleRecord".to_string(), can_toggle_record.into());
+        }
+        Self {
+            can_toggle_mute: permissions.can_toggle_mute,
+            can_toggle_video: permissions.can_toggle_video,
+            can_toggle_hand: permissions.can_toggle_hand,
+            can_toggle_blur: permissions.can_toggle_blur,
+            can_leave: permissions.can_leave,
+            can_react: permissions.can_react,
+            can_toggle_share_tray: permissions.can_toggle_share_tray,
+            can_toggle_chat: permissions.can_toggle_chat,
+            can_stop_sharing: permissions.can_stop_sharing,
+            can_pair: permissions.can_pair,
+            extra,
+        }
+    }
+}
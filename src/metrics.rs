@@ -0,0 +1,211 @@
+use crate::messages::MeetingState;
+use metrics::{counter, describe_counter, describe_gauge, gauge, Label};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Messages sent to Teams, a counter.
+pub const MESSAGES_SENT: &str = "teams_ws_messages_sent_total";
+/// Frames received from Teams, a counter.
+pub const MESSAGES_RECEIVED: &str = "teams_ws_messages_received_total";
+/// Successful reconnects after the connection was lost, a counter.
+pub const RECONNECTS: &str = "teams_ws_reconnects_total";
+/// Errors by `ErrorKind` in the `kind` label, a counter.
+pub const ERRORS: &str = "teams_ws_errors_total";
+/// Whether Teams last reported being in a meeting, a gauge.
+pub const IN_MEETING: &str = "teams_ws_in_meeting";
+/// Whether Teams last reported being muted, a gauge.
+pub const MUTED: &str = "teams_ws_muted";
+/// Seconds since the unix epoch of the last error of the `kind` label, a gauge.
+pub const LAST_ERROR: &str = "teams_ws_last_error_timestamp_seconds";
+
+/// What failed, recorded as the `kind` label of the error metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Sending a message failed.
+    Send,
+    /// Teams sent a frame that could not be parsed.
+    Malformed,
+    /// A reconnect attempt failed.
+    Reconnect,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Send => "send",
+            ErrorKind::Malformed => "malformed",
+            ErrorKind::Reconnect => "reconnect",
+        }
+    }
+}
+
+/// Records the activity of a `TeamsWebsocket` through the `metrics` facade,
+/// e.g. for Grafana dashboards of a long-running daemon.
+///
+/// The metrics go to the recorder the application installed, e.g. the
+/// Prometheus exporter of `metrics-exporter-prometheus`; without one they
+/// are dropped. Set it with `TeamsWebsocketBuilder::metrics`. Needs the
+/// `metrics` feature.
+///
+/// | Metric                                  | Type    |
+/// |-----------------------------------------|---------|
+/// | `teams_ws_messages_sent_total`          | counter |
+/// | `teams_ws_messages_received_total`      | counter |
+/// | `teams_ws_reconnects_total`             | counter |
+/// | `teams_ws_errors_total{kind}`           | counter |
+/// | `teams_ws_in_meeting`                   | gauge   |
+/// | `teams_ws_muted`                        | gauge   |
+/// | `teams_ws_last_error_timestamp_seconds{kind}` | gauge |
+///
+/// The gauges are recorded once Teams reported a state or an error
+/// occurred. Every metric carries the labels added with `label`, e.g. to
+/// tell the accounts of an `Aggregator` apart.
+///
+/// # Example
+/// ```rust
+/// metrics_exporter_prometheus::PrometheusBuilder::new().install()?;
+/// let metrics = Metrics::new().label("account", "work");
+/// let websocket = TeamsWebsocket::builder(identifier).metrics(metrics).build()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    labels: Vec<(String, String)>,
+}
+
+impl Metrics {
+    /// Creates metrics without labels and describes them to the installed
+    /// recorder.
+    pub fn new() -> Self {
+        describe_counter!(MESSAGES_SENT, "Messages sent to Teams.");
+        describe_counter!(MESSAGES_RECEIVED, "Frames received from Teams.");
+        describe_counter!(
+            RECONNECTS,
+            "Successful reconnects after the connection was lost."
+        );
+        describe_counter!(
+            ERRORS,
+            "Failed sends, malformed frames and failed reconnect attempts."
+        );
+        describe_gauge!(
+            IN_MEETING,
+            "Whether Teams last reported being in a meeting."
+        );
+        describe_gauge!(MUTED, "Whether Teams last reported being muted.");
+        describe_gauge!(LAST_ERROR, "When the last error of the kind occurred.");
+        Self { labels: Vec::new() }
+    }
+
+    /// Adds the label `key` with `value` to every metric.
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.push((key.into(), value.into()));
+        self
+    }
+
+    fn labels(&self) -> Vec<Label> {
+        self.labels
+            .iter()
+            .map(|(key, value)| Label::new(key.clone(), value.clone()))
+            .collect()
+    }
+
+    pub(crate) fn record_sent(&self) {
+        counter!(MESSAGES_SENT, self.labels()).increment(1);
+    }
+
+    pub(crate) fn record_received(&self) {
+        counter!(MESSAGES_RECEIVED, self.labels()).increment(1);
+    }
+
+    pub(crate) fn record_reconnect(&self) {
+        counter!(RECONNECTS, self.labels()).increment(1);
+    }
+
+    pub(crate) fn record_state(&self, state: &MeetingState) {
+        gauge!(IN_MEETING, self.labels()).set(u8::from(state.is_in_meeting));
+        gauge!(MUTED, self.labels()).set(u8::from(state.is_muted));
+    }
+
+    pub(crate) fn record_error(&self, kind: ErrorKind) {
+        let mut labels = self.labels();
+        labels.push(Label::new("kind", kind.as_str()));
+        counter!(ERRORS, labels.clone()).increment(1);
+        let at_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs_f64())
+            .unwrap_or_default();
+        gauge!(LAST_ERROR, labels).set(at_secs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ClientMessage, MeetingAction, MeetingUpdate};
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    #[test]
+    fn test_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let server = MockTeamsServer::builder()
+                    .meeting_updates([MeetingUpdate {
+                        meeting_permissions: None,
+                        meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+                    }])
+                    .start()
+                    .await
+                    .unwrap();
+                let metrics = Metrics::new().label("account", "work");
+                let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                    .ignore_environment()
+                    .url(server.url())
+                    .metrics(metrics.clone())
+                    .build()
+                    .unwrap();
+                websocket.connect().await.unwrap();
+                websocket.receive().await.unwrap();
+                websocket
+                    .send_and_wait(ClientMessage::new(MeetingAction::Mute, None))
+                    .await
+                    .unwrap();
+                metrics.record_error(ErrorKind::Malformed);
+            })
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let value = |name: &str, kind: Option<&str>| {
+            snapshot
+                .iter()
+                .find(|(key, ..)| {
+                    let has = |name, value| {
+                        key.key()
+                            .labels()
+                            .any(|label| label.key() == name && label.value() == value)
+                    };
+                    key.key().name() == name
+                        && has("account", "work")
+                        && kind.is_none_or(|kind| has("kind", kind))
+                })
+                .and_then(|(.., value)| match value {
+                    DebugValue::Counter(value) => Some(*value as f64),
+                    DebugValue::Gauge(value) => Some(value.into_inner()),
+                    DebugValue::Histogram(_) => None,
+                })
+        };
+        assert_eq!(value(MESSAGES_SENT, None), Some(1.0));
+        assert_eq!(value(MESSAGES_RECEIVED, None), Some(2.0));
+        assert_eq!(value(ERRORS, Some("malformed")), Some(1.0));
+        assert_eq!(value(ERRORS, Some("send")), None);
+        assert_eq!(value(IN_MEETING, None), Some(1.0));
+        assert_eq!(value(MUTED, None), Some(0.0));
+        assert!(value(LAST_ERROR, Some("malformed")).is_some_and(|at| at > 0.0));
+    }
+}
@@ -0,0 +1,418 @@
+use crate::messages::{ClientMessage, MeetingAction, MeetingUpdate, ServerMessage};
+use futures_util::{SinkExt, StreamExt};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// What the server pushes to the connected clients.
+#[derive(Debug, Clone)]
+enum Event {
+    Message(ServerMessage),
+    Disconnect,
+}
+
+/// The behaviour of a `MockTeamsServer`, shared by its connections.
+#[derive(Debug, Clone, Default)]
+struct Script {
+    updates: Vec<MeetingUpdate>,
+    rejected: Vec<(MeetingAction, String)>,
+    token_refresh: Option<String>,
+}
+
+/// What the clients sent to a `MockTeamsServer`.
+#[derive(Debug, Default)]
+struct Recorded {
+    received: Vec<ClientMessage>,
+    invalid: Vec<String>,
+    tokens: Vec<String>,
+    handshakes: Vec<Handshake>,
+}
+
+/// The HTTP request a client opened a connection with.
+///
+/// # Fields
+///
+/// * `query` - The query of the URL, including the token.
+/// * `headers` - The headers in order, values lossily decoded as UTF-8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Handshake {
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Builds a `MockTeamsServer`, see `MockTeamsServer::builder`.
+#[derive(Debug, Default)]
+pub struct MockTeamsServerBuilder {
+    script: Script,
+}
+
+impl MockTeamsServerBuilder {
+    /// Sends `updates` in order to every client right after the handshake,
+    /// e.g. joining a meeting and unmuting.
+    pub fn meeting_updates(mut self, updates: impl IntoIterator<Item = MeetingUpdate>) -> Self {
+        self.script.updates.extend(updates);
+        self
+    }
+
+    /// Answers `action` with `error_msg` instead of `Success`, e.g.
+    /// `"No active call"`.
+    pub fn reject(mut self, action: MeetingAction, error_msg: impl Into<String>) -> Self {
+        self.script.rejected.push((action, error_msg.into()));
+        self
+    }
+
+    /// Sends `token` as `tokenRefresh` after the first action of every
+    /// connection with another token, as Teams does once the user allowed
+    /// pairing.
+    pub fn token_refresh(mut self, token: impl Into<String>) -> Self {
+        self.script.token_refresh = Some(token.into());
+        self
+    }
+
+    /// Starts the server on a free port of 127.0.0.1.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no port can be bound.
+    pub async fn start(self) -> Result<MockTeamsServer, Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (events, _) = broadcast::channel(64);
+        let recorded = Arc::new(Mutex::new(Recorded::default()));
+        let script = Arc::new(self.script);
+        let task = {
+            let events = events.clone();
+            let recorded = recorded.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let connection =
+                        serve(stream, script.clone(), recorded.clone(), events.subscribe());
+                    tokio::spawn(connection);
+                }
+            })
+        };
+        Ok(MockTeamsServer {
+            addr,
+            events,
+            recorded,
+            task,
+        })
+    }
+}
+
+/// A WebSocket server behaving like the Teams local API, for integration
+/// tests of Teams controllers without a running Teams client.
+///
+/// Every action is recorded and answered with `Success` and its request
+/// id, unless rejected with `MockTeamsServerBuilder::reject`. Frames that
+/// are not a valid action are recorded as invalid and answered with an
+/// error. The server stops when dropped.
+///
+/// Needs the `test-util` feature.
+///
+/// # Example
+/// ```rust
+/// let server = MockTeamsServer::builder()
+///     .meeting_updates([joined_meeting])
+///     .token_refresh("paired")
+///     .start()
+///     .await?;
+/// let mut websocket = TeamsWebsocket::builder(identifier).url(server.url()).build()?;
+/// websocket.connect().await?;
+/// websocket.send(ClientMessage::new(MeetingAction::Mute, None)).await?;
+/// // ... receive the update, the answer and the token
+/// server.assert_actions(&[MeetingAction::Mute]);
+/// ```
+pub struct MockTeamsServer {
+    addr: SocketAddr,
+    events: broadcast::Sender<Event>,
+    recorded: Arc<Mutex<Recorded>>,
+    task: JoinHandle<()>,
+}
+
+impl MockTeamsServer {
+    pub fn builder() -> MockTeamsServerBuilder {
+        MockTeamsServerBuilder::default()
+    }
+
+    /// Starts a server answering every action with `Success`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no port can be bound.
+    pub async fn start() -> Result<Self, Box<dyn Error>> {
+        Self::builder().start().await
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the `ws://` URL to connect to.
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Returns the actions received on all connections, oldest first.
+    pub fn received(&self) -> Vec<ClientMessage> {
+        self.recorded.lock().unwrap().received.clone()
+    }
+
+    /// Returns the text frames that were not a valid action.
+    pub fn invalid_frames(&self) -> Vec<String> {
+        self.recorded.lock().unwrap().invalid.clone()
+    }
+
+    /// Returns the token of every connection, as sent in the URL, empty
+    /// if there was none.
+    pub fn tokens(&self) -> Vec<String> {
+        self.recorded.lock().unwrap().tokens.clone()
+    }
+
+    /// Returns the handshake of every connection, oldest first.
+    pub fn handshakes(&self) -> Vec<Handshake> {
+        self.recorded.lock().unwrap().handshakes.clone()
+    }
+
+    /// Panics unless exactly `expected` were received, in order, and no
+    /// invalid frames.
+    pub fn assert_actions(&self, expected: &[MeetingAction]) {
+        let recorded = self.recorded.lock().unwrap();
+        let actions: Vec<_> = recorded.received.iter().map(|m| m.action).collect();
+        assert_eq!(actions, expected, "unexpected actions sent to Teams");
+        assert!(
+            recorded.invalid.is_empty(),
+            "invalid frames sent to Teams: {:?}",
+            recorded.invalid
+        );
+    }
+
+    /// Sends `message` to the connected clients.
+    pub fn send(&self, message: ServerMessage) {
+        let _ = self.events.send(Event::Message(message));
+    }
+
+    /// Sends `update` to the connected clients, e.g. leaving the meeting.
+    pub fn send_update(&self, update: MeetingUpdate) {
+        self.send(ServerMessage {
+            request_id: None,
+            response: None,
+            error_msg: None,
+            token_refresh: None,
+            meeting_update: Some(update),
+            extra: serde_json::Map::new(),
+        });
+    }
+
+    /// Closes the connections, as Teams does when it quits. New connections
+    /// are still accepted.
+    pub fn disconnect(&self) {
+        let _ = self.events.send(Event::Disconnect);
+    }
+}
+
+impl Drop for MockTeamsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Handles one connection of the server.
+async fn serve(
+    stream: TcpStream,
+    script: Arc<Script>,
+    recorded: Arc<Mutex<Recorded>>,
+    mut events: broadcast::Receiver<Event>,
+) {
+    let mut token = String::new();
+    let mut handshake = None;
+    // The error type is given by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        let query = request.uri().query().unwrap_or_default();
+        token = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .unwrap_or_default()
+            .to_string();
+        handshake = Some(Handshake {
+            query: query.to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.to_string(), value)
+                })
+                .collect(),
+        });
+        Ok(response)
+    };
+    let Ok(mut ws_stream) = tokio_tungstenite::accept_hdr_async(stream, callback).await else {
+        return;
+    };
+    {
+        let mut recording = recorded.lock().unwrap();
+        recording.tokens.push(token.clone());
+        recording.handshakes.extend(handshake);
+    }
+    let mut outgoing: Vec<ServerMessage> = script
+        .updates
+        .iter()
+        .map(|update| ServerMessage {
+            request_id: None,
+            response: None,
+            error_msg: None,
+            token_refresh: None,
+            meeting_update: Some(update.clone()),
+            extra: serde_json::Map::new(),
+        })
+        .collect();
+    let mut refreshed = script.token_refresh.as_ref().is_none_or(|t| *t == token);
+    loop {
+        for message in outgoing.drain(..) {
+            let text = serde_json::to_string(&message).unwrap();
+            if ws_stream.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+        let text = tokio::select! {
+            frame = ws_stream.next() => match frame {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+            event = events.recv() => match event {
+                Ok(Event::Message(message)) => {
+                    outgoing.push(message);
+                    continue;
+                }
+                Ok(Event::Disconnect) | Err(broadcast::error::RecvError::Closed) => {
+                    let _ = ws_stream.close(None).await;
+                    return;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            },
+        };
+        let message = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => message,
+            Err(e) => {
+                // Answered with the request id if there is one, as Teams does.
+                let request_id = serde_json::from_str::<serde_json::Value>(&text)
+                    .ok()
+                    .and_then(|value| value.get("requestId")?.as_u64())
+                    .and_then(|id| u32::try_from(id).ok());
+                recorded.lock().unwrap().invalid.push(text);
+                outgoing.push(ServerMessage {
+                    request_id,
+                    response: None,
+                    error_msg: Some(format!("Invalid message: {}", e)),
+                    token_refresh: None,
+                    meeting_update: None,
+                    extra: serde_json::Map::new(),
+                });
+                continue;
+            }
+        };
+        let rejected = script
+            .rejected
+            .iter()
+            .find(|(action, _)| *action == message.action);
+        outgoing.push(ServerMessage {
+            request_id: message.request_id,
+            response: rejected.is_none().then(|| "Success".to_string()),
+            error_msg: rejected.map(|(_, error_msg)| error_msg.clone()),
+            token_refresh: None,
+            meeting_update: None,
+            extra: serde_json::Map::new(),
+        });
+        recorded.lock().unwrap().received.push(message);
+        if !refreshed {
+            refreshed = true;
+            outgoing.push(ServerMessage {
+                request_id: None,
+                response: None,
+                error_msg: None,
+                token_refresh: script.token_refresh.clone(),
+                meeting_update: None,
+                extra: serde_json::Map::new(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingState;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_mock_teams_server() {
+        Runtime::new().unwrap().block_on(async {
+            let joined = MeetingUpdate {
+                meeting_state: Some(MeetingState {
+                    is_in_meeting: true,
+                    ..MeetingState::new()
+                }),
+                meeting_permissions: None,
+            };
+            let server = MockTeamsServer::builder()
+                .meeting_updates([joined])
+                .reject(MeetingAction::RaiseHand, "No active call")
+                .token_refresh("paired")
+                .start()
+                .await
+                .unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let update = websocket.receive().await.unwrap();
+            assert!(
+                update
+                    .meeting_update
+                    .unwrap()
+                    .meeting_state
+                    .unwrap()
+                    .is_in_meeting
+            );
+
+            let mute = ClientMessage::new(MeetingAction::Mute, None);
+            let answer = websocket.send_and_wait(mute).await.unwrap();
+            assert_eq!(answer.response.as_deref(), Some("Success"));
+            let token = websocket.receive().await.unwrap();
+            assert_eq!(token.token_refresh.as_deref(), Some("paired"));
+            let raise = ClientMessage::new(MeetingAction::RaiseHand, None);
+            let answer = websocket.send_and_wait(raise).await.unwrap();
+            assert_eq!(answer.error_msg.as_deref(), Some("No active call"));
+            server.assert_actions(&[MeetingAction::Mute, MeetingAction::RaiseHand]);
+            assert_eq!(server.tokens(), [""]);
+
+            server.disconnect();
+            assert!(websocket.receive().await.is_err());
+            websocket.connect().await.unwrap();
+            websocket.receive().await.unwrap();
+            assert_eq!(server.tokens(), ["", "paired"]);
+        });
+    }
+}
@@ -0,0 +1,213 @@
+use crate::reconnect::ReconnectPolicy;
+#[cfg(feature = "rustls")]
+use crate::tls::CertificatePin;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// What `TeamsWebsocket::connect` does while already connected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlreadyConnected {
+    /// Keeps the connection and returns right away.
+    #[default]
+    Keep,
+    /// Fails with `TeamsWsError::AlreadyConnected`.
+    Fail,
+}
+
+/// Where the request ids `TeamsWebsocket::send` assigns start counting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RequestIdStart {
+    /// Starts at 0.
+    #[default]
+    Zero,
+    /// Starts at the given id.
+    At(u32),
+    /// Starts at a random id, so the ids of clients sharing a Teams
+    /// instance, or of successive runs, do not repeat each other.
+    Random,
+}
+
+impl RequestIdStart {
+    /// Returns the first request id to assign.
+    pub fn first(self) -> u32 {
+        match self {
+            RequestIdStart::Zero => 0,
+            RequestIdStart::At(id) => id,
+            RequestIdStart::Random => RandomState::new().build_hasher().finish() as u32,
+        }
+    }
+}
+
+/// Options controlling how `TeamsWebsocket` establishes its connection.
+///
+/// Frames are not compressed, `permessage-deflate` is not supported by
+/// tungstenite; tunnel with `ssh -C` over slow links.
+///
+/// # Fields
+///
+/// * `allow_remote` - Whether non-loopback hosts may be connected to. Off by default, so a
+///   mistyped URL cannot send the pairing token across the network.
+/// * `check_permissions` - Whether `send` refuses actions the latest `MeetingPermissions` do not
+///   allow with `TeamsWsError::PermissionDenied`, instead of Teams answering with an error.
+///   Actions are sent while no permissions were received yet. Off by default.
+/// * `dry_run` - Whether `send` only logs the messages it would send, for developing
+///   automations against a live meeting. Also while not connected, the messages are never
+///   queued.
+/// * `query_state_on_connect` - Whether `connect`, also when reconnecting, queries the meeting
+///   state and waits for the answer before returning, like `TeamsWebsocket::ready`, so
+///   `meeting_state` is never stale. The wait is bounded by `connect_timeout`.
+/// * `fallback_protocol_versions` - Older protocol versions to retry the handshake with, in
+///   order, if Teams rejects the one of the `AppIdentifiers`.
+/// * `detect_flavor` - Whether `connect` falls back to the protocol versions of the other
+///   `TeamsFlavor`s if Teams rejects the one of the `AppIdentifiers`, so one binary works with
+///   classic and new Teams.
+/// * `state_refresh` - The interval at which `receive` queries the meeting state, so trackers
+///   catch up on updates Teams did not push, e.g. after its UI hung. Only queried while
+///   connected, not after `close`. Off by default.
+/// * `keepalive` - The interval at which `receive` pings Teams. A connection whose ping is not
+///   answered until the next one is due is treated as lost and re-established according to
+///   `reconnect`. Off by default.
+/// * `connect_timeout` - How long `connect` waits for the handshake of one protocol version,
+///   `None` to wait as long as the operating system does.
+/// * `receive_timeout` - How long `TeamsWebsocket::receive` waits for a message, `None` to wait
+///   as long as it takes.
+/// * `reconnect` - How `receive` re-establishes a dropped connection, `None` to return the error.
+/// * `already_connected` - What `connect` does while connected, use `TeamsWebsocket::reconnect`
+///   to replace the connection.
+/// * `request_id_start` - Where the request ids assigned by `send` start, they wrap around after
+///   `u32::MAX`.
+/// * `headers` - Extra HTTP headers sent with the handshake, e.g. the authorization a proxy in
+///   front of Teams requires. A `Connector` does not get them.
+/// * `query_params` - Extra query parameters appended to the URL after the ones of the protocol.
+/// * `certificate_pins` - Pinned server identities for `wss://` URLs (requires the `rustls` feature).
+/// * `accept_invalid_certificates` - Whether `wss://` servers are trusted without verifying their
+///   certificate, e.g. a reverse proxy with a self-signed one (requires the `rustls` or
+///   `native-tls` feature, rustls is used if both are enabled).
+///   Ignored if `certificate_pins` are set, which are the safer way to trust such a proxy.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionOptions {
+    pub allow_remote: bool,
+    pub check_permissions: bool,
+    pub dry_run: bool,
+    pub query_state_on_connect: bool,
+    pub fallback_protocol_versions: Vec<&'static str>,
+    pub detect_flavor: bool,
+    pub state_refresh: Option<Duration>,
+    pub keepalive: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub receive_timeout: Option<Duration>,
+    pub reconnect: Option<ReconnectPolicy>,
+    pub already_connected: AlreadyConnected,
+    pub request_id_start: RequestIdStart,
+    pub headers: Vec<(String, String)>,
+    pub query_params: Vec<(String, String)>,
+    #[cfg(feature = "rustls")]
+    pub certificate_pins: Vec<CertificatePin>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub accept_invalid_certificates: bool,
+}
+
+/// Returns the host `url` connects to, without brackets for IPv6 addresses.
+///
+/// Parsed by the `url` crate, like the URL the connection is opened with,
+/// so the host checked is the one connected to, e.g. `evil.example` for
+/// `ws://evil.example\@127.0.0.1:8124`.
+#[cfg(all(feature = "url", not(feature = "slim")))]
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    match url::Url::parse(url).ok()?.host()? {
+        url::Host::Ipv6(ip) => Some(ip.to_string()),
+        host => Some(host.to_string()),
+    }
+}
+
+/// Returns the host `url` connects to, without brackets for IPv6 addresses.
+///
+/// URLs with a `\`, `@` or whitespace in the authority have no host, parsers
+/// disagree on which host those connect to.
+#[cfg(any(feature = "slim", not(feature = "url")))]
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    let host_port = url_authority(url)?;
+    let host = if let Some(bracketed) = host_port.strip_prefix('[') {
+        bracketed.split(']').next()?
+    } else {
+        host_port.split(':').next()?
+    };
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Returns the port `url` connects to, or the default port of its scheme.
+#[cfg(all(feature = "url", not(feature = "slim")))]
+pub(crate) fn url_port(url: &str) -> Option<u16> {
+    url::Url::parse(url).ok()?.port_or_known_default()
+}
+
+/// Returns the port `url` connects to, or the default port of its scheme.
+#[cfg(any(feature = "slim", not(feature = "url")))]
+pub(crate) fn url_port(url: &str) -> Option<u16> {
+    let (scheme, _) = url.split_once("://")?;
+    let host_port = url_authority(url)?;
+    let port = match host_port.rsplit_once(']') {
+        Some((_, after)) => after.strip_prefix(':'),
+        None => host_port.split_once(':').map(|(_, port)| port),
+    };
+    match port {
+        Some(port) => port.parse().ok(),
+        None if scheme.eq_ignore_ascii_case("wss") => Some(443),
+        None => Some(80),
+    }
+}
+
+/// Returns the authority of `url`, refusing the ones `url_host` cannot
+/// tell the host of.
+#[cfg(any(feature = "slim", not(feature = "url")))]
+fn url_authority(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    (!authority.contains(['\\', '@']) && !authority.contains(char::is_whitespace))
+        .then_some(authority)
+}
+
+/// Returns whether `host` refers to the local machine.
+pub(crate) fn is_loopback_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("ws://127.0.0.1:8124").as_deref(),
+            Some("127.0.0.1")
+        );
+        assert_eq!(
+            url_host("wss://teams.example:443/path?x=1").as_deref(),
+            Some("teams.example")
+        );
+        assert_eq!(url_host("ws://[::1]:8124/").as_deref(), Some("::1"));
+        assert_eq!(url_host("127.0.0.1:8124"), None);
+        #[cfg(all(feature = "url", not(feature = "slim")))]
+        assert_eq!(
+            url_host("ws://evil.example\\@127.0.0.1:8124").as_deref(),
+            Some("evil.example")
+        );
+        #[cfg(any(feature = "slim", not(feature = "url")))]
+        assert_eq!(url_host("ws://evil.example\\@127.0.0.1:8124"), None);
+        assert!(is_loopback_host("localhost"));
+        assert!(is_loopback_host("::1"));
+        assert!(is_loopback_host("127.0.0.2"));
+        assert!(!is_loopback_host("192.168.1.10"));
+        assert_eq!(url_port("ws://127.0.0.1:8124"), Some(8124));
+        assert_eq!(url_port("wss://[::1]/"), Some(443));
+        assert_eq!(url_port("ws://localhost"), Some(80));
+        assert!(!is_loopback_host("teams.example"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::logging;
+use crate::messages::MeetingAction;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// A request sent to Teams that was not answered yet.
+///
+/// # Fields
+///
+/// * `id` - The request id sent with the message.
+/// * `action` - The action of the request.
+/// * `sent_at` - When the request was sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRequest {
+    pub id: u32,
+    pub action: MeetingAction,
+    pub sent_at: Instant,
+}
+
+impl PendingRequest {
+    /// Returns how long the request is waiting for an answer.
+    pub fn age(&self) -> Duration {
+        self.sent_at.elapsed()
+    }
+}
+
+/// The in-flight requests of a `TeamsWebsocket`, by request id.
+#[derive(Debug, Default)]
+pub(crate) struct PendingRequests {
+    requests: BTreeMap<u32, PendingRequest>,
+}
+
+impl PendingRequests {
+    pub(crate) fn insert(&mut self, id: u32, action: MeetingAction) {
+        self.requests.insert(
+            id,
+            PendingRequest {
+                id,
+                action,
+                sent_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Removes the request answered by a message with `id`.
+    pub(crate) fn resolve(&mut self, id: u32) -> Option<PendingRequest> {
+        let request = self.requests.remove(&id);
+        if let Some(request) = &request {
+            trace!(
+                target: logging::CONNECTION,
+                "Request {} ({:?}) answered after {:?}",
+                id,
+                request.action,
+                request.age()
+            );
+        }
+        request
+    }
+
+    /// Returns whether a request with `id` is waiting for an answer.
+    pub(crate) fn contains(&self, id: u32) -> bool {
+        self.requests.contains_key(&id)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &PendingRequest> {
+        self.requests.values()
+    }
+
+    /// Removes and returns the requests older than `max_age`.
+    pub(crate) fn expire(&mut self, max_age: Duration) -> Vec<PendingRequest> {
+        let expired: Vec<u32> = self
+            .requests
+            .values()
+            .filter(|request| request.age() > max_age)
+            .map(|request| request.id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.requests.remove(&id))
+            .inspect(|request| {
+                warn!(
+                    target: logging::CONNECTION,
+                    "Teams did not answer request {} ({:?}) within {:?}",
+                    request.id,
+                    request.action,
+                    max_age
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.requests.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_requests() {
+        let mut requests = PendingRequests::default();
+        requests.insert(0, MeetingAction::Mute);
+        requests.insert(1, MeetingAction::RaiseHand);
+        requests.insert(2, MeetingAction::BlurBackground);
+        assert_eq!(
+            requests.resolve(1).unwrap().action,
+            MeetingAction::RaiseHand
+        );
+        assert!(requests.resolve(1).is_none());
+
+        requests.requests.get_mut(&0).unwrap().sent_at -= Duration::from_secs(60);
+        let expired = requests.expire(Duration::from_secs(30));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, 0);
+        assert_eq!(requests.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::event::Event;
+use crate::logging;
+use crate::messages::ClientMessage;
+use crate::sandbox::{Sandbox, SandboxedWebsocket};
+use crate::state::StateTracker;
+use crate::TeamsWebsocket;
+use std::error::Error;
+
+/// The version of the dynamic plugin interface, bumped on incompatible changes.
+#[cfg(feature = "dynamic-plugins")]
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// An event handler extending a `PluginHost`.
+///
+/// Plugins are registered at startup with `PluginHost::register` or, with
+/// the `dynamic-plugins` feature, loaded from a directory of dynamic
+/// libraries declared with `declare_plugin!`.
+///
+/// # Example
+/// ```rust
+/// struct MuteOnJoin;
+///
+/// impl Plugin for MuteOnJoin {
+///     fn name(&self) -> &str {
+///         "mute-on-join"
+///     }
+///
+///     fn on_event(&mut self, event: &Event, _state: &StateTracker) -> Vec<ClientMessage> {
+///         match event {
+///             Event::StateChanged(MeetingStateDelta::InMeeting(true)) => {
+///                 vec![ClientMessage::new(MeetingAction::Mute, None)]
+///             }
+///             _ => vec![],
+///         }
+///     }
+/// }
+/// ```
+pub trait Plugin: Send {
+    /// The name the plugin's messages are attributed to, as `plugin:<name>`.
+    fn name(&self) -> &str;
+
+    /// Handles `event` and returns the messages to send.
+    fn on_event(&mut self, event: &Event, state: &StateTracker) -> Vec<ClientMessage>;
+}
+
+/// Declares the entry points of a plugin built as a `cdylib`, for loading
+/// with `PluginHost::load_directory`.
+///
+/// The plugin must be built with the same compiler and version of this
+/// crate as the host, as the trait object crosses the library boundary.
+///
+/// # Example
+/// ```rust
+/// ms_teams_ws::declare_plugin!(MuteOnJoin);
+/// ```
+#[cfg(feature = "dynamic-plugins")]
+#[macro_export]
+macro_rules! declare_plugin {
+    ($plugin:expr) => {
+        #[no_mangle]
+        pub extern "C" fn teams_ws_plugin_api_version() -> u32 {
+            $crate::plugin::PLUGIN_API_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn teams_ws_plugin_create() -> *mut ::std::ffi::c_void {
+            let plugin: ::std::boxed::Box<dyn $crate::plugin::Plugin> =
+                ::std::boxed::Box::new($plugin);
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(plugin)) as *mut ::std::ffi::c_void
+        }
+    };
+}
+
+struct Registered {
+    plugin: Box<dyn Plugin>,
+    sandbox: Sandbox,
+}
+
+/// Runs registered plugins against a connection.
+///
+/// Every plugin runs in its own `Sandbox`: plugins without read access do
+/// not see state changes, and actions the sandbox does not allow are not sent.
+pub struct PluginHost {
+    // Declared before `libraries`, the plugins must be dropped before the
+    // libraries containing their code are unloaded.
+    plugins: Vec<Registered>,
+    tracker: StateTracker,
+    #[cfg(feature = "dynamic-plugins")]
+    libraries: Vec<libloading::Library>,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            tracker: StateTracker::new(),
+            #[cfg(feature = "dynamic-plugins")]
+            libraries: Vec::new(),
+        }
+    }
+
+    /// Registers a trusted plugin, which may send any action.
+    pub fn register(&mut self, plugin: impl Plugin + 'static) -> &mut Self {
+        self.register_sandboxed(plugin, Sandbox::unrestricted())
+    }
+
+    /// Registers a plugin restricted by `sandbox`.
+    pub fn register_sandboxed(
+        &mut self,
+        plugin: impl Plugin + 'static,
+        sandbox: Sandbox,
+    ) -> &mut Self {
+        self.add(Box::new(plugin), sandbox);
+        self
+    }
+
+    fn add(&mut self, plugin: Box<dyn Plugin>, sandbox: Sandbox) {
+        info!(target: logging::AUTOMATION, "Registered plugin {}", plugin.name());
+        self.plugins.push(Registered { plugin, sandbox });
+    }
+
+    /// Returns the names of the registered plugins.
+    pub fn plugins(&self) -> impl Iterator<Item = &str> {
+        self.plugins
+            .iter()
+            .map(|registered| registered.plugin.name())
+    }
+
+    /// Returns the state accumulated from the meeting updates seen so far.
+    pub fn state(&self) -> &StateTracker {
+        &self.tracker
+    }
+
+    /// Loads every dynamic library in `directory` as a plugin restricted by
+    /// `sandbox` and returns the number of plugins loaded.
+    ///
+    /// Libraries built against a different `PLUGIN_API_VERSION` are skipped.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization code, and the plugins must
+    /// be built with `declare_plugin!` by the same compiler as the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be read or a library cannot be loaded.
+    #[cfg(feature = "dynamic-plugins")]
+    pub unsafe fn load_directory(
+        &mut self,
+        directory: impl AsRef<std::path::Path>,
+        sandbox: Sandbox,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str())
+                != Some(std::env::consts::DLL_EXTENSION)
+            {
+                continue;
+            }
+            let library = libloading::Library::new(&path)?;
+            let version: libloading::Symbol<extern "C" fn() -> u32> =
+                library.get(b"teams_ws_plugin_api_version")?;
+            if version() != PLUGIN_API_VERSION {
+                warn!(
+                    target: logging::AUTOMATION,
+                    "Skipping plugin {}: API version {} instead of {}",
+                    path.display(),
+                    version(),
+                    PLUGIN_API_VERSION
+                );
+                continue;
+            }
+            let create: libloading::Symbol<extern "C" fn() -> *mut std::ffi::c_void> =
+                library.get(b"teams_ws_plugin_create")?;
+            let plugin = *Box::from_raw(create() as *mut Box<dyn Plugin>);
+            self.add(plugin, sandbox.clone());
+            self.libraries.push(library);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Passes `event` to all plugins and returns the messages they sent,
+    /// attributed to `plugin:<name>`, with the sandbox of the sending plugin.
+    pub fn handle(&mut self, event: &Event) -> Vec<(ClientMessage, &Sandbox)> {
+        let hidden = StateTracker::new();
+        let mut sent = Vec::new();
+        for registered in self.plugins.iter_mut() {
+            let state = if registered.sandbox.read_state {
+                &self.tracker
+            } else if matches!(
+                event,
+                Event::StateChanged(_) | Event::Session(_) | Event::PhaseChanged(_)
+            ) {
+                continue;
+            } else {
+                &hidden
+            };
+            let origin = format!("plugin:{}", registered.plugin.name());
+            for message in registered.plugin.on_event(event, state) {
+                sent.push((message.with_origin(origin.as_str()), &registered.sandbox));
+            }
+        }
+        sent
+    }
+
+    /// Processes the connection until it ends, passing its events to the plugins.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that ended the connection, after the plugins
+    /// handled `Event::Disconnected`.
+    pub async fn run(&mut self, websocket: &mut TeamsWebsocket) -> Result<(), Box<dyn Error>> {
+        self.dispatch(websocket, &Event::Connected).await;
+        loop {
+            match websocket.receive_resilient().await {
+                Ok(message) => {
+                    let Some(update) = message.meeting_update else {
+                        continue;
+                    };
+                    for event in self.tracker.events(&update) {
+                        self.dispatch(websocket, &event).await;
+                    }
+                }
+                Err(e) => {
+                    let event = Event::disconnected(websocket, e.as_ref());
+                    self.dispatch(websocket, &event).await;
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, websocket: &mut TeamsWebsocket, event: &Event) {
+        for (message, sandbox) in self.handle(event) {
+            let origin = message.origin.clone().unwrap_or_default();
+            let action = message.action;
+            let mut sandboxed = SandboxedWebsocket::new(websocket, sandbox);
+            if let Err(e) = sandboxed.send(message).await {
+                warn!(target: logging::AUTOMATION, "{} failed to send {:?}: {}", origin, action, e);
+            }
+        }
+    }
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingAction;
+    use crate::state::MeetingStateDelta;
+
+    struct MuteOnJoin;
+
+    impl Plugin for MuteOnJoin {
+        fn name(&self) -> &str {
+            "mute-on-join"
+        }
+
+        fn on_event(&mut self, event: &Event, _state: &StateTracker) -> Vec<ClientMessage> {
+            match event {
+                Event::StateChanged(MeetingStateDelta::InMeeting(true)) => {
+                    vec![ClientMessage::new(MeetingAction::Mute, None)]
+                }
+                _ => vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn test_plugin_host() {
+        let mut host = PluginHost::new();
+        host.register(MuteOnJoin).register_sandboxed(
+            MuteOnJoin,
+            Sandbox {
+                read_state: false,
+                ..Sandbox::unrestricted()
+            },
+        );
+        assert_eq!(host.plugins().count(), 2);
+
+        let sent = host.handle(&Event::StateChanged(MeetingStateDelta::InMeeting(true)));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0.action, MeetingAction::Mute);
+        assert_eq!(sent[0].0.origin.as_deref(), Some("plugin:mute-on-join"));
+        assert!(host.handle(&Event::Connected).is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+
+/// Appends `params` as an `application/x-www-form-urlencoded` query to
+/// `base`, producing the same result as `url::Url::parse_with_params` for the
+/// URLs the Teams API uses.
+///
+/// This is all the `url` crate is needed for, so `slim` builds use it instead.
+///
+/// # Errors
+///
+/// Returns an error if `base` is not an absolute `ws://` or `wss://` URL.
+#[cfg_attr(all(feature = "url", not(feature = "slim")), allow(dead_code))]
+pub(crate) fn url_with_params(
+    base: &str,
+    params: &[(&str, &str)],
+) -> Result<String, Box<dyn Error>> {
+    let (scheme, rest) = base
+        .split_once("://")
+        .ok_or_else(|| format!("invalid url {}: missing scheme", base))?;
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme != "ws" && scheme != "wss" {
+        return Err(Box::from(format!(
+            "invalid url {}: unsupported scheme",
+            base
+        )));
+    }
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (without_query, query) = match rest.split_once('?') {
+        Some((without_query, query)) => (without_query, Some(query)),
+        None => (rest, None),
+    };
+    let (authority, path) = match without_query.find('/') {
+        Some(index) => without_query.split_at(index),
+        None => (without_query, "/"),
+    };
+    // Parsers disagree on which host `a\@b` and `a@b` connect to.
+    if authority.is_empty()
+        || authority.contains(['\\', '@'])
+        || authority.contains(char::is_whitespace)
+    {
+        return Err(Box::from(format!("invalid url {}: invalid host", base)));
+    }
+
+    let mut url = format!("{}://{}{}?", scheme, authority, path);
+    if let Some(query) = query.filter(|query| !query.is_empty()) {
+        url.push_str(query);
+        url.push('&');
+    }
+    let encoded = params
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+    url.push_str(&encoded);
+    Ok(url)
+}
+
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: [(&str, &str); 3] = [
+        ("protocol-version", "1.0.0"),
+        ("app", "Test App/ä"),
+        ("token", "a+b=c&d"),
+    ];
+
+    #[test]
+    fn test_url_with_params() {
+        assert_eq!(
+            url_with_params("ws://127.0.0.1:8124", &PARAMS).unwrap(),
+            "ws://127.0.0.1:8124/?protocol-version=1.0.0&app=Test+App%2F%C3%A4&token=a%2Bb%3Dc%26d"
+        );
+        assert!(url_with_params("127.0.0.1:8124", &PARAMS).is_err());
+        assert!(url_with_params("http://127.0.0.1:8124", &PARAMS).is_err());
+        assert!(url_with_params("ws://evil.example\\@127.0.0.1:8124", &PARAMS).is_err());
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn test_url_with_params_matches_url_crate() {
+        for base in [
+            "ws://127.0.0.1:8124",
+            "wss://[::1]:8443/api?x=1",
+            "ws://localhost/",
+        ] {
+            assert_eq!(
+                url_with_params(base, &PARAMS).unwrap(),
+                url::Url::parse_with_params(base, &PARAMS).unwrap().as_str()
+            );
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::logging;
+use crate::messages::{ClientMessage, CustomAction};
+use crate::TeamsWsError;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Commands older than this are dropped instead of replayed by default.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// The number of commands kept by default, older ones are dropped first.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// What `CommandQueue::push` does when the queue is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drops the oldest command to make room.
+    #[default]
+    DropOldest,
+    /// Drops the command being queued.
+    DropNewest,
+    /// Fails with `TeamsWsError::QueueFull`.
+    Error,
+}
+
+/// A command waiting for the connection.
+///
+/// # Fields
+///
+/// * `queued_at_ms` - Milliseconds since the unix epoch when the command was queued.
+/// * `origin` - The integration that issued the command, see `ClientMessage::origin`.
+/// * `message` - The command.
+/// * `custom` - The action defined outside this crate, see `ClientMessage::custom`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueuedCommand {
+    pub queued_at_ms: u128,
+    pub origin: Option<String>,
+    pub message: ClientMessage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<CustomAction>,
+}
+
+impl QueuedCommand {
+    fn age(&self) -> Duration {
+        let now = now_ms().unwrap_or_default();
+        Duration::from_millis(now.saturating_sub(self.queued_at_ms) as u64)
+    }
+
+    pub(crate) fn into_message(self) -> ClientMessage {
+        ClientMessage {
+            origin: self.origin,
+            custom: self.custom,
+            ..self.message
+        }
+    }
+}
+
+fn now_ms() -> Result<u128, Box<dyn Error>> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis())
+}
+
+/// Commands sent while Teams is not connected, replayed by
+/// `TeamsWebsocket::connect`.
+///
+/// A queue created with `CommandQueue::persistent` is kept in a file, so
+/// commands survive a restart of the process. Commands older than the
+/// maximum age are dropped on replay. When the queue is full, the
+/// `OverflowPolicy` decides which command is lost.
+///
+/// # Example
+/// ```rust
+/// let queue = CommandQueue::persistent("queue.jsonl")?.max_age(Duration::from_secs(60));
+/// let mut websocket = TeamsWebsocket::builder(identifier).command_queue(queue).build()?;
+/// websocket.send(ClientMessage::new(MeetingAction::Mute, None)).await?; // queued
+/// websocket.connect().await?; // sends the mute if within a minute
+/// ```
+#[derive(Debug)]
+pub struct CommandQueue {
+    commands: VecDeque<QueuedCommand>,
+    path: Option<PathBuf>,
+    max_age: Duration,
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl CommandQueue {
+    /// Creates a queue kept in memory.
+    pub fn new() -> Self {
+        Self {
+            commands: VecDeque::new(),
+            path: None,
+            max_age: DEFAULT_MAX_AGE,
+            capacity: DEFAULT_CAPACITY,
+            overflow: OverflowPolicy::default(),
+        }
+    }
+
+    /// Creates a queue kept in the file at `path`, loading the commands
+    /// queued by a previous run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an existing file cannot be read or parsed. A
+    /// last line without a line break that cannot be parsed was torn by
+    /// the previous run and is skipped.
+    pub fn persistent(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut commands = VecDeque::new();
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                let mut lines = content
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .peekable();
+                while let Some(line) = lines.next() {
+                    match serde_json::from_str(line) {
+                        Ok(command) => commands.push_back(command),
+                        Err(e) if lines.peek().is_none() && !content.ends_with('\n') => {
+                            warn!(
+                                target: logging::QUEUE,
+                                "Skipping torn last line of {}: {}",
+                                path.display(),
+                                e
+                            );
+                        }
+                        Err(e) => return Err(Box::new(e)),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+        if !commands.is_empty() {
+            info!(
+                target: logging::QUEUE,
+                "Loaded {} queued commands from {}",
+                commands.len(),
+                path.display()
+            );
+        }
+        Ok(Self {
+            commands,
+            path: Some(path.to_path_buf()),
+            ..Self::new()
+        })
+    }
+
+    /// Drops commands older than `max_age` instead of replaying them.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Keeps at most `capacity` commands, see `overflow`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets what happens to commands pushed while the queue is full.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Returns the queued commands, oldest first.
+    pub fn commands(&self) -> impl Iterator<Item = &QueuedCommand> {
+        self.commands.iter()
+    }
+
+    /// Returns whether a queued command has the request id `id`.
+    pub(crate) fn contains_request(&self, id: u32) -> bool {
+        self.commands
+            .iter()
+            .any(|command| command.message.request_id == Some(id))
+    }
+
+    /// Queues `message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command cannot be written to the queue file,
+    /// or `TeamsWsError::QueueFull` if the queue is full and the overflow
+    /// policy is `OverflowPolicy::Error`.
+    pub fn push(&mut self, message: ClientMessage) -> Result<(), Box<dyn Error>> {
+        if self.commands.len() >= self.capacity {
+            match self.overflow {
+                OverflowPolicy::DropOldest => {}
+                OverflowPolicy::DropNewest => {
+                    warn!(
+                        target: logging::QUEUE,
+                        "Command queue full, dropping {:?}",
+                        message.action
+                    );
+                    return Ok(());
+                }
+                OverflowPolicy::Error => {
+                    return Err(Box::new(TeamsWsError::QueueFull {
+                        action: message.action,
+                    }));
+                }
+            }
+        }
+        let command = QueuedCommand {
+            queued_at_ms: now_ms()?,
+            origin: message.origin.clone(),
+            custom: message.custom.clone(),
+            message,
+        };
+        self.commands.push_back(command);
+        if self.commands.len() > self.capacity {
+            let dropped = self.commands.pop_front();
+            warn!(target: logging::QUEUE, "Command queue full, dropping {:?}", dropped);
+            return self.save();
+        }
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                let line = serde_json::to_string(self.commands.back().unwrap())?;
+                writeln!(file, "{}", line)?;
+                file.sync_data()?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Puts `commands` back at the front of the queue, e.g. after a replay
+    /// failed. They keep the time they were queued at.
+    pub(crate) fn requeue(&mut self, commands: Vec<QueuedCommand>) -> Result<(), Box<dyn Error>> {
+        for command in commands.into_iter().rev() {
+            self.commands.push_front(command);
+        }
+        self.commands.truncate(self.capacity);
+        self.save()
+    }
+
+    /// Removes all commands and returns those not older than the maximum age.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue file cannot be cleared, the commands stay queued then.
+    pub fn take_fresh(&mut self) -> Result<Vec<ClientMessage>, Box<dyn Error>> {
+        Ok(self
+            .take_fresh_commands()?
+            .into_iter()
+            .map(QueuedCommand::into_message)
+            .collect())
+    }
+
+    /// Removes all commands and returns those not older than the maximum
+    /// age, like `take_fresh`, with the time they were queued at.
+    pub(crate) fn take_fresh_commands(&mut self) -> Result<Vec<QueuedCommand>, Box<dyn Error>> {
+        let commands = std::mem::take(&mut self.commands);
+        if let Err(e) = self.save() {
+            self.commands = commands;
+            return Err(e);
+        }
+        Ok(commands
+            .into_iter()
+            .filter(|command| {
+                let fresh = command.age() <= self.max_age;
+                if !fresh {
+                    info!(
+                        target: logging::QUEUE,
+                        "Dropping stale queued command {:?}",
+                        command.message.action
+                    );
+                }
+                fresh
+            })
+            .collect())
+    }
+
+    /// Writes the queue file, replacing it only once the new content is
+    /// written completely.
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for command in &self.commands {
+            content.push_str(&serde_json::to_string(command)?);
+            content.push('\n');
+        }
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MeetingAction;
+
+    #[test]
+    fn test_persistent_command_queue() {
+        let path =
+            std::env::temp_dir().join(format!("teams-ws-queue-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut queue = CommandQueue::persistent(&path).unwrap();
+        queue
+            .push(ClientMessage::new(MeetingAction::Mute, None).with_origin("hotkeys"))
+            .unwrap();
+        queue
+            .push(ClientMessage::new(MeetingAction::RaiseHand, None))
+            .unwrap();
+        drop(queue);
+
+        let mut queue = CommandQueue::persistent(&path).unwrap();
+        assert_eq!(queue.len(), 2);
+        queue.commands.front_mut().unwrap().queued_at_ms = 0;
+        let fresh = queue.take_fresh().unwrap();
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh[0].action, MeetingAction::RaiseHand);
+        assert!(CommandQueue::persistent(&path).unwrap().is_empty());
+
+        queue
+            .push(ClientMessage::new(MeetingAction::Mute, None))
+            .unwrap();
+        queue.commands.front_mut().unwrap().queued_at_ms = 1;
+        let taken = queue.take_fresh_commands().unwrap();
+        assert!(taken.is_empty());
+        queue
+            .push(ClientMessage::new(MeetingAction::Mute, None))
+            .unwrap();
+        let taken = queue.take_fresh_commands().unwrap();
+        let queued_at_ms = taken[0].queued_at_ms;
+        queue.requeue(taken).unwrap();
+        let queue = CommandQueue::persistent(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.commands().next().unwrap().queued_at_ms, queued_at_ms);
+
+        // A line torn by a crash while appending is skipped.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"queued_at_ms\":").unwrap();
+        drop(file);
+        assert_eq!(CommandQueue::persistent(&path).unwrap().len(), 1);
+        std::fs::write(&path, "{}\n").unwrap();
+        assert!(CommandQueue::persistent(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_command_queue_overflow() {
+        let push = |queue: &mut CommandQueue, action| queue.push(ClientMessage::new(action, None));
+        let mut queue = CommandQueue::new().capacity(1);
+        push(&mut queue, MeetingAction::Mute).unwrap();
+        push(&mut queue, MeetingAction::RaiseHand).unwrap();
+        assert_eq!(
+            queue.commands().next().unwrap().message.action,
+            MeetingAction::RaiseHand
+        );
+
+        let mut queue = CommandQueue::new()
+            .capacity(1)
+            .overflow(OverflowPolicy::DropNewest);
+        push(&mut queue, MeetingAction::Mute).unwrap();
+        push(&mut queue, MeetingAction::RaiseHand).unwrap();
+        assert_eq!(
+            queue.commands().next().unwrap().message.action,
+            MeetingAction::Mute
+        );
+
+        let mut queue = CommandQueue::new()
+            .capacity(1)
+            .overflow(OverflowPolicy::Error);
+        push(&mut queue, MeetingAction::Mute).unwrap();
+        let error = push(&mut queue, MeetingAction::RaiseHand).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(TeamsWsError::QueueFull {
+                action: MeetingAction::RaiseHand
+            })
+        ));
+        assert_eq!(queue.len(), 1);
+    }
+}
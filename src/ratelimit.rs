@@ -0,0 +1,169 @@
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction};
+use crate::TeamsWsError;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The actions `RateLimiter::debounce_toggles` debounces, which undo
+/// themselves when repeated.
+pub const TOGGLE_ACTIONS: [MeetingAction; 5] = [
+    MeetingAction::ToggleMute,
+    MeetingAction::ToggleVideo,
+    MeetingAction::ToggleBlurBackground,
+    MeetingAction::ToggleHand,
+    MeetingAction::ToggleUI,
+];
+
+/// Limits how often commands are sent, so e.g. a bouncing hardware button
+/// cannot flood Teams with `toggle-mute` and leave the state flipped the
+/// wrong way.
+///
+/// A debounced action is refused within its window after it was last
+/// sent. The overall limit refuses commands beyond `max` within any
+/// `per` interval. Refused commands fail with `TeamsWsError::RateLimited`;
+/// state queries are never refused.
+///
+/// # Example
+/// ```rust
+/// let limiter = RateLimiter::new()
+///     .limit(10, Duration::from_secs(1))
+///     .debounce_toggles(Duration::from_millis(300));
+/// let websocket = TeamsWebsocket::builder(identifier).rate_limiter(limiter).build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    limit: Option<(usize, Duration)>,
+    debounce: HashMap<MeetingAction, Duration>,
+    /// When the commands within the limit interval were sent.
+    sent: VecDeque<Instant>,
+    /// When each debounced action was last sent.
+    last: HashMap<MeetingAction, Instant>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows at most `max` commands within any `per` interval.
+    pub fn limit(mut self, max: usize, per: Duration) -> Self {
+        self.limit = Some((max, per));
+        self
+    }
+
+    /// Refuses `action` within `window` after it was sent.
+    pub fn debounce(mut self, action: MeetingAction, window: Duration) -> Self {
+        self.debounce.insert(action, window);
+        self
+    }
+
+    /// Debounces all `TOGGLE_ACTIONS` with `window`.
+    pub fn debounce_toggles(self, window: Duration) -> Self {
+        TOGGLE_ACTIONS
+            .into_iter()
+            .fold(self, |limiter, action| limiter.debounce(action, window))
+    }
+
+    /// Admits `message` or fails with `TeamsWsError::RateLimited` if it
+    /// comes too soon. Only `record` counts it as sent, so a command that
+    /// is refused or fails later does not use up the limit.
+    pub fn check(&self, message: &ClientMessage) -> Result<(), TeamsWsError> {
+        if message.action == MeetingAction::QueryMeetingState {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let limited = |action, retry_after| {
+            let e = TeamsWsError::RateLimited {
+                action,
+                retry_after,
+            };
+            info!(target: logging::POLICY, "{}", e);
+            Err(e)
+        };
+        if let (Some(window), Some(last)) = (
+            self.debounce.get(&message.action),
+            self.last.get(&message.action),
+        ) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < *window {
+                return limited(message.action, *window - elapsed);
+            }
+        }
+        if let Some((max, per)) = self.limit {
+            let recent: Vec<_> = self
+                .sent
+                .iter()
+                .filter(|at| now.duration_since(**at) < per)
+                .collect();
+            if recent.len() >= max {
+                let oldest = recent.first().map_or(now, |at| **at);
+                return limited(message.action, per - now.duration_since(oldest));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts `message` as sent, after `check` admitted it.
+    pub fn record(&mut self, message: &ClientMessage) {
+        if message.action == MeetingAction::QueryMeetingState {
+            return;
+        }
+        let now = Instant::now();
+        if let Some((_, per)) = self.limit {
+            while self
+                .sent
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= per)
+            {
+                self.sent.pop_front();
+            }
+            self.sent.push_back(now);
+        }
+        if self.debounce.contains_key(&message.action) {
+            self.last.insert(message.action, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let message = |action| ClientMessage::new(action, None);
+        let mut limiter = RateLimiter::new()
+            .limit(3, Duration::from_secs(60))
+            .debounce_toggles(Duration::from_secs(60));
+        let mut send = |action| {
+            let message = message(action);
+            limiter.check(&message)?;
+            limiter.record(&message);
+            Ok::<_, TeamsWsError>(())
+        };
+        send(MeetingAction::ToggleMute).unwrap();
+        let e = send(MeetingAction::ToggleMute).unwrap_err();
+        assert!(matches!(
+            e,
+            TeamsWsError::RateLimited { action: MeetingAction::ToggleMute, retry_after }
+                if retry_after > Duration::from_secs(59)
+        ));
+        send(MeetingAction::Mute).unwrap();
+        send(MeetingAction::Mute).unwrap();
+        assert!(send(MeetingAction::Unmute).is_err());
+        send(MeetingAction::QueryMeetingState).unwrap();
+
+        // Commands that are checked but not sent do not count.
+        let limiter = RateLimiter::new()
+            .limit(1, Duration::from_secs(60))
+            .debounce(MeetingAction::ToggleMute, Duration::from_secs(60));
+        limiter.check(&message(MeetingAction::ToggleMute)).unwrap();
+        limiter.check(&message(MeetingAction::ToggleMute)).unwrap();
+
+        let mut limiter = RateLimiter::new().debounce(MeetingAction::ToggleMute, Duration::ZERO);
+        for _ in 0..2 {
+            limiter.check(&message(MeetingAction::ToggleMute)).unwrap();
+            limiter.record(&message(MeetingAction::ToggleMute));
+        }
+    }
+}
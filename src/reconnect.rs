@@ -0,0 +1,97 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How `TeamsWebsocket::receive` re-establishes a dropped connection, e.g.
+/// when the Teams client restarts.
+///
+/// The delay before attempt `n` (counting from 0) is `initial_delay *
+/// multiplier^n`, capped at `max_delay` and reduced by a random share of
+/// up to `jitter`, so several clients do not reconnect in lockstep.
+///
+/// # Fields
+///
+/// * `initial_delay` - The delay before the first attempt.
+/// * `max_delay` - The longest delay between attempts.
+/// * `multiplier` - The factor the delay grows by after every failed attempt.
+/// * `jitter` - The share of the delay, between 0 and 1, that is randomly left out.
+/// * `max_attempts` - The attempts after which `receive` gives up, `None` to retry forever.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier)
+///     .reconnect(ReconnectPolicy {
+///         max_attempts: Some(10),
+///         ..ReconnectPolicy::default()
+///     })
+///     .build()?;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Returns the delay before attempt `attempt`, without jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.min(i32::MAX as u32) as i32);
+        self.initial_delay
+            .mul_f64(factor.min(u32::MAX as f64))
+            .min(self.max_delay)
+    }
+
+    /// Returns the delay before attempt `attempt`, with jitter.
+    pub fn jittered_delay(&self, attempt: u32) -> Duration {
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        self.delay(attempt)
+            .mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random)
+    }
+
+    /// Returns whether attempt `attempt` may be made.
+    pub fn allows(&self, attempt: u32) -> bool {
+        self.max_attempts.is_none_or(|max| attempt < max)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_policy() {
+        let policy = ReconnectPolicy {
+            max_attempts: Some(3),
+            ..ReconnectPolicy::default()
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(10), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+        for attempt in 0..5 {
+            let delay = policy.jittered_delay(attempt);
+            assert!(delay <= policy.delay(attempt));
+            assert!(delay >= policy.delay(attempt).mul_f64(0.8));
+        }
+        assert!(policy.allows(2));
+        assert!(!policy.allows(3));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::logging;
+use crate::transport::{Connector, Transport, TransportError};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether a frame was sent to Teams or received from it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// One line of a recording.
+///
+/// # Fields
+///
+/// * `at_ms` - Milliseconds since the unix epoch when the frame was sent or received.
+/// * `direction` - Whether the frame was sent or received.
+/// * `text` - The text of the frame as on the wire.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    pub at_ms: u128,
+    pub direction: Direction,
+    pub text: String,
+}
+
+/// Writes every frame sent to and received from Teams with a timestamp to
+/// a JSONL file, for offline debugging and, with `ReplayTransport`, for
+/// regression tests against the sessions of different Teams versions.
+///
+/// Set with `TeamsWebsocketBuilder::recorder`. The file contains the
+/// token Teams sends on pairing, treat it like a password.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier)
+///     .recorder(Recorder::create("session.jsonl")?)
+///     .build()?;
+/// ```
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+}
+
+impl Recorder {
+    /// Creates the recording at `path`, replacing an existing one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: options.open(path)?,
+        })
+    }
+
+    /// Returns the path of the recording.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `text` as a frame going in `direction`.
+    pub fn record(&self, direction: Direction, text: &str) -> Result<(), Box<dyn Error>> {
+        let frame = RecordedFrame {
+            at_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            direction,
+            text: text.to_string(),
+        };
+        let line = serde_json::to_string(&frame)? + "\n";
+        (&self.file).write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads the frames of the recording at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is not a frame.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<RecordedFrame>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut frames = Vec::new();
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame =
+            serde_json::from_str(&line).map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// A `Transport` playing a recording back: it delivers the received frames
+/// in order, each once the client sent the frames recorded before it, and
+/// ends the connection after the last one. Timing is not reproduced.
+///
+/// As a `Connector` it starts over on every connect, so reconnects replay
+/// the session again.
+///
+/// # Example
+/// ```rust
+/// let websocket = TeamsWebsocket::builder(identifier)
+///     .connector(ReplayTransport::load("session.jsonl")?)
+///     .build()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReplayTransport {
+    frames: Vec<RecordedFrame>,
+    position: usize,
+}
+
+impl ReplayTransport {
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        Self {
+            frames,
+            position: 0,
+        }
+    }
+
+    /// Replays the recording at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `load`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(load(path)?))
+    }
+
+    /// Returns the frames not replayed yet.
+    pub fn remaining(&self) -> &[RecordedFrame] {
+        &self.frames[self.position..]
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_text(&mut self, text: String) -> BoxFuture<'_, Result<(), TransportError>> {
+        match self.frames.get(self.position) {
+            Some(frame) if frame.direction == Direction::Sent => {
+                if frame.text != text {
+                    debug!(
+                        target: logging::CODEC,
+                        "Replay expected {} but got {}",
+                        frame.text,
+                        text
+                    );
+                }
+                self.position += 1;
+            }
+            _ => debug!(target: logging::CODEC, "Replay did not expect {}", text),
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn recv_text(&mut self) -> BoxFuture<'_, Option<Result<String, TransportError>>> {
+        Box::pin(async move {
+            match self.frames.get(self.position) {
+                Some(frame) if frame.direction == Direction::Received => {
+                    self.position += 1;
+                    Some(Ok(frame.text.clone()))
+                }
+                // Waits for the client to send what was sent at this point.
+                Some(_) => std::future::pending().await,
+                None => None,
+            }
+        })
+    }
+}
+
+impl Connector for ReplayTransport {
+    fn connect<'a>(
+        &'a self,
+        _url: &'a str,
+    ) -> BoxFuture<'a, Result<Box<dyn Transport>, TransportError>> {
+        Box::pin(async move {
+            Ok(Box::new(ReplayTransport::new(self.frames.clone())) as Box<dyn Transport>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ClientMessage, MeetingAction, MeetingState, MeetingUpdate};
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_record_and_replay() {
+        Runtime::new().unwrap().block_on(async {
+            let path = std::env::temp_dir()
+                .join(format!("teams-ws-recording-{}.jsonl", std::process::id()));
+            let server = MockTeamsServer::start().await.unwrap();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .recorder(Recorder::create(&path).unwrap())
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            websocket
+                .send(ClientMessage::new(MeetingAction::ToggleMute, None))
+                .await
+                .unwrap();
+            let answer = websocket.receive().await.unwrap();
+            server.send_update(MeetingUpdate {
+                meeting_permissions: None,
+                meeting_state: Some(MeetingState::new().with_in_meeting(true)),
+            });
+            let update = websocket.receive().await.unwrap();
+            websocket.close().await.unwrap();
+
+            let frames = load(&path).unwrap();
+            let directions: Vec<_> = frames.iter().map(|frame| frame.direction).collect();
+            assert_eq!(
+                directions,
+                [Direction::Sent, Direction::Received, Direction::Received]
+            );
+
+            let mut replayed = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .connector(ReplayTransport::load(&path).unwrap())
+                .build()
+                .unwrap();
+            replayed.connect().await.unwrap();
+            replayed
+                .send(ClientMessage::new(MeetingAction::ToggleMute, None))
+                .await
+                .unwrap();
+            assert_eq!(replayed.receive().await.unwrap(), answer);
+            assert_eq!(replayed.receive().await.unwrap(), update);
+            assert!(replayed.receive().await.is_err());
+            let _ = std::fs::remove_file(&path);
+        });
+    }
+}
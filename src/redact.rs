@@ -0,0 +1,75 @@
+/// Query parameters whose values are never printed.
+const SECRET_PARAMS: [&str; 1] = ["token"];
+
+const MASK: &str = "***";
+
+/// A URL that may carry the API token as a query parameter.
+///
+/// `Display` and `Debug` mask the token, so the URL can be logged or embedded
+/// in errors safely. The raw URL is only available through `expose()`, which
+/// should be used for nothing but opening the connection.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretUrl(String);
+
+impl SecretUrl {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self(url.into())
+    }
+
+    /// Returns the URL including the token.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SecretUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", redact_url(&self.0))
+    }
+}
+
+impl std::fmt::Debug for SecretUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretUrl({:?})", redact_url(&self.0))
+    }
+}
+
+/// Returns `url` with the values of secret query parameters masked.
+pub fn redact_url(url: &str) -> String {
+    let Some((base, rest)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let (query, fragment) = match rest.split_once('#') {
+        Some((query, fragment)) => (query, Some(fragment)),
+        None => (rest, None),
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_PARAMS.contains(&name) => format!("{}={}", name, MASK),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    match fragment {
+        Some(fragment) => format!("{}?{}#{}", base, query, fragment),
+        None => format!("{}?{}", base, query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_masked() {
+        let url = SecretUrl::new("ws://127.0.0.1:8124/?app=Test&token=secret&app-version=1.0");
+        assert_eq!(
+            url.to_string(),
+            "ws://127.0.0.1:8124/?app=Test&token=***&app-version=1.0"
+        );
+        assert!(!format!("{:?}", url).contains("secret"));
+        assert!(url.expose().contains("token=secret"));
+        assert_eq!(redact_url("ws://127.0.0.1:8124"), "ws://127.0.0.1:8124");
+    }
+}
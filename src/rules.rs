@@ -0,0 +1,426 @@
+use crate::event::Event;
+use crate::lifecycle::{LifecycleTrigger, MeetingPhase};
+use crate::logging;
+use crate::messages::{
+    ClientMessage, ClientMessageParameter, ClientMessageParameterType, MeetingAction,
+};
+use crate::sandbox::{Sandbox, SandboxedWebsocket};
+use crate::state::{MeetingStateDelta, StateTracker};
+use crate::TeamsWebsocket;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Instant;
+
+/// Macros may call macros, this bounds the nesting to catch cycles.
+const MAX_MACRO_DEPTH: usize = 8;
+
+/// A scheduled rule: its index, the period if it repeats, and when it fires next.
+type Timer = (usize, Option<Duration>, Instant);
+
+/// What makes a rule fire.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// A meeting state field changed to the given value, e.g. `{"state": {"in_meeting": true}}`.
+    State(MeetingStateDelta),
+    /// The meeting lifecycle entered a phase, e.g. `{"phase": "presenting"}`.
+    Phase(MeetingPhase),
+    /// A meeting lifecycle transition happened, e.g. `{"lifecycle": "joined"}`.
+    Lifecycle(LifecycleTrigger),
+    /// Some time after a meeting lifecycle transition, e.g.
+    /// `{"after": {"lifecycle": "joined", "seconds": 3300}}`. Pending rules
+    /// are dropped when the meeting ends or the connection is lost.
+    After {
+        lifecycle: LifecycleTrigger,
+        seconds: u64,
+    },
+    /// The connection was established.
+    Connected,
+    /// The connection ended.
+    Disconnected,
+    /// Periodically, e.g. `{"every": {"seconds": 60}}`.
+    Every { seconds: u64 },
+}
+
+impl Trigger {
+    fn matches(&self, event: &Event) -> bool {
+        match (self, event) {
+            (Trigger::State(delta), Event::StateChanged(changed)) => delta == changed,
+            (Trigger::Phase(phase), Event::PhaseChanged(transition)) => *phase == transition.to,
+            (Trigger::Lifecycle(trigger), Event::PhaseChanged(transition)) => {
+                *trigger == transition.trigger
+            }
+            (Trigger::Connected, Event::Connected) => true,
+            (Trigger::Disconnected, Event::Disconnected(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// What a rule does when it fires.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Sends an action to Teams, e.g. `{"send": "mute"}`.
+    Send(MeetingAction),
+    /// Sends an action with a parameter, e.g. `{"send_with": {"action": "send-reaction", "parameter": "like"}}`.
+    SendWith {
+        action: MeetingAction,
+        parameter: ClientMessageParameterType,
+    },
+    /// Runs the actions of a named macro, e.g. `{"macro": "focus"}`.
+    Macro(String),
+    /// Posts JSON to an `http://` URL. Without a body, the rule name and event are posted.
+    /// Skipped unless the sandbox allows webhooks, see `Sandbox::allow_webhooks`.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        body: Option<serde_json::Value>,
+    },
+}
+
+/// A named trigger with the actions it runs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub when: Trigger,
+    pub then: Vec<RuleAction>,
+}
+
+/// Rules and macros, defined programmatically or loaded from JSON.
+///
+/// # Example
+/// ```json
+/// {
+///   "macros": {"focus": [{"send": "mute"}, {"send": "blur-background"}]},
+///   "rules": [
+///     {"name": "join", "when": {"state": {"in_meeting": true}}, "then": [{"macro": "focus"}]},
+///     {"name": "busylight", "when": {"state": {"in_meeting": false}},
+///      "then": [{"webhook": {"url": "http://127.0.0.1:8080/off"}}]}
+///   ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub macros: BTreeMap<String, Vec<RuleAction>>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn rule(mut self, name: impl Into<String>, when: Trigger, then: Vec<RuleAction>) -> Self {
+        self.rules.push(Rule {
+            name: name.into(),
+            when,
+            then,
+        });
+        self
+    }
+
+    pub fn add_macro(mut self, name: impl Into<String>, actions: Vec<RuleAction>) -> Self {
+        self.macros.insert(name.into(), actions);
+        self
+    }
+}
+
+/// Runs a `RuleSet` against a connection: "when X happens do Y" without
+/// writing an event loop.
+///
+/// Actions are sent through a `SandboxedWebsocket`, attributed to the
+/// origin `rules:<rule name>`. The sandbox is unrestricted by default, use
+/// `RulesEngine::sandbox` for rule sets from untrusted sources.
+pub struct RulesEngine {
+    rules: RuleSet,
+    sandbox: Sandbox,
+    tracker: StateTracker,
+    fired: Option<UnboundedSender<Event>>,
+}
+
+impl RulesEngine {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            sandbox: Sandbox::unrestricted(),
+            tracker: StateTracker::new(),
+            fired: None,
+        }
+    }
+
+    /// Sends an `Event::RuleFired` to `sender` whenever a rule fires.
+    pub fn notify(mut self, sender: UnboundedSender<Event>) -> Self {
+        self.fired = Some(sender);
+        self
+    }
+
+    /// Restricts the actions the rules may send.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Returns the state accumulated from the meeting updates seen so far.
+    pub fn state(&self) -> &StateTracker {
+        &self.tracker
+    }
+
+    /// Returns the rules triggered by `event` with their actions, macros expanded.
+    pub fn handle(&self, event: &Event) -> Vec<(&Rule, Vec<RuleAction>)> {
+        self.rules
+            .rules
+            .iter()
+            .filter(|rule| rule.when.matches(event))
+            .map(|rule| (rule, self.expand(&rule.then, 0)))
+            .collect()
+    }
+
+    fn expand(&self, actions: &[RuleAction], depth: usize) -> Vec<RuleAction> {
+        let mut expanded = Vec::new();
+        for action in actions {
+            match action {
+                RuleAction::Macro(name) => match self.rules.macros.get(name) {
+                    Some(_) if depth >= MAX_MACRO_DEPTH => {
+                        warn!(
+                            target: logging::AUTOMATION,
+                            "Macro {} nested too deeply, skipping",
+                            name
+                        );
+                    }
+                    Some(actions) => expanded.extend(self.expand(actions, depth + 1)),
+                    None => warn!(target: logging::AUTOMATION, "Unknown macro {}", name),
+                },
+                action => expanded.push(action.clone()),
+            }
+        }
+        expanded
+    }
+
+    /// Processes the connection until it ends, firing the matching rules.
+    ///
+    /// The websocket must be connected. Failing actions and malformed
+    /// frames are logged and do not stop the engine, see
+    /// `TeamsWebsocket::receive_resilient`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that ended the connection, after the `Disconnected`
+    /// rules ran.
+    pub async fn run(&mut self, websocket: &mut TeamsWebsocket) -> Result<(), Box<dyn Error>> {
+        let mut timers: Vec<Timer> = self
+            .rules
+            .rules
+            .iter()
+            .enumerate()
+            .filter_map(|(index, rule)| match rule.when {
+                Trigger::Every { seconds } => {
+                    let period = Duration::from_secs(seconds.max(1));
+                    Some((index, Some(period), Instant::now() + period))
+                }
+                _ => None,
+            })
+            .collect();
+
+        self.dispatch(websocket, &Event::Connected).await;
+        loop {
+            let next_timer = timers
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, _, deadline))| *deadline)
+                .map(|(position, (_, _, deadline))| (position, *deadline));
+            let timer_deadline = next_timer
+                .map(|(_, deadline)| deadline)
+                .unwrap_or_else(Instant::now);
+            tokio::select! {
+                message = websocket.receive_resilient() => match message {
+                    Ok(message) => {
+                        let Some(update) = message.meeting_update else {
+                            continue;
+                        };
+                        for event in self.tracker.events(&update) {
+                            self.schedule(&event, &mut timers);
+                            self.dispatch(websocket, &event).await;
+                        }
+                    }
+                    Err(e) => {
+                        let event = Event::disconnected(websocket, e.as_ref());
+                        self.dispatch(websocket, &event).await;
+                        return Err(e);
+                    }
+                },
+                _ = tokio::time::sleep_until(timer_deadline), if next_timer.is_some() => {
+                    let (position, _) = next_timer.unwrap();
+                    let index = match &mut timers[position] {
+                        (index, Some(period), deadline) => {
+                            *deadline += *period;
+                            *index
+                        }
+                        (index, None, _) => {
+                            let index = *index;
+                            timers.remove(position);
+                            index
+                        }
+                    };
+                    let rule = &self.rules.rules[index];
+                    let actions = self.expand(&rule.then, 0);
+                    self.notify_fired(rule);
+                    perform(websocket, &self.sandbox, rule, &actions, None).await;
+                }
+            }
+        }
+    }
+
+    /// Schedules the `After` rules triggered by `event` and drops pending
+    /// ones when the meeting ended.
+    fn schedule(&self, event: &Event, timers: &mut Vec<Timer>) {
+        let Event::PhaseChanged(transition) = event else {
+            return;
+        };
+        if transition.to == MeetingPhase::Idle {
+            timers.retain(|(_, period, _)| period.is_some());
+        }
+        for (index, rule) in self.rules.rules.iter().enumerate() {
+            if let Trigger::After { lifecycle, seconds } = rule.when {
+                if lifecycle == transition.trigger {
+                    let deadline = Instant::now() + Duration::from_secs(seconds);
+                    timers.push((index, None, deadline));
+                }
+            }
+        }
+    }
+
+    fn notify_fired(&self, rule: &Rule) {
+        if let Some(sender) = &self.fired {
+            let _ = sender.send(Event::RuleFired(rule.name.clone()));
+        }
+    }
+
+    async fn dispatch(&self, websocket: &mut TeamsWebsocket, event: &Event) {
+        for (rule, actions) in self.handle(event) {
+            debug!(target: logging::AUTOMATION, "Rule {} triggered by {:?}", rule.name, event);
+            self.notify_fired(rule);
+            perform(websocket, &self.sandbox, rule, &actions, Some(event)).await;
+        }
+    }
+}
+
+async fn perform(
+    websocket: &mut TeamsWebsocket,
+    sandbox: &Sandbox,
+    rule: &Rule,
+    actions: &[RuleAction],
+    event: Option<&Event>,
+) {
+    let origin = format!("rules:{}", rule.name);
+    for action in actions {
+        let message = match action {
+            RuleAction::Send(action) => ClientMessage::new(*action, None),
+            RuleAction::SendWith { action, parameter } => ClientMessage::new(
+                *action,
+                Some(ClientMessageParameter::new(parameter.clone())),
+            ),
+            RuleAction::Webhook { url, .. } if !sandbox.allow_webhooks => {
+                warn!(
+                    target: logging::AUTOMATION,
+                    "Rule {} may not call webhook {}, not allowed by the sandbox",
+                    rule.name,
+                    url
+                );
+                continue;
+            }
+            RuleAction::Webhook { url, body } => {
+                let url = url.clone();
+                let body = body
+                    .clone()
+                    .unwrap_or_else(|| serde_json::json!({ "rule": rule.name, "event": event }));
+                tokio::spawn(async move {
+                    if let Err(e) = crate::webhook::post_json(&url, &body).await {
+                        warn!(target: logging::AUTOMATION, "Error calling webhook {}: {}", url, e);
+                    }
+                });
+                continue;
+            }
+            RuleAction::Macro(_) => continue,
+        };
+        let mut sandboxed = SandboxedWebsocket::new(websocket, sandbox);
+        if let Err(e) = sandboxed.send(message.with_origin(origin.as_str())).await {
+            warn!(
+                target: logging::AUTOMATION,
+                "Rule {} failed to send {:?}: {}",
+                rule.name,
+                action,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"{
+        "macros": {
+            "focus": [{"send": "mute"}, {"macro": "blur"}],
+            "blur": [{"send": "blur-background"}],
+            "loop": [{"macro": "loop"}]
+        },
+        "rules": [
+            {"name": "join", "when": {"state": {"in_meeting": true}}, "then": [{"macro": "focus"}]},
+            {"name": "hello", "when": "connected", "then": [
+                {"send_with": {"action": "send-reaction", "parameter": "like"}},
+                {"macro": "loop"}
+            ]},
+            {"name": "tick", "when": {"every": {"seconds": 60}}, "then": [{"webhook": {"url": "http://127.0.0.1:1/"}}]}
+        ]
+    }"#;
+
+    #[test]
+    fn test_rules_from_json() {
+        let engine = RulesEngine::new(RuleSet::from_json(RULES).unwrap());
+        assert_eq!(engine.rules().rules.len(), 3);
+        assert_eq!(engine.rules().rules[2].when, Trigger::Every { seconds: 60 });
+
+        let triggered = engine.handle(&Event::StateChanged(MeetingStateDelta::InMeeting(true)));
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].0.name, "join");
+        assert_eq!(
+            triggered[0].1,
+            vec![
+                RuleAction::Send(MeetingAction::Mute),
+                RuleAction::Send(MeetingAction::BlurBackground)
+            ]
+        );
+        assert!(engine
+            .handle(&Event::StateChanged(MeetingStateDelta::InMeeting(false)))
+            .is_empty());
+
+        let triggered = engine.handle(&Event::Connected);
+        assert_eq!(
+            triggered[0].1,
+            vec![RuleAction::SendWith {
+                action: MeetingAction::React,
+                parameter: ClientMessageParameterType::ReactLike
+            }]
+        );
+    }
+}
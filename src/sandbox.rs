@@ -0,0 +1,152 @@
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction, ServerMessage};
+use crate::{TeamsWebsocket, TeamsWsError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+
+/// Permissions of an automation script or rule set.
+///
+/// The default sandbox may read the meeting state but not send any action, so
+/// a shared community script cannot, for example, leave a call on the user's
+/// behalf unless `LeaveCall` is explicitly allowed.
+///
+/// # Fields
+///
+/// * `read_state` - Whether meeting updates are visible.
+/// * `allow_all_actions` - Whether every action may be sent.
+/// * `allowed_actions` - The actions that may be sent if not all are allowed.
+/// * `allow_webhooks` - Whether rules may call webhooks.
+///
+/// # Example
+/// ```rust
+/// let sandbox: Sandbox = serde_json::from_str(r#"{"allowed_actions": ["mute", "unmute"]}"#)?;
+/// assert!(sandbox.allows(MeetingAction::Mute));
+/// assert!(!sandbox.allows(MeetingAction::LeaveCall));
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct Sandbox {
+    pub read_state: bool,
+    pub allow_all_actions: bool,
+    pub allowed_actions: BTreeSet<MeetingAction>,
+    pub allow_webhooks: bool,
+}
+
+impl Sandbox {
+    /// A sandbox that may read the state but not send any action.
+    pub fn read_only() -> Self {
+        Self {
+            read_state: true,
+            allow_all_actions: false,
+            allowed_actions: BTreeSet::new(),
+            allow_webhooks: false,
+        }
+    }
+
+    /// A sandbox that may read the state and send the given actions.
+    pub fn with_actions(actions: impl IntoIterator<Item = MeetingAction>) -> Self {
+        Self {
+            allowed_actions: actions.into_iter().collect(),
+            ..Self::read_only()
+        }
+    }
+
+    /// A sandbox without restrictions, for trusted code.
+    pub fn unrestricted() -> Self {
+        Self {
+            allow_all_actions: true,
+            allow_webhooks: true,
+            ..Self::read_only()
+        }
+    }
+
+    /// Returns whether `action` may be sent.
+    pub fn allows(&self, action: MeetingAction) -> bool {
+        self.allow_all_actions || self.allowed_actions.contains(&action)
+    }
+
+    /// Fails with `TeamsWsError::SandboxViolation` if `action` may not be sent.
+    pub fn check(&self, action: MeetingAction) -> Result<(), TeamsWsError> {
+        if self.allows(action) {
+            Ok(())
+        } else {
+            Err(TeamsWsError::SandboxViolation { action })
+        }
+    }
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::read_only()
+    }
+}
+
+/// A `TeamsWebsocket` restricted by a `Sandbox`, handed to automation code.
+pub struct SandboxedWebsocket<'a> {
+    websocket: &'a mut TeamsWebsocket,
+    sandbox: &'a Sandbox,
+}
+
+impl<'a> SandboxedWebsocket<'a> {
+    pub fn new(websocket: &'a mut TeamsWebsocket, sandbox: &'a Sandbox) -> Self {
+        Self { websocket, sandbox }
+    }
+
+    pub fn sandbox(&self) -> &Sandbox {
+        self.sandbox
+    }
+
+    /// Sends `message` if the sandbox allows its action.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeamsWsError::SandboxViolation` for actions the sandbox does not allow,
+    /// otherwise the errors of `TeamsWebsocket::send`.
+    pub async fn send(&mut self, message: ClientMessage) -> Result<u32, Box<dyn Error>> {
+        if let Err(e) = self.sandbox.check(message.action) {
+            warn!(target: logging::POLICY, "{}", e);
+            return Err(Box::new(e));
+        }
+        self.websocket.send(message).await
+    }
+
+    /// Receives the next message, without meeting updates unless the sandbox
+    /// may read the state.
+    pub async fn receive(&mut self) -> Result<ServerMessage, Box<dyn Error>> {
+        let mut message = self.websocket.receive().await?;
+        if !self.sandbox.read_state {
+            message.meeting_update = None;
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox() {
+        let sandbox = Sandbox::default();
+        assert!(sandbox.read_state);
+        assert!(matches!(
+            sandbox.check(MeetingAction::LeaveCall),
+            Err(TeamsWsError::SandboxViolation {
+                action: MeetingAction::LeaveCall
+            })
+        ));
+
+        let sandbox: Sandbox =
+            serde_json::from_str(r#"{"allowed_actions": ["mute", "toggle-mute"]}"#).unwrap();
+        assert_eq!(
+            sandbox,
+            Sandbox::with_actions([MeetingAction::Mute, MeetingAction::ToggleMute])
+        );
+        assert!(sandbox.allows(MeetingAction::ToggleMute));
+        assert!(!sandbox.allows(MeetingAction::LeaveCall));
+        assert!(Sandbox::unrestricted().allows(MeetingAction::LeaveCall));
+        assert!(!sandbox.allow_webhooks);
+        assert!(Sandbox::unrestricted().allow_webhooks);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::lifecycle::LifecycleTrigger;
+use crate::messages::{ClientMessageParameterType, MeetingAction};
+use crate::rules::{RuleAction, RuleSet, Trigger};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+/// When a scenario step runs.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(untagged)]
+enum When {
+    Connection(Connection),
+    Lifecycle(LifecycleTrigger),
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Connection {
+    Connected,
+    Disconnected,
+}
+
+/// One step of a scenario.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    #[serde(default)]
+    name: Option<String>,
+    on: When,
+    #[serde(default)]
+    after_minutes: Option<u64>,
+    #[serde(rename = "do")]
+    actions: Vec<String>,
+}
+
+/// An automation scenario in TOML, for users who do not write Rust.
+///
+/// Each `[[step]]` runs its actions `on` an event: `connected`,
+/// `disconnected` or a meeting lifecycle transition (`join`, `joined`,
+/// `start_presenting`, `stop_presenting`, `leave`, `left`), optionally
+/// `after_minutes` later while the meeting lasts. Actions are
+///
+/// * an action name, e.g. `"mute"` or `"blur-background"`
+/// * an action with a parameter, e.g. `"send-reaction:like"`
+/// * a macro defined in `[macros]`
+/// * an `http://` URL, which is posted to, e.g. to set a busylight
+///
+/// Scenarios run in a `RulesEngine`, see `Scenario::to_rules`.
+///
+/// # Example
+/// ```toml
+/// [macros]
+/// focus = ["blur-background", "mute"]
+///
+/// [[step]]
+/// on = "joined"
+/// do = ["focus", "http://127.0.0.1:8080/light/red"]
+///
+/// [[step]]
+/// on = "joined"
+/// after_minutes = 55
+/// do = ["http://127.0.0.1:8080/notify"]
+/// ```
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    macros: BTreeMap<String, Vec<String>>,
+    #[serde(default, rename = "step")]
+    steps: Vec<Step>,
+}
+
+impl Scenario {
+    /// Parses a scenario from TOML.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for invalid TOML or unknown events and actions.
+    pub fn from_toml(toml: &str) -> Result<Self, Box<dyn Error>> {
+        let scenario: Scenario = toml::from_str(toml)?;
+        scenario.to_rules()?;
+        Ok(scenario)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Converts the scenario to the rules executing it.
+    ///
+    /// # Example
+    /// ```rust
+    /// let rules = Scenario::from_file("scenario.toml")?.to_rules()?;
+    /// RulesEngine::new(rules).run(&mut websocket).await?;
+    /// ```
+    pub fn to_rules(&self) -> Result<RuleSet, Box<dyn Error>> {
+        let mut rules = RuleSet::new();
+        for (name, actions) in &self.macros {
+            rules = rules.add_macro(name.clone(), self.actions(actions)?);
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            let when = match (step.on, step.after_minutes) {
+                (When::Connection(Connection::Connected), None) => Trigger::Connected,
+                (When::Connection(Connection::Disconnected), None) => Trigger::Disconnected,
+                (When::Lifecycle(lifecycle), None) => Trigger::Lifecycle(lifecycle),
+                (When::Lifecycle(lifecycle), Some(minutes)) => Trigger::After {
+                    lifecycle,
+                    seconds: minutes * 60,
+                },
+                (When::Connection(_), Some(_)) => {
+                    return Err(Box::from(format!(
+                        "step {}: after_minutes requires a meeting event",
+                        index + 1
+                    )))
+                }
+            };
+            let name = step
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("step {}", index + 1));
+            rules = rules.rule(name, when, self.actions(&step.actions)?);
+        }
+        Ok(rules)
+    }
+
+    fn actions(&self, actions: &[String]) -> Result<Vec<RuleAction>, Box<dyn Error>> {
+        actions.iter().map(|action| self.action(action)).collect()
+    }
+
+    fn action(&self, action: &str) -> Result<RuleAction, Box<dyn Error>> {
+        if action.starts_with("http://") {
+            return Ok(RuleAction::Webhook {
+                url: action.to_string(),
+                body: None,
+            });
+        }
+        if self.macros.contains_key(action) {
+            return Ok(RuleAction::Macro(action.to_string()));
+        }
+        let parse = |name: &str| serde_json::Value::String(name.to_string());
+        match action.split_once(':') {
+            Some((action_name, parameter)) => Ok(RuleAction::SendWith {
+                action: serde_json::from_value::<MeetingAction>(parse(action_name))
+                    .map_err(|_| format!("unknown action {}", action_name))?,
+                parameter: serde_json::from_value::<ClientMessageParameterType>(parse(parameter))
+                    .map_err(|_| format!("unknown parameter {}", parameter))?,
+            }),
+            None => Ok(RuleAction::Send(
+                serde_json::from_value(parse(action))
+                    .map_err(|_| format!("unknown action or macro {}", action))?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenario_from_toml() {
+        let scenario = Scenario::from_toml(
+            r#"
+            [macros]
+            focus = ["blur-background", "mute"]
+
+            [[step]]
+            on = "joined"
+            do = ["focus", "send-reaction:like", "http://127.0.0.1:8080/light/red"]
+
+            [[step]]
+            name = "wrap up"
+            on = "joined"
+            after_minutes = 55
+            do = ["http://127.0.0.1:8080/notify"]
+
+            [[step]]
+            on = "connected"
+            do = ["query-state"]
+            "#,
+        )
+        .unwrap();
+        let rules = scenario.to_rules().unwrap();
+        assert_eq!(
+            rules.macros["focus"][1],
+            RuleAction::Send(MeetingAction::Mute)
+        );
+        assert_eq!(
+            rules.rules[0].when,
+            Trigger::Lifecycle(LifecycleTrigger::Joined)
+        );
+        assert_eq!(
+            rules.rules[0].then[1],
+            RuleAction::SendWith {
+                action: MeetingAction::React,
+                parameter: ClientMessageParameterType::ReactLike
+            }
+        );
+        assert_eq!(rules.rules[1].name, "wrap up");
+        assert_eq!(
+            rules.rules[1].when,
+            Trigger::After {
+                lifecycle: LifecycleTrigger::Joined,
+                seconds: 3300
+            }
+        );
+        assert_eq!(rules.rules[2].when, Trigger::Connected);
+
+        assert!(Scenario::from_toml("[[step]]\non = \"joined\"\ndo = [\"dance\"]").is_err());
+        assert!(Scenario::from_toml("[[step]]\non = \"lunch\"\ndo = []").is_err());
+    }
+}
@@ -0,0 +1,438 @@
+use crate::event::Event;
+use crate::logging;
+use crate::messages::{ClientMessage, MeetingAction, Reaction};
+use crate::sandbox::{Sandbox, SandboxedWebsocket};
+use crate::state::StateTracker;
+use crate::TeamsWebsocket;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// How often `ScriptHost::run` looks for added, changed or removed scripts.
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Bounds the work of a single hook call, so a looping script cannot hang the host.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+struct Script {
+    ast: AST,
+    scope: Scope<'static>,
+    modified: SystemTime,
+}
+
+/// State shared between the host and the functions registered in the engine.
+#[derive(Default)]
+struct Context {
+    sandbox: Sandbox,
+    state: Dynamic,
+    permissions: Dynamic,
+    pending: Vec<ClientMessage>,
+}
+
+/// Hot-loads `.rhai` automation scripts from a directory and runs their hooks.
+///
+/// Scripts define any of the hooks
+///
+/// * `on_connected()`
+/// * `on_disconnected()`
+/// * `on_state_changed(field, value)`, e.g. `on_state_changed("muted", true)`, or
+///   `on_state_changed("unread_message_count", 5)` with `()` if Teams stopped counting
+/// * `on_phase_changed(from, to)`, e.g. `on_phase_changed("in_meeting", "presenting")`
+///
+/// and can use
+///
+/// * `state()` / `permissions()` - the current `MeetingState` / `MeetingPermissions` as a map,
+///   or `()` if unknown or the sandbox does not allow reading the state
+/// * `send(action)` - sends an action by its wire name, e.g. `send("toggle-mute")`
+/// * `react(reaction)` - sends a reaction, e.g. `react("like")`
+/// * `print(text)` / `debug(text)` - log at info / debug level
+///
+/// Each script runs in the host's `Sandbox`, which is read-only by default so
+/// dropped-in scripts cannot act on the user's behalf unless allowed.
+///
+/// # Example
+/// ```rust
+/// let mut host = ScriptHost::new("scripts").sandbox(Sandbox::with_actions([MeetingAction::Mute]));
+/// host.run(&mut websocket).await?;
+/// ```
+pub struct ScriptHost {
+    directory: PathBuf,
+    engine: Engine,
+    scripts: BTreeMap<String, Script>,
+    sandbox: Sandbox,
+    sandboxes: BTreeMap<String, Sandbox>,
+    context: Arc<Mutex<Context>>,
+    tracker: StateTracker,
+}
+
+impl ScriptHost {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        let context = Arc::new(Mutex::new(Context::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: logging::AUTOMATION, "{}", text));
+        engine.on_debug(
+            |text, _, position| debug!(target: logging::AUTOMATION, "{:?}: {}", position, text),
+        );
+        register_api(&mut engine, &context);
+        Self {
+            directory: directory.into(),
+            engine,
+            scripts: BTreeMap::new(),
+            sandbox: Sandbox::read_only(),
+            sandboxes: BTreeMap::new(),
+            context,
+            tracker: StateTracker::new(),
+        }
+    }
+
+    /// Sets the sandbox of all scripts without a sandbox of their own.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Sets the sandbox of the script `name`, the file name without `.rhai`.
+    pub fn script_sandbox(mut self, name: impl Into<String>, sandbox: Sandbox) -> Self {
+        self.sandboxes.insert(name.into(), sandbox);
+        self
+    }
+
+    /// Returns the names of the loaded scripts.
+    pub fn scripts(&self) -> impl Iterator<Item = &str> {
+        self.scripts.keys().map(String::as_str)
+    }
+
+    /// Returns the state accumulated from the meeting updates seen so far.
+    pub fn state(&self) -> &StateTracker {
+        &self.tracker
+    }
+
+    /// Loads new and changed scripts and unloads removed ones.
+    ///
+    /// Scripts that fail to compile are logged and skipped, a previously
+    /// loaded version is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be read.
+    pub fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut present = Vec::new();
+        for entry in std::fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("rhai") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let name = name.to_string();
+            let modified = std::fs::metadata(&path)?.modified()?;
+            present.push(name.clone());
+            if self
+                .scripts
+                .get(&name)
+                .is_some_and(|script| script.modified == modified)
+            {
+                continue;
+            }
+            match self.load(&name, &path, modified) {
+                Ok(script) => {
+                    info!(target: logging::AUTOMATION, "Loaded script {}", name);
+                    self.scripts.insert(name, script);
+                }
+                Err(e) => {
+                    warn!(
+                        target: logging::AUTOMATION,
+                        "Error loading script {}: {}",
+                        path.display(),
+                        e
+                    )
+                }
+            }
+        }
+        self.scripts.retain(|name, _| {
+            let keep = present.contains(name);
+            if !keep {
+                info!(target: logging::AUTOMATION, "Unloaded script {}", name);
+            }
+            keep
+        });
+        Ok(())
+    }
+
+    /// Compiles the script `name` and runs its top level code in its own
+    /// sandbox, without the meeting state. Messages sent by the top level
+    /// code are dropped, only hooks send.
+    fn load(
+        &self,
+        name: &str,
+        path: &Path,
+        modified: SystemTime,
+    ) -> Result<Script, Box<EvalAltResult>> {
+        let ast = self.engine.compile_file(path.to_path_buf())?;
+        {
+            let mut context = self.context.lock().unwrap();
+            context.sandbox = self.sandboxes.get(name).unwrap_or(&self.sandbox).clone();
+            context.state = Dynamic::UNIT;
+            context.permissions = Dynamic::UNIT;
+            context.pending.clear();
+        }
+        let mut scope = Scope::new();
+        let result = self.engine.run_ast_with_scope(&mut scope, &ast);
+        let pending = std::mem::take(&mut self.context.lock().unwrap().pending);
+        if !pending.is_empty() {
+            warn!(
+                target: logging::AUTOMATION,
+                "Script {} sent {} messages while loading, dropping them",
+                name,
+                pending.len()
+            );
+        }
+        result?;
+        Ok(Script {
+            ast,
+            scope,
+            modified,
+        })
+    }
+
+    /// Runs the hooks of all scripts for `event` and returns the messages
+    /// they sent, attributed to `script:<name>`.
+    pub fn handle(&mut self, event: &Event) -> Vec<(String, ClientMessage)> {
+        let (hook, args): (&str, Vec<Dynamic>) = match event {
+            Event::Connected => ("on_connected", vec![]),
+            Event::Disconnected(_) => ("on_disconnected", vec![]),
+            Event::StateChanged(delta) => {
+                let Ok(serde_json::Value::Object(change)) = serde_json::to_value(delta) else {
+                    return Vec::new();
+                };
+                let Some((field, value)) = change.into_iter().next() else {
+                    return Vec::new();
+                };
+                let value = match value {
+                    serde_json::Value::Bool(value) => value.into(),
+                    serde_json::Value::Number(count) => {
+                        Dynamic::from(count.as_i64().unwrap_or_default())
+                    }
+                    _ => Dynamic::UNIT,
+                };
+                ("on_state_changed", vec![field.into(), value])
+            }
+            Event::Session(_) | Event::RuleFired(_) | Event::TeamsError(_) => return Vec::new(),
+            Event::PhaseChanged(transition) => {
+                let phase = |phase| match serde_json::to_value(phase) {
+                    Ok(serde_json::Value::String(name)) => Dynamic::from(name),
+                    _ => Dynamic::UNIT,
+                };
+                (
+                    "on_phase_changed",
+                    vec![phase(transition.from), phase(transition.to)],
+                )
+            }
+        };
+
+        let mut sent = Vec::new();
+        for (name, script) in self.scripts.iter_mut() {
+            let sandbox = self.sandboxes.get(name).unwrap_or(&self.sandbox).clone();
+            {
+                let mut context = self.context.lock().unwrap();
+                let (state, permissions) = if sandbox.read_state {
+                    (
+                        to_dynamic(self.tracker.meeting_state()),
+                        to_dynamic(self.tracker.permissions()),
+                    )
+                } else {
+                    (Dynamic::UNIT, Dynamic::UNIT)
+                };
+                context.sandbox = sandbox;
+                context.state = state;
+                context.permissions = permissions;
+            }
+            let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut script.scope,
+                &script.ast,
+                hook,
+                args.clone(),
+            );
+            match result {
+                Ok(_) => {}
+                Err(e) if matches!(*e, EvalAltResult::ErrorFunctionNotFound(ref f, _) if f.starts_with(hook)) =>
+                    {}
+                Err(e) => {
+                    warn!(target: logging::AUTOMATION, "Script {} failed in {}: {}", name, hook, e)
+                }
+            }
+            let pending = std::mem::take(&mut self.context.lock().unwrap().pending);
+            sent.extend(pending.into_iter().map(|message| {
+                let origin = format!("script:{}", name);
+                (name.clone(), message.with_origin(origin))
+            }));
+        }
+        sent
+    }
+
+    /// Processes the connection until it ends, running the script hooks and
+    /// reloading the scripts every `RELOAD_INTERVAL`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that ended the connection.
+    pub async fn run(&mut self, websocket: &mut TeamsWebsocket) -> Result<(), Box<dyn Error>> {
+        self.reload()?;
+        self.dispatch(websocket, &Event::Connected).await;
+        let mut reload = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            tokio::select! {
+                message = websocket.receive_resilient() => match message {
+                    Ok(message) => {
+                        let Some(update) = message.meeting_update else {
+                            continue;
+                        };
+                        for event in self.tracker.events(&update) {
+                            self.dispatch(websocket, &event).await;
+                        }
+                    }
+                    Err(e) => {
+                        let event = Event::disconnected(websocket, e.as_ref());
+                        self.dispatch(websocket, &event).await;
+                        return Err(e);
+                    }
+                },
+                _ = reload.tick() => {
+                    if let Err(e) = self.reload() {
+                        warn!(
+                            target: logging::AUTOMATION,
+                            "Error reloading scripts from {}: {}",
+                            self.directory.display(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, websocket: &mut TeamsWebsocket, event: &Event) {
+        for (name, message) in self.handle(event) {
+            let sandbox = self.sandboxes.get(&name).unwrap_or(&self.sandbox);
+            let action = message.action;
+            let mut sandboxed = SandboxedWebsocket::new(websocket, sandbox);
+            if let Err(e) = sandboxed.send(message).await {
+                warn!(
+                    target: logging::AUTOMATION,
+                    "Script {} failed to send {:?}: {}",
+                    name,
+                    action,
+                    e
+                );
+            }
+        }
+    }
+}
+
+fn to_dynamic<T: serde::Serialize>(value: Option<&T>) -> Dynamic {
+    value
+        .and_then(|value| rhai::serde::to_dynamic(value).ok())
+        .unwrap_or(Dynamic::UNIT)
+}
+
+fn parse<T: serde::de::DeserializeOwned>(name: &str) -> Result<T, Box<EvalAltResult>> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| format!("unknown name {}", name).into())
+}
+
+fn queue(context: &Mutex<Context>, message: ClientMessage) -> Result<(), Box<EvalAltResult>> {
+    let mut context = context.lock().unwrap();
+    context
+        .sandbox
+        .check(message.action)
+        .map_err(|e| e.to_string())?;
+    context.pending.push(message);
+    Ok(())
+}
+
+fn register_api(engine: &mut Engine, context: &Arc<Mutex<Context>>) {
+    let shared = context.clone();
+    engine.register_fn("state", move || shared.lock().unwrap().state.clone());
+    let shared = context.clone();
+    engine.register_fn("permissions", move || {
+        shared.lock().unwrap().permissions.clone()
+    });
+    let shared = context.clone();
+    engine.register_fn("send", move |action: &str| {
+        let action: MeetingAction = parse(action)?;
+        queue(&shared, ClientMessage::new(action, None))
+    });
+    let shared = context.clone();
+    engine.register_fn("react", move |reaction: &str| {
+        let reaction: Reaction = parse(reaction)?;
+        queue(&shared, ClientMessage::reaction(reaction))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{DisconnectInitiator, DisconnectReport};
+    use crate::state::MeetingStateDelta;
+
+    #[test]
+    fn test_script_host() {
+        let directory =
+            std::env::temp_dir().join(format!("teams-ws-scripts-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("auto.rhai"),
+            r#"
+                fn on_state_changed(field, value) {
+                    if field == "in_meeting" && value {
+                        send("mute");
+                        react("like");
+                    }
+                }
+                fn on_connected() {
+                    send("leave-call");
+                }
+            "#,
+        )
+        .unwrap();
+        std::fs::write(directory.join("broken.rhai"), "fn (").unwrap();
+
+        let mut host = ScriptHost::new(&directory).sandbox(Sandbox::with_actions([
+            MeetingAction::Mute,
+            MeetingAction::React,
+        ]));
+        host.reload().unwrap();
+        assert_eq!(host.scripts().collect::<Vec<_>>(), vec!["auto"]);
+
+        let sent = host.handle(&Event::StateChanged(MeetingStateDelta::InMeeting(true)));
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].1.action, MeetingAction::Mute);
+        assert_eq!(sent[0].1.origin.as_deref(), Some("script:auto"));
+        assert_eq!(sent[1].1.action, MeetingAction::React);
+        // Top level code does not send, not even as a later hook of another script.
+        std::fs::write(directory.join("top.rhai"), r#"send("mute");"#).unwrap();
+        host.reload().unwrap();
+        assert_eq!(host.scripts().collect::<Vec<_>>(), vec!["auto", "top"]);
+        assert!(host.handle(&Event::Connected).is_empty());
+        assert!(host
+            .handle(&Event::Disconnected(DisconnectReport::new(
+                DisconnectInitiator::Client,
+                "closed by client",
+                None,
+            )))
+            .is_empty());
+
+        std::fs::remove_file(directory.join("auto.rhai")).unwrap();
+        std::fs::remove_file(directory.join("top.rhai")).unwrap();
+        host.reload().unwrap();
+        assert_eq!(host.scripts().count(), 0);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
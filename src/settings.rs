@@ -0,0 +1,327 @@
+use crate::logging;
+use crate::redact::redact_url;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// The default URL of the local Teams API.
+pub const DEFAULT_URL: &str = "ws://127.0.0.1:8124";
+
+/// A setting that can be provided by one of the configuration layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SettingKey {
+    Url,
+    Token,
+}
+
+impl SettingKey {
+    /// All known settings, in reporting order.
+    pub const ALL: [SettingKey; 2] = [SettingKey::Url, SettingKey::Token];
+
+    /// The key used for this setting in a config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettingKey::Url => "url",
+            SettingKey::Token => "token",
+        }
+    }
+
+    /// The environment variable read for this setting.
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            SettingKey::Url => "TEAMS_WS_URL",
+            SettingKey::Token => "TEAMS_WS_TOKEN",
+        }
+    }
+
+    fn is_secret(&self) -> bool {
+        matches!(self, SettingKey::Token)
+    }
+
+    /// Returns `value` of this setting as it may be printed.
+    fn printable(&self, value: &str) -> String {
+        if self.is_secret() {
+            "***".to_string()
+        } else {
+            redact_url(value)
+        }
+    }
+}
+
+/// Formats a layer of settings for `Debug` with the secrets masked.
+fn debug_layer(values: &BTreeMap<SettingKey, String>) -> BTreeMap<SettingKey, String> {
+    values
+        .iter()
+        .map(|(key, value)| (*key, key.printable(value)))
+        .collect()
+}
+
+/// Where an effective setting value came from.
+///
+/// Layers are listed from lowest to highest precedence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingSource {
+    Default,
+    ConfigFile(PathBuf),
+    Environment(&'static str),
+    Explicit,
+}
+
+impl std::fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingSource::Default => write!(f, "default"),
+            SettingSource::ConfigFile(path) => write!(f, "config file {}", path.display()),
+            SettingSource::Environment(var) => write!(f, "environment variable {}", var),
+            SettingSource::Explicit => write!(f, "explicit"),
+        }
+    }
+}
+
+/// Merges defaults < config file < environment < explicit values into the
+/// effective settings.
+///
+/// # Example
+/// ```rust
+/// let settings = SettingsResolver::new()
+///     .config_file("teams.json")?
+///     .environment()
+///     .explicit(SettingKey::Token, "secret")
+///     .resolve();
+/// println!("{}", settings);
+/// ```
+#[derive(Clone)]
+pub struct SettingsResolver {
+    defaults: BTreeMap<SettingKey, String>,
+    config_file: Option<(PathBuf, BTreeMap<SettingKey, String>)>,
+    environment: BTreeMap<SettingKey, String>,
+    explicit: BTreeMap<SettingKey, String>,
+}
+
+impl SettingsResolver {
+    pub fn new() -> Self {
+        let mut defaults = BTreeMap::new();
+        defaults.insert(SettingKey::Url, DEFAULT_URL.to_string());
+        Self {
+            defaults,
+            config_file: None,
+            environment: BTreeMap::new(),
+            explicit: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the config file layer from a JSON object such as
+    /// `{"url": "ws://127.0.0.1:8124", "token": "..."}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a JSON object or
+    /// a setting in it is not a string.
+    pub fn config_file(self, path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        self.config_str(path, &content)
+    }
+
+    /// Loads the config file layer from already read content, attributing the
+    /// values to `path`. The `identifiers` of a `Config` are skipped.
+    pub fn config_str(
+        mut self,
+        path: impl AsRef<Path>,
+        content: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let raw: BTreeMap<String, serde_json::Value> = serde_json::from_str(content)?;
+        let mut values = BTreeMap::new();
+        for (name, value) in raw {
+            match (SettingKey::ALL.iter().find(|key| key.name() == name), value) {
+                (Some(key), serde_json::Value::String(value)) => {
+                    values.insert(*key, value);
+                }
+                (Some(_), _) => {
+                    return Err(format!(
+                        "setting {} in {} is not a string",
+                        name,
+                        path.as_ref().display()
+                    )
+                    .into())
+                }
+                (None, _) if name == "identifiers" => {}
+                (None, _) => warn!(
+                    target: logging::CONFIG,
+                    "Ignoring unknown setting {} in {}",
+                    name,
+                    path.as_ref().display()
+                ),
+            }
+        }
+        self.config_file = Some((path.as_ref().to_path_buf(), values));
+        Ok(self)
+    }
+
+    /// Reads the environment layer from the process environment.
+    pub fn environment(self) -> Self {
+        self.environment_from(std::env::vars())
+    }
+
+    /// Reads the environment layer from the given variables.
+    pub fn environment_from<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        for (name, value) in vars {
+            if let Some(key) = SettingKey::ALL
+                .iter()
+                .find(|key| key.env_var() == name.as_ref())
+            {
+                self.environment.insert(*key, value.into());
+            }
+        }
+        self
+    }
+
+    /// Sets an explicit value, which takes precedence over every other layer.
+    pub fn explicit(mut self, key: SettingKey, value: impl Into<String>) -> Self {
+        self.explicit.insert(key, value.into());
+        self
+    }
+
+    /// Computes the effective settings.
+    pub fn resolve(&self) -> ResolvedSettings {
+        let mut values = BTreeMap::new();
+        for key in SettingKey::ALL {
+            let resolved = if let Some(value) = self.explicit.get(&key) {
+                Some((value.clone(), SettingSource::Explicit))
+            } else if let Some(value) = self.environment.get(&key) {
+                Some((value.clone(), SettingSource::Environment(key.env_var())))
+            } else if let Some((path, value)) = self
+                .config_file
+                .as_ref()
+                .and_then(|(path, values)| values.get(&key).map(|value| (path, value)))
+            {
+                Some((value.clone(), SettingSource::ConfigFile(path.clone())))
+            } else {
+                self.defaults
+                    .get(&key)
+                    .map(|value| (value.clone(), SettingSource::Default))
+            };
+            if let Some(resolved) = resolved {
+                values.insert(key, resolved);
+            }
+        }
+        ResolvedSettings { values }
+    }
+}
+
+impl std::fmt::Debug for SettingsResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let config_file = self
+            .config_file
+            .as_ref()
+            .map(|(path, values)| (path, debug_layer(values)));
+        f.debug_struct("SettingsResolver")
+            .field("defaults", &debug_layer(&self.defaults))
+            .field("config_file", &config_file)
+            .field("environment", &debug_layer(&self.environment))
+            .field("explicit", &debug_layer(&self.explicit))
+            .finish()
+    }
+}
+
+impl Default for SettingsResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The effective settings together with the layer each value came from.
+///
+/// `Display` and `Debug` mask the token.
+#[derive(Clone, Default, PartialEq)]
+pub struct ResolvedSettings {
+    values: BTreeMap<SettingKey, (String, SettingSource)>,
+}
+
+impl ResolvedSettings {
+    /// Returns the effective value of a setting.
+    pub fn get(&self, key: SettingKey) -> Option<&str> {
+        self.values.get(&key).map(|(value, _)| value.as_str())
+    }
+
+    /// Returns the layer the effective value of a setting came from.
+    pub fn source(&self, key: SettingKey) -> Option<&SettingSource> {
+        self.values.get(&key).map(|(_, source)| source)
+    }
+}
+
+impl std::fmt::Display for ResolvedSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, (value, source)) in &self.values {
+            writeln!(f, "{} = {} ({})", key.name(), key.printable(value), source)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ResolvedSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values: BTreeMap<_, _> = self
+            .values
+            .iter()
+            .map(|(key, (value, source))| (key, (key.printable(value), source)))
+            .collect();
+        f.debug_struct("ResolvedSettings")
+            .field("values", &values)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let settings = SettingsResolver::new()
+            .config_str(
+                "teams.json",
+                r#"{"url": "ws://127.0.0.1:1", "token": "file"}"#,
+            )
+            .unwrap()
+            .environment_from([("TEAMS_WS_TOKEN", "env")])
+            .resolve();
+        assert_eq!(settings.get(SettingKey::Url), Some("ws://127.0.0.1:1"));
+        assert_eq!(
+            settings.source(SettingKey::Url),
+            Some(&SettingSource::ConfigFile(PathBuf::from("teams.json")))
+        );
+        assert_eq!(settings.get(SettingKey::Token), Some("env"));
+        assert_eq!(
+            settings.source(SettingKey::Token),
+            Some(&SettingSource::Environment("TEAMS_WS_TOKEN"))
+        );
+    }
+
+    #[test]
+    fn test_explicit_wins_and_token_is_masked() {
+        let settings = SettingsResolver::new()
+            .environment_from([
+                ("TEAMS_WS_URL", "ws://127.0.0.1:2"),
+                ("TEAMS_WS_TOKEN", "env"),
+            ])
+            .explicit(SettingKey::Url, "ws://127.0.0.1:3")
+            .resolve();
+        assert_eq!(settings.get(SettingKey::Url), Some("ws://127.0.0.1:3"));
+        assert_eq!(
+            settings.source(SettingKey::Url),
+            Some(&SettingSource::Explicit)
+        );
+        let report = settings.to_string();
+        assert!(report.contains("url = ws://127.0.0.1:3 (explicit)"));
+        assert!(report.contains("token = *** (environment variable TEAMS_WS_TOKEN)"));
+        let resolver = SettingsResolver::new().explicit(SettingKey::Token, "hunter2");
+        assert!(!format!("{:?}", resolver).contains("hunter2"));
+        assert!(!format!("{:?}", resolver.resolve()).contains("hunter2"));
+    }
+}
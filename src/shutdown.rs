@@ -0,0 +1,126 @@
+use crate::logging;
+use crate::TeamsWsError;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Stops long-running operations promptly on application shutdown instead
+/// of them hanging on socket reads.
+///
+/// Once triggered, `TeamsWebsocket::receive` and its variants, waiting in
+/// `ready`, `pair` and `send_and_wait`, and the reconnect loop fail with
+/// `TeamsWsError::Cancelled`, and the task of a `TeamsClient` shuts the
+/// connection down and ends. Every `TeamsWebsocket` has its own signal,
+/// see `TeamsWebsocket::shutdown_signal`; share one across connections
+/// with `TeamsWebsocketBuilder::shutdown_signal`. A triggered signal stays
+/// triggered.
+///
+/// # Example
+/// ```rust
+/// let shutdown = ShutdownSignal::new();
+/// let mut websocket = TeamsWebsocket::builder(identifier)
+///     .shutdown_signal(shutdown.clone())
+///     .build()?;
+/// tokio::spawn(async move {
+///     tokio::signal::ctrl_c().await.ok();
+///     shutdown.trigger();
+/// });
+/// while let Ok(message) = websocket.receive().await {
+///     println!("{}", message);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ShutdownSignal {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Stops the operations using this signal.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Completes once the signal is triggered.
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail.
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Runs `operation` unless the signal is triggered first.
+    pub(crate) async fn guard<T>(
+        &self,
+        operation: impl Future<Output = Result<T, Box<dyn Error>>>,
+    ) -> Result<T, Box<dyn Error>> {
+        tokio::select! {
+            biased;
+            _ = self.triggered() => {
+                debug!(target: logging::CONNECTION, "{}", TeamsWsError::Cancelled);
+                Err(Box::new(TeamsWsError::Cancelled))
+            }
+            result = operation => result,
+        }
+    }
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TeamsClient;
+    use crate::event::ConnectionStatus;
+    use crate::mock::MockTeamsServer;
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_shutdown_signal() {
+        Runtime::new().unwrap().block_on(async {
+            let server = MockTeamsServer::start().await.unwrap();
+            let shutdown = ShutdownSignal::new();
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .url(server.url())
+                .shutdown_signal(shutdown.clone())
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            let signal = websocket.shutdown_signal();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                signal.trigger();
+            });
+            let error = websocket.receive().await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(TeamsWsError::Cancelled)
+            ));
+            assert!(shutdown.is_triggered());
+
+            let client = TeamsClient::run(websocket);
+            let websocket = tokio::time::timeout(Duration::from_secs(5), client.join())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(websocket.status(), ConnectionStatus::Closed);
+        });
+    }
+}
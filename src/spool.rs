@@ -0,0 +1,270 @@
+use crate::event::Event;
+use crate::logging;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of events kept by default, older ones are dropped first.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// An event waiting for its consumer.
+///
+/// # Fields
+///
+/// * `spooled_at_ms` - Milliseconds since the unix epoch when the event was spooled.
+/// * `event` - The event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpooledEvent {
+    pub spooled_at_ms: u128,
+    pub event: Event,
+}
+
+/// Events for a downstream consumer, e.g. a webhook target, kept until the
+/// consumer accepted them.
+///
+/// A spool created with `EventSpool::persistent` is kept in a file, so
+/// events survive a restart of the process. Events are delivered in the
+/// order they were pushed; delivery stops at the first failure and resumes
+/// with that event on the next call.
+///
+/// # Example
+/// ```rust
+/// let mut spool = EventSpool::persistent("webhook.jsonl")?;
+/// for event in tracker.events(&update) {
+///     spool.push(event)?;
+/// }
+/// if let Err(e) = spool.deliver_webhook("http://127.0.0.1:8080/teams").await {
+///     log::info!("Webhook unreachable, {} events spooled: {}", spool.len(), e);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct EventSpool {
+    events: VecDeque<SpooledEvent>,
+    path: Option<PathBuf>,
+    capacity: usize,
+}
+
+impl EventSpool {
+    /// Creates a spool kept in memory.
+    pub fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+            path: None,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    /// Creates a spool kept in the file at `path`, loading the events
+    /// spooled by a previous run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an existing file cannot be read or parsed.
+    pub fn persistent(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut events = VecDeque::new();
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    events.push_back(serde_json::from_str(line)?);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+        if !events.is_empty() {
+            info!(
+                target: logging::QUEUE,
+                "Loaded {} spooled events from {}",
+                events.len(),
+                path.display()
+            );
+        }
+        Ok(Self {
+            events,
+            path: Some(path.to_path_buf()),
+            ..Self::new()
+        })
+    }
+
+    /// Keeps at most `capacity` events, dropping the oldest.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the spooled events, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &SpooledEvent> {
+        self.events.iter()
+    }
+
+    /// Spools `event`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event cannot be written to the spool file.
+    pub fn push(&mut self, event: Event) -> Result<(), Box<dyn Error>> {
+        let spooled_at_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        self.events.push_back(SpooledEvent {
+            spooled_at_ms,
+            event,
+        });
+        if self.events.len() > self.capacity {
+            let dropped = self.events.pop_front();
+            warn!(target: logging::QUEUE, "Event spool full, dropping {:?}", dropped);
+            return self.save();
+        }
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                let line = serde_json::to_string(self.events.back().unwrap())?;
+                writeln!(file, "{}", line)?;
+                file.sync_data()?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Passes the spooled events in order to `deliver` and removes those it
+    /// accepted. Returns the number of delivered events.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of `deliver`, the failed event and those
+    /// after it stay spooled. Also fails if the spool file cannot be updated.
+    pub async fn deliver<F, Fut>(&mut self, mut deliver: F) -> Result<usize, Box<dyn Error>>
+    where
+        F: FnMut(Event) -> Fut,
+        Fut: Future<Output = Result<(), Box<dyn Error + Send + Sync>>>,
+    {
+        let mut delivered = 0;
+        let mut result = Ok(());
+        while let Some(spooled) = self.events.front() {
+            if let Err(e) = deliver(spooled.event.clone()).await {
+                result = Err(e as Box<dyn Error>);
+                break;
+            }
+            self.events.pop_front();
+            delivered += 1;
+        }
+        if delivered > 0 {
+            debug!(target: logging::QUEUE, "Delivered {} spooled events", delivered);
+            self.save()?;
+        }
+        result.map(|_| delivered)
+    }
+
+    /// Delivers the spooled events by posting each as JSON to `url`, see
+    /// `webhook::post_json`. Events are accepted with a 2xx status.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target is unreachable or answers with another status.
+    pub async fn deliver_webhook(&mut self, url: &str) -> Result<usize, Box<dyn Error>> {
+        self.deliver(|event| async move {
+            let body = serde_json::to_value(&event)?;
+            let status = crate::webhook::post_json(url, &body).await?;
+            if !(200..300).contains(&status) {
+                return Err(format!("webhook {} answered {}", url, status).into());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for spooled in &self.events {
+            content.push_str(&serde_json::to_string(spooled)?);
+            content.push('\n');
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+impl Default for EventSpool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MeetingStateDelta;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_event_spool() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let path =
+                std::env::temp_dir().join(format!("teams-ws-spool-{}.jsonl", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+
+            let mut spool = EventSpool::persistent(&path).unwrap();
+            spool.push(Event::Connected).unwrap();
+            spool
+                .push(Event::StateChanged(MeetingStateDelta::Muted(true)))
+                .unwrap();
+            spool.push(Event::RuleFired("mute".to_string())).unwrap();
+
+            // The consumer is down after the first event.
+            let mut received = Vec::new();
+            let result = spool
+                .deliver(|event| {
+                    let accept = received.is_empty();
+                    received.push(event);
+                    async move {
+                        if accept {
+                            Ok(())
+                        } else {
+                            Err("unreachable".into())
+                        }
+                    }
+                })
+                .await;
+            assert!(result.is_err());
+            drop(spool);
+
+            let mut spool = EventSpool::persistent(&path).unwrap();
+            assert_eq!(spool.len(), 2);
+            let mut received = Vec::new();
+            let delivered = spool
+                .deliver(|event| {
+                    received.push(event);
+                    async { Ok(()) }
+                })
+                .await
+                .unwrap();
+            assert_eq!(delivered, 2);
+            assert_eq!(
+                received,
+                vec![
+                    Event::StateChanged(MeetingStateDelta::Muted(true)),
+                    Event::RuleFired("mute".to_string())
+                ]
+            );
+            assert!(EventSpool::persistent(&path).unwrap().is_empty());
+            std::fs::remove_file(&path).unwrap();
+        });
+    }
+}
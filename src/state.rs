@@ -0,0 +1,384 @@
+use crate::event::Event;
+use crate::lifecycle::{LifecycleTrigger, MeetingLifecycle, Transition};
+use crate::messages::{MeetingAction, MeetingPermissions, MeetingState, MeetingUpdate};
+use crate::TeamsWsError;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// A change of a single `MeetingState` field, e.g. `Muted(true)`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum MeetingStateDelta {
+    Muted(bool),
+    HandRaised(bool),
+    InMeeting(bool),
+    RecordingOn(bool),
+    BackgroundBlurred(bool),
+    Sharing(bool),
+    UnreadMessages(bool),
+    VideoOn(bool),
+    OnHold(bool),
+    RecordingPaused(bool),
+    /// The number of unread messages changed, `None` if Teams stopped
+    /// reporting it. Reported alongside `UnreadMessages`, which only
+    /// changes when the first message arrives or the last one is read.
+    UnreadMessageCount(Option<u32>),
+}
+
+impl MeetingStateDelta {
+    /// Returns the changes from `old` to `new`.
+    ///
+    /// Without a previous state, the fields of `new` that differ from
+    /// `MeetingState::default()` are reported.
+    pub fn between(old: Option<&MeetingState>, new: &MeetingState) -> Vec<Self> {
+        let default = MeetingState::default();
+        let old = old.unwrap_or(&default);
+        let fields = [
+            (
+                old.is_muted,
+                new.is_muted,
+                MeetingStateDelta::Muted as fn(bool) -> Self,
+            ),
+            (
+                old.is_hand_raised,
+                new.is_hand_raised,
+                MeetingStateDelta::HandRaised,
+            ),
+            (
+                old.is_in_meeting,
+                new.is_in_meeting,
+                MeetingStateDelta::InMeeting,
+            ),
+            (
+                old.is_recording_on,
+                new.is_recording_on,
+                MeetingStateDelta::RecordingOn,
+            ),
+            (
+                old.is_background_blurred,
+                new.is_background_blurred,
+                MeetingStateDelta::BackgroundBlurred,
+            ),
+            (old.is_sharing, new.is_sharing, MeetingStateDelta::Sharing),
+            (
+                old.has_unread_messages,
+                new.has_unread_messages,
+                MeetingStateDelta::UnreadMessages,
+            ),
+            (old.is_video_on, new.is_video_on, MeetingStateDelta::VideoOn),
+            (old.is_on_hold, new.is_on_hold, MeetingStateDelta::OnHold),
+            (
+                old.is_recording_paused,
+                new.is_recording_paused,
+                MeetingStateDelta::RecordingPaused,
+            ),
+        ];
+        let mut deltas: Vec<_> = fields
+            .into_iter()
+            .filter(|(old, new, _)| old != new)
+            .map(|(_, new, delta)| delta(new))
+            .collect();
+        if old.unread_message_count != new.unread_message_count {
+            deltas.push(MeetingStateDelta::UnreadMessageCount(
+                new.unread_message_count,
+            ));
+        }
+        deltas
+    }
+}
+
+/// A milestone of a meeting, derived from a `MeetingStateDelta` so
+/// downstream code does not diff meeting states itself.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEvent {
+    MeetingStarted,
+    MeetingEnded,
+    RecordingStarted,
+    RecordingStopped,
+    ScreenShareStarted,
+    ScreenShareStopped,
+    HandRaised,
+    HandLowered,
+    CallHeld,
+    CallResumed,
+    RecordingPaused,
+    RecordingResumed,
+}
+
+impl SessionEvent {
+    /// Returns the milestone `delta` is, if any.
+    pub fn from_delta(delta: MeetingStateDelta) -> Option<Self> {
+        match delta {
+            MeetingStateDelta::InMeeting(true) => Some(SessionEvent::MeetingStarted),
+            MeetingStateDelta::InMeeting(false) => Some(SessionEvent::MeetingEnded),
+            MeetingStateDelta::RecordingOn(true) => Some(SessionEvent::RecordingStarted),
+            MeetingStateDelta::RecordingOn(false) => Some(SessionEvent::RecordingStopped),
+            MeetingStateDelta::Sharing(true) => Some(SessionEvent::ScreenShareStarted),
+            MeetingStateDelta::Sharing(false) => Some(SessionEvent::ScreenShareStopped),
+            MeetingStateDelta::HandRaised(true) => Some(SessionEvent::HandRaised),
+            MeetingStateDelta::HandRaised(false) => Some(SessionEvent::HandLowered),
+            MeetingStateDelta::OnHold(true) => Some(SessionEvent::CallHeld),
+            MeetingStateDelta::OnHold(false) => Some(SessionEvent::CallResumed),
+            MeetingStateDelta::RecordingPaused(true) => Some(SessionEvent::RecordingPaused),
+            MeetingStateDelta::RecordingPaused(false) => Some(SessionEvent::RecordingResumed),
+            MeetingStateDelta::Muted(_)
+            | MeetingStateDelta::BackgroundBlurred(_)
+            | MeetingStateDelta::UnreadMessages(_)
+            | MeetingStateDelta::UnreadMessageCount(_)
+            | MeetingStateDelta::VideoOn(_) => None,
+        }
+    }
+}
+
+/// A meeting state together with when Teams reported it, so e.g. a tray
+/// icon can gray out its indicators while the connection is flaky instead
+/// of showing an outdated mute status.
+///
+/// Reporting an unchanged state, e.g. answering
+/// `ConnectionOptions::state_refresh`, updates `last_updated` too.
+///
+/// # Fields
+///
+/// * `state` - The meeting state Teams last reported.
+/// * `last_updated` - When Teams reported it.
+///
+/// # Example
+/// ```rust
+/// match handle.state_snapshot() {
+///     Some(snapshot) if !snapshot.is_stale(Duration::from_secs(30)) => tray.show(&snapshot.state),
+///     _ => tray.gray_out(),
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateSnapshot {
+    pub state: MeetingState,
+    pub last_updated: Instant,
+}
+
+impl StateSnapshot {
+    pub fn new(state: MeetingState, last_updated: Instant) -> Self {
+        Self {
+            state,
+            last_updated,
+        }
+    }
+
+    /// Returns how long ago Teams reported the state.
+    pub fn age(&self) -> Duration {
+        self.last_updated.elapsed()
+    }
+
+    /// Returns whether Teams reported the state longer than `max_age` ago.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age() > max_age
+    }
+}
+
+/// Accumulates the latest `MeetingState` and `MeetingPermissions` from
+/// meeting updates and follows the `MeetingLifecycle`.
+#[derive(Clone, Debug, Default)]
+pub struct StateTracker {
+    meeting_state: Option<MeetingState>,
+    permissions: Option<MeetingPermissions>,
+    lifecycle: MeetingLifecycle,
+}
+
+impl StateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `update` and returns the state fields that changed.
+    pub fn apply(&mut self, update: &MeetingUpdate) -> Vec<MeetingStateDelta> {
+        self.events(update)
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::StateChanged(delta) => Some(delta),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Applies `update` and returns the state changes followed by the
+    /// session events and lifecycle transitions they caused.
+    pub fn events(&mut self, update: &MeetingUpdate) -> Vec<Event> {
+        if let Some(permissions) = &update.meeting_permissions {
+            self.permissions = Some(permissions.clone());
+        }
+        let Some(state) = &update.meeting_state else {
+            return Vec::new();
+        };
+        let deltas = MeetingStateDelta::between(self.meeting_state.as_ref(), state);
+        self.meeting_state = Some(state.clone());
+        let transitions = self.lifecycle.observe(state);
+        let sessions: Vec<_> = deltas
+            .iter()
+            .filter_map(|delta| SessionEvent::from_delta(*delta))
+            .collect();
+        deltas
+            .into_iter()
+            .map(Event::StateChanged)
+            .chain(sessions.into_iter().map(Event::Session))
+            .chain(transitions.into_iter().map(Event::PhaseChanged))
+            .collect()
+    }
+
+    /// Returns the meeting lifecycle derived from the updates so far.
+    pub fn lifecycle(&self) -> &MeetingLifecycle {
+        &self.lifecycle
+    }
+
+    /// Fires a lifecycle trigger not derived from meeting updates, e.g.
+    /// `LifecycleTrigger::Leave` after sending `MeetingAction::LeaveCall`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeamsWsError::InvalidTransition` if `trigger` is not allowed in the current phase.
+    pub fn fire(&mut self, trigger: LifecycleTrigger) -> Result<Transition, TeamsWsError> {
+        self.lifecycle.fire(trigger)
+    }
+
+    /// Returns the latest meeting state, if any update contained one.
+    pub fn meeting_state(&self) -> Option<&MeetingState> {
+        self.meeting_state.as_ref()
+    }
+
+    /// Returns the latest meeting permissions, if any update contained them.
+    pub fn permissions(&self) -> Option<&MeetingPermissions> {
+        self.permissions.as_ref()
+    }
+
+    /// Returns whether the latest meeting permissions allow `action`, e.g.
+    /// to enable a button. Without permissions only queries are allowed.
+    pub fn can(&self, action: MeetingAction) -> bool {
+        match &self.permissions {
+            Some(permissions) => permissions.allows(action),
+            None => !action.requires_meeting(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_tracker() {
+        let mut tracker = StateTracker::new();
+        let mut state = MeetingState {
+            is_in_meeting: true,
+            ..MeetingState::default()
+        };
+        assert!(!tracker.can(MeetingAction::Mute));
+        let update = MeetingUpdate {
+            meeting_permissions: Some(MeetingPermissions {
+                can_toggle_mute: true,
+                ..MeetingPermissions::default()
+            }),
+            meeting_state: Some(state.clone()),
+        };
+        assert_eq!(
+            tracker.apply(&update),
+            vec![MeetingStateDelta::InMeeting(true)]
+        );
+        assert!(tracker.permissions().is_some());
+        assert!(tracker.can(MeetingAction::ToggleMute));
+        assert!(!tracker.can(MeetingAction::LeaveCall));
+        assert!(tracker.can(MeetingAction::QueryMeetingState));
+
+        state.is_muted = true;
+        state.is_in_meeting = false;
+        let update = MeetingUpdate {
+            meeting_permissions: None,
+            meeting_state: Some(state),
+        };
+        assert_eq!(
+            tracker.apply(&update),
+            vec![
+                MeetingStateDelta::Muted(true),
+                MeetingStateDelta::InMeeting(false)
+            ]
+        );
+        assert!(tracker.meeting_state().unwrap().is_muted);
+        assert!(tracker.permissions().is_some());
+
+        let unread = |count| MeetingUpdate {
+            meeting_permissions: None,
+            meeting_state: Some(
+                MeetingState::new()
+                    .with_muted(true)
+                    .with_unread_messages(true)
+                    .with_unread_message_count(count),
+            ),
+        };
+        assert_eq!(
+            tracker.apply(&unread(Some(3))),
+            vec![
+                MeetingStateDelta::UnreadMessages(true),
+                MeetingStateDelta::UnreadMessageCount(Some(3))
+            ]
+        );
+        assert_eq!(
+            tracker.apply(&unread(Some(5))),
+            vec![MeetingStateDelta::UnreadMessageCount(Some(5))]
+        );
+        assert_eq!(
+            tracker.apply(&unread(None)),
+            vec![MeetingStateDelta::UnreadMessageCount(None)]
+        );
+    }
+
+    #[test]
+    fn test_state_snapshot() {
+        let reported = Instant::now() - Duration::from_secs(10);
+        let snapshot = StateSnapshot::new(MeetingState::new().with_muted(true), reported);
+        assert!(snapshot.age() >= Duration::from_secs(10));
+        assert!(snapshot.is_stale(Duration::from_secs(5)));
+        assert!(!snapshot.is_stale(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_session_events() {
+        let mut tracker = StateTracker::new();
+        let state = MeetingState {
+            is_in_meeting: true,
+            is_sharing: true,
+            is_muted: true,
+            ..MeetingState::default()
+        };
+        let update = MeetingUpdate {
+            meeting_permissions: None,
+            meeting_state: Some(state),
+        };
+        let sessions: Vec<_> = tracker
+            .events(&update)
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Session(session) => Some(session),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                SessionEvent::MeetingStarted,
+                SessionEvent::ScreenShareStarted
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::Session(SessionEvent::HandLowered)).unwrap(),
+            r#"{"session":"hand_lowered"}"#
+        );
+
+        let held = MeetingState::new().with_in_meeting(true).with_on_hold(true);
+        let update = MeetingUpdate {
+            meeting_permissions: None,
+            meeting_state: Some(held),
+        };
+        let events = tracker.events(&update);
+        assert!(events.contains(&Event::StateChanged(MeetingStateDelta::OnHold(true))));
+        assert!(events.contains(&Event::Session(SessionEvent::CallHeld)));
+    }
+}
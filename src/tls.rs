@@ -0,0 +1,192 @@
+use crate::logging;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::sync::Arc;
+
+/// A pinned server identity for `wss://` connections.
+///
+/// When at least one pin is configured, a server is trusted if and only if
+/// its end-entity certificate matches one of the pins. The system or webpki
+/// roots are not consulted, so a locally trusted CA cannot impersonate a
+/// tunneled Teams API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertificatePin {
+    /// SHA-256 fingerprint of the DER encoded certificate.
+    Certificate([u8; 32]),
+    /// SHA-256 fingerprint of the DER encoded SubjectPublicKeyInfo, which
+    /// survives certificate renewal with the same key.
+    PublicKey([u8; 32]),
+}
+
+impl CertificatePin {
+    /// Creates a certificate pin from a hex fingerprint as printed by
+    /// `openssl x509 -fingerprint -sha256`, colons are optional.
+    pub fn certificate_sha256(fingerprint: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(CertificatePin::Certificate(parse_fingerprint(fingerprint)?))
+    }
+
+    /// Creates a public key pin from a hex SHA-256 fingerprint of the
+    /// SubjectPublicKeyInfo, colons are optional.
+    pub fn public_key_sha256(fingerprint: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(CertificatePin::PublicKey(parse_fingerprint(fingerprint)?))
+    }
+
+    fn matches(&self, certificate: &CertificateDer<'_>) -> bool {
+        match self {
+            CertificatePin::Certificate(fingerprint) => {
+                Sha256::digest(certificate.as_ref()).as_slice() == fingerprint
+            }
+            CertificatePin::PublicKey(fingerprint) => {
+                match webpki::EndEntityCert::try_from(certificate) {
+                    Ok(cert) => {
+                        Sha256::digest(cert.subject_public_key_info().as_ref()).as_slice()
+                            == fingerprint
+                    }
+                    Err(e) => {
+                        warn!(
+                            target: logging::CONNECTION,
+                            "Error parsing server certificate: {}",
+                            e
+                        );
+                        false
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_fingerprint(fingerprint: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    let hex: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(Box::from("fingerprint must be 32 hex encoded bytes"));
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(bytes)
+}
+
+/// Trusts servers matching one of `pins`, or any server if `accept_any`.
+/// Handshake signatures are verified either way.
+#[derive(Debug)]
+struct PinnedVerifier {
+    pins: Vec<CertificatePin>,
+    accept_any: bool,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.pins.iter().any(|pin| pin.matches(end_entity)) {
+            Ok(ServerCertVerified::assertion())
+        } else if self.accept_any {
+            debug!(target: logging::CONNECTION, "Accepting unverified server certificate");
+            Ok(ServerCertVerified::assertion())
+        } else {
+            warn!(target: logging::CONNECTION, "Server certificate does not match any pin");
+            Err(rustls::Error::General(
+                "server certificate does not match any pin".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Builds a rustls client config that only trusts servers matching `pins`,
+/// or, if there are none and `accept_invalid` is set, any server.
+pub(crate) fn pinned_client_config(
+    pins: &[CertificatePin],
+    accept_invalid: bool,
+) -> Result<Arc<ClientConfig>, rustls::Error> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedVerifier {
+        pins: pins.to_vec(),
+        accept_any: accept_invalid && pins.is_empty(),
+        provider: provider.clone(),
+    };
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_pin() {
+        let certificate = CertificateDer::from(vec![1u8, 2, 3]);
+        let fingerprint = Sha256::digest(certificate.as_ref())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":");
+        let pin = CertificatePin::certificate_sha256(&fingerprint).unwrap();
+        assert!(pin.matches(&certificate));
+        assert!(!pin.matches(&CertificateDer::from(vec![4u8])));
+        assert!(CertificatePin::certificate_sha256("AB:CD").is_err());
+        assert!(!CertificatePin::PublicKey([0; 32]).matches(&certificate));
+
+        let mut verifier = PinnedVerifier {
+            pins: Vec::new(),
+            accept_any: false,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        };
+        let name = ServerName::try_from("teams.example").unwrap();
+        let verify = |verifier: &PinnedVerifier| {
+            verifier.verify_server_cert(&certificate, &[], &name, &[], UnixTime::now())
+        };
+        assert!(verify(&verifier).is_err());
+        verifier.accept_any = true;
+        assert!(verify(&verifier).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::logging;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Persists the token Teams sends after pairing and on refreshes, so the
+/// next run connects without pairing again.
+///
+/// Set with `TeamsWebsocketBuilder::token_store`, which also loads the
+/// stored token unless one is given explicitly.
+///
+/// # Example
+/// ```rust
+/// struct Keyring;
+///
+/// impl TokenStore for Keyring {
+///     fn load(&self) -> Result<Option<String>, Box<dyn Error>> {
+///         Ok(keyring_entry()?.get_password().ok())
+///     }
+///
+///     fn store(&mut self, token: &str) -> Result<(), Box<dyn Error>> {
+///         Ok(keyring_entry()?.set_password(token)?)
+///     }
+/// }
+/// ```
+pub trait TokenStore: Send + Sync {
+    /// Returns the stored token, or `None` if none was stored yet.
+    fn load(&self) -> Result<Option<String>, Box<dyn Error>>;
+
+    /// Replaces the stored token with `token`.
+    fn store(&mut self, token: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// A `TokenStore` keeping the token in a file readable only by the user.
+#[derive(Clone, Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Result<Option<String>, Box<dyn Error>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(token) => Ok(Some(token.trim().to_string()).filter(|token| !token.is_empty())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    fn store(&mut self, token: &str) -> Result<(), Box<dyn Error>> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        std::io::Write::write_all(&mut file, token.as_bytes())?;
+        file.sync_data()?;
+        debug!(target: logging::CONFIG, "Stored token in {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_token_store() {
+        let path = std::env::temp_dir().join(format!("teams-ws-token-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = FileTokenStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+        store.store("first").unwrap();
+        store.store("second").unwrap();
+        assert_eq!(store.load().unwrap().as_deref(), Some("second"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use futures_util::future::BoxFuture;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::VecDeque;
+use std::error::Error;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+
+/// The errors of a `Transport`.
+pub type TransportError = Box<dyn Error + Send + Sync>;
+
+/// A connection carrying the text frames of the Teams protocol, to use
+/// another WebSocket stack, an in-memory pipe in tests or a replayer of
+/// recorded sessions instead of tokio-tungstenite.
+///
+/// Opened by a `Connector` set with `TeamsWebsocketBuilder::connector`.
+/// Transports have no pings: keepalive pings and `TeamsWebsocket::ping`
+/// are answered right away, so a transport must end `recv_text` itself
+/// when the peer is gone.
+///
+/// # Example
+/// ```rust
+/// struct Pipe(mpsc::UnboundedSender<String>, mpsc::UnboundedReceiver<String>);
+///
+/// impl Transport for Pipe {
+///     fn send_text(&mut self, text: String) -> BoxFuture<'_, Result<(), TransportError>> {
+///         Box::pin(async move { Ok(self.0.send(text)?) })
+///     }
+///
+///     fn recv_text(&mut self) -> BoxFuture<'_, Option<Result<String, TransportError>>> {
+///         Box::pin(async move { self.1.recv().await.map(Ok) })
+///     }
+/// }
+/// ```
+pub trait Transport: Send + Sync {
+    /// Sends a text frame.
+    fn send_text(&mut self, text: String) -> BoxFuture<'_, Result<(), TransportError>>;
+
+    /// Receives the next text frame, or `None` once the connection ended.
+    ///
+    /// The future is dropped when `receive` is cancelled, e.g. by a
+    /// timeout, so it must not lose frames when dropped.
+    fn recv_text(&mut self) -> BoxFuture<'_, Option<Result<String, TransportError>>>;
+
+    /// Closes the connection. Does nothing by default.
+    fn close(&mut self) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Opens a `Transport` on every `connect` and reconnect of a `TeamsWebsocket`.
+///
+/// `url` is the Teams URL with the identifiers and the token as query
+/// parameters, as sent in the WebSocket handshake.
+///
+/// # Example
+/// ```rust
+/// struct Replay(PathBuf);
+///
+/// impl Connector for Replay {
+///     fn connect(&self, _url: &str) -> BoxFuture<'_, Result<Box<dyn Transport>, TransportError>> {
+///         Box::pin(async move { Ok(Box::new(Replayer::open(&self.0)?) as Box<dyn Transport>) })
+///     }
+/// }
+///
+/// let websocket = TeamsWebsocket::builder(identifier).connector(Replay(path)).build()?;
+/// ```
+pub trait Connector: Send + Sync {
+    fn connect<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> BoxFuture<'a, Result<Box<dyn Transport>, TransportError>>;
+}
+
+type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// The socket of a connection: tokio-tungstenite, or a `Transport` with
+/// the frames it cannot carry emulated.
+pub(crate) enum Socket {
+    WebSocket(Box<WebSocketStream>),
+    Transport {
+        transport: Box<dyn Transport>,
+        /// The answers to the pings sent, returned before the next frame.
+        pongs: VecDeque<Vec<u8>>,
+    },
+}
+
+impl Socket {
+    pub(crate) fn transport(transport: Box<dyn Transport>) -> Self {
+        Socket::Transport {
+            transport,
+            pongs: VecDeque::new(),
+        }
+    }
+
+    pub(crate) async fn next(&mut self) -> Option<Result<Message, tungstenite::Error>> {
+        match self {
+            Socket::WebSocket(socket) => socket.next().await,
+            Socket::Transport { transport, pongs } => {
+                if let Some(payload) = pongs.pop_front() {
+                    return Some(Ok(Message::Pong(payload)));
+                }
+                let next = transport.recv_text().await?;
+                Some(next.map(Message::Text).map_err(transport_error))
+            }
+        }
+    }
+
+    pub(crate) async fn send(&mut self, message: Message) -> Result<(), tungstenite::Error> {
+        match self {
+            Socket::WebSocket(socket) => socket.send(message).await,
+            Socket::Transport { transport, pongs } => match message {
+                Message::Text(text) => transport.send_text(text).await.map_err(transport_error),
+                Message::Ping(payload) => {
+                    pongs.push_back(payload);
+                    Ok(())
+                }
+                Message::Close(_) => transport.close().await.map_err(transport_error),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), tungstenite::Error> {
+        match self {
+            Socket::WebSocket(socket) => socket.flush().await,
+            Socket::Transport { .. } => Ok(()),
+        }
+    }
+
+    pub(crate) async fn close(
+        &mut self,
+        frame: Option<CloseFrame<'static>>,
+    ) -> Result<(), tungstenite::Error> {
+        match self {
+            Socket::WebSocket(socket) => socket.close(frame).await,
+            Socket::Transport { transport, .. } => transport.close().await.map_err(transport_error),
+        }
+    }
+}
+
+/// Reports the errors of transports as I/O errors, so disconnects are
+/// attributed to the network.
+fn transport_error(e: TransportError) -> tungstenite::Error {
+    tungstenite::Error::Io(std::io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::ConnectionStatus;
+    use crate::messages::{ClientMessage, MeetingAction, MeetingState};
+    use crate::types::AppIdentifiers;
+    use crate::TeamsWebsocket;
+    use std::sync::{Arc, Mutex};
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc;
+
+    struct Pipe(
+        mpsc::UnboundedSender<String>,
+        mpsc::UnboundedReceiver<String>,
+    );
+
+    impl Transport for Pipe {
+        fn send_text(&mut self, text: String) -> BoxFuture<'_, Result<(), TransportError>> {
+            Box::pin(async move { Ok(self.0.send(text)?) })
+        }
+
+        fn recv_text(&mut self) -> BoxFuture<'_, Option<Result<String, TransportError>>> {
+            Box::pin(async move { self.1.recv().await.map(Ok) })
+        }
+    }
+
+    /// Hands out the one end of a pipe, the test keeps the other.
+    struct PipeConnector(Mutex<Option<Pipe>>, Arc<Mutex<Option<String>>>);
+
+    impl Connector for PipeConnector {
+        fn connect<'a>(
+            &'a self,
+            url: &'a str,
+        ) -> BoxFuture<'a, Result<Box<dyn Transport>, TransportError>> {
+            Box::pin(async move {
+                *self.1.lock().unwrap() = Some(url.to_string());
+                let pipe = self.0.lock().unwrap().take().ok_or("already connected")?;
+                Ok(Box::new(pipe) as Box<dyn Transport>)
+            })
+        }
+    }
+
+    #[test]
+    fn test_connector() {
+        Runtime::new().unwrap().block_on(async {
+            let (to_teams, mut sent) = mpsc::unbounded_channel();
+            let (teams, from_teams) = mpsc::unbounded_channel();
+            let url = Arc::new(Mutex::new(None));
+            let connector =
+                PipeConnector(Mutex::new(Some(Pipe(to_teams, from_teams))), url.clone());
+            let mut websocket = TeamsWebsocket::builder(AppIdentifiers::default())
+                .ignore_environment()
+                .token("secret")
+                .connector(connector)
+                .build()
+                .unwrap();
+            websocket.connect().await.unwrap();
+            assert_eq!(websocket.status(), ConnectionStatus::Connected);
+            assert!(url
+                .lock()
+                .unwrap()
+                .as_deref()
+                .unwrap()
+                .contains("token=secret"));
+
+            websocket
+                .send(ClientMessage::new(MeetingAction::Mute, None))
+                .await
+                .unwrap();
+            assert!(sent.recv().await.unwrap().contains("\"action\":\"mute\""));
+            websocket.ping().await.unwrap();
+
+            let state = MeetingState::new().with_in_meeting(true);
+            teams
+                .send(serde_json::json!({ "meetingUpdate": { "meetingState": state } }).to_string())
+                .unwrap();
+            let message = websocket.receive().await.unwrap();
+            assert_eq!(message.meeting_update.unwrap().meeting_state, Some(state));
+
+            drop(teams);
+            assert!(websocket.receive().await.is_err());
+            assert_eq!(websocket.status(), ConnectionStatus::Disconnected);
+        });
+    }
+}
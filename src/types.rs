@@ -1,19 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
 /// A struct representing the identifiers for an teams API user.
 ///
+/// The values can be static strings or built at runtime, e.g. from a config
+/// file. `Default` identifies this crate on the current operating system;
+/// set at least `app` and `app_version` to your application's.
+///
 /// # Fields
 ///
-/// * `protocol_version` - A static string slice representing the version of the protocol.
-/// * `manufacturer` - A static string slice representing the manufacturer of the device.
-/// * `device` - A static string slice representing the device name.
-/// * `app` - A static string slice representing the application name.
-/// * `app_version` - A static string slice representing the version of the application.
-#[derive(Clone)]
-#[derive(Debug)]
-#[derive(PartialEq)]
+/// * `protocol_version` - The version of the protocol, see `TeamsFlavor`.
+/// * `manufacturer` - The manufacturer of the device.
+/// * `device` - The device name.
+/// * `app` - The application name.
+/// * `app_version` - The version of the application.
+///
+/// # Example
+/// ```rust
+/// let identifier = AppIdentifiers::builder()
+///     .app(env!("CARGO_PKG_NAME"))
+///     .app_version(env!("CARGO_PKG_VERSION"))
+///     .device(hostname)
+///     .build();
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct AppIdentifiers {
-    pub protocol_version: &'static str,
-    pub manufacturer: &'static str,
-    pub device: &'static str,
-    pub app: &'static str,
-    pub app_version: &'static str,
+    pub protocol_version: Cow<'static, str>,
+    pub manufacturer: Cow<'static, str>,
+    pub device: Cow<'static, str>,
+    pub app: Cow<'static, str>,
+    pub app_version: Cow<'static, str>,
+}
+
+impl AppIdentifiers {
+    /// Creates an `AppIdentifiersBuilder` starting from the defaults.
+    pub fn builder() -> AppIdentifiersBuilder {
+        AppIdentifiersBuilder {
+            identifier: AppIdentifiers::default(),
+        }
+    }
+}
+
+impl Default for AppIdentifiers {
+    fn default() -> Self {
+        Self {
+            protocol_version: Cow::Borrowed(TeamsFlavor::New.protocol_version()),
+            manufacturer: Cow::Borrowed(env!("CARGO_PKG_NAME")),
+            device: Cow::Borrowed(std::env::consts::OS),
+            app: Cow::Borrowed(env!("CARGO_PKG_NAME")),
+            app_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// A builder for `AppIdentifiers`, see `AppIdentifiers::builder`.
+#[derive(Clone, Debug)]
+pub struct AppIdentifiersBuilder {
+    identifier: AppIdentifiers,
+}
+
+impl AppIdentifiersBuilder {
+    pub fn protocol_version(mut self, protocol_version: impl Into<Cow<'static, str>>) -> Self {
+        self.identifier.protocol_version = protocol_version.into();
+        self
+    }
+
+    pub fn manufacturer(mut self, manufacturer: impl Into<Cow<'static, str>>) -> Self {
+        self.identifier.manufacturer = manufacturer.into();
+        self
+    }
+
+    pub fn device(mut self, device: impl Into<Cow<'static, str>>) -> Self {
+        self.identifier.device = device.into();
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<Cow<'static, str>>) -> Self {
+        self.identifier.app = app.into();
+        self
+    }
+
+    pub fn app_version(mut self, app_version: impl Into<Cow<'static, str>>) -> Self {
+        self.identifier.app_version = app_version.into();
+        self
+    }
+
+    pub fn build(self) -> AppIdentifiers {
+        self.identifier
+    }
+}
+
+/// The Teams client behind the local API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeamsFlavor {
+    /// Classic Teams, speaking protocol version 1.0.0.
+    Classic,
+    /// The new Teams client, speaking protocol version 2.0.0.
+    New,
+}
+
+impl TeamsFlavor {
+    /// The flavors in the order they are tried when detecting the client.
+    pub const ALL: [TeamsFlavor; 2] = [TeamsFlavor::New, TeamsFlavor::Classic];
+
+    /// Returns the protocol version advertised to this flavor.
+    pub fn protocol_version(self) -> &'static str {
+        match self {
+            TeamsFlavor::Classic => "1.0.0",
+            TeamsFlavor::New => "2.0.0",
+        }
+    }
+
+    /// Returns the flavor speaking `protocol_version`.
+    pub fn from_protocol_version(protocol_version: &str) -> Self {
+        if protocol_version.starts_with("1.") || protocol_version == "1" {
+            TeamsFlavor::Classic
+        } else {
+            TeamsFlavor::New
+        }
+    }
+}
+
+impl std::fmt::Display for TeamsFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeamsFlavor::Classic => write!(f, "classic Teams"),
+            TeamsFlavor::New => write!(f, "new Teams"),
+        }
+    }
+}
+
+/// The HTTP response Teams, or a proxy in front of it, upgraded the
+/// connection with.
+///
+/// # Fields
+///
+/// * `status` - The status code, 101 for a WebSocket.
+/// * `headers` - The headers in order, values lossily decoded as UTF-8.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakeResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+}
+
+impl HandshakeResponse {
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Details of an established connection.
+///
+/// # Fields
+///
+/// * `url` - The URL connected to, without the query carrying the token.
+/// * `protocol_version` - The protocol version Teams accepted.
+/// * `flavor` - The Teams client, derived from the protocol version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionInfo {
+    pub url: String,
+    pub protocol_version: Cow<'static, str>,
+    pub flavor: TeamsFlavor,
+}
+
+impl std::fmt::Display for ConnectionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (protocol version {}, {})",
+            self.url, self.protocol_version, self.flavor
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_identifiers_builder() {
+        let defaults = AppIdentifiers::default();
+        assert_eq!(defaults.protocol_version, "2.0.0");
+        assert_eq!(defaults.app_version, env!("CARGO_PKG_VERSION"));
+
+        let device = format!("desk-{}", 1);
+        let identifier = AppIdentifiers::builder()
+            .app("streamdeck")
+            .device(device)
+            .build();
+        assert_eq!(identifier.app, "streamdeck");
+        assert_eq!(identifier.device, "desk-1");
+        assert_eq!(identifier.manufacturer, defaults.manufacturer);
+    }
 }
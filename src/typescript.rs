@@ -0,0 +1,65 @@
+use crate::messages::{
+    ClientMessage, ClientMessageParameter, ClientMessageParameterType, MeetingAction,
+    MeetingPermissions, MeetingState, MeetingUpdate, ServerMessage,
+};
+use crate::state::MeetingStateDelta;
+use std::error::Error;
+use std::path::Path;
+use ts_rs::TS;
+
+/// Returns TypeScript definitions of the message and state types, as they
+/// are serialized to JSON.
+///
+/// Bridges serving these types to browser dashboards or Stream Deck plugins
+/// can ship the definitions, so the JavaScript side stays in sync with the
+/// Rust types.
+///
+/// # Example
+/// ```rust
+/// std::fs::write("teams-ws.d.ts", ms_teams_ws::typescript::definitions())?;
+/// ```
+pub fn definitions() -> String {
+    let declarations = [
+        ClientMessage::decl(),
+        ClientMessageParameter::decl(),
+        ClientMessageParameterType::decl(),
+        MeetingAction::decl(),
+        ServerMessage::decl(),
+        MeetingUpdate::decl(),
+        MeetingPermissions::decl(),
+        MeetingState::decl(),
+        MeetingStateDelta::decl(),
+    ];
+    let mut definitions = String::new();
+    for declaration in declarations {
+        definitions.push_str("export ");
+        definitions.push_str(&declaration);
+        definitions.push_str("\n\n");
+    }
+    definitions
+}
+
+/// Writes `definitions()` to the file at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+pub fn export(path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, definitions())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definitions() {
+        let definitions = definitions();
+        assert!(definitions.contains("export type ClientMessage = {"));
+        assert!(definitions.contains("requestId: number | null"));
+        assert!(!definitions.contains("origin"));
+        assert!(definitions.contains("\"toggle-mute\""));
+        assert!(definitions.contains("isInMeeting: boolean"));
+    }
+}
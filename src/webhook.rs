@@ -0,0 +1,111 @@
+use crate::logging;
+use std::error::Error;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How long `post_json` waits for the webhook target to answer.
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts `body` as JSON to an `http://` URL and returns the response status.
+///
+/// This is a deliberately small HTTP/1.1 client for local webhook targets,
+/// it does not support TLS, redirects or chunked request bodies.
+///
+/// # Errors
+///
+/// Returns an error if the URL is not `http://`, the connection fails, the
+/// target does not answer within `WEBHOOK_TIMEOUT` or the response has no
+/// valid status line.
+pub async fn post_json(
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<u16, Box<dyn Error + Send + Sync>> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported webhook url {}, only http:// is supported", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') && !authority.ends_with(']') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let body = serde_json::to_string(body)?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    );
+
+    let exchange = async {
+        let mut stream = TcpStream::connect(address).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok::<_, std::io::Error>(response)
+    };
+    let response = tokio::time::timeout(WEBHOOK_TIMEOUT, exchange)
+        .await
+        .map_err(|_| {
+            format!(
+                "webhook {} did not answer within {:?}",
+                url, WEBHOOK_TIMEOUT
+            )
+        })??;
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or("invalid webhook response")?;
+    debug!(target: logging::BRIDGE, "Webhook {} answered {}", url, status);
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_post_json() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = String::new();
+                let mut buffer = [0u8; 1024];
+                while !request.ends_with('}') {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.push_str(&String::from_utf8_lossy(&buffer[..read]));
+                }
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                    .await
+                    .unwrap();
+                request
+            });
+            let status = post_json(&url, &serde_json::json!({"muted": true}))
+                .await
+                .unwrap();
+            assert_eq!(status, 204);
+            let request = server.await.unwrap();
+            assert!(request.starts_with("POST /hook HTTP/1.1"));
+            assert!(request.ends_with(r#"{"muted":true}"#));
+            assert!(
+                post_json("https://example.invalid", &serde_json::Value::Null)
+                    .await
+                    .is_err()
+            );
+        });
+    }
+}
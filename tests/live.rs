@@ -0,0 +1,164 @@
+//! Tests against a live or emulated Teams client.
+//!
+//! They are skipped unless `TEAMS_WS_LIVE_URL` points at the endpoint, e.g.
+//! `ws://127.0.0.1:8124`. `TEAMS_WS_LIVE_TOKEN` is the pairing token, without
+//! it `live_pairing` waits for the pairing to be confirmed in Teams. The
+//! action test needs a running meeting and leaves it only with
+//! `TEAMS_WS_LIVE_LEAVE=1`.
+//!
+//! ```sh
+//! TEAMS_WS_LIVE_URL=ws://127.0.0.1:8124 TEAMS_WS_LIVE_TOKEN=... cargo test --test live
+//! ```
+
+use ms_teams_ws::messages::{ClientMessage, MeetingAction, ServerMessage};
+use ms_teams_ws::types::AppIdentifiers;
+use ms_teams_ws::TeamsWebsocket;
+use std::borrow::Cow;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+
+/// How long Teams may take to answer a request.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the pairing to be confirmed in Teams.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The client allows a single connection per app, run the tests one at a time.
+static LIVE: Mutex<()> = Mutex::new(());
+
+const IDENTIFIER: AppIdentifiers = AppIdentifiers {
+    protocol_version: Cow::Borrowed("2.0.0"),
+    manufacturer: Cow::Borrowed("ms-teams-ws"),
+    device: Cow::Borrowed("live-tests"),
+    app: Cow::Borrowed("ms-teams-ws"),
+    app_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+};
+
+/// Returns a websocket for the live endpoint, or `None` to skip the test.
+fn live_websocket() -> Option<TeamsWebsocket> {
+    let Ok(url) = std::env::var("TEAMS_WS_LIVE_URL") else {
+        eprintln!("TEAMS_WS_LIVE_URL not set, skipping live test");
+        return None;
+    };
+    let mut builder = TeamsWebsocket::builder(IDENTIFIER)
+        .ignore_environment()
+        .url(url)
+        .allow_remote(true);
+    if let Ok(token) = std::env::var("TEAMS_WS_LIVE_TOKEN") {
+        builder = builder.token(token);
+    }
+    Some(builder.build().unwrap())
+}
+
+/// Sends `action` and returns the answer to it.
+async fn request(
+    websocket: &mut TeamsWebsocket,
+    action: MeetingAction,
+) -> Result<ServerMessage, Box<dyn Error>> {
+    let message = ClientMessage::new(action, None);
+    timeout(ANSWER_TIMEOUT, websocket.send_and_wait(message)).await?
+}
+
+#[test]
+fn live_pairing() {
+    let _live = LIVE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mut websocket) = live_websocket() else {
+        return;
+    };
+    Runtime::new().unwrap().block_on(async {
+        websocket.connect().await.unwrap();
+        if std::env::var("TEAMS_WS_LIVE_TOKEN").is_ok() {
+            let answer = request(&mut websocket, MeetingAction::QueryMeetingState)
+                .await
+                .unwrap();
+            assert_eq!(answer.error_msg, None);
+            return;
+        }
+        eprintln!("Confirm the pairing in Teams");
+        websocket
+            .send(ClientMessage::new(MeetingAction::QueryMeetingState, None))
+            .await
+            .unwrap();
+        let token = timeout(PAIRING_TIMEOUT, async {
+            loop {
+                let message = websocket.receive_resilient().await.unwrap();
+                if let Some(token) = message.token_refresh {
+                    return token;
+                }
+            }
+        })
+        .await
+        .expect("pairing was not confirmed");
+        assert!(!token.is_empty());
+    });
+}
+
+#[test]
+fn live_actions() {
+    let _live = LIVE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mut websocket) = live_websocket() else {
+        return;
+    };
+    Runtime::new().unwrap().block_on(async {
+        websocket.connect().await.unwrap();
+        request(&mut websocket, MeetingAction::QueryMeetingState)
+            .await
+            .unwrap();
+        if websocket.is_in_meeting() != Some(true) {
+            eprintln!("Teams is not in a meeting, skipping actions");
+            return;
+        }
+        // Toggles are sent twice to restore the state they change. Reactions
+        // and toggling the UI need parameters and are left out.
+        let actions = [
+            MeetingAction::ToggleMute,
+            MeetingAction::ToggleMute,
+            MeetingAction::Mute,
+            MeetingAction::Unmute,
+            MeetingAction::ToggleVideo,
+            MeetingAction::ToggleVideo,
+            MeetingAction::ToggleBlurBackground,
+            MeetingAction::ToggleBlurBackground,
+            MeetingAction::RaiseHand,
+            MeetingAction::LowerHand,
+            MeetingAction::ToggleHand,
+            MeetingAction::ToggleHand,
+            MeetingAction::StopSharing,
+        ];
+        for action in actions {
+            let answer = request(&mut websocket, action).await;
+            assert!(
+                answer.is_ok(),
+                "{:?} was not answered: {:?}",
+                action,
+                answer
+            );
+        }
+        if std::env::var("TEAMS_WS_LIVE_LEAVE").as_deref() == Ok("1") {
+            request(&mut websocket, MeetingAction::LeaveCall)
+                .await
+                .unwrap();
+        }
+    });
+}
+
+#[test]
+fn live_reconnect() {
+    let _live = LIVE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mut websocket) = live_websocket() else {
+        return;
+    };
+    Runtime::new().unwrap().block_on(async {
+        for _ in 0..3 {
+            websocket.connect().await.unwrap();
+            request(&mut websocket, MeetingAction::QueryMeetingState)
+                .await
+                .unwrap();
+            websocket.close().await.unwrap();
+        }
+        assert!(websocket.disconnect_report().is_some());
+    });
+}